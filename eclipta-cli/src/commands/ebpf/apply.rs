@@ -0,0 +1,589 @@
+use crate::commands::ebpf::hook::{
+    attached_version, attachments, detach, find_hook, load_pin_dir, pin_root, pinned_versions, sanitize, Hook,
+};
+use crate::commands::ebpf::load::{handle_load, LoadOptions};
+use crate::commands::ebpf::unload::{handle_unload, UnloadOptions};
+use crate::commands::ebpf::upgrade::{handle_upgrade, UpgradeOptions};
+use crate::commands::ebpf::upload::store_object;
+use crate::db::programs::{get_program_by_title, insert_program, set_program_status, Program};
+use crate::utils::bpftool::{kernel_name, loaded_programs_for, object_program_names};
+use crate::utils::db::{ensure_db_ready, DbPool};
+use crate::utils::logger::{error, info, success, warn};
+use crate::utils::paths::default_state_path;
+use crate::utils::state::{load_state, save_state, AppliedRecord, State};
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use prettytable::{format, row, Table};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct ApplyOptions {
+    /// Manifest listing the programs that should run
+    #[arg(short = 'f', long = "file", default_value = "eclipta-programs.yaml")]
+    pub file: PathBuf,
+
+    /// Print the plan without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Also remove programs applied earlier that are no longer in the manifest
+    #[arg(long)]
+    pub prune: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    programs: Vec<ProgramSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProgramSpec {
+    /// Registry title; defaults to the file stem of `path`
+    title: Option<String>,
+    /// Registry version; defaults to a hash of the object at `path`
+    version: Option<String>,
+    /// Object to register when the title/version isn't in the registry yet
+    path: Option<PathBuf>,
+    iface: Option<String>,
+    cgroup: Option<PathBuf>,
+    #[serde(default)]
+    globals: BTreeMap<String, Value>,
+    /// Map name to a JSON or CSV file of initial entries
+    #[serde(default)]
+    maps: BTreeMap<String, PathBuf>,
+    health_window: Option<String>,
+    #[serde(default)]
+    allow_idle: bool,
+    #[serde(default)]
+    keep_maps: bool,
+    #[serde(default)]
+    state: Desired,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Desired {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// A manifest entry resolved against the registry and the kernel
+struct Target {
+    title: String,
+    version: String,
+    desired: Desired,
+    /// Object to copy into the registry first
+    register: Option<PathBuf>,
+    object: PathBuf,
+    /// None for program types that can't be swapped in place, which go through load and unload
+    hook: Option<Hook>,
+    iface: Option<String>,
+    cgroup: Option<PathBuf>,
+    globals: Vec<String>,
+    map_init: Vec<String>,
+    health_window: String,
+    allow_idle: bool,
+    keep_maps: bool,
+    spec_hash: String,
+    /// Registry version of the running program, if the program is known
+    running: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Install,
+    Upgrade { from: String },
+    Reconfigure,
+    Unchanged,
+    Remove,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Install => write!(f, "install"),
+            Action::Upgrade { from } => write!(f, "upgrade from {}", from),
+            Action::Reconfigure => write!(f, "reconfigure"),
+            Action::Unchanged => write!(f, "unchanged"),
+            Action::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+struct Step {
+    action: Action,
+    /// None for programs pruned from the state file only
+    target: Option<Target>,
+    record: Option<AppliedRecord>,
+}
+
+impl Step {
+    fn title(&self) -> &str {
+        self.target.as_ref().map(|t| t.title.as_str())
+            .or(self.record.as_ref().map(|r| r.title.as_str()))
+            .unwrap_or("-")
+    }
+}
+
+/// Converge the kernel on the manifest: register new objects, then install, upgrade,
+/// reconfigure or remove programs until what runs matches what is listed
+pub async fn handle_apply(opts: ApplyOptions) -> Result<()> {
+    let text = std::fs::read_to_string(&opts.file)
+        .with_context(|| format!("Failed to read {}", opts.file.display()))?;
+    let manifest: Manifest = serde_yaml::from_str(&text)
+        .with_context(|| format!("Invalid manifest {}", opts.file.display()))?;
+    let base = opts.file.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+    let pool = ensure_db_ready().await
+        .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
+    let state_path = default_state_path();
+    let mut state = load_state(&state_path);
+
+    let mut steps = Vec::new();
+    for spec in &manifest.programs {
+        let target = resolve(&pool, spec, base).await?;
+        if steps.iter().any(|s: &Step| s.title() == target.title) {
+            return Err(anyhow!("'{}' is listed more than once", target.title));
+        }
+        let record = state.applied.iter().find(|r| r.title == target.title).cloned();
+        let action = plan(&target, record.as_ref());
+        steps.push(Step { action, target: Some(target), record });
+    }
+    if opts.prune {
+        for record in &state.applied {
+            if !steps.iter().any(|s| s.title() == record.title) {
+                steps.push(Step { action: Action::Remove, target: None, record: Some(record.clone()) });
+            }
+        }
+    }
+
+    print_plan(&steps);
+    if opts.dry_run {
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for mut step in steps {
+        let title = step.title().to_string();
+        if step.action == Action::Unchanged {
+            continue;
+        }
+        info(&format!("{}: {}", title, step.action));
+        match execute(&pool, &opts.file, &mut step).await {
+            Ok(()) => {
+                record_step(&mut state, step);
+                save_state(&state_path, state.clone())
+                    .with_context(|| format!("Failed to write {}", state_path.display()))?;
+            }
+            Err(e) => {
+                error(&format!("{}: {:#}", title, e));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} program(s) failed to converge", failed));
+    }
+    success("Everything in the manifest is applied");
+    Ok(())
+}
+
+async fn resolve(pool: &DbPool, spec: &ProgramSpec, base: &Path) -> Result<Target> {
+    let path = spec.path.as_ref().map(|p| base.join(p));
+    let title = match (&spec.title, &path) {
+        (Some(t), _) => t.clone(),
+        (None, Some(p)) => p.file_stem().map(|s| s.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Can't name a program after {}", p.display()))?,
+        (None, None) => return Err(anyhow!("Every program needs a title or a path")),
+    };
+    let version = match (&spec.version, &path) {
+        (Some(v), _) => v.clone(),
+        (None, Some(p)) => {
+            let data = std::fs::read(p).with_context(|| format!("Failed to read {}", p.display()))?;
+            format!("local-{:016x}", fnv1a(&data))
+        }
+        (None, None) => return Err(anyhow!("'{}' needs a version or a path", title)),
+    };
+
+    let versions = get_program_by_title(pool, &title).await
+        .context("Failed to fetch programs from database")?;
+    let registered = versions.iter().find(|p| p.version == version);
+    let (object, register) = match (registered, path) {
+        (Some(p), _) => (PathBuf::from(&p.path), None),
+        (None, Some(p)) => (p.clone(), Some(p)),
+        (None, None) => return Err(anyhow!("Version '{}' of '{}' is not registered and has no path", version, title)),
+    };
+    let cgroup = spec.cgroup.clone();
+    let hook = find_hook(&object, spec.iface.as_deref(), cgroup.as_deref())?;
+    if hook.is_none() && !spec.globals.is_empty() {
+        return Err(anyhow!("'{}': globals need an XDP, TC or cgroup program", title));
+    }
+
+    // Compact JSON keeps strings quoted, so `--global` parses them back unchanged
+    let globals: Vec<String> = spec.globals.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    let map_init: Vec<String> = spec.maps.iter()
+        .map(|(m, f)| {
+            let file = base.join(f);
+            format!("{}={}", m, std::fs::canonicalize(&file).unwrap_or(file).display())
+        })
+        .collect();
+
+    let maps: Vec<(&str, Vec<u8>)> = spec.maps.iter()
+        .map(|(m, f)| (m.as_str(), std::fs::read(base.join(f)).unwrap_or_default()))
+        .collect();
+    let spec_hash = spec_hash(&version, &hook_label(hook.as_ref()), &globals, &maps);
+
+    let running = match &hook {
+        Some(hook) => running_version(hook, &title, &object, &versions).await?,
+        None => loaded_version(&object, &versions).await?,
+    };
+    Ok(Target {
+        title,
+        version,
+        desired: spec.state,
+        register,
+        object,
+        hook,
+        iface: spec.iface.clone(),
+        cgroup,
+        globals,
+        map_init,
+        health_window: spec.health_window.clone().unwrap_or_else(|| "10s".to_string()),
+        allow_idle: spec.allow_idle,
+        keep_maps: spec.keep_maps,
+        spec_hash,
+        running,
+    })
+}
+
+/// Registry version attached to the hook, "unmanaged" for a program of this title that
+/// wasn't loaded by upgrade or apply
+async fn running_version(hook: &Hook, title: &str, object: &Path, versions: &[Program]) -> Result<Option<String>> {
+    if let Some(pinned) = attached_version(hook, title).await? {
+        let version = versions.iter()
+            .find(|p| sanitize(&p.version) == pinned.version)
+            .map(|p| p.version.clone())
+            .unwrap_or(pinned.version);
+        return Ok(Some(version));
+    }
+    let attached = attachments(hook).await?;
+    let names = object_program_names(object).unwrap_or_default();
+    if attached.iter().any(|a| names.iter().any(|n| kernel_name(n) == a.name)) {
+        return Ok(Some("unmanaged".to_string()));
+    }
+    Ok(None)
+}
+
+/// Registry version `load` has pinned, "unmanaged" for programs of the object loaded otherwise
+async fn loaded_version(object: &Path, versions: &[Program]) -> Result<Option<String>> {
+    let pinned = versions.iter()
+        .find(|p| std::fs::read_dir(load_pin_dir(Path::new(&p.path))).is_ok_and(|mut d| d.next().is_some()));
+    if let Some(p) = pinned {
+        return Ok(Some(p.version.clone()));
+    }
+    if !loaded_programs_for(object).await?.is_empty() {
+        return Ok(Some("unmanaged".to_string()));
+    }
+    Ok(None)
+}
+
+/// The hook column of the plan and the state file
+fn hook_label(hook: Option<&Hook>) -> String {
+    hook.map(|h| h.to_string()).unwrap_or_else(|| "load".to_string())
+}
+
+/// Fingerprint of everything a reconfigure would change
+fn spec_hash(version: &str, hook: &str, globals: &[String], maps: &[(&str, Vec<u8>)]) -> String {
+    let mut hashed = format!("{}\n{}\n", version, hook).into_bytes();
+    for g in globals {
+        hashed.extend_from_slice(g.as_bytes());
+        hashed.push(b'\n');
+    }
+    for (m, contents) in maps {
+        hashed.extend_from_slice(m.as_bytes());
+        hashed.extend_from_slice(contents);
+        hashed.push(b'\n');
+    }
+    format!("{:016x}", fnv1a(&hashed))
+}
+
+fn plan(target: &Target, record: Option<&AppliedRecord>) -> Action {
+    match (target.desired, &target.running) {
+        (Desired::Absent, None) if record.is_none() => Action::Unchanged,
+        (Desired::Absent, _) => Action::Remove,
+        (Desired::Present, None) => Action::Install,
+        (Desired::Present, Some(v)) if *v != target.version => Action::Upgrade { from: v.clone() },
+        (Desired::Present, Some(_)) => match record {
+            Some(r) if r.spec_hash == target.spec_hash => Action::Unchanged,
+            _ => Action::Reconfigure,
+        },
+    }
+}
+
+fn print_plan(steps: &[Step]) {
+    if steps.is_empty() {
+        info("The manifest lists no programs");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(row!["Program", "Version", "Hook", "Running", "Action"]);
+    for step in steps {
+        let (version, hook, running) = match (&step.target, &step.record) {
+            (Some(t), _) => (t.version.clone(), hook_label(t.hook.as_ref()), t.running.clone().unwrap_or_else(|| "-".to_string())),
+            (None, Some(r)) => (r.version.clone(), r.hook.clone(), "?".to_string()),
+            (None, None) => continue,
+        };
+        let mut action = step.action.to_string();
+        if step.target.as_ref().is_some_and(|t| t.register.is_some()) && step.action != Action::Remove {
+            action = format!("register, {}", action);
+        }
+        table.add_row(row![step.title(), version, hook, running, action]);
+    }
+    print!("{}", table);
+}
+
+/// Registering a new object points the step's target at the stored copy, which is what the
+/// registry and the state file refer to from then on
+async fn execute(pool: &DbPool, manifest: &Path, step: &mut Step) -> Result<()> {
+    if step.action == Action::Remove {
+        return remove(pool, step).await;
+    }
+    let Some(t) = &mut step.target else { return Ok(()) };
+    if let Some(path) = t.register.take() {
+        let stored = store_object(&path, &t.title)
+            .with_context(|| format!("Failed to store {}", path.display()))?;
+        let description = format!("Applied from {}", manifest.display());
+        insert_program(pool, &t.title, &description, &t.version, &stored.to_string_lossy()).await
+            .context("Failed to register the program")?;
+        info(&format!("Registered {} as '{}' {}", path.display(), t.title, t.version));
+        t.object = stored;
+    }
+    let t = &*t;
+    if t.hook.is_none() {
+        return load_step(pool, &step.action, t).await;
+    }
+
+    let from_version = match (&step.action, &t.running) {
+        (Action::Reconfigure, _) => Some(t.version.clone()),
+        (Action::Upgrade { .. }, Some(v)) if v != "unmanaged" => Some(v.clone()),
+        _ => None,
+    };
+    handle_upgrade(UpgradeOptions {
+        title: t.title.clone(),
+        to_version: t.version.clone(),
+        from_version,
+        reload: step.action == Action::Reconfigure,
+        iface: t.iface.clone(),
+        cgroup: t.cgroup.clone(),
+        health_window: t.health_window.clone(),
+        allow_idle: t.allow_idle,
+        keep_maps: t.keep_maps && step.action != Action::Install,
+        map_init: t.map_init.clone(),
+        globals: t.globals.clone(),
+    })
+    .await
+}
+
+/// Programs with no XDP, TC or cgroup hook can't be swapped in place: the new version is
+/// loaded with `load`, taking over the old one's maps when asked, and the old one unloaded
+/// after it. A reconfigure has to unload first, since an object is only loaded once
+async fn load_step(pool: &DbPool, action: &Action, t: &Target) -> Result<()> {
+    let versions = get_program_by_title(pool, &t.title).await
+        .context("Failed to fetch programs from database")?;
+    let id = versions.iter().find(|p| p.version == t.version).map(|p| p.id)
+        .ok_or_else(|| anyhow!("'{}' {} is not registered", t.title, t.version))?;
+    let old = match (action, &t.running) {
+        (Action::Upgrade { .. }, Some(v)) if v == "unmanaged" => {
+            return Err(anyhow!("'{}' was loaded outside apply; unload it first", t.title));
+        }
+        (Action::Upgrade { .. } | Action::Reconfigure, Some(v)) => versions.iter().find(|p| &p.version == v),
+        _ => None,
+    };
+    let same = old.is_some_and(|o| o.version == t.version);
+    if let (Some(o), true) = (old, same) {
+        unload_object(Path::new(&o.path), t.iface.clone()).await?;
+    }
+    handle_load(LoadOptions {
+        program: None,
+        id: Some(id),
+        title: None,
+        iface: t.iface.clone(),
+        socket_fd: None,
+        map_init: t.map_init.clone(),
+        reuse_maps_from: old.filter(|_| t.keep_maps && !same).map(|o| o.id.to_string()),
+        tail_calls: Vec::new(),
+        share_maps: Vec::new(),
+        ttl: None,
+        active_window: None,
+    })
+    .await?;
+    if let (Some(o), false) = (old, same) {
+        unload_object(Path::new(&o.path), t.iface.clone()).await?;
+    }
+    Ok(())
+}
+
+/// Unload and unpin what `load` put in place for `object`
+async fn unload_object(object: &Path, iface: Option<String>) -> Result<()> {
+    handle_unload(UnloadOptions {
+        program: Some(object.to_path_buf()),
+        id: None,
+        title: None,
+        name: object_program_names(object).ok().and_then(|n| n.into_iter().next()),
+        iface,
+        socket_fd: None,
+        tracepoint: None,
+        state_file: None,
+        unpin: true,
+        json: false,
+        verbose: false,
+    })
+    .await
+}
+
+/// Detach every pinned version of the title, or unload the versions `load` put in place,
+/// drop its pins and mark it inactive
+async fn remove(pool: &DbPool, step: &Step) -> Result<()> {
+    let title = step.title();
+    let (hook, iface) = match (&step.target, &step.record) {
+        (Some(t), _) => (t.hook.clone(), t.iface.clone()),
+        (None, Some(r)) => (find_hook(Path::new(&r.path), r.iface.as_deref(), r.cgroup.as_deref())?, r.iface.clone()),
+        (None, None) => return Ok(()),
+    };
+    let versions = get_program_by_title(pool, title).await?;
+    let Some(hook) = hook else {
+        for p in &versions {
+            let path = Path::new(&p.path);
+            if std::fs::read_dir(load_pin_dir(path)).is_ok_and(|mut d| d.next().is_some()) {
+                unload_object(path, iface.clone()).await?;
+            }
+            if p.status == "active" {
+                set_program_status(pool, p.id, "deactive").await?;
+            }
+        }
+        success(&format!("'{}' removed", title));
+        return Ok(());
+    };
+    let pinned = pinned_versions(title).await;
+    for a in attachments(&hook).await? {
+        if pinned.iter().any(|v| v.prog_ids.contains(&a.prog_id)) {
+            detach(&hook, &a).await?;
+            info(&format!("Detached program {} from {}", a.prog_id, hook));
+        }
+    }
+    if pinned.is_empty() {
+        warn(&format!("'{}' has no pinned versions; programs loaded another way are left alone", title));
+    }
+    let root = pin_root(title);
+    if root.exists() {
+        std::fs::remove_dir_all(&root).with_context(|| format!("Failed to remove {}", root.display()))?;
+    }
+    for p in versions {
+        if p.status == "active" {
+            set_program_status(pool, p.id, "deactive").await?;
+        }
+    }
+    success(&format!("'{}' removed", title));
+    Ok(())
+}
+
+fn record_step(state: &mut State, step: Step) {
+    let title = step.title().to_string();
+    state.applied.retain(|r| r.title != title);
+    let (Some(t), false) = (step.target, step.action == Action::Remove) else { return };
+    state.applied.push(AppliedRecord {
+        title,
+        version: t.version,
+        hook: hook_label(t.hook.as_ref()),
+        path: t.object.display().to_string(),
+        iface: t.iface,
+        cgroup: t.cgroup,
+        spec_hash: t.spec_hash,
+        applied_at: chrono::Utc::now().timestamp(),
+    });
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(desired: Desired, version: &str, running: Option<&str>) -> Target {
+        Target {
+            title: "filter".to_string(),
+            version: version.to_string(),
+            desired,
+            register: None,
+            object: PathBuf::from("filter.o"),
+            hook: Some(Hook::Xdp { iface: "eth0".to_string() }),
+            iface: Some("eth0".to_string()),
+            cgroup: None,
+            globals: Vec::new(),
+            map_init: Vec::new(),
+            health_window: "10s".to_string(),
+            allow_idle: false,
+            keep_maps: false,
+            spec_hash: "a".to_string(),
+            running: running.map(str::to_string),
+        }
+    }
+
+    fn record(spec_hash: &str) -> AppliedRecord {
+        AppliedRecord {
+            title: "filter".to_string(),
+            version: "1.0".to_string(),
+            hook: "XDP on eth0".to_string(),
+            path: "filter.o".to_string(),
+            iface: Some("eth0".to_string()),
+            cgroup: None,
+            spec_hash: spec_hash.to_string(),
+            applied_at: 0,
+        }
+    }
+
+    #[test]
+    fn plan_compares_running_version_and_spec() {
+        use Desired::*;
+        assert_eq!(plan(&target(Present, "1.0", None), None), Action::Install);
+        assert_eq!(plan(&target(Present, "1.1", Some("1.0")), Some(&record("a"))), Action::Upgrade { from: "1.0".to_string() });
+        assert_eq!(plan(&target(Present, "1.0", Some("unmanaged")), None), Action::Upgrade { from: "unmanaged".to_string() });
+        assert_eq!(plan(&target(Present, "1.0", Some("1.0")), Some(&record("a"))), Action::Unchanged);
+        assert_eq!(plan(&target(Present, "1.0", Some("1.0")), Some(&record("b"))), Action::Reconfigure);
+        // Running but never applied: its settings are unknown
+        assert_eq!(plan(&target(Present, "1.0", Some("1.0")), None), Action::Reconfigure);
+
+        assert_eq!(plan(&target(Absent, "1.0", None), None), Action::Unchanged);
+        assert_eq!(plan(&target(Absent, "1.0", None), Some(&record("a"))), Action::Remove);
+        assert_eq!(plan(&target(Absent, "1.0", Some("1.0")), None), Action::Remove);
+    }
+
+    #[test]
+    fn spec_hash_changes_with_every_setting() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+
+        let globals = vec!["debug=1".to_string()];
+        let maps = vec![("blocked", b"10.0.0.1,1\n".to_vec())];
+        let base = spec_hash("1.0", "XDP on eth0", &globals, &maps);
+        assert_eq!(base, spec_hash("1.0", "XDP on eth0", &globals, &maps));
+        assert_ne!(base, spec_hash("1.1", "XDP on eth0", &globals, &maps));
+        assert_ne!(base, spec_hash("1.0", "XDP on eth1", &globals, &maps));
+        assert_ne!(base, spec_hash("1.0", "XDP on eth0", &["debug=2".to_string()], &maps));
+        assert_ne!(base, spec_hash("1.0", "XDP on eth0", &globals, &[("blocked", b"10.0.0.2,1\n".to_vec())]));
+        assert_ne!(base, spec_hash("1.0", "XDP on eth0", &globals, &[]));
+        assert_eq!(hook_label(None), "load");
+    }
+}
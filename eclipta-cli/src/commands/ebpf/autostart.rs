@@ -0,0 +1,354 @@
+use crate::commands::ebpf::globals::GlobalSpec;
+use crate::commands::ebpf::hook::{attached_version, find_hook, sanitize};
+use crate::commands::ebpf::load::{handle_load, LoadOptions};
+use crate::commands::ebpf::upgrade::{handle_upgrade, UpgradeOptions};
+use crate::db::autostart::{clear_autostart, list_autostart, record_autostart_result, set_autostart, AutostartEntry};
+use crate::db::programs::{get_map_init, get_program_by_title};
+use crate::utils::bpftool::loaded_programs_for;
+use crate::utils::db::{ensure_db_ready, DbPool};
+use crate::utils::logger::{error, info, success, warn};
+use crate::utils::systemd::{install_units, oneshot_service};
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Subcommand};
+use prettytable::{format, row, Table};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const UNIT_NAME: &str = "eclipta-autostart.service";
+
+#[derive(Args, Debug)]
+pub struct AutostartOptions {
+    #[command(subcommand)]
+    pub command: AutostartCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AutostartCommand {
+    /// Flag a registered program to be loaded on boot
+    Enable(AutostartEnableOptions),
+    /// Stop loading a program on boot
+    Disable {
+        /// Registered program title
+        #[arg(long)]
+        title: String,
+    },
+    /// Show flagged programs and how their last start went
+    List,
+    /// Load every flagged program in dependency order
+    Run(AutostartRunOptions),
+    /// Write a systemd unit that runs `eclipta autostart run` on boot
+    Install(AutostartInstallOptions),
+}
+
+#[derive(Args, Debug)]
+pub struct AutostartEnableOptions {
+    /// Registered program title
+    #[arg(long)]
+    pub title: String,
+
+    /// Version to load (default: the active one, else the newest)
+    #[arg(long)]
+    pub version: Option<String>,
+
+    /// Interface of XDP and TC programs
+    #[arg(long)]
+    pub iface: Option<String>,
+
+    /// cgroup v2 directory of cgroup programs
+    #[arg(long)]
+    pub cgroup: Option<PathBuf>,
+
+    /// Set a non-const global variable on load, e.g. "debug=1" (repeatable)
+    #[arg(long = "global", value_name = "NAME=VALUE")]
+    pub globals: Vec<String>,
+
+    /// Title of a program that must be running first (repeatable)
+    #[arg(long, value_name = "TITLE")]
+    pub after: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AutostartRunOptions {
+    /// How long each program must stay attached before the next one loads
+    #[arg(long, default_value = "0s")]
+    pub health_window: String,
+}
+
+#[derive(Args, Debug)]
+pub struct AutostartInstallOptions {
+    /// Directory the unit is written to
+    #[arg(long, default_value = "/etc/systemd/system")]
+    pub unit_dir: PathBuf,
+
+    /// Print the unit instead of writing it
+    #[arg(long)]
+    pub print: bool,
+
+    /// Reload systemd and enable the unit after writing it
+    #[arg(long)]
+    pub enable: bool,
+}
+
+pub async fn handle_autostart(opts: AutostartOptions) -> Result<()> {
+    match opts.command {
+        AutostartCommand::Enable(o) => handle_autostart_enable(o).await,
+        AutostartCommand::Disable { title } => handle_autostart_disable(&title).await,
+        AutostartCommand::List => handle_autostart_list().await,
+        AutostartCommand::Run(o) => handle_autostart_run(o).await,
+        AutostartCommand::Install(o) => handle_autostart_install(o).await,
+    }
+}
+
+async fn pool() -> Result<DbPool> {
+    ensure_db_ready().await.map_err(|e| anyhow!("Failed to initialize database: {}", e))
+}
+
+async fn handle_autostart_enable(o: AutostartEnableOptions) -> Result<()> {
+    let pool = pool().await?;
+    let versions = get_program_by_title(&pool, &o.title).await
+        .context("Failed to fetch programs from database")?;
+    let program = match &o.version {
+        Some(v) => versions.iter().find(|p| &p.version == v),
+        None => versions.iter().find(|p| p.status == "active").or(versions.first()),
+    }
+    .ok_or_else(|| anyhow!("No matching version of '{}' is registered", o.title))?;
+
+    // Surface a missing --iface or a bad --global now rather than on the next boot
+    let hook = find_hook(Path::new(&program.path), o.iface.as_deref(), o.cgroup.as_deref())?;
+    for g in &o.globals {
+        GlobalSpec::parse(g)?;
+    }
+    if hook.is_none() && !o.globals.is_empty() {
+        return Err(anyhow!("--global needs an XDP, TC or cgroup program; '{}' is started with load", o.title));
+    }
+    if o.after.contains(&o.title) {
+        return Err(anyhow!("'{}' can't start after itself", o.title));
+    }
+
+    let cgroup = o.cgroup.as_ref().map(|c| c.display().to_string());
+    set_autostart(&pool, program.id, o.iface.as_deref(), cgroup.as_deref(), &o.globals, &o.after).await
+        .context("Failed to flag the program")?;
+    let how = hook.map(|h| h.to_string()).unwrap_or_else(|| "with load".to_string());
+    success(&format!("'{}' {} will be loaded on boot ({})", o.title, program.version, how));
+    Ok(())
+}
+
+async fn handle_autostart_disable(title: &str) -> Result<()> {
+    let pool = pool().await?;
+    match clear_autostart(&pool, title).await? {
+        0 => warn(&format!("'{}' was not flagged for autostart", title)),
+        _ => success(&format!("'{}' will no longer be loaded on boot", title)),
+    }
+    Ok(())
+}
+
+async fn handle_autostart_list() -> Result<()> {
+    let pool = pool().await?;
+    let entries = list_autostart(&pool).await?;
+    if entries.is_empty() {
+        info("No programs are flagged for autostart (add one with: eclipta autostart enable)");
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(row!["Title", "Version", "Target", "After", "Last run", "Status", "Error"]);
+    for e in &entries {
+        let target = e.iface.clone().or(e.cgroup.clone()).unwrap_or_else(|| "-".to_string());
+        let last_run = e.last_run_at.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_else(|| "-".to_string());
+        table.add_row(row![
+            e.title,
+            e.version,
+            target,
+            e.after.join(", "),
+            last_run,
+            e.last_status.as_deref().unwrap_or("-"),
+            e.last_error.as_deref().unwrap_or("")
+        ]);
+    }
+    print!("{}", table);
+    Ok(())
+}
+
+/// Load every flagged program, recording each outcome instead of stopping at the first failure
+async fn handle_autostart_run(o: AutostartRunOptions) -> Result<()> {
+    let pool = pool().await?;
+    let entries = list_autostart(&pool).await?;
+    if entries.is_empty() {
+        info("No programs are flagged for autostart");
+        return Ok(());
+    }
+
+    let (order, unordered) = dependency_order(&entries);
+    let mut outcome: HashMap<&str, bool> = HashMap::new();
+    for (i, reason) in &unordered {
+        let e = &entries[*i];
+        error(&format!("{}: {}", e.title, reason));
+        record_autostart_result(&pool, e.program_id, "failed", Some(reason)).await?;
+        outcome.insert(&e.title, false);
+    }
+
+    for i in order {
+        let e = &entries[i];
+        if let Some(dep) = e.after.iter().find(|d| outcome.get(d.as_str()) == Some(&false)) {
+            let reason = format!("dependency '{}' did not start", dep);
+            warn(&format!("{}: skipped, {}", e.title, reason));
+            record_autostart_result(&pool, e.program_id, "skipped", Some(&reason)).await?;
+            outcome.insert(&e.title, false);
+            continue;
+        }
+        info(&format!("Starting '{}' {}...", e.title, e.version));
+        match start(&pool, e, &o.health_window).await {
+            Ok(()) => {
+                record_autostart_result(&pool, e.program_id, "ok", None).await?;
+                outcome.insert(&e.title, true);
+            }
+            Err(err) => {
+                let reason = format!("{:#}", err);
+                error(&format!("{}: {}", e.title, reason));
+                record_autostart_result(&pool, e.program_id, "failed", Some(&reason)).await?;
+                outcome.insert(&e.title, false);
+            }
+        }
+    }
+
+    let failed = outcome.values().filter(|ok| !**ok).count();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} autostart programs did not start", failed, entries.len()));
+    }
+    success(&format!("All {} autostart programs are running", entries.len()));
+    Ok(())
+}
+
+async fn start(pool: &DbPool, e: &AutostartEntry, health_window: &str) -> Result<()> {
+    let cgroup = e.cgroup.as_ref().map(PathBuf::from);
+    let Some(hook) = find_hook(Path::new(&e.path), e.iface.as_deref(), cgroup.as_deref())? else {
+        return load(e).await;
+    };
+    if attached_version(&hook, &e.title).await?.is_some_and(|v| v.version == sanitize(&e.version)) {
+        info(&format!("'{}' {} is already running", e.title, e.version));
+        return Ok(());
+    }
+    handle_upgrade(UpgradeOptions {
+        title: e.title.clone(),
+        to_version: e.version.clone(),
+        from_version: None,
+        reload: false,
+        iface: e.iface.clone(),
+        cgroup,
+        health_window: health_window.to_string(),
+        // Right after boot there may be no traffic yet
+        allow_idle: true,
+        keep_maps: false,
+        map_init: get_map_init(pool, e.program_id).await?,
+        globals: e.globals.clone(),
+    })
+    .await
+}
+
+/// Programs that can't be swapped in place, such as tracepoints and kprobes, go through `load`,
+/// which takes the registry's map init files itself. There is no health window to wait out
+async fn load(e: &AutostartEntry) -> Result<()> {
+    if !loaded_programs_for(Path::new(&e.path)).await?.is_empty() {
+        info(&format!("'{}' {} is already loaded", e.title, e.version));
+        return Ok(());
+    }
+    if !e.globals.is_empty() {
+        return Err(anyhow!("--global needs an XDP, TC or cgroup program"));
+    }
+    handle_load(LoadOptions {
+        program: None,
+        id: Some(e.program_id),
+        title: None,
+        iface: e.iface.clone(),
+        socket_fd: None,
+        map_init: Vec::new(),
+        reuse_maps_from: None,
+        tail_calls: Vec::new(),
+        share_maps: Vec::new(),
+        ttl: None,
+        active_window: None,
+    })
+    .await
+}
+
+/// Indices in an order where every program comes after the ones it names, alphabetical
+/// among equals; programs with unknown or cyclic dependencies are returned with a reason
+fn dependency_order(entries: &[AutostartEntry]) -> (Vec<usize>, Vec<(usize, String)>) {
+    let index: HashMap<&str, usize> = entries.iter().enumerate().map(|(i, e)| (e.title.as_str(), i)).collect();
+    let mut unordered = Vec::new();
+    let mut pending: Vec<usize> = Vec::new();
+    for (i, e) in entries.iter().enumerate() {
+        match e.after.iter().find(|d| !index.contains_key(d.as_str())) {
+            Some(d) => unordered.push((i, format!("depends on '{}', which is not flagged for autostart", d))),
+            None => pending.push(i),
+        }
+    }
+
+    let mut order = Vec::new();
+    loop {
+        let ready = pending.iter().position(|&i| {
+            entries[i].after.iter().all(|d| order.contains(&index[d.as_str()]))
+        });
+        match ready {
+            Some(p) => order.push(pending.remove(p)),
+            None => break,
+        }
+    }
+    // Whatever is left waits on a cycle or on a program that can't be ordered
+    for i in pending {
+        unordered.push((i, format!("dependency cycle or unresolvable dependency among: {}", entries[i].after.join(", "))));
+    }
+    (order, unordered)
+}
+
+async fn handle_autostart_install(o: AutostartInstallOptions) -> Result<()> {
+    let unit = oneshot_service("Load eclipta autostart eBPF programs", "autostart run", true, Some("multi-user.target"))?;
+    install_units(&o.unit_dir, &[(UNIT_NAME, unit)], o.print, o.enable.then_some(UNIT_NAME)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, after: &[&str]) -> AutostartEntry {
+        AutostartEntry {
+            program_id: 0,
+            title: title.to_string(),
+            version: "1.0.0".to_string(),
+            path: String::new(),
+            iface: None,
+            cgroup: None,
+            globals: Vec::new(),
+            after: after.iter().map(|a| a.to_string()).collect(),
+            last_status: None,
+            last_error: None,
+            last_run_at: None,
+        }
+    }
+
+    #[test]
+    fn dependencies_start_first() {
+        // Listed alphabetically, as list_autostart returns them
+        let entries = [entry("a", &["c"]), entry("b", &[]), entry("c", &["b"]), entry("d", &[])];
+        let (order, unordered) = dependency_order(&entries);
+        assert_eq!(order, vec![1, 2, 0, 3]);
+        assert!(unordered.is_empty());
+    }
+
+    #[test]
+    fn cycles_and_unknown_dependencies_are_not_ordered() {
+        let entries = [
+            entry("a", &["b"]),
+            entry("b", &["a"]),
+            entry("c", &["missing"]),
+            entry("d", &["a"]),
+            entry("e", &[]),
+        ];
+        let (order, unordered) = dependency_order(&entries);
+        assert_eq!(order, vec![4]);
+        let reasons: Vec<(usize, &str)> = unordered.iter().map(|(i, r)| (*i, r.as_str())).collect();
+        assert_eq!(reasons[0], (2, "depends on 'missing', which is not flagged for autostart"));
+        assert_eq!(reasons[1..].iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![0, 1, 3]);
+        assert!(reasons[1].1.starts_with("dependency cycle"));
+    }
+}
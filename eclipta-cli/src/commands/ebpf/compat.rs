@@ -0,0 +1,237 @@
+use crate::commands::ebpf::load::resolve_object;
+use crate::utils::btf::Btf;
+use crate::utils::core_relo::{CoreObject, CoreRelo, TargetBtf};
+use crate::utils::db::ensure_db_ready;
+use crate::utils::logger::{error, info, success, warn};
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct CompatOptions {
+    /// Object file to check
+    #[arg(short, long)]
+    pub program: Option<PathBuf>,
+
+    /// Registry id of the program to check
+    #[arg(long)]
+    pub id: Option<i32>,
+
+    /// Registry title of the program to check
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// Kernel BTF to check against: a raw BTF file (e.g. a copy of /sys/kernel/btf/vmlinux
+    /// or a btfhub .btf), a vmlinux ELF, or a directory of them (repeatable)
+    #[arg(long, required = true)]
+    pub btf: Vec<PathBuf>,
+
+    /// Print the results as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Also list existence checks that fail, which programs are expected to handle
+    #[arg(short, long)]
+    pub verbose: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KernelReport {
+    pub kernel: String,
+    pub compatible: bool,
+    /// Sections with at least one relocation that does not resolve
+    pub programs: Vec<ProgramReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProgramReport {
+    pub section: String,
+    pub programs: Vec<String>,
+    /// Relocations the loader would fail on
+    pub failures: Vec<ReloIssue>,
+    /// `bpf_core_*_exists` checks that evaluate to false on this kernel
+    pub guarded: Vec<ReloIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloIssue {
+    pub relocation: String,
+    pub kind: &'static str,
+    pub reason: String,
+}
+
+/// Check the CO-RE relocations of an object against kernel BTF files, without touching the
+/// running kernel
+pub async fn handle_compat(opts: CompatOptions) -> Result<()> {
+    let path = match &opts.program {
+        // A plain file needs no database, so the check also runs on build machines
+        Some(p) if opts.id.is_none() && opts.title.is_none() => p.clone(),
+        _ => {
+            let pool = ensure_db_ready().await
+                .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
+            resolve_object(&pool, opts.id, opts.title.as_deref(), opts.program.as_ref(), "check").await?.0
+        }
+    };
+
+    let object = CoreObject::from_elf(&path)?;
+    let sections = section_programs(&path)?;
+    if object.relos.is_empty() {
+        info(&format!("{} has no CO-RE relocations; it runs wherever its types match the kernel it was built for", path.display()));
+        return Ok(());
+    }
+    let relos = unique(&object.relos);
+    if !opts.json {
+        info(&format!("{}: {} CO-RE relocations in {} sections", path.display(), relos.len(), sections.len().max(1)));
+    }
+
+    let kernels = kernel_files(&opts.btf)?;
+    let mut reports = Vec::new();
+    for (label, file) in &kernels {
+        let btf = match Btf::from_file(file) {
+            Ok(btf) => TargetBtf::new(btf),
+            Err(e) => {
+                warn(&format!("Skipping {}: {:#}", file.display(), e));
+                continue;
+            }
+        };
+        reports.push(check_kernel(label, &object, &relos, &btf, &sections));
+    }
+    if reports.is_empty() {
+        return Err(anyhow!("None of the given BTF files could be read"));
+    }
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        print_reports(&reports, opts.verbose);
+    }
+
+    let failing = reports.iter().filter(|r| !r.compatible).count();
+    if failing > 0 {
+        return Err(anyhow!("{} of {} kernels can't run every program of {}", failing, reports.len(), path.display()));
+    }
+    if !opts.json {
+        success(&format!("Compatible with all {} kernels", reports.len()));
+    }
+    Ok(())
+}
+
+fn check_kernel(
+    label: &str,
+    object: &CoreObject,
+    relos: &[&CoreRelo],
+    target: &TargetBtf,
+    sections: &BTreeMap<String, Vec<String>>,
+) -> KernelReport {
+    let mut by_section: BTreeMap<&str, ProgramReport> = BTreeMap::new();
+    for relo in relos {
+        let Err(reason) = object.resolve(relo, target) else { continue };
+        let report = by_section.entry(&relo.section).or_insert_with(|| ProgramReport {
+            section: relo.section.clone(),
+            programs: sections.get(&relo.section).cloned().unwrap_or_default(),
+            failures: Vec::new(),
+            guarded: Vec::new(),
+        });
+        let issue = ReloIssue { relocation: object.describe(relo), kind: relo.kind.describe(), reason };
+        if relo.kind.is_guard() {
+            report.guarded.push(issue);
+        } else {
+            report.failures.push(issue);
+        }
+    }
+    let programs: Vec<ProgramReport> = by_section.into_values().collect();
+    KernelReport {
+        kernel: label.to_string(),
+        compatible: programs.iter().all(|p| p.failures.is_empty()),
+        programs,
+    }
+}
+
+fn print_reports(reports: &[KernelReport], verbose: bool) {
+    for r in reports {
+        let failing: Vec<&ProgramReport> = r.programs.iter().filter(|p| !p.failures.is_empty()).collect();
+        if failing.is_empty() {
+            success(&format!("{}: all relocations resolve", r.kernel));
+        } else {
+            error(&format!("{}: {} program section(s) would fail to load", r.kernel, failing.len()));
+        }
+        for p in &r.programs {
+            if p.failures.is_empty() && !verbose {
+                continue;
+            }
+            let names = if p.programs.is_empty() { String::new() } else { format!(" ({})", p.programs.join(", ")) };
+            println!("  {}{}", p.section, names);
+            for i in &p.failures {
+                println!("    ✗ {} [{}]: {}", i.relocation, i.kind, i.reason);
+            }
+            if verbose {
+                for i in &p.guarded {
+                    println!("    ? {} [{}]: {}", i.relocation, i.kind, i.reason);
+                }
+            }
+        }
+    }
+}
+
+/// The same access at several instructions is checked and reported once
+fn unique(relos: &[CoreRelo]) -> Vec<&CoreRelo> {
+    let mut out: Vec<&CoreRelo> = Vec::new();
+    for r in relos {
+        let seen = out.iter().any(|o| {
+            o.section == r.section && o.type_id == r.type_id && o.access == r.access && o.kind == r.kind
+        });
+        if !seen {
+            out.push(r);
+        }
+    }
+    out
+}
+
+/// Every BTF file named on the command line, expanding directories, with a display label
+fn kernel_files(paths: &[PathBuf]) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push((path.display().to_string(), path.clone()));
+            continue;
+        }
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .collect();
+        entries.sort();
+        for e in entries {
+            let label = e.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            files.push((label, e));
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!("No BTF files found in {}", paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")));
+    }
+    Ok(files)
+}
+
+/// Program function names of each ELF section
+fn section_programs(path: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    let data = std::fs::read(path).context("Failed to read eBPF object")?;
+    let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+    let mut sections: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for sym in obj.symbols() {
+        if sym.kind() != SymbolKind::Text || !sym.is_global() {
+            continue;
+        }
+        let section = sym.section_index()
+            .and_then(|i| obj.section_by_index(i).ok())
+            .and_then(|s| s.name().ok().map(str::to_string));
+        if let (Some(section), Ok(name)) = (section, sym.name()) {
+            if section != ".text" {
+                sections.entry(section).or_default().push(name.to_string());
+            }
+        }
+    }
+    Ok(sections)
+}
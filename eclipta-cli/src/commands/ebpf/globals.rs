@@ -0,0 +1,128 @@
+use crate::utils::bpftool::{map_lookup, map_update, MapRef, MapValue};
+use crate::utils::btf::Btf;
+use crate::utils::logger::success;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Offset, encoded bytes and name of one variable inside its section
+type Patch<'a> = (u32, Vec<u8>, &'a str);
+
+/// One `--global <name>=<value>` request
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalSpec {
+    pub name: String,
+    pub value: Value,
+}
+
+impl GlobalSpec {
+    /// The value is JSON when it parses as such (numbers, arrays, objects), a string otherwise
+    pub fn parse(spec: &str) -> Result<GlobalSpec> {
+        let (name, value) = spec
+            .split_once('=')
+            .filter(|(n, _)| !n.is_empty())
+            .ok_or_else(|| anyhow!("Invalid --global '{}', expected <name>=<value>", spec))?;
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+        Ok(GlobalSpec { name: name.to_string(), value })
+    }
+}
+
+/// Write global variables into the `.data`/`.bss` maps of an object loaded with
+/// `bpftool prog loadall ... pinmaps <maps_dir>`, before its programs attach.
+/// `.rodata` is frozen by the loader, so const globals can't be changed this way
+pub async fn set_globals(object: &Path, maps_dir: &Path, globals: &[GlobalSpec]) -> Result<()> {
+    if globals.is_empty() {
+        return Ok(());
+    }
+    let btf = Btf::from_elf(object)?
+        .ok_or_else(|| anyhow!("{} has no BTF; rebuild it with -g to set globals", object.display()))?;
+
+    // Patch each section's single value once, with every variable that lives in it
+    let mut sections: Vec<(String, Vec<Patch>)> = Vec::new();
+    for g in globals {
+        let (section, offset, ty) = btf
+            .global(&g.name)
+            .ok_or_else(|| anyhow!("{} has no global variable '{}'", object.display(), g.name))?;
+        if section.starts_with(".rodata") {
+            return Err(anyhow!(
+                "'{}' is const ({}), which is frozen at load; declare it without const to set it",
+                g.name, section
+            ));
+        }
+        let bytes = btf.encode(ty, &g.value).map_err(|e| anyhow!("Global '{}': {}", g.name, e))?;
+        match sections.iter_mut().find(|(s, _)| *s == section) {
+            Some((_, vars)) => vars.push((offset, bytes, &g.name)),
+            None => sections.push((section, vec![(offset, bytes, &g.name)])),
+        }
+    }
+
+    for (section, vars) in sections {
+        let map = MapRef::Pinned(section_map(object, maps_dir, &section)?);
+        let key = 0u32.to_ne_bytes();
+        let Some(entry) = map_lookup(&map, &key).await? else {
+            return Err(anyhow!("{} has no value to patch", map));
+        };
+        let MapValue::Single(mut data) = entry.value else {
+            return Err(anyhow!("{} is per-CPU, not a data section", map));
+        };
+        for (offset, bytes, name) in &vars {
+            let range = *offset as usize..*offset as usize + bytes.len();
+            data.get_mut(range)
+                .ok_or_else(|| anyhow!("Global '{}' lies outside {}", name, section))?
+                .copy_from_slice(bytes);
+        }
+        map_update(&map, &key, &data, "any").await?;
+        let names: Vec<&str> = vars.iter().map(|(_, _, n)| *n).collect();
+        success(&format!("Set {} in {}", names.join(", "), section));
+    }
+    Ok(())
+}
+
+/// The pin of a section map: its libbpf name with dots as underscores
+fn section_map(object: &Path, maps_dir: &Path, section: &str) -> Result<PathBuf> {
+    let stem = object.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    let name = libbpf_map_name(stem.split('.').next().unwrap_or_default(), section);
+    [name, section.to_string()]
+        .iter()
+        .map(|n| maps_dir.join(n.replace('.', "_")))
+        .find(|p| p.exists())
+        .ok_or_else(|| anyhow!("No pinned map for {} in {}", section, maps_dir.display()))
+}
+
+/// libbpf names section maps `<object prefix><section>` within the kernel's 15 characters,
+/// and custom `.data.<name>` sections without the prefix
+fn libbpf_map_name(object: &str, section: &str) -> String {
+    let suffix_len = section.len().clamp(7, 15);
+    let prefix_len = match section.get(1..) {
+        Some(rest) if !rest.contains('.') => 15 - suffix_len,
+        _ => 0,
+    };
+    object.chars().take(prefix_len).chain(section.chars().take(suffix_len))
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn section_map_names_match_libbpf() {
+        assert_eq!(libbpf_map_name("xdp_filter", ".data"), "xdp_filt.data");
+        assert_eq!(libbpf_map_name("xdp_filter", ".rodata"), "xdp_filt.rodata");
+        assert_eq!(libbpf_map_name("tc", ".bss"), "tc.bss");
+        assert_eq!(libbpf_map_name("my-prog", ".bss"), "my_prog.bss");
+        assert_eq!(libbpf_map_name("xdp_filter", ".data.limits"), ".data.limits");
+    }
+
+    #[test]
+    fn user_maps_ending_in_data_are_not_section_maps() {
+        let dir = std::env::temp_dir().join(format!("eclipta-globals-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("conn_data"), b"").unwrap();
+        assert!(section_map(Path::new("/tmp/xdp_filter.o"), &dir, ".data").is_err());
+        std::fs::write(dir.join("xdp_filt_data"), b"").unwrap();
+        assert_eq!(section_map(Path::new("/tmp/xdp_filter.o"), &dir, ".data").unwrap(), dir.join("xdp_filt_data"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,624 @@
+use crate::commands::ebpf::load::{TC_EGRESS_SECTION, TC_INGRESS_SECTION, XDP_DROP_SECTION, XDP_SECTION};
+use crate::db::programs::Program;
+use crate::utils::bpftool::{bpftool_json, prog_show, prog_show_pinned, ProgInfo};
+use crate::utils::paths::default_pin_prefix;
+use anyhow::{anyhow, Context, Result};
+use object::{Object, ObjectSection};
+use nix::libc;
+use serde_json::Value;
+use std::ffi::CString;
+use std::fmt;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Where a program attaches; every kind can be swapped without detaching first, through its
+/// bpf link when it has one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hook {
+    Xdp { iface: String },
+    Tc { iface: String, direction: &'static str },
+    Cgroup { path: PathBuf, attach_type: String },
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hook::Xdp { iface } => write!(f, "XDP on {}", iface),
+            Hook::Tc { iface, direction } => write!(f, "TC {} on {}", direction, iface),
+            Hook::Cgroup { path, attach_type } => write!(f, "cgroup {} on {}", attach_type, path.display()),
+        }
+    }
+}
+
+/// How a program sits on its hook, so another one can take exactly its place
+#[derive(Debug, Clone)]
+pub enum AttachMode {
+    /// bpftool's mode: driver, generic or offload
+    Xdp(String),
+    Tc { pref: u64, handle: String },
+    /// Attach flags: empty, "override" or "multi"
+    Cgroup(String),
+    /// A bpf link (XDP and cgroup links, TCX), swapped by updating its program. `pin` is
+    /// where it is pinned, if anywhere
+    Link { id: u32, pin: Option<PathBuf> },
+}
+
+impl AttachMode {
+    /// The mode `attach` uses when nothing is being replaced
+    pub fn default_for(hook: &Hook) -> AttachMode {
+        match hook {
+            Hook::Xdp { .. } => AttachMode::Xdp("driver".to_string()),
+            Hook::Tc { .. } => AttachMode::Tc { pref: 1, handle: "0x1".to_string() },
+            Hook::Cgroup { .. } => AttachMode::Cgroup(String::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Attached {
+    pub prog_id: u32,
+    pub name: String,
+    pub mode: AttachMode,
+}
+
+/// A version loaded by upgrade or apply, pinned under `<pin prefix>/<title>/v<version>@<time>`
+#[derive(Debug, Clone)]
+pub struct PinnedVersion {
+    /// Sanitized like the directory name
+    pub version: String,
+    pub dir: PathBuf,
+    pub prog_ids: Vec<u32>,
+}
+
+/// The hook of the object's first attachable program
+pub fn detect_hook(path: &Path, iface: Option<&str>, cgroup: Option<&Path>) -> Result<Hook> {
+    find_hook(path, iface, cgroup)?.ok_or_else(|| anyhow!(
+        "{} has no XDP, TC or cgroup program; other program types can't be swapped in place, use unload and load",
+        path.display()
+    ))
+}
+
+/// Like `detect_hook`, but None for objects whose programs can't be swapped in place
+pub fn find_hook(path: &Path, iface: Option<&str>, cgroup: Option<&Path>) -> Result<Option<Hook>> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+    let sections: Vec<String> = obj.sections().filter_map(|s| s.name().ok().map(str::to_string)).collect();
+    hook_for_sections(&sections, iface, cgroup)
+}
+
+/// The hook of the first attachable section, or None when no section names one
+fn hook_for_sections(sections: &[String], iface: Option<&str>, cgroup: Option<&Path>) -> Result<Option<Hook>> {
+    let iface = || iface.map(str::to_string).ok_or_else(|| anyhow!("XDP and TC programs need an interface"));
+    for name in sections {
+        match name.as_str() {
+            XDP_SECTION | XDP_DROP_SECTION => return Ok(Some(Hook::Xdp { iface: iface()? })),
+            TC_INGRESS_SECTION => return Ok(Some(Hook::Tc { iface: iface()?, direction: "ingress" })),
+            TC_EGRESS_SECTION => return Ok(Some(Hook::Tc { iface: iface()?, direction: "egress" })),
+            _ => {}
+        }
+        if let Some(attach_type) = cgroup_attach_type(name) {
+            let path = cgroup.map(Path::to_path_buf).ok_or_else(|| anyhow!("cgroup programs need a cgroup directory"))?;
+            return Ok(Some(Hook::Cgroup { path, attach_type }));
+        }
+    }
+    Ok(None)
+}
+
+/// bpftool's attach type for libbpf-style cgroup section names
+fn cgroup_attach_type(section: &str) -> Option<String> {
+    if section == "sockops" {
+        return Some("sock_ops".to_string());
+    }
+    let kind = section.strip_prefix("cgroup_skb/").or_else(|| section.strip_prefix("cgroup/"))?;
+    Some(match kind {
+        "dev" => "device",
+        "sock" => "sock_create",
+        other => other,
+    }.to_string())
+}
+
+/// Programs currently on the hook. Those attached through a bpf link are reported as
+/// `AttachMode::Link`, since only updating the link replaces them
+pub async fn attachments(hook: &Hook) -> Result<Vec<Attached>> {
+    let mut found = match hook {
+        Hook::Xdp { iface } => parse_xdp(&bpftool_json(&args(&["net", "show", "dev", iface])).await?),
+        Hook::Tc { iface, direction } => {
+            // Without a clsact qdisc tc fails, which just means no filter is attached
+            match run("tc", &args(&["-j", "filter", "show", "dev", iface, direction])).await {
+                Ok(out) => parse_tc(&serde_json::from_str(&out).unwrap_or(Value::Null)),
+                Err(_) => Vec::new(),
+            }
+        }
+        Hook::Cgroup { path, attach_type } => {
+            parse_cgroup(&bpftool_json(&args(&["cgroup", "show", &path.display().to_string()])).await?, attach_type)
+        }
+    };
+    let cgroup_id = match hook {
+        Hook::Cgroup { path, .. } => Some(std::fs::metadata(path).with_context(|| format!("Failed to stat {}", path.display()))?.ino()),
+        _ => None,
+    };
+    // TCX links don't show up in `tc filter show`, and netlink or cgroup queries can't
+    // tell a link from a plain attachment
+    let links = bpftool_json(&args(&["-f", "link", "show"])).await.unwrap_or(Value::Null);
+    merge_links(&mut found, hook_links(hook, &links, cgroup_id));
+
+    let mut attached = Vec::new();
+    for (prog_id, mode) in found {
+        let name = prog_show(prog_id).await.map(|p| p.name).unwrap_or_default();
+        attached.push(Attached { prog_id, name, mode });
+    }
+    Ok(attached)
+}
+
+fn parse_xdp(net: &Value) -> Vec<(u32, AttachMode)> {
+    list(net.get(0).and_then(|n| n.get("xdp")))
+        .filter_map(|x| {
+            let id = x.get("id").and_then(Value::as_u64)?;
+            let mode = x.get("mode").and_then(Value::as_str).unwrap_or("driver");
+            Some((id as u32, AttachMode::Xdp(mode.to_string())))
+        })
+        .collect()
+}
+
+fn parse_tc(filters: &Value) -> Vec<(u32, AttachMode)> {
+    list(Some(filters))
+        .filter_map(|f| {
+            let id = f.pointer("/options/prog/id").and_then(Value::as_u64)?;
+            let pref = f.get("pref").and_then(Value::as_u64).unwrap_or(1);
+            let handle = f.pointer("/options/handle").and_then(Value::as_str).unwrap_or("0x1");
+            Some((id as u32, AttachMode::Tc { pref, handle: handle.to_string() }))
+        })
+        .collect()
+}
+
+fn parse_cgroup(progs: &Value, attach_type: &str) -> Vec<(u32, AttachMode)> {
+    list(Some(progs))
+        .filter(|c| c.get("attach_type").and_then(Value::as_str).is_some_and(|t| same_attach_type(t, attach_type)))
+        .filter_map(|c| {
+            let id = c.get("id").and_then(Value::as_u64)?;
+            let flags = c.get("attach_flags").and_then(Value::as_str).unwrap_or("");
+            Some((id as u32, AttachMode::Cgroup(flags.to_string())))
+        })
+        .collect()
+}
+
+/// Older bpftool names cgroup attach types like "ingress", newer ones use libbpf's
+/// "cgroup_inet_ingress"
+fn same_attach_type(reported: &str, attach_type: &str) -> bool {
+    reported == attach_type || reported.strip_suffix(attach_type).is_some_and(|p| p.ends_with('_'))
+}
+
+/// Links of `bpftool -f link show` on the hook, as (program id, link mode)
+fn hook_links(hook: &Hook, links: &Value, cgroup_id: Option<u64>) -> Vec<(u32, AttachMode)> {
+    let str_of = |l: &Value, k: &str| l.get(k).and_then(Value::as_str).map(str::to_string);
+    list(Some(links))
+        .filter(|l| {
+            let kind = str_of(l, "type");
+            let dev = str_of(l, "devname");
+            let attach = str_of(l, "attach_type").unwrap_or_default();
+            match hook {
+                Hook::Xdp { iface } => kind.as_deref() == Some("xdp") && dev.as_ref() == Some(iface),
+                Hook::Tc { iface, direction } => {
+                    kind.as_deref() == Some("tcx") && dev.as_ref() == Some(iface) && attach == format!("tcx_{}", direction)
+                }
+                Hook::Cgroup { attach_type, .. } => {
+                    kind.as_deref() == Some("cgroup")
+                        && l.get("cgroup_id").and_then(Value::as_u64) == cgroup_id
+                        && same_attach_type(&attach, attach_type)
+                }
+            }
+        })
+        .filter_map(|l| {
+            let id = l.get("id").and_then(Value::as_u64)? as u32;
+            let prog_id = l.get("prog_id").and_then(Value::as_u64)? as u32;
+            let pin = list(l.get("pinned")).find_map(Value::as_str).map(PathBuf::from);
+            Some((prog_id, AttachMode::Link { id, pin }))
+        })
+        .collect()
+}
+
+/// Mark attachments made through a link as such and add links the query didn't list
+fn merge_links(found: &mut Vec<(u32, AttachMode)>, links: Vec<(u32, AttachMode)>) {
+    for (prog_id, link) in links {
+        match found.iter_mut().find(|(id, mode)| *id == prog_id && !matches!(mode, AttachMode::Link { .. })) {
+            Some(entry) => entry.1 = link,
+            None => found.push((prog_id, link)),
+        }
+    }
+}
+
+/// Put the pinned program on the hook in place of `replacing`, without a window where the
+/// hook is empty. Links are updated to the new program; cgroup multi attachments briefly
+/// run both programs instead
+pub async fn attach(hook: &Hook, pin: &Path, replacing: Option<&Attached>) -> Result<()> {
+    if let Some(Attached { prog_id, mode: AttachMode::Link { id, .. }, .. }) = replacing {
+        return link_update(*id, pin, *prog_id)
+            .with_context(|| format!("Failed to update link {} on {}", id, hook));
+    }
+    let pin = pin.display().to_string();
+    match hook {
+        Hook::Xdp { iface } => {
+            let mode = match replacing.map(|a| &a.mode) {
+                Some(AttachMode::Xdp(m)) => xdp_attach_type(m),
+                _ => "xdp",
+            };
+            bpftool_json(&args(&["net", "attach", mode, "pinned", &pin, "dev", iface, "overwrite"])).await?;
+        }
+        Hook::Tc { iface, direction } => {
+            let (pref, handle) = match replacing.map(|a| &a.mode) {
+                Some(AttachMode::Tc { pref, handle }) => (pref.to_string(), handle.clone()),
+                _ => {
+                    // Fails harmlessly when the qdisc already exists
+                    let _ = run("tc", &args(&["qdisc", "add", "dev", iface, "clsact"])).await;
+                    ("1".to_string(), "0x1".to_string())
+                }
+            };
+            run("tc", &args(&[
+                "filter", "replace", "dev", iface, direction, "protocol", "all", "pref", &pref,
+                "handle", &handle, "bpf", "da", "object-pinned", &pin,
+            ])).await?;
+        }
+        Hook::Cgroup { path, attach_type } => {
+            let cgroup = path.display().to_string();
+            let flags = match replacing.map(|a| &a.mode) {
+                Some(AttachMode::Cgroup(f)) => f.clone(),
+                _ => String::new(),
+            };
+            let mut attach = args(&["cgroup", "attach", &cgroup, attach_type, "pinned", &pin]);
+            if !flags.is_empty() {
+                attach.push(flags.clone());
+            }
+            // Without BPF_F_ALLOW_MULTI the kernel replaces the attached program atomically
+            bpftool_json(&attach).await?;
+            if let (Some(old), "multi") = (replacing, flags.as_str()) {
+                detach(hook, old).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn detach(hook: &Hook, attached: &Attached) -> Result<()> {
+    match (hook, &attached.mode) {
+        (_, AttachMode::Link { id, .. }) => {
+            bpftool_json(&args(&["link", "detach", "id", &id.to_string()])).await?;
+        }
+        (Hook::Xdp { iface }, AttachMode::Xdp(mode)) => {
+            bpftool_json(&args(&["net", "detach", xdp_attach_type(mode), "dev", iface])).await?;
+        }
+        (Hook::Tc { iface, direction }, AttachMode::Tc { pref, .. }) => {
+            run("tc", &args(&["filter", "del", "dev", iface, direction, "pref", &pref.to_string()])).await?;
+        }
+        (Hook::Cgroup { path, attach_type }, AttachMode::Cgroup(_)) => {
+            let cgroup = path.display().to_string();
+            bpftool_json(&args(&["cgroup", "detach", &cgroup, attach_type, "id", &attached.prog_id.to_string()])).await?;
+        }
+        _ => return Err(anyhow!("Program {} is not attached as {}", attached.prog_id, hook)),
+    }
+    Ok(())
+}
+
+const BPF_OBJ_GET: libc::c_int = 7;
+const BPF_PROG_GET_FD_BY_ID: libc::c_int = 13;
+const BPF_LINK_UPDATE: libc::c_int = 29;
+const BPF_LINK_GET_FD_BY_ID: libc::c_int = 30;
+/// Only update the link while it still runs `old_prog_fd`
+const BPF_F_REPLACE: u32 = 1 << 2;
+
+#[repr(C)]
+struct ObjGetAttr {
+    pathname: u64,
+    bpf_fd: u32,
+    file_flags: u32,
+}
+
+#[repr(C)]
+struct GetFdByIdAttr {
+    id: u32,
+    next_id: u32,
+    open_flags: u32,
+}
+
+#[repr(C)]
+struct LinkUpdateAttr {
+    link_fd: u32,
+    new_prog_fd: u32,
+    flags: u32,
+    old_prog_fd: u32,
+}
+
+/// bpf(2), returning the fd the command opened
+fn sys_bpf<T>(cmd: libc::c_int, attr: &mut T) -> std::io::Result<libc::c_long> {
+    let size = std::mem::size_of::<T>() as libc::c_uint;
+    let ret = unsafe { libc::syscall(libc::SYS_bpf, cmd, attr as *mut T, size) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+fn owned_fd(fd: libc::c_long) -> OwnedFd {
+    unsafe { OwnedFd::from_raw_fd(fd as RawFd) }
+}
+
+/// Point link `link_id` at the pinned program with BPF_LINK_UPDATE, failing if the link no
+/// longer runs `old_prog_id`. bpftool has no command for this
+fn link_update(link_id: u32, pin: &Path, old_prog_id: u32) -> Result<()> {
+    let path = CString::new(pin.as_os_str().as_bytes())?;
+    let prog = owned_fd(
+        sys_bpf(BPF_OBJ_GET, &mut ObjGetAttr { pathname: path.as_ptr() as u64, bpf_fd: 0, file_flags: 0 })
+            .with_context(|| format!("Failed to open {}", pin.display()))?,
+    );
+    let link = owned_fd(
+        sys_bpf(BPF_LINK_GET_FD_BY_ID, &mut GetFdByIdAttr { id: link_id, next_id: 0, open_flags: 0 })
+            .with_context(|| format!("Failed to open link {}", link_id))?,
+    );
+    let old = owned_fd(
+        sys_bpf(BPF_PROG_GET_FD_BY_ID, &mut GetFdByIdAttr { id: old_prog_id, next_id: 0, open_flags: 0 })
+            .with_context(|| format!("Failed to open program {}", old_prog_id))?,
+    );
+    let mut update = LinkUpdateAttr {
+        link_fd: link.as_raw_fd() as u32,
+        new_prog_fd: prog.as_raw_fd() as u32,
+        flags: BPF_F_REPLACE,
+        old_prog_fd: old.as_raw_fd() as u32,
+    };
+    sys_bpf(BPF_LINK_UPDATE, &mut update)?;
+    Ok(())
+}
+
+/// Move a replaced link's pin into the directory of the version now running on it, so
+/// releasing the old version's pins doesn't detach the new program
+pub fn adopt_link(replaced: &Attached, version_dir: &Path) -> Result<()> {
+    let AttachMode::Link { pin: Some(pin), .. } = &replaced.mode else { return Ok(()) };
+    if !pin.starts_with(default_pin_prefix()) {
+        return Ok(());
+    }
+    let links = version_dir.join("links");
+    std::fs::create_dir_all(&links)?;
+    let to = links.join(pin.file_name().unwrap_or_default());
+    std::fs::rename(pin, &to)
+        .with_context(|| format!("Failed to move the link pin {} to {}", pin.display(), to.display()))
+}
+
+fn xdp_attach_type(mode: &str) -> &'static str {
+    match mode {
+        "generic" => "xdpgeneric",
+        "offload" => "xdpoffload",
+        _ => "xdpdrv",
+    }
+}
+
+/// `bpftool prog loadall` the object under `dir` and return the program meant for the hook.
+/// `reuse` names maps to open from existing pins instead of creating them
+pub async fn load_pinned(object: &Path, dir: &Path, hook: &Hook, reuse: &[(String, PathBuf)]) -> Result<(PathBuf, ProgInfo)> {
+    let mut load = args(&["prog", "loadall", &object.display().to_string(), &dir.display().to_string()]);
+    // Section names like "tc_ingress" aren't known to libbpf, so name the type
+    match hook {
+        Hook::Xdp { .. } => load.extend(args(&["type", "xdp"])),
+        Hook::Tc { .. } => load.extend(args(&["type", "classifier"])),
+        Hook::Cgroup { .. } => {}
+    }
+    for (name, pin) in reuse {
+        load.extend(args(&["map", "name", name, "pinned", &pin.display().to_string()]));
+    }
+    load.extend(args(&["pinmaps", &maps_dir(dir).display().to_string()]));
+    bpftool_json(&load).await?;
+
+    let mut candidates = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let prog = prog_show_pinned(&path).await?;
+            candidates.push((path, prog));
+        }
+    }
+    let fits = |p: &ProgInfo| match hook {
+        Hook::Xdp { .. } => p.prog_type == "xdp",
+        Hook::Tc { .. } => p.prog_type == "sched_cls",
+        Hook::Cgroup { .. } => p.prog_type.starts_with("cgroup") || p.prog_type == "sock_ops",
+    };
+    let pick = match hook {
+        // Objects with both directions name their programs after them
+        Hook::Tc { direction, .. } => candidates.iter().position(|(_, p)| fits(p) && p.name.contains(direction)),
+        _ => None,
+    }
+    .or_else(|| candidates.iter().position(|(_, p)| fits(p)))
+    .ok_or_else(|| anyhow!("{} loaded, but none of its programs fits {}", object.display(), hook))?;
+    Ok(candidates.swap_remove(pick))
+}
+
+/// Where the maps of a version pinned by `load_pinned` live
+pub fn maps_dir(version_dir: &Path) -> PathBuf {
+    version_dir.join("maps")
+}
+
+/// Pins of every version of a registry title
+pub fn pin_root(title: &str) -> PathBuf {
+    default_pin_prefix().join(sanitize(title))
+}
+
+/// A fresh directory name, so reloading the running version never reuses its pins
+pub fn new_version_dir(title: &str, version: &str) -> PathBuf {
+    pin_root(title).join(format!("v{}@{}", sanitize(version), chrono::Utc::now().timestamp()))
+}
+
+/// Versions of `title` pinned by upgrade or apply, with the programs in each
+pub async fn pinned_versions(title: &str) -> Vec<PinnedVersion> {
+    let Ok(entries) = std::fs::read_dir(pin_root(title)) else { return Vec::new() };
+    let mut versions = Vec::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = name.strip_prefix('v').filter(|_| dir.is_dir()) else { continue };
+        let version = stem.rsplit_once('@').map(|(v, _)| v).unwrap_or(stem).to_string();
+        let mut prog_ids = Vec::new();
+        for file in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            if file.path().is_file() {
+                if let Ok(p) = prog_show_pinned(&file.path()).await {
+                    prog_ids.push(p.id);
+                }
+            }
+        }
+        versions.push(PinnedVersion { version, dir, prog_ids });
+    }
+    versions
+}
+
+/// Where `load` pins what has to outlive it: the object's programs, their links under
+/// `links/` and tail-call prog arrays under `tail_calls/`
+pub fn load_pin_dir(object: &Path) -> PathBuf {
+    let stem = object.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    default_pin_prefix().join("loaded").join(sanitize(&stem))
+}
+
+/// Kernel program IDs belonging to a registry entry: the programs `load` pinned for its
+/// object plus those upgrade or apply pinned for its version under its title
+pub async fn registry_prog_ids(program: &Program) -> Vec<u32> {
+    let version = sanitize(&program.version);
+    let mut ids: Vec<u32> = pinned_versions(&program.title).await
+        .into_iter()
+        .filter(|v| v.version == version)
+        .flat_map(|v| v.prog_ids)
+        .collect();
+    for file in std::fs::read_dir(load_pin_dir(Path::new(&program.path))).into_iter().flatten().flatten() {
+        if file.path().is_file() {
+            if let Ok(p) = prog_show_pinned(&file.path()).await {
+                ids.push(p.id);
+            }
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// The pinned version of `title` whose program is attached to the hook
+pub async fn attached_version(hook: &Hook, title: &str) -> Result<Option<PinnedVersion>> {
+    let attached = attachments(hook).await?;
+    Ok(pinned_versions(title).await
+        .into_iter()
+        .find(|v| attached.iter().any(|a| v.prog_ids.contains(&a.prog_id))))
+}
+
+pub fn args(parts: &[&str]) -> Vec<String> {
+    parts.iter().map(|s| s.to_string()).collect()
+}
+
+fn list(v: Option<&Value>) -> impl Iterator<Item = &Value> {
+    v.and_then(Value::as_array).into_iter().flatten()
+}
+
+/// Run a command and return its stdout, failing with its stderr
+pub async fn run(program: &str, args: &[String]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to execute {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} {}: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A bpffs-safe path component
+pub fn sanitize(s: &str) -> String {
+    s.chars().map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sections(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn hook_comes_from_the_first_attachable_section() {
+        let hook = hook_for_sections(&sections(&[".text", "license", "tc_egress", "xdp"]), Some("eth0"), None).unwrap();
+        assert_eq!(hook, Some(Hook::Tc { iface: "eth0".to_string(), direction: "egress" }));
+
+        let cgroup = Path::new("/sys/fs/cgroup/app");
+        let hook = hook_for_sections(&sections(&["cgroup/dev"]), None, Some(cgroup)).unwrap();
+        assert_eq!(hook, Some(Hook::Cgroup { path: cgroup.to_path_buf(), attach_type: "device".to_string() }));
+        let hook = hook_for_sections(&sections(&["cgroup_skb/ingress"]), None, Some(cgroup)).unwrap();
+        assert_eq!(hook, Some(Hook::Cgroup { path: cgroup.to_path_buf(), attach_type: "ingress".to_string() }));
+
+        assert!(hook_for_sections(&sections(&["xdp"]), None, None).is_err());
+        assert_eq!(hook_for_sections(&sections(&["tracepoint/syscalls/sys_enter_open"]), None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn netlink_attachments_are_parsed() {
+        let net = json!([{ "xdp": [{ "devname": "eth0", "ifindex": 2, "mode": "generic", "id": 41 }], "tc": [] }]);
+        let xdp = parse_xdp(&net);
+        assert!(matches!(xdp.as_slice(), [(41, AttachMode::Xdp(m))] if m == "generic"));
+
+        let filters = json!([
+            { "protocol": "all", "pref": 49152, "kind": "bpf" },
+            { "protocol": "all", "pref": 49152, "kind": "bpf", "options": { "handle": "0x1", "prog": { "id": 7, "name": "tc_ingress" } } }
+        ]);
+        let tc = parse_tc(&filters);
+        assert!(matches!(tc.as_slice(), [(7, AttachMode::Tc { pref: 49152, handle })] if handle == "0x1"));
+
+        let progs = json!([
+            { "id": 3, "attach_type": "cgroup_inet_ingress", "attach_flags": "multi" },
+            { "id": 4, "attach_type": "egress", "attach_flags": "" }
+        ]);
+        assert!(matches!(parse_cgroup(&progs, "ingress").as_slice(), [(3, AttachMode::Cgroup(f))] if f == "multi"));
+        assert!(matches!(parse_cgroup(&progs, "egress").as_slice(), [(4, _)]));
+        assert!(parse_cgroup(&progs, "sock_create").is_empty());
+    }
+
+    #[test]
+    fn links_on_the_hook_are_found() {
+        let links = json!([
+            { "id": 10, "type": "xdp", "prog_id": 41, "devname": "eth0", "ifindex": 2,
+              "pinned": ["/sys/fs/bpf/eclipta/loaded/filter/links/xdp_filter"] },
+            { "id": 11, "type": "xdp", "prog_id": 50, "devname": "eth1", "ifindex": 3 },
+            { "id": 12, "type": "tcx", "prog_id": 60, "devname": "eth0", "ifindex": 2, "attach_type": "tcx_egress" },
+            { "id": 13, "type": "cgroup", "prog_id": 70, "cgroup_id": 1234, "attach_type": "cgroup_inet_egress" },
+            { "id": 14, "type": "tracepoint", "prog_id": 80 }
+        ]);
+
+        let xdp = hook_links(&Hook::Xdp { iface: "eth0".to_string() }, &links, None);
+        assert!(matches!(xdp.as_slice(), [(41, AttachMode::Link { id: 10, pin: Some(p) })]
+            if p == Path::new("/sys/fs/bpf/eclipta/loaded/filter/links/xdp_filter")));
+
+        let egress = Hook::Tc { iface: "eth0".to_string(), direction: "egress" };
+        assert!(matches!(hook_links(&egress, &links, None).as_slice(), [(60, AttachMode::Link { id: 12, pin: None })]));
+        let ingress = Hook::Tc { iface: "eth0".to_string(), direction: "ingress" };
+        assert!(hook_links(&ingress, &links, None).is_empty());
+
+        let cgroup = Hook::Cgroup { path: PathBuf::from("/sys/fs/cgroup/app"), attach_type: "egress".to_string() };
+        assert!(matches!(hook_links(&cgroup, &links, Some(1234)).as_slice(), [(70, AttachMode::Link { id: 13, .. })]));
+        assert!(hook_links(&cgroup, &links, Some(99)).is_empty());
+    }
+
+    #[test]
+    fn link_attachments_replace_their_netlink_entries() {
+        let mut found = vec![(41, AttachMode::Xdp("driver".to_string()))];
+        merge_links(&mut found, vec![
+            (41, AttachMode::Link { id: 10, pin: None }),
+            (60, AttachMode::Link { id: 12, pin: None }),
+        ]);
+        assert_eq!(found.len(), 2);
+        assert!(matches!(found[0], (41, AttachMode::Link { id: 10, .. })));
+        assert!(matches!(found[1], (60, AttachMode::Link { id: 12, .. })));
+    }
+
+    #[test]
+    fn attach_types_match_old_and_new_bpftool_names() {
+        assert!(same_attach_type("ingress", "ingress"));
+        assert!(same_attach_type("cgroup_inet_ingress", "ingress"));
+        assert!(same_attach_type("cgroup_device", "device"));
+        assert!(!same_attach_type("cgroup_inet_egress", "ingress"));
+        assert!(!same_attach_type("xingress", "ingress"));
+    }
+}
@@ -4,6 +4,7 @@ use std::path::PathBuf;
 use crate::utils::logger::success;
 use crate::utils::db::ensure_db_ready;
 use crate::db::programs::{get_program_by_id, get_program_by_title};
+use crate::commands::ebpf::tail_calls::tail_call_graph;
 use serde_json;
 use anyhow::{Result, anyhow};
 
@@ -112,10 +113,13 @@ pub async fn handle_inspect(opts: InspectOptions) -> Result<()> {
 
     let programs: Vec<_> = bpf.programs().map(|(name, _)| name.to_string()).collect();
     let maps: Vec<_> = bpf.maps().map(|(name, _)| name.to_string()).collect();
+    // Only a loaded instance has wired slots; without bpftool the graph is just empty
+    let tail_calls = tail_call_graph(&program_path).await.unwrap_or_default();
     let mut output_data = serde_json::json!({
         "elf_path": program_path.display().to_string(),
         "programs": programs,
         "maps": maps,
+        "tail_calls": tail_calls,
     });
     if let Some(ref metadata) = program_metadata {
         output_data["metadata"] = serde_json::json!({
@@ -158,6 +162,15 @@ pub async fn handle_inspect(opts: InspectOptions) -> Result<()> {
                 println!("  {}", name); 
             }
         }
+
+        println!("\nTail Calls:");
+        if tail_calls.is_empty() {
+            println!("  (none wired)");
+        } else {
+            for edge in &tail_calls {
+                println!("  {} -> {}[{}] -> {} (prog {})", edge.callers.join(", "), edge.map, edge.index, edge.target, edge.target_id);
+            }
+        }
         
         if opts.verbose {
             println!("\nTechnical Details:");
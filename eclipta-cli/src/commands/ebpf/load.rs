@@ -1,16 +1,28 @@
 use clap::Args;
 use std::path::PathBuf;
-use crate::db::programs::{get_program_by_id, get_program_by_title};
-use crate::utils::db::ensure_db_ready;
+use crate::commands::ebpf::map_init::{populate_maps, read_specs, MapInit};
+use crate::commands::ebpf::map_state::{plan_carry_over, restore_carry_over, MapCarryOver};
+use crate::commands::ebpf::shared_maps::{plan_shares, SharePlan};
+use crate::commands::system::probe::{check_object_caps, check_object_support};
+use crate::commands::ebpf::tail_calls::{tail_calls_for, wire_tail_calls, TailCall};
+use crate::commands::ebpf::hook::load_pin_dir;
+use crate::db::programs::{get_map_init, get_program_by_id, get_program_by_title, set_map_init, Program as RegistryProgram};
+use crate::utils::db::{ensure_db_ready, DbPool};
+use crate::utils::paths::default_state_path;
+use crate::utils::schedule::ActiveWindow;
+use crate::utils::state::{load_state, save_state, AttachmentRecord, LeaseRecord};
 use aya::{
     Ebpf, 
+    EbpfLoader,
     programs::{
+        links::{FdLink, LinkError},
         Program, 
         ProgramError
     }
 };
 use object::{Object, ObjectSection};
 use std::collections::HashSet;
+use std::path::Path;
 use tokio::process::Command;
 use anyhow::{Result, Context, anyhow};
 
@@ -30,6 +42,79 @@ pub struct LoadOptions {
 
     #[arg(long)]
     pub socket_fd: Option<i32>,
+
+    /// Fill a map before the program attaches, e.g. "blocked_ips=ips.csv" (repeatable).
+    /// Remembered for registry programs, so later loads repopulate the same maps
+    #[arg(long = "map-init", value_name = "MAP=FILE")]
+    pub map_init: Vec<String>,
+
+    /// Keep the map state of a loaded registry program (title or id): compatible pinned
+    /// maps are reused, other maps are copied over; incompatible layouts abort the load
+    #[arg(long = "reuse-maps-from", value_name = "TITLE|ID")]
+    pub reuse_maps_from: Option<String>,
+
+    /// Put a program of the object into a prog array slot for tail calls, e.g.
+    /// "jmp_table[1]=handle_tcp" (repeatable). Programs named `tail__<map>__<index>` are wired automatically
+    #[arg(long = "tail-call", value_name = "MAP[INDEX]=PROGRAM")]
+    pub tail_calls: Vec<String>,
+
+    /// Open a map of another loaded program instead of creating it, e.g. "events=producer"
+    /// or "events=/sys/fs/bpf/events" (repeatable). The map must be pinned by name in this object
+    #[arg(long = "share-map", value_name = "MAP=PIN|TITLE")]
+    pub share_maps: Vec<String>,
+
+    /// Unload the program after this long, e.g. "15m" (enforced by `eclipta sweep`)
+    #[arg(long)]
+    pub ttl: Option<String>,
+
+    /// Only keep the program loaded during a weekly window, e.g. "Mon-Fri 09:00-18:00"
+    /// (local time, enforced by `eclipta sweep`)
+    #[arg(long = "active-window")]
+    pub active_window: Option<String>,
+}
+
+/// Map work done between loading the programs and attaching them
+#[derive(Default)]
+pub struct MapSetup {
+    pub inits: Vec<MapInit>,
+    pub carry: Option<MapCarryOver>,
+    pub tail_calls: Vec<TailCall>,
+    pub shares: SharePlan,
+}
+
+impl MapSetup {
+    /// Open the object, reopening pinned maps the carry-over decided to reuse or that are shared
+    pub fn open(&self, path: &PathBuf) -> Result<Ebpf, aya::EbpfError> {
+        let mut loader = EbpfLoader::new();
+        if let Some(dir) = self.carry.as_ref().and_then(|c| c.pin_path.as_ref()).or(self.shares.pin_path.as_ref()) {
+            loader.map_pin_path(dir);
+        }
+        loader.load_file(path)
+    }
+
+    /// Load tail call targets the attach path would otherwise leave unloaded
+    pub fn load_targets(&self, ebpf: &mut Ebpf) -> Result<()> {
+        for call in &self.tail_calls {
+            let program = ebpf.program_mut(&call.program)
+                .ok_or_else(|| anyhow!("Tail call target '{}' is not a program of this object", call.program))?;
+            if program.fd().is_err() {
+                load_program_by_type(program)
+                    .with_context(|| format!("Failed to load tail call target '{}'", call.program))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check shared maps were reopened, restore carried-over state, apply --map-init files on
+    /// top, then fill prog arrays. Runs after loading and before attaching
+    pub async fn apply(&self, path: &PathBuf) -> Result<()> {
+        self.shares.confirm(path).await?;
+        if let Some(carry) = &self.carry {
+            restore_carry_over(path, carry).await?;
+        }
+        populate_maps(path, &self.inits).await?;
+        wire_tail_calls(path, &self.tail_calls, &load_pin_dir(path)).await
+    }
 }
 
 pub const XDP_SECTION: &str = "xdp";
@@ -57,64 +142,252 @@ pub async fn handle_load(opts: LoadOptions) -> Result<()> {
     let pool = ensure_db_ready().await
         .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
 
-    let program_path = if let Some(id) = opts.id {
-        let program = get_program_by_id(&pool, id).await
-            .context("Failed to fetch program from database")?
-            .ok_or_else(|| anyhow!("No program found with id {}", id))?;
-        
-        println!("Found program: ID: {}, Title: {}", program.id, program.title);
-        PathBuf::from(program.path)
-    } else if let Some(ref title) = opts.title {
-        let programs = get_program_by_title(&pool, title).await
-            .context("Failed to fetch programs from database")?;
-        
-        match programs.len() {
-            1 => {
-                let program = &programs[0];
-                println!("Found program: ID: {}, Title: {}", program.id, program.title);
-                PathBuf::from(program.path.clone())
-            }
-            n if n > 1 => {
-                return Err(anyhow!("Multiple programs found with title '{}'. Please use --id to specify which one to load.", title));
-            }
-            _ => {
-                return Err(anyhow!("No program found with title '{}'", title));
-            }
-        }
-    } else if let Some(ref program_path) = opts.program {
-        println!("Using direct program path: {}", program_path.display());
-        program_path.clone()
-    } else {
-        return Err(anyhow!("Please specify a program to load using --id, --title, or --program"));
-    };
+    let ttl = opts.ttl.as_deref()
+        .map(|t| humantime::parse_duration(t).map_err(|e| anyhow!("Invalid --ttl '{}': {}", t, e)))
+        .transpose()?;
+    let window = opts.active_window.as_deref().map(ActiveWindow::parse).transpose()?;
+
+    let (program_path, registry) = resolve_object(&pool, opts.id, opts.title.as_deref(), opts.program.as_ref(), "load").await?;
+    let registry_id = registry.as_ref().map(|p| p.id);
+    let registry_title = registry.map(|p| p.title);
 
     println!("Validating eBPF ELF object...");
     let requirements = validate_ebpf_file(&program_path)?;
     
     println!("Checking runtime arguments...");
     validate_runtime_args(&opts, &requirements)?;
+    check_object_support(&program_path)?;
+    check_object_caps(&program_path)?;
+
+    let pin_dir = load_pin_dir(&program_path);
+    if std::fs::read_dir(&pin_dir).is_ok_and(|mut d| d.next().is_some()) {
+        return Err(anyhow!(
+            "{} is already loaded (pinned at {}); unload it first",
+            program_path.display(), pin_dir.display()
+        ));
+    }
+
+    // Explicit --map-init wins; otherwise reuse what the registry remembers
+    let map_init_specs = match registry_id {
+        Some(id) if opts.map_init.is_empty() => get_map_init(&pool, id).await
+            .context("Failed to fetch map init files from database")?,
+        _ => opts.map_init.clone(),
+    };
+    let mut maps = MapSetup {
+        inits: read_specs(&map_init_specs)?,
+        carry: None,
+        tail_calls: tail_calls_for(&program_path, &opts.tail_calls)?,
+        shares: SharePlan::default(),
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let lease = (ttl.is_some() || window.is_some()).then(|| LeaseRecord {
+        name: registry_title.clone().unwrap_or_else(|| {
+            program_path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default()
+        }),
+        registry_id,
+        program: program_path.clone(),
+        iface: opts.iface.clone(),
+        map_init: maps.inits.iter().map(|m| m.spec.canonical()).collect(),
+        tail_calls: opts.tail_calls.clone(),
+        share_maps: opts.share_maps.clone(),
+        loaded_at: now,
+        expires_at: ttl.map(|t| now + t.as_secs() as i64),
+        window: window.as_ref().map(|w| w.to_string()),
+        suspended: false,
+    });
+    if let (Some(mut lease), Some(w)) = (lease.clone(), &window) {
+        if !w.contains(chrono::Local::now()) {
+            lease.suspended = true;
+            record_lease(lease)?;
+            println!("Outside the active window '{}'; `eclipta sweep` loads the program when it opens", w);
+            return Ok(());
+        }
+    }
+
+    if let Some(ref from) = opts.reuse_maps_from {
+        let old = find_previous_program(&pool, from, &program_path).await?;
+        let carry = plan_carry_over(&PathBuf::from(&old.path), &program_path).await?;
+        carry.report(&format!("program {} ({} v{})", old.id, old.title, old.version));
+        maps.carry = Some(carry);
+    }
+
+    maps.shares = plan_shares(&pool, &program_path, &opts.share_maps).await?;
+    if maps.shares.pin_path.is_some() && maps.carry.as_ref().is_some_and(|c| c.pin_path.is_some()) {
+        // The loader takes a single pin directory
+        return Err(anyhow!("--share-map can't be combined with --reuse-maps-from when pinned maps are reused"));
+    }
+    if let Err(e) = maps.shares.stage().await {
+        maps.shares.unstage();
+        return Err(e);
+    }
 
     let should_skip_verifier = requirements.sections.iter()
         .all(|s| s.contains("TC"));
     
-    // Load and attach the program, keeping the Ebpf object alive
-    let attach_result = if should_skip_verifier {
+    // Load and attach the program, pinning what has to outlive this process
+    let loaded = if should_skip_verifier {
         println!("Skipping Aya verifier for TC-only programs");
-        attach_program_to_kernel(&program_path, &requirements, &opts).await?
+        attach_program_to_kernel(&program_path, &requirements, &opts, &maps).await
     } else {
         println!("Loading and attaching eBPF program using Aya...");
-        load_and_attach_ebpf(&program_path, &requirements, &opts).await?
+        load_and_attach_ebpf(&program_path, &requirements, &opts, &maps).await
     };
+    let (attach_result, attached_name) = match loaded {
+        Ok(r) => r,
+        Err(e) => {
+            // Pins made before the failure would keep a half-set-up program or its maps loaded
+            let _ = std::fs::remove_dir_all(&pin_dir);
+            maps.shares.unstage();
+            return Err(e);
+        }
+    };
+    record_attachment(&program_path, &requirements, attached_name, &pin_dir)?;
 
     println!("Verifying kernel program attachment...");
     verify_kernel_attachment(&requirements, &opts).await?;
 
     print_program_summary(&requirements, &opts, &attach_result)?;
 
+    if maps.shares.pin_path.is_some() {
+        let path = default_state_path();
+        let mut st = load_state(&path);
+        maps.shares.record(&program_path, &mut st);
+        save_state(&path, st).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    if let (Some(id), false) = (registry_id, opts.map_init.is_empty()) {
+        let specs: Vec<String> = maps.inits.iter().map(|m| m.spec.canonical()).collect();
+        set_map_init(&pool, id, &specs).await
+            .context("Failed to save map init files to database")?;
+        println!("Saved {} map init file(s) for program {}", specs.len(), id);
+    }
+
+    if let Some(lease) = lease {
+        let summary = lease.describe(now);
+        record_lease(lease)?;
+        println!("Lease recorded ({}); run `eclipta sweep` to enforce it", summary);
+    }
+
     println!("eBPF program loaded and attached successfully!");
     Ok(())
 }
 
+/// Object named by `--id`, `--title` or `--program`, with its registry entry for the first two
+pub async fn resolve_object(
+    pool: &DbPool,
+    id: Option<i32>,
+    title: Option<&str>,
+    program: Option<&PathBuf>,
+    action: &str,
+) -> Result<(PathBuf, Option<RegistryProgram>)> {
+    if let Some(id) = id {
+        let program = get_program_by_id(pool, id).await
+            .context("Failed to fetch program from database")?
+            .ok_or_else(|| anyhow!("No program found with id {}", id))?;
+        
+        println!("Found program: ID: {}, Title: {}", program.id, program.title);
+        Ok((PathBuf::from(&program.path), Some(program)))
+    } else if let Some(title) = title {
+        let mut programs = get_program_by_title(pool, title).await
+            .context("Failed to fetch programs from database")?;
+        
+        match programs.len() {
+            1 => {
+                let program = programs.remove(0);
+                println!("Found program: ID: {}, Title: {}", program.id, program.title);
+                Ok((PathBuf::from(&program.path), Some(program)))
+            }
+            n if n > 1 => {
+                Err(anyhow!("Multiple programs found with title '{}'. Please use --id to specify which one to {}.", title, action))
+            }
+            _ => {
+                Err(anyhow!("No program found with title '{}'", title))
+            }
+        }
+    } else if let Some(program_path) = program {
+        println!("Using direct program path: {}", program_path.display());
+        Ok((program_path.clone(), None))
+    } else {
+        Err(anyhow!("Please specify a program to {} using --id, --title, or --program", action))
+    }
+}
+
+/// Pin every loaded program of the object, which keeps them and their maps loaded
+fn pin_programs(ebpf: &mut Ebpf, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create {} (is bpffs mounted at /sys/fs/bpf?)", dir.display()))?;
+    for (name, program) in ebpf.programs_mut() {
+        if program.fd().is_ok() {
+            program.pin(dir.join(name))
+                .with_context(|| format!("Failed to pin program '{}' in {}", name, dir.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Pin the link of an attached program; unpinning it is what detaches the program later.
+/// Returns false for attachments without a bpf link (netlink XDP and TC, perf event
+/// tracepoints before 5.15): the netlink ones stay attached anyway, perf events don't
+fn pin_link<L>(link: L, dir: &Path, name: &str) -> Result<bool>
+where
+    FdLink: TryFrom<L, Error = LinkError>,
+{
+    let Ok(link) = FdLink::try_from(link) else { return Ok(false) };
+    let path = dir.join("links").join(name);
+    std::fs::create_dir_all(dir.join("links"))?;
+    link.pin(&path)
+        .with_context(|| format!("Failed to pin the link of '{}' at {}", name, path.display()))?;
+    Ok(true)
+}
+
+/// Remember the pins of a load so `unload` can release them
+fn record_attachment(object: &Path, requirements: &ProgramRequirements, name: Option<String>, pin_dir: &Path) -> Result<()> {
+    let path = default_state_path();
+    let mut st = load_state(&path);
+    st.attachments.retain(|r| r.object.as_deref() != Some(object));
+    let name = name.unwrap_or_else(|| object.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default());
+    st.attachments.push(AttachmentRecord {
+        pinned_prog: Some(pin_dir.join(&name)).filter(|p| p.exists()),
+        name,
+        kind: requirements.program_type.clone(),
+        trace_category: requirements.tracepoint_category.clone(),
+        trace_name: requirements.tracepoint_name.clone(),
+        pinned_maps: Vec::new(),
+        pid: std::process::id(),
+        created_at: chrono::Utc::now().timestamp(),
+        object: Some(object.to_path_buf()),
+        pin_dir: Some(pin_dir.to_path_buf()),
+    });
+    save_state(&path, st).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Remember a TTL or window for `eclipta sweep`, replacing any lease on the same object
+fn record_lease(lease: LeaseRecord) -> Result<()> {
+    let path = default_state_path();
+    let mut st = load_state(&path);
+    st.leases.retain(|l| l.program != lease.program);
+    st.leases.push(lease);
+    save_state(&path, st).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Registry program named by `--reuse-maps-from`: an id, or the newest version of a
+/// title other than the object being loaded
+async fn find_previous_program(pool: &DbPool, from: &str, new_path: &PathBuf) -> Result<RegistryProgram> {
+    if let Ok(id) = from.parse::<i32>() {
+        return get_program_by_id(pool, id).await
+            .context("Failed to fetch program from database")?
+            .ok_or_else(|| anyhow!("No program found with id {}", id));
+    }
+    let mut programs = get_program_by_title(pool, from).await
+        .context("Failed to fetch programs from database")?;
+    if programs.is_empty() {
+        return Err(anyhow!("No program found with title '{}'", from));
+    }
+    let idx = programs.iter().position(|p| PathBuf::from(&p.path) != *new_path).unwrap_or(0);
+    Ok(programs.swap_remove(idx))
+}
+
 pub fn validate_ebpf_file(path: &PathBuf) -> Result<ProgramRequirements> {
     if !path.exists() {
         return Err(anyhow!("File does not exist: {}", path.display()));
@@ -233,10 +506,12 @@ fn validate_runtime_args(opts: &LoadOptions, requirements: &ProgramRequirements)
 async fn load_and_attach_ebpf(
     path: &PathBuf, 
     requirements: &ProgramRequirements, 
-    opts: &LoadOptions
-) -> Result<String> {
-    let mut ebpf = Ebpf::load_file(path)
+    opts: &LoadOptions,
+    maps: &MapSetup,
+) -> Result<(String, Option<String>)> {
+    let mut ebpf = maps.open(path)
         .context("Failed to load eBPF object with Aya")?;
+    let pin_dir = load_pin_dir(path);
 
     let map_count = ebpf.maps().count();
     if map_count == 0 {
@@ -262,6 +537,9 @@ async fn load_and_attach_ebpf(
 
     println!("Aya eBPF loading completed successfully");
 
+    maps.apply(path).await?;
+    pin_programs(&mut ebpf, &pin_dir)?;
+
     // Now attach the programs based on type
     match requirements.program_type.as_str() {
         "XDP" => {
@@ -270,11 +548,12 @@ async fn load_and_attach_ebpf(
             
             for (name, program) in ebpf.programs_mut() {
                 if let Program::Xdp(xdp_prog) = program {
-                    xdp_prog.attach(iface, aya::programs::XdpFlags::default())
+                    let link_id = xdp_prog.attach(iface, aya::programs::XdpFlags::default())
                         .context("Failed to attach XDP program to interface")?;
+                    pin_link(xdp_prog.take_link(link_id)?, &pin_dir, name)?;
                     
                     println!("XDP program '{}' attached to interface '{}'", name, iface);
-                    return Ok(format!("XDP program attached to {}", iface));
+                    return Ok((format!("XDP program attached to {}", iface), Some(name.to_string())));
                 }
             }
             Err(anyhow!("No XDP program found in eBPF object"))
@@ -288,11 +567,14 @@ async fn load_and_attach_ebpf(
             
             for (prog_name, program) in ebpf.programs_mut() {
                 if let Program::TracePoint(tp_prog) = program {
-                    tp_prog.attach(category, name)
+                    let link_id = tp_prog.attach(category, name)
                         .context(format!("Failed to attach Tracepoint program to '{}:{}'", category, name))?;
+                    if !pin_link(tp_prog.take_link(link_id)?, &pin_dir, prog_name)? {
+                        return Err(anyhow!("This kernel can't pin tracepoint links (5.15 or later is needed), so the program would detach when eclipta exits; use `eclipta run` instead"));
+                    }
                     
                     println!("Tracepoint program '{}' attached to '{}:{}'", prog_name, category, name);
-                    return Ok(format!("Tracepoint program attached to {}:{}", category, name));
+                    return Ok((format!("Tracepoint program attached to {}:{}", category, name), Some(prog_name.to_string())));
                 }
             }
             Err(anyhow!("No Tracepoint program found in eBPF object"))
@@ -300,7 +582,7 @@ async fn load_and_attach_ebpf(
         
         _ => {
             // For other program types, just return success since they were loaded
-            Ok(format!("Program type {} loaded successfully", requirements.program_type))
+            Ok((format!("Program type {} loaded successfully", requirements.program_type), None))
         }
     }
 }
@@ -308,71 +590,92 @@ async fn load_and_attach_ebpf(
 async fn attach_program_to_kernel(
     path: &PathBuf, 
     requirements: &ProgramRequirements, 
-    opts: &LoadOptions
-) -> Result<String> {
+    opts: &LoadOptions,
+    maps: &MapSetup,
+) -> Result<(String, Option<String>)> {
+    let pin_dir = load_pin_dir(path);
     match requirements.program_type.as_str() {
         "XDP" => {
             let iface = opts.iface.as_ref()
                 .ok_or_else(|| anyhow!("Interface required for XDP programs"))?;
             
-            let mut ebpf = Ebpf::load_file(path)
+            let mut ebpf = maps.open(path)
                 .context("Failed to load eBPF for XDP attachment")?;
+            maps.load_targets(&mut ebpf)?;
             
+            let mut attached = None;
             for (name, program) in ebpf.programs_mut() {
                 if let Program::Xdp(xdp_prog) = program {
-                    xdp_prog.load()
-                        .context("Failed to load XDP program")?;
+                    if xdp_prog.fd().is_err() {
+                        xdp_prog.load()
+                            .context("Failed to load XDP program")?;
+                    }
+                    maps.apply(path).await?;
                     
-                    xdp_prog.attach(iface, aya::programs::XdpFlags::default())
+                    let link_id = xdp_prog.attach(iface, aya::programs::XdpFlags::default())
                         .context("Failed to attach XDP program to interface")?;
+                    pin_link(xdp_prog.take_link(link_id)?, &pin_dir, name)?;
                     
                     println!("XDP program '{}' attached to interface '{}'", name, iface);
-                    return Ok(format!("XDP program attached to {}", iface));
+                    attached = Some(name.to_string());
+                    break;
                 }
             }
-            Err(anyhow!("No XDP program found in eBPF object"))
+            let name = attached.ok_or_else(|| anyhow!("No XDP program found in eBPF object"))?;
+            pin_programs(&mut ebpf, &pin_dir)?;
+            Ok((format!("XDP program attached to {}", iface), Some(name)))
         }
         
         "TC" => {
             let iface = opts.iface.as_ref()
                 .ok_or_else(|| anyhow!("Interface required for TC programs"))?;
             
-            let mut ebpf = Ebpf::load_file(path)
+            let mut ebpf = maps.open(path)
                 .context("Failed to load eBPF for TC attachment")?;
+            maps.load_targets(&mut ebpf)?;
             
+            let mut attached = None;
             for (name, program) in ebpf.programs_mut() {
                 if let Program::SchedClassifier(tc_prog) = program {
-                    tc_prog.load()
-                        .context("Failed to load TC program")?;
+                    if tc_prog.fd().is_err() {
+                        tc_prog.load()
+                            .context("Failed to load TC program")?;
+                    }
+                    maps.apply(path).await?;
                     
                     if name.contains("ingress") {
-                        tc_prog.attach(iface, aya::programs::TcAttachType::Ingress)
+                        let link_id = tc_prog.attach(iface, aya::programs::TcAttachType::Ingress)
                             .context("Failed to attach TC program to ingress")?;
+                        pin_link(tc_prog.take_link(link_id)?, &pin_dir, name)?;
                         println!("TC program '{}' attached to interface '{}' ingress", name, iface);
                     } else if name.contains("egress") {
-                        tc_prog.attach(iface, aya::programs::TcAttachType::Egress)
+                        let link_id = tc_prog.attach(iface, aya::programs::TcAttachType::Egress)
                             .context("Failed to attach TC program to egress")?;
+                        pin_link(tc_prog.take_link(link_id)?, &pin_dir, name)?;
                         println!("TC program '{}' attached to interface '{}' egress", name, iface);
                     }
                     
-                    return Ok(format!("TC program attached to {} {}", iface, 
-                        if name.contains("ingress") { "ingress" } else { "egress" }));
+                    attached = Some(name.to_string());
+                    break;
                 }
             }
-            Err(anyhow!("No TC program attached to interface '{}'", iface))
+            let name = attached.ok_or_else(|| anyhow!("No TC program attached to interface '{}'", iface))?;
+            pin_programs(&mut ebpf, &pin_dir)?;
+            Ok((format!("TC program attached to {} {}", iface,
+                if name.contains("ingress") { "ingress" } else { "egress" }), Some(name)))
         }
         
         "SocketFilter" => {
             let socket_fd = opts.socket_fd
                 .ok_or_else(|| anyhow!("Socket FD required for SocketFilter programs"))?;
             
-            let mut ebpf = Ebpf::load_file(path)
+            let mut ebpf = maps.open(path)
                 .context("Failed to load eBPF for SocketFilter attachment")?;
             
             for (_name, program) in ebpf.programs_mut() {
                 if let Program::SocketFilter(_sf_prog) = program {
                     println!("SocketFilter attachment requires proper socket handling - skipping attachment");
-                    return Ok(format!("SocketFilter program loaded but not attached (FD: {})", socket_fd));
+                    return Ok((format!("SocketFilter program loaded but not attached (FD: {})", socket_fd), None));
                 }
             }
             Err(anyhow!("No SocketFilter program found in eBPF object"))
@@ -384,27 +687,38 @@ async fn attach_program_to_kernel(
             let name = requirements.tracepoint_name.as_ref()
                 .ok_or_else(|| anyhow!("Tracepoint name not found in ELF sections"))?;
             
-            let mut ebpf = Ebpf::load_file(path)
+            let mut ebpf = maps.open(path)
                 .context("Failed to load eBPF for Tracepoint attachment")?;
+            maps.load_targets(&mut ebpf)?;
             
+            let mut attached = None;
             for (prog_name, program) in ebpf.programs_mut() {
                 if let Program::TracePoint(tp_prog) = program {
-                    tp_prog.load()
-                        .context("Failed to load Tracepoint program")?;
+                    if tp_prog.fd().is_err() {
+                        tp_prog.load()
+                            .context("Failed to load Tracepoint program")?;
+                    }
+                    maps.apply(path).await?;
                     
-                    tp_prog.attach(category, name)
+                    let link_id = tp_prog.attach(category, name)
                         .context(format!("Failed to attach Tracepoint program to '{}:{}'", category, name))?;
+                    if !pin_link(tp_prog.take_link(link_id)?, &pin_dir, prog_name)? {
+                        return Err(anyhow!("This kernel can't pin tracepoint links (5.15 or later is needed), so the program would detach when eclipta exits; use `eclipta run` instead"));
+                    }
                     
                     println!("Tracepoint program '{}' attached to '{}:{}'", prog_name, category, name);
-                    return Ok(format!("Tracepoint program attached to {}:{}", category, name));
+                    attached = Some(prog_name.to_string());
+                    break;
                 }
             }
-            Err(anyhow!("No Tracepoint program found in eBPF object"))
+            let prog_name = attached.ok_or_else(|| anyhow!("No Tracepoint program found in eBPF object"))?;
+            pin_programs(&mut ebpf, &pin_dir)?;
+            Ok((format!("Tracepoint program attached to {}:{}", category, name), Some(prog_name)))
         }
         
         _ => {
             println!("Program type '{}' not yet implemented for kernel attachment", requirements.program_type);
-            Ok(format!("Program type {} loaded but not attached", requirements.program_type))
+            Ok((format!("Program type {} loaded but not attached", requirements.program_type), None))
         }
     }
 }
@@ -0,0 +1,654 @@
+use crate::commands::ebpf::map_watch::{handle_map_watch, MapWatchOptions};
+use crate::db::programs::{get_program_by_id, get_program_by_title};
+use crate::utils::bpftool::{
+    kernel_name, loaded_programs_for, map_delete, map_dump, map_list, map_lookup, map_show, map_update,
+    MapEntry, MapInfo, MapRef, MapValue,
+};
+use crate::utils::btf::{hex, parse_int, write_int, Btf, TypeId};
+use crate::utils::db::ensure_db_ready;
+use crate::utils::logger::{info, success, warn};
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use prettytable::{format, row, Cell, Row, Table};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct MapOptions {
+    #[command(subcommand)]
+    pub command: MapCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MapCommand {
+    /// List the maps of a program
+    List(MapListOptions),
+    /// Print every entry of a map
+    Dump(MapDumpOptions),
+    /// Look up one key
+    Get(MapKeyOptions),
+    /// Create or overwrite one entry
+    Update(MapUpdateOptions),
+    /// Delete one key
+    Delete(MapKeyOptions),
+    /// Delete every entry (array maps are zeroed instead)
+    Clear(MapClearOptions),
+    /// Re-read a map periodically and show what changed
+    Watch(MapWatchOptions),
+}
+
+/// Which map to operate on: a program's map by name, a pinned map or a kernel map ID
+#[derive(Args, Debug, Clone)]
+pub struct MapSelector {
+    /// Registered program ID owning the map
+    #[arg(long, conflicts_with_all = ["title", "program"])]
+    pub id: Option<i32>,
+
+    /// Registered program title owning the map
+    #[arg(long, conflicts_with_all = ["id", "program"])]
+    pub title: Option<String>,
+
+    /// eBPF object file owning the map (also supplies BTF types for --pin / --map-id)
+    #[arg(short, long, conflicts_with_all = ["id", "title"])]
+    pub program: Option<PathBuf>,
+
+    /// Pinned map path, e.g. /sys/fs/bpf/my_map
+    #[arg(long, conflicts_with = "map_id")]
+    pub pin: Option<PathBuf>,
+
+    /// Kernel map ID as shown by `bpftool map show`
+    #[arg(long)]
+    pub map_id: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+pub struct MapListOptions {
+    #[command(flatten)]
+    pub target: MapSelector,
+
+    /// Output in JSON format
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MapDumpOptions {
+    #[command(flatten)]
+    pub target: MapSelector,
+
+    /// Map name inside the program
+    #[arg(short, long)]
+    pub map: Option<String>,
+
+    /// Print raw bytes even when BTF types are available
+    #[arg(long)]
+    pub hex: bool,
+
+    /// Output in JSON format
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MapKeyOptions {
+    #[command(flatten)]
+    pub target: MapSelector,
+
+    /// Map name inside the program
+    #[arg(short, long)]
+    pub map: Option<String>,
+
+    /// Key as JSON or a plain value, e.g. 1234, 0x10, '"nginx"' or '{"pid": 1, "port": 80}'
+    pub key: String,
+
+    /// Treat the key as hex bytes, e.g. "01 00 00 00"
+    #[arg(long)]
+    pub hex: bool,
+
+    /// Output in JSON format
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MapUpdateOptions {
+    #[command(flatten)]
+    pub target: MapSelector,
+
+    /// Map name inside the program
+    #[arg(short, long)]
+    pub map: Option<String>,
+
+    /// Key as JSON or a plain value
+    pub key: String,
+
+    /// Value as JSON or a plain value
+    pub value: String,
+
+    /// Treat key and value as hex bytes
+    #[arg(long)]
+    pub hex: bool,
+
+    /// any: create or overwrite, exist: only overwrite, noexist: only create
+    #[arg(long, default_value = "any", value_parser = ["any", "exist", "noexist"])]
+    pub mode: String,
+}
+
+#[derive(Args, Debug)]
+pub struct MapClearOptions {
+    #[command(flatten)]
+    pub target: MapSelector,
+
+    /// Map name inside the program
+    #[arg(short, long)]
+    pub map: Option<String>,
+
+    /// Do not ask for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+pub async fn handle_map(opts: MapOptions) -> Result<()> {
+    match opts.command {
+        MapCommand::List(o) => handle_map_list(o).await,
+        MapCommand::Dump(o) => handle_map_dump(o).await,
+        MapCommand::Get(o) => handle_map_get(o).await,
+        MapCommand::Update(o) => handle_map_update(o).await,
+        MapCommand::Delete(o) => handle_map_delete(o).await,
+        MapCommand::Clear(o) => handle_map_clear(o).await,
+        MapCommand::Watch(o) => handle_map_watch(o).await,
+    }
+}
+
+/// BTF key/value types of a map, when its object was built with `-g`
+pub struct MapTypes {
+    pub btf: Btf,
+    pub key: TypeId,
+    pub value: TypeId,
+}
+
+/// A kernel map plus everything needed to encode and render its entries
+pub struct ResolvedMap {
+    pub target: MapRef,
+    pub info: MapInfo,
+    pub name: String,
+    pub types: Option<MapTypes>,
+}
+
+impl ResolvedMap {
+    /// Attach BTF types from `object` (if any) to a kernel map
+    pub fn new(target: MapRef, info: MapInfo, name: String, object: Option<&Path>) -> ResolvedMap {
+        let types = match object {
+            Some(path) => match Btf::from_elf(path) {
+                Ok(Some(btf)) => map_types(btf, &name, &info),
+                Ok(None) => None,
+                Err(e) => {
+                    warn(&format!("Ignoring BTF of {}: {}", path.display(), e));
+                    None
+                }
+            },
+            None => None,
+        };
+        ResolvedMap { target, info, name, types }
+    }
+
+    pub fn encode_key(&self, text: &str, hex: bool) -> Result<Vec<u8>> {
+        let ty = self.types.as_ref().map(|t| (&t.btf, t.key));
+        encode_arg(text, ty, self.info.bytes_key as usize, hex, "key")
+    }
+
+    pub fn encode_value(&self, text: &str, hex: bool) -> Result<Vec<u8>> {
+        let ty = self.types.as_ref().map(|t| (&t.btf, t.value));
+        encode_arg(text, ty, self.info.bytes_value as usize, hex, "value")
+    }
+
+    /// Encode a key already parsed from JSON, e.g. from a map-init file
+    pub fn encode_key_json(&self, value: &Value) -> Result<Vec<u8>> {
+        let ty = self.types.as_ref().map(|t| (&t.btf, t.key));
+        encode_json(value, ty, self.info.bytes_key as usize, "key")
+    }
+
+    pub fn encode_value_json(&self, value: &Value) -> Result<Vec<u8>> {
+        let ty = self.types.as_ref().map(|t| (&t.btf, t.value));
+        encode_json(value, ty, self.info.bytes_value as usize, "value")
+    }
+
+    pub fn render_key(&self, bytes: &[u8]) -> Value {
+        match &self.types {
+            Some(t) => t.btf.format(t.key, bytes),
+            None => Value::from(hex(bytes)),
+        }
+    }
+
+    pub fn render_value(&self, bytes: &[u8]) -> Value {
+        match &self.types {
+            Some(t) => t.btf.format(t.value, bytes),
+            None => Value::from(hex(bytes)),
+        }
+    }
+
+    pub fn entry_json(&self, entry: &MapEntry) -> Value {
+        match &entry.value {
+            MapValue::Single(v) => json!({ "key": self.render_key(&entry.key), "value": self.render_value(v) }),
+            MapValue::PerCpu(values) => json!({
+                "key": self.render_key(&entry.key),
+                "values": values
+                    .iter()
+                    .map(|(cpu, v)| json!({ "cpu": cpu, "value": self.render_value(v) }))
+                    .collect::<Vec<_>>(),
+            }),
+        }
+    }
+
+    pub fn print_entry(&self, entry: &MapEntry) {
+        let key = display(&self.render_key(&entry.key));
+        match &entry.value {
+            MapValue::Single(v) => println!("{} => {}", key, display(&self.render_value(v))),
+            MapValue::PerCpu(values) => {
+                println!("{} =>", key);
+                for (cpu, v) in values {
+                    println!("    cpu{}: {}", cpu, display(&self.render_value(v)));
+                }
+            }
+        }
+    }
+}
+
+/// eBPF object owning the selected map, looked up in the registry for --id/--title
+pub async fn selector_object(sel: &MapSelector) -> Result<Option<PathBuf>> {
+    if let Some(path) = &sel.program {
+        return Ok(Some(path.clone()));
+    }
+    if sel.id.is_none() && sel.title.is_none() {
+        return Ok(None);
+    }
+    let pool = ensure_db_ready()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to database: {}", e))?;
+    let program = if let Some(id) = sel.id {
+        get_program_by_id(&pool, id)
+            .await?
+            .ok_or_else(|| anyhow!("No program found with id {}", id))?
+    } else {
+        let title = sel.title.as_deref().unwrap_or_default();
+        let mut programs = get_program_by_title(&pool, title).await?;
+        match programs.len() {
+            0 => return Err(anyhow!("No program found with title '{}'", title)),
+            1 => programs.remove(0),
+            _ => {
+                return Err(anyhow!(
+                    "Multiple programs found with title '{}'. Please use --id to specify which one.",
+                    title
+                ))
+            }
+        }
+    };
+    Ok(Some(PathBuf::from(program.path)))
+}
+
+pub async fn resolve_map(sel: &MapSelector, map: Option<&str>) -> Result<ResolvedMap> {
+    let object = selector_object(sel).await?;
+
+    let target = if let Some(pin) = &sel.pin {
+        MapRef::Pinned(pin.clone())
+    } else if let Some(id) = sel.map_id {
+        MapRef::Id(id)
+    } else {
+        let object = object
+            .as_ref()
+            .ok_or_else(|| anyhow!("Select a map with --id, --title or --program plus --map, or with --pin / --map-id"))?;
+        let name = map.ok_or_else(|| anyhow!("Please name the map with --map"))?;
+        MapRef::Id(find_loaded_map(object, name).await?.id)
+    };
+
+    let info = map_show(&target).await?;
+    let name = map.map(str::to_string).unwrap_or_else(|| info.name.clone());
+    Ok(ResolvedMap::new(target, info, name, object.as_deref()))
+}
+
+fn map_types(btf: Btf, name: &str, info: &MapInfo) -> Option<MapTypes> {
+    let (key, value) = btf.map_types(name)?;
+    let sizes = (btf.size_of(key)?, btf.size_of(value)?);
+    if sizes != (info.bytes_key as usize, info.bytes_value as usize) {
+        warn(&format!(
+            "BTF types of '{}' ({} / {} bytes) do not match the kernel map ({} / {} bytes); showing hex",
+            name, sizes.0, sizes.1, info.bytes_key, info.bytes_value
+        ));
+        return None;
+    }
+    Some(MapTypes { btf, key, value })
+}
+
+/// Map named `name` of the most recently loaded instance of `object`
+pub async fn find_newest_loaded_map(object: &Path, name: &str) -> Result<MapInfo> {
+    let mut progs = loaded_programs_for(object).await?;
+    if progs.is_empty() {
+        return Err(anyhow!("No program from {} is loaded in the kernel", object.display()));
+    }
+    // Keep the latest instance of each program: IDs only grow, so that is the highest one
+    progs.sort_by_key(|p| std::cmp::Reverse(p.id));
+    let mut seen = Vec::new();
+    progs.retain(|p| {
+        let first = !seen.contains(&p.name);
+        seen.push(p.name.clone());
+        first
+    });
+    let ids: Vec<u32> = progs.iter().flat_map(|p| p.map_ids.iter().copied()).collect();
+    map_list()
+        .await?
+        .into_iter()
+        .filter(|m| ids.contains(&m.id) && m.name == kernel_name(name))
+        .max_by_key(|m| m.id)
+        .ok_or_else(|| anyhow!("Map '{}' is not used by any program of {}", name, object.display()))
+}
+
+/// Kernel map named `name` that belongs to a loaded program of `object`
+pub async fn find_loaded_map(object: &Path, name: &str) -> Result<MapInfo> {
+    let progs = loaded_programs_for(object).await?;
+    if progs.is_empty() {
+        return Err(anyhow!(
+            "No program from {} is loaded in the kernel (load it first, or use --pin / --map-id)",
+            object.display()
+        ));
+    }
+    let ids: Vec<u32> = progs.iter().flat_map(|p| p.map_ids.iter().copied()).collect();
+    let mut matches: Vec<MapInfo> = map_list()
+        .await?
+        .into_iter()
+        .filter(|m| ids.contains(&m.id) && m.name == kernel_name(name))
+        .collect();
+    matches.sort_by_key(|m| m.id);
+    matches.dedup_by_key(|m| m.id);
+
+    match matches.len() {
+        0 => Err(anyhow!("Map '{}' is not used by any loaded program of {}", name, object.display())),
+        1 => Ok(matches.remove(0)),
+        _ => {
+            let ids: Vec<String> = matches.iter().map(|m| m.id.to_string()).collect();
+            Err(anyhow!(
+                "Map '{}' is loaded more than once (ids {}); pick one with --map-id",
+                name,
+                ids.join(", ")
+            ))
+        }
+    }
+}
+
+/// Encode a command-line key or value: hex bytes, BTF-typed JSON, or a plain integer
+fn encode_arg(text: &str, ty: Option<(&Btf, TypeId)>, size: usize, is_hex: bool, what: &str) -> Result<Vec<u8>> {
+    if is_hex {
+        let bytes = parse_hex(text)?;
+        if bytes.len() != size {
+            return Err(anyhow!("The {} must be {} bytes, got {}", what, size, bytes.len()));
+        }
+        return Ok(bytes);
+    }
+    match ty {
+        Some(_) => {
+            let value = serde_json::from_str(text).unwrap_or_else(|_| Value::from(text));
+            encode_json(&value, ty, size, what)
+        }
+        // Without types a bare argument is an integer (42, -1, 0x2a); raw bytes need --hex
+        None => {
+            let v = parse_int(&Value::from(text)).map_err(|_| {
+                anyhow!("Map has no BTF types; give the {} as an integer, or as {} hex bytes with --hex", what, size)
+            })?;
+            encode_untyped_int(v, size, what)
+        }
+    }
+}
+
+/// Encode a JSON key or value by its BTF type. Without types, numbers are integers and
+/// strings are hex bytes in memory order ("01 00 00 00" or "0x01000000"), as dumps print them
+fn encode_json(value: &Value, ty: Option<(&Btf, TypeId)>, size: usize, what: &str) -> Result<Vec<u8>> {
+    let bytes = match (ty, value) {
+        (Some((btf, id)), _) => btf.encode(id, value)
+            .map_err(|e| anyhow!("Invalid {} for {}: {}", what, btf.type_name(id), e))?,
+        (None, Value::Number(_)) => encode_untyped_int(parse_int(value)?, size, what)?,
+        (None, Value::String(text)) => parse_hex(text)
+            .map_err(|e| anyhow!("Map has no BTF types, so a string {} is hex bytes: {}", what, e))?,
+        (None, _) => {
+            return Err(anyhow!("Map has no BTF types; give the {} as an integer or a string of hex bytes ({} bytes)", what, size))
+        }
+    };
+
+    if bytes.len() != size {
+        return Err(anyhow!("The {} must be {} bytes, got {}", what, size, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+fn encode_untyped_int(v: i128, size: usize, what: &str) -> Result<Vec<u8>> {
+    if !matches!(size, 1 | 2 | 4 | 8) {
+        return Err(anyhow!("The {} is {} bytes, which is not an integer size; give it as hex bytes", what, size));
+    }
+    let mut out = vec![0u8; size];
+    write_int(&mut out, size, v, &format!("a {}-byte {}", size, what))?;
+    Ok(out)
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    let digits: String = text
+        .split_whitespace()
+        .map(|t| t.trim_start_matches("0x"))
+        .collect();
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            if pair.len() != 2 {
+                return Err(anyhow!("Hex input '{}' has an odd number of digits", text));
+            }
+            u8::from_str_radix(pair, 16).map_err(|_| anyhow!("Invalid hex byte '{}'", pair))
+        })
+        .collect()
+}
+
+/// Plain strings print without quotes, everything else as compact JSON
+pub fn display(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn describe_type(types: Option<&MapTypes>, id: impl Fn(&MapTypes) -> TypeId, bytes: u32) -> String {
+    match types {
+        Some(t) => format!("{} ({}B)", t.btf.type_name(id(t)), bytes),
+        None => format!("{}B", bytes),
+    }
+}
+
+async fn handle_map_list(opts: MapListOptions) -> Result<()> {
+    let sel = &opts.target;
+    let maps: Vec<ResolvedMap> = if sel.pin.is_some() || sel.map_id.is_some() {
+        vec![resolve_map(sel, None).await?]
+    } else {
+        let object = selector_object(sel)
+            .await?
+            .ok_or_else(|| anyhow!("Select a program with --id, --title or --program, or a map with --pin / --map-id"))?;
+        let progs = loaded_programs_for(&object).await?;
+        if progs.is_empty() {
+            return Err(anyhow!("No program from {} is loaded in the kernel", object.display()));
+        }
+        let ids: Vec<u32> = progs.iter().flat_map(|p| p.map_ids.iter().copied()).collect();
+        let btf = Btf::from_elf(&object).ok().flatten();
+        let mut out = Vec::new();
+        for info in map_list().await?.into_iter().filter(|m| ids.contains(&m.id)) {
+            // Recover the full (untruncated) name from BTF when possible
+            let name = btf
+                .as_ref()
+                .and_then(|b| b.map_names().into_iter().find(|n| kernel_name(n) == info.name))
+                .unwrap_or_else(|| info.name.clone());
+            let types = btf.clone().and_then(|b| map_types(b, &name, &info));
+            out.push(ResolvedMap { target: MapRef::Id(info.id), info, name, types });
+        }
+        out
+    };
+
+    if opts.json {
+        let list: Vec<Value> = maps
+            .iter()
+            .map(|m| {
+                json!({
+                    "id": m.info.id,
+                    "name": m.name,
+                    "type": m.info.map_type,
+                    "key_size": m.info.bytes_key,
+                    "value_size": m.info.bytes_value,
+                    "max_entries": m.info.max_entries,
+                    "key_type": m.types.as_ref().map(|t| t.btf.type_name(t.key)),
+                    "value_type": m.types.as_ref().map(|t| t.btf.type_name(t.value)),
+                    "pinned": m.info.pinned,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&list)?);
+        return Ok(());
+    }
+
+    if maps.is_empty() {
+        info("Program has no maps");
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["ID", "Name", "Type", "Key", "Value", "Max Entries"]);
+    for m in &maps {
+        table.add_row(Row::new(vec![
+            Cell::new(&m.info.id.to_string()),
+            Cell::new(&m.name),
+            Cell::new(&m.info.map_type),
+            Cell::new(&describe_type(m.types.as_ref(), |t| t.key, m.info.bytes_key)),
+            Cell::new(&describe_type(m.types.as_ref(), |t| t.value, m.info.bytes_value)),
+            Cell::new(&m.info.max_entries.to_string()),
+        ]));
+    }
+    table.printstd();
+    Ok(())
+}
+
+async fn handle_map_dump(opts: MapDumpOptions) -> Result<()> {
+    let mut map = resolve_map(&opts.target, opts.map.as_deref()).await?;
+    if opts.hex {
+        map.types = None;
+    }
+    let entries = map_dump(&map.target).await?;
+
+    if opts.json {
+        let list: Vec<Value> = entries.iter().map(|e| map.entry_json(e)).collect();
+        println!("{}", serde_json::to_string_pretty(&list)?);
+        return Ok(());
+    }
+    for entry in &entries {
+        map.print_entry(entry);
+    }
+    info(&format!("{} entries in '{}' ({})", entries.len(), map.name, map.target));
+    Ok(())
+}
+
+async fn handle_map_get(opts: MapKeyOptions) -> Result<()> {
+    let map = resolve_map(&opts.target, opts.map.as_deref()).await?;
+    let key = map.encode_key(&opts.key, opts.hex)?;
+    let entry = map_lookup(&map.target, &key)
+        .await?
+        .ok_or_else(|| anyhow!("Key {} not found in '{}'", opts.key, map.name))?;
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&map.entry_json(&entry))?);
+    } else {
+        map.print_entry(&entry);
+    }
+    Ok(())
+}
+
+async fn handle_map_update(opts: MapUpdateOptions) -> Result<()> {
+    let map = resolve_map(&opts.target, opts.map.as_deref()).await?;
+    let key = map.encode_key(&opts.key, opts.hex)?;
+    let value = map.encode_value(&opts.value, opts.hex)?;
+    map_update(&map.target, &key, &value, &opts.mode).await?;
+    success(&format!("Updated {} in '{}'", opts.key, map.name));
+    Ok(())
+}
+
+async fn handle_map_delete(opts: MapKeyOptions) -> Result<()> {
+    let map = resolve_map(&opts.target, opts.map.as_deref()).await?;
+    if map.info.is_array() {
+        return Err(anyhow!("Entries of {} maps cannot be deleted; use `map update` to overwrite them", map.info.map_type));
+    }
+    let key = map.encode_key(&opts.key, opts.hex)?;
+    if map_delete(&map.target, &key).await? {
+        success(&format!("Deleted {} from '{}'", opts.key, map.name));
+        Ok(())
+    } else {
+        Err(anyhow!("Key {} not found in '{}'", opts.key, map.name))
+    }
+}
+
+async fn handle_map_clear(opts: MapClearOptions) -> Result<()> {
+    let map = resolve_map(&opts.target, opts.map.as_deref()).await?;
+    let entries = map_dump(&map.target).await?;
+    if entries.is_empty() {
+        info(&format!("'{}' is already empty", map.name));
+        return Ok(());
+    }
+
+    if !opts.yes {
+        print!("Clear {} entries from '{}' ({})? [y/N] ", entries.len(), map.name, map.target);
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            info("Aborted");
+            return Ok(());
+        }
+    }
+
+    let zero = vec![0u8; map.info.bytes_value as usize];
+    let mut cleared = 0usize;
+    for entry in &entries {
+        if map.info.is_array() {
+            map_update(&map.target, &entry.key, &zero, "any").await?;
+        } else if !map_delete(&map.target, &entry.key).await? {
+            // Deleted concurrently by the program itself
+            continue;
+        }
+        cleared += 1;
+    }
+    success(&format!("Cleared {} entries from '{}'", cleared, map.name));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn untyped_integers_must_fit_the_size() {
+        assert_eq!(encode_json(&json!(255), None, 1, "key").unwrap(), vec![0xff]);
+        assert_eq!(encode_json(&json!(-1), None, 4, "value").unwrap(), vec![0xff; 4]);
+        let err = encode_json(&json!(300), None, 1, "key").unwrap_err();
+        assert!(err.to_string().contains("does not fit in a 1-byte key"), "{}", err);
+        assert!(encode_json(&json!(70000), None, 2, "value").is_err());
+        assert!(encode_json(&json!(1), None, 3, "key").is_err());
+    }
+
+    #[test]
+    fn untyped_strings_are_hex_bytes() {
+        assert_eq!(encode_json(&json!("01 02 03"), None, 3, "key").unwrap(), vec![1, 2, 3]);
+        // Never read as the decimal number 1000000
+        assert_eq!(encode_json(&json!("01000000"), None, 4, "key").unwrap(), vec![1, 0, 0, 0]);
+        assert_eq!(encode_json(&json!("0x0102"), None, 2, "key").unwrap(), vec![0x01, 0x02]);
+        assert!(encode_json(&json!("01 02"), None, 3, "key").is_err());
+        assert!(encode_json(&json!("nginx"), None, 5, "key").is_err());
+    }
+
+    #[test]
+    fn untyped_arguments_are_integers_unless_hex() {
+        assert_eq!(encode_arg("1000000", None, 4, false, "key").unwrap(), 1_000_000u32.to_le_bytes());
+        assert_eq!(encode_arg("0x10", None, 2, false, "key").unwrap(), vec![0x10, 0]);
+        assert_eq!(encode_arg("01 00", None, 2, true, "key").unwrap(), vec![1, 0]);
+        assert!(encode_arg("01 00", None, 2, false, "key").is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::commands::ebpf::map::{find_newest_loaded_map, ResolvedMap};
+use crate::utils::bpftool::{map_update_batch, MapRef};
+use crate::utils::logger::{info, success};
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// One `--map-init <map>=<file>` request
+#[derive(Debug, Clone)]
+pub struct MapInitSpec {
+    pub map: String,
+    pub file: PathBuf,
+}
+
+impl MapInitSpec {
+    pub fn parse(spec: &str) -> Result<MapInitSpec> {
+        let (map, file) = spec
+            .split_once('=')
+            .filter(|(m, f)| !m.is_empty() && !f.is_empty())
+            .ok_or_else(|| anyhow!("Invalid --map-init '{}', expected <map>=<file.json|csv>", spec))?;
+        Ok(MapInitSpec { map: map.to_string(), file: PathBuf::from(file) })
+    }
+
+    /// Form stored in the registry; relative paths are made absolute so reloads
+    /// from another directory find the same file
+    pub fn canonical(&self) -> String {
+        let file = std::fs::canonicalize(&self.file).unwrap_or_else(|_| self.file.clone());
+        format!("{}={}", self.map, file.display())
+    }
+}
+
+/// A spec together with the entries read from its file
+#[derive(Debug, Clone)]
+pub struct MapInit {
+    pub spec: MapInitSpec,
+    pub entries: Vec<(Value, Value)>,
+}
+
+/// Read every spec's file up front so a bad file fails before anything is loaded
+pub fn read_specs(specs: &[String]) -> Result<Vec<MapInit>> {
+    specs
+        .iter()
+        .map(|s| {
+            let spec = MapInitSpec::parse(s)?;
+            let entries = read_entries(&spec.file)?;
+            Ok(MapInit { spec, entries })
+        })
+        .collect()
+}
+
+/// Write the entries into the maps of the freshly loaded `object`, before its programs attach
+pub async fn populate_maps(object: &Path, inits: &[MapInit]) -> Result<()> {
+    for MapInit { spec, entries } in inits {
+        let kernel_map = find_newest_loaded_map(object, &spec.map).await?;
+        if entries.len() > kernel_map.max_entries as usize {
+            return Err(anyhow!(
+                "{} has {} entries but map '{}' holds at most {}",
+                spec.file.display(),
+                entries.len(),
+                spec.map,
+                kernel_map.max_entries
+            ));
+        }
+
+        let map = ResolvedMap::new(MapRef::Id(kernel_map.id), kernel_map, spec.map.clone(), Some(object));
+        info(&format!("Populating map '{}' from {}...", spec.map, spec.file.display()));
+
+        // Encode everything first so a bad row leaves the map untouched
+        let mut encoded = Vec::with_capacity(entries.len());
+        for (i, (key, value)) in entries.iter().enumerate() {
+            let k = map
+                .encode_key_json(key)
+                .with_context(|| format!("{} entry {}", spec.file.display(), i + 1))?;
+            let v = map
+                .encode_value_json(value)
+                .with_context(|| format!("{} entry {}", spec.file.display(), i + 1))?;
+            encoded.push((k, v));
+        }
+        let updates: Vec<(&[u8], &[u8])> = encoded.iter().map(|(k, v)| (k.as_slice(), v.as_slice())).collect();
+        map_update_batch(&map.target, &updates, "any").await?;
+        success(&format!("Inserted {} entries into map '{}'", encoded.len(), spec.map));
+    }
+    Ok(())
+}
+
+fn read_entries(path: &Path) -> Result<Vec<(Value, Value)>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read map init file {}", path.display()))?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => parse_json(&text).with_context(|| format!("Invalid map init file {}", path.display())),
+        Some("csv") => parse_csv(&text).with_context(|| format!("Invalid map init file {}", path.display())),
+        _ => Err(anyhow!("Map init file {} must end in .json or .csv", path.display())),
+    }
+}
+
+/// Either `[{"key": .., "value": ..}, ...]` (the output of `map dump --json`)
+/// or an object whose member names are the keys
+fn parse_json(text: &str) -> Result<Vec<(Value, Value)>> {
+    match serde_json::from_str(text)? {
+        Value::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| match item {
+                Value::Object(mut fields) => {
+                    let key = fields.remove("key");
+                    let value = fields.remove("value");
+                    key.zip(value)
+                        .ok_or_else(|| anyhow!("entry {} needs both \"key\" and \"value\"", i + 1))
+                }
+                _ => Err(anyhow!("entry {} is not an object", i + 1)),
+            })
+            .collect(),
+        Value::Object(fields) => Ok(fields.into_iter().map(|(k, v)| (field_value(&k), v)).collect()),
+        _ => Err(anyhow!("expected an array of {{\"key\", \"value\"}} objects or an object")),
+    }
+}
+
+/// `key,value` per line; `#` starts a comment and a leading `key,value` header is skipped.
+/// Fields may be JSON, so the split happens at the first comma outside brackets and quotes
+fn parse_csv(text: &str) -> Result<Vec<(Value, Value)>> {
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = split_row(line).ok_or_else(|| anyhow!("line {}: expected <key>,<value>", n + 1))?;
+        if entries.is_empty() && key.eq_ignore_ascii_case("key") && value.eq_ignore_ascii_case("value") {
+            continue;
+        }
+        entries.push((field_value(key), field_value(value)));
+    }
+    Ok(entries)
+}
+
+fn split_row(line: &str) -> Option<(&str, &str)> {
+    let mut depth = 0i32;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '[' | '{' if !quoted => depth += 1,
+            ']' | '}' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => return Some((line[..i].trim(), line[i + 1..].trim())),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a field as JSON when it is JSON, otherwise keep it as a string
+fn field_value(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::from(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rows_split_at_the_first_top_level_comma() {
+        assert_eq!(split_row("1, 2"), Some(("1", "2")));
+        assert_eq!(split_row("[1, 2], {\"a\": 1, \"b\": 2}"), Some(("[1, 2]", "{\"a\": 1, \"b\": 2}")));
+        assert_eq!(split_row("\"a,\\\"b\", 3"), Some(("\"a,\\\"b\"", "3")));
+        assert_eq!(split_row("no comma"), None);
+        assert_eq!(split_row("[1, 2]"), None);
+    }
+
+    #[test]
+    fn csv_skips_comments_and_the_header() {
+        let entries = parse_csv("# ports\nkey,value\n\n80, 1\n\"10.0.0.1\", {\"hits\": 2}\nlabel,plain text\n").unwrap();
+        assert_eq!(entries, vec![
+            (json!(80), json!(1)),
+            (json!("10.0.0.1"), json!({"hits": 2})),
+            (json!("label"), json!("plain text")),
+        ]);
+        // Only a first row of key,value is a header
+        assert_eq!(parse_csv("1,2\nkey,value").unwrap().len(), 2);
+        let err = parse_csv("1,2\n3").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn json_takes_dumps_and_objects() {
+        let dump = parse_json(r#"[{"key": [0, 1], "value": 7}, {"key": 2, "value": {"a": 1}}]"#).unwrap();
+        assert_eq!(dump, vec![(json!([0, 1]), json!(7)), (json!(2), json!({"a": 1}))]);
+
+        let object = parse_json(r#"{"80": true, "name": 3}"#).unwrap();
+        assert_eq!(object, vec![(json!(80), json!(true)), (json!("name"), json!(3))]);
+
+        assert!(parse_json(r#"[{"key": 1}]"#).unwrap_err().to_string().contains("entry 1"));
+        assert!(parse_json(r#"[1]"#).is_err());
+        assert!(parse_json("3").is_err());
+    }
+}
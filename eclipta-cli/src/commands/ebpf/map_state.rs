@@ -0,0 +1,197 @@
+use crate::commands::ebpf::map::find_newest_loaded_map;
+use crate::utils::bpftool::{loaded_programs_for, map_dump, map_update_batch, MapInfo, MapRef, MapValue};
+use crate::utils::btf::{Btf, TypeId};
+use crate::utils::logger::{info, success, warn};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+/// `pinning` value of libbpf-style maps pinned under the loader's pin path by name
+pub(crate) const LIBBPF_PIN_BY_NAME: u32 = 1;
+
+/// Map types whose contents are kernel handles or transient data, never copied over
+const NOT_CARRIED: &[&str] = &[
+    "prog_array", "perf_event_array", "ringbuf", "user_ringbuf", "stack_trace", "cgroup_array",
+    "array_of_maps", "hash_of_maps", "devmap", "devmap_hash", "sockmap", "sockhash", "xskmap",
+    "cpumap", "reuseport_sockarray",
+];
+
+/// What happens to one map of the new object during an upgrade
+#[derive(Debug)]
+pub enum MapPlan {
+    /// The new program opens the old pinned map, so nothing is copied
+    ReusePinned(PathBuf),
+    /// Entries of the old map with this id, read and written into the new one right before
+    /// the swap so nothing the old program writes during the load is lost
+    Restore(u32),
+    /// Starts empty, with the reason shown to the user
+    Fresh(String),
+}
+
+/// How the maps of `old_object` carry over into a load of `new_object`
+#[derive(Debug, Default)]
+pub struct MapCarryOver {
+    /// Directory to hand the loader so pinned-by-name maps are reopened instead of created
+    pub pin_path: Option<PathBuf>,
+    pub maps: Vec<(String, MapPlan)>,
+}
+
+impl MapCarryOver {
+    /// Log what happens to each map, warning about the ones that lose their state
+    pub fn report(&self, old_label: &str) {
+        info(&format!("Carrying map state over from {}:", old_label));
+        for (name, plan) in &self.maps {
+            match plan {
+                MapPlan::ReusePinned(path) => info(&format!("  {}: reuse pinned map {}", name, path.display())),
+                MapPlan::Restore(id) => info(&format!("  {}: copy entries from map {}", name, id)),
+                MapPlan::Fresh(reason) => info(&format!("  {}: start empty ({})", name, reason)),
+            }
+        }
+        let fresh = self.maps.iter().filter(|(_, p)| matches!(p, MapPlan::Fresh(_))).count();
+        if fresh > 0 {
+            warn(&format!("{} map(s) will start empty", fresh));
+        }
+    }
+}
+
+/// Compare every map of `new_object` with the loaded maps of `old_object` and decide how
+/// to carry its state. Fails with a layout diff if any shared map changed incompatibly.
+pub async fn plan_carry_over(old_object: &Path, new_object: &Path) -> Result<MapCarryOver> {
+    let new_btf = Btf::from_elf(new_object)?
+        .ok_or_else(|| anyhow!("{} has no BTF; rebuild it with -g to carry map state", new_object.display()))?;
+    let old_btf = Btf::from_elf(old_object)?;
+    if loaded_programs_for(old_object).await?.is_empty() {
+        return Err(anyhow!("No program from {} is loaded, so there is no map state to carry", old_object.display()));
+    }
+
+    let mut carry = MapCarryOver::default();
+    let mut problems = Vec::new();
+    for name in new_btf.map_names() {
+        let old = match find_newest_loaded_map(old_object, &name).await {
+            Ok(m) => m,
+            Err(_) => {
+                carry.maps.push((name, MapPlan::Fresh("not in the old program".to_string())));
+                continue;
+            }
+        };
+
+        let diff = compare(&name, &old, old_btf.as_ref(), &new_btf);
+        if !diff.is_empty() {
+            problems.push(format!("map '{}':\n{}", name, diff.join("\n")));
+            continue;
+        }
+
+        let new_type = new_btf.map_uint(&name, "type").map(map_type_name).unwrap_or_else(|| old.map_type.clone());
+        let plan = if NOT_CARRIED.contains(&new_type.as_str()) {
+            MapPlan::Fresh(format!("{} maps are not carried over", new_type))
+        } else if let Some(pin) = reusable_pin(&name, &old, &new_type, &new_btf, carry.pin_path.as_deref()) {
+            carry.pin_path = pin.parent().map(Path::to_path_buf);
+            MapPlan::ReusePinned(pin)
+        } else if old.is_percpu() || new_type.contains("percpu") {
+            // bpftool writes one value to every CPU, which would multiply per-CPU counters
+            MapPlan::Fresh("per-CPU maps keep state only when pinned by name".to_string())
+        } else {
+            MapPlan::Restore(old.id)
+        };
+        carry.maps.push((name, plan));
+    }
+
+    if !problems.is_empty() {
+        return Err(anyhow!(
+            "Map layouts changed incompatibly, refusing to carry state:\n{}",
+            problems.join("\n")
+        ));
+    }
+    Ok(carry)
+}
+
+/// Copy the old maps into the maps of the freshly loaded `new_object`, before its programs attach
+pub async fn restore_carry_over(new_object: &Path, carry: &MapCarryOver) -> Result<()> {
+    for (name, plan) in &carry.maps {
+        let MapPlan::Restore(old_id) = plan else { continue };
+        let entries = map_dump(&MapRef::Id(*old_id)).await?;
+        let target = MapRef::Id(find_newest_loaded_map(new_object, name).await?.id);
+        let updates: Vec<(&[u8], &[u8])> = entries
+            .iter()
+            .filter_map(|e| match &e.value {
+                MapValue::Single(value) => Some((e.key.as_slice(), value.as_slice())),
+                MapValue::PerCpu(_) => None,
+            })
+            .collect();
+        map_update_batch(&target, &updates, "any").await?;
+        success(&format!("Restored {} entries into map '{}'", updates.len(), name));
+        if updates.len() < entries.len() {
+            warn(&format!("Skipped {} per-CPU entries of map '{}'", entries.len() - updates.len(), name));
+        }
+    }
+    Ok(())
+}
+
+/// Differences that make the old map's bytes unusable by the new program, as diff lines
+pub(crate) fn compare(name: &str, old: &MapInfo, old_btf: Option<&Btf>, new_btf: &Btf) -> Vec<String> {
+    let mut diff = Vec::new();
+    let mut line = |what: &str, old: String, new: String| {
+        if old != new {
+            diff.push(format!("  - {}: {}\n  + {}: {}", what, old, what, new));
+        }
+    };
+
+    let Some((key, value)) = new_btf.map_types(name) else {
+        // Sizes given with __uint(key_size/value_size) instead of types
+        let size = |m: &str, fallback: u32| new_btf.map_uint(name, m).unwrap_or(fallback);
+        line("key size", old.bytes_key.to_string(), size("key_size", old.bytes_key).to_string());
+        line("value size", old.bytes_value.to_string(), size("value_size", old.bytes_value).to_string());
+        return diff;
+    };
+    let size = |id: TypeId| new_btf.size_of(id).unwrap_or(0).to_string();
+    line("key size", old.bytes_key.to_string(), size(key));
+    line("value size", old.bytes_value.to_string(), size(value));
+    if let Some(max) = new_btf.map_uint(name, "max_entries") {
+        if max < old.max_entries {
+            line("max_entries", old.max_entries.to_string(), max.to_string());
+        }
+    }
+
+    if let Some((btf, (old_key, old_value))) = old_btf.and_then(|b| Some((b, b.map_types(name)?))) {
+        diff.extend(layout_diff("key", &btf.layout(old_key), &new_btf.layout(key)));
+        diff.extend(layout_diff("value", &btf.layout(old_value), &new_btf.layout(value)));
+    }
+    diff
+}
+
+fn layout_diff(what: &str, old: &[String], new: &[String]) -> Vec<String> {
+    if old == new {
+        return Vec::new();
+    }
+    let mut out = vec![format!("  {} layout:", what)];
+    out.extend(old.iter().filter(|l| !new.contains(l)).map(|l| format!("    - {}", l)));
+    out.extend(new.iter().filter(|l| !old.contains(l)).map(|l| format!("    + {}", l)));
+    out
+}
+
+/// The old map's pin, if the new definition is pinned by name and would reopen it as is.
+/// The loader takes a single pin directory, so pins elsewhere fall back to restoring
+fn reusable_pin(name: &str, old: &MapInfo, new_type: &str, new_btf: &Btf, pin_dir: Option<&Path>) -> Option<PathBuf> {
+    if new_btf.map_uint(name, "pinning") != Some(LIBBPF_PIN_BY_NAME) || new_type != old.map_type {
+        return None;
+    }
+    if new_btf.map_uint(name, "max_entries").is_some_and(|max| max != old.max_entries) {
+        return None;
+    }
+    old.pinned
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.file_name().is_some_and(|f| f == name) && pin_dir.map_or(true, |d| p.parent() == Some(d)))
+}
+
+/// bpftool's spelling of a `BPF_MAP_TYPE_*` number
+pub(crate) fn map_type_name(t: u32) -> String {
+    const NAMES: &[&str] = &[
+        "unspec", "hash", "array", "prog_array", "perf_event_array", "percpu_hash", "percpu_array",
+        "stack_trace", "cgroup_array", "lru_hash", "lru_percpu_hash", "lpm_trie", "array_of_maps",
+        "hash_of_maps", "devmap", "sockmap", "cpumap", "xskmap", "sockhash", "cgroup_storage",
+        "reuseport_sockarray", "percpu_cgroup_storage", "queue", "stack", "sk_storage", "devmap_hash",
+        "struct_ops", "ringbuf", "inode_storage", "task_storage", "bloom_filter", "user_ringbuf",
+        "cgrp_storage",
+    ];
+    NAMES.get(t as usize).map(|n| n.to_string()).unwrap_or_else(|| format!("type {}", t))
+}
@@ -0,0 +1,273 @@
+use crate::commands::ebpf::map::{display, resolve_map, MapSelector, ResolvedMap};
+use crate::utils::bpftool::{map_dump, MapValue};
+use crate::utils::logger::info;
+use anyhow::{anyhow, Result};
+use clap::Args;
+use prettytable::{format, row, Cell, Row, Table};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tokio::signal;
+
+#[derive(Args, Debug)]
+pub struct MapWatchOptions {
+    #[command(flatten)]
+    pub target: MapSelector,
+
+    /// Map name inside the program
+    #[arg(short, long)]
+    pub map: Option<String>,
+
+    /// Time between reads, e.g. "1s" or "500ms"
+    #[arg(long, default_value = "1s")]
+    pub interval: String,
+
+    /// table: refresh a table in place, log: print changes as they happen
+    #[arg(long, default_value = "table", value_parser = ["table", "log"])]
+    pub view: String,
+
+    /// Rows shown in table view, busiest keys first
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+
+    /// Print raw bytes even when BTF types are available
+    #[arg(long)]
+    pub hex: bool,
+}
+
+/// Map contents at one point in time, per-CPU values already summed
+type Snapshot = BTreeMap<Vec<u8>, Value>;
+
+pub async fn handle_map_watch(opts: MapWatchOptions) -> Result<()> {
+    let interval = humantime::parse_duration(&opts.interval)
+        .map_err(|e| anyhow!("Invalid --interval '{}': {}", opts.interval, e))?;
+    if interval.is_zero() {
+        return Err(anyhow!("--interval must be greater than zero"));
+    }
+
+    let mut map = resolve_map(&opts.target, opts.map.as_deref()).await?;
+    if opts.hex {
+        map.types = None;
+    }
+    let table_view = opts.view == "table";
+
+    let mut prev = snapshot(&map).await?;
+    let mut prev_at = Instant::now();
+    if table_view {
+        render_table(&map, &prev, &Snapshot::new(), 0.0, opts.top, (0, 0, 0));
+    } else {
+        info(&format!(
+            "Watching '{}' ({}), {} entries, every {} (Ctrl+C to exit)",
+            map.name,
+            map.target,
+            prev.len(),
+            humantime::format_duration(interval)
+        ));
+    }
+
+    let ctrl_c = signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = &mut ctrl_c => break,
+        }
+
+        let current = snapshot(&map).await?;
+        let elapsed = prev_at.elapsed().as_secs_f64();
+        prev_at = Instant::now();
+
+        if table_view {
+            let counts = diff_counts(&prev, &current);
+            render_table(&map, &current, &prev, elapsed, opts.top, counts);
+        } else {
+            print_changes(&map, &prev, &current, elapsed);
+        }
+        prev = current;
+    }
+    Ok(())
+}
+
+async fn snapshot(map: &ResolvedMap) -> Result<Snapshot> {
+    Ok(map_dump(&map.target)
+        .await?
+        .into_iter()
+        .map(|e| {
+            let value = aggregate(map, &e.value);
+            (e.key, value)
+        })
+        .collect())
+}
+
+/// Render a value, summing per-CPU copies; untyped 1/2/4/8 byte values are read as counters
+pub fn aggregate(map: &ResolvedMap, value: &MapValue) -> Value {
+    let render = |bytes: &[u8]| -> Value {
+        if map.types.is_none() && matches!(bytes.len(), 1 | 2 | 4 | 8) {
+            let mut buf = [0u8; 8];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            return Value::from(u64::from_le_bytes(buf));
+        }
+        map.render_value(bytes)
+    };
+    match value {
+        MapValue::Single(v) => render(v),
+        MapValue::PerCpu(values) => values
+            .iter()
+            .map(|(_, v)| render(v))
+            .reduce(|a, b| sum_values(&a, &b))
+            .unwrap_or(Value::Null),
+    }
+}
+
+/// Add numbers leaf by leaf; non-numeric leaves keep the first value
+fn sum_values(a: &Value, b: &Value) -> Value {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => {
+            if let (Some(x), Some(y)) = (x.as_u64(), y.as_u64()) {
+                Value::from(x.wrapping_add(y))
+            } else if let (Some(x), Some(y)) = (x.as_i64(), y.as_i64()) {
+                Value::from(x.wrapping_add(y))
+            } else {
+                Value::from(x.as_f64().unwrap_or(0.0) + y.as_f64().unwrap_or(0.0))
+            }
+        }
+        (Value::Object(x), Value::Object(y)) => Value::Object(
+            x.iter()
+                .map(|(k, v)| (k.clone(), y.get(k).map(|w| sum_values(v, w)).unwrap_or_else(|| v.clone())))
+                .collect(),
+        ),
+        (Value::Array(x), Value::Array(y)) if x.len() == y.len() => {
+            Value::Array(x.iter().zip(y).map(|(v, w)| sum_values(v, w)).collect())
+        }
+        _ => a.clone(),
+    }
+}
+
+/// Change per second of every numeric leaf, or `None` if the value has no numbers
+fn rate(old: &Value, new: &Value, elapsed: f64) -> Option<Value> {
+    if elapsed <= 0.0 {
+        return None;
+    }
+    match (old, new) {
+        (Value::Number(x), Value::Number(y)) => {
+            let d = y.as_f64()? - x.as_f64()?;
+            Some(Value::from(d / elapsed))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            let fields: Map<String, Value> = y
+                .iter()
+                .filter_map(|(k, v)| Some((k.clone(), rate(x.get(k)?, v, elapsed)?)))
+                .collect();
+            if fields.is_empty() {
+                None
+            } else {
+                Some(Value::Object(fields))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Sum of absolute rates, used to rank keys by activity
+fn magnitude(rate: &Value) -> f64 {
+    match rate {
+        Value::Number(n) => n.as_f64().unwrap_or(0.0).abs(),
+        Value::Object(fields) => fields.values().map(magnitude).sum(),
+        _ => 0.0,
+    }
+}
+
+fn format_rate(rate: &Value) -> String {
+    match rate {
+        Value::Number(n) => format!("{:+.1}/s", n.as_f64().unwrap_or(0.0)),
+        Value::Object(fields) => fields
+            .iter()
+            .filter(|(_, v)| magnitude(v) > 0.0)
+            .map(|(k, v)| format!("{} {}", k, format_rate(v)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+/// (added, removed, changed) between two snapshots
+fn diff_counts(prev: &Snapshot, current: &Snapshot) -> (usize, usize, usize) {
+    let added = current.keys().filter(|k| !prev.contains_key(*k)).count();
+    let removed = prev.keys().filter(|k| !current.contains_key(*k)).count();
+    let changed = current
+        .iter()
+        .filter(|(k, v)| prev.get(*k).is_some_and(|old| old != *v))
+        .count();
+    (added, removed, changed)
+}
+
+fn print_changes(map: &ResolvedMap, prev: &Snapshot, current: &Snapshot, elapsed: f64) {
+    let now = chrono::Local::now().format("%H:%M:%S");
+    for (key, value) in current {
+        let k = display(&map.render_key(key));
+        match prev.get(key) {
+            None => println!("{} + {} => {}", now, k, display(value)),
+            Some(old) if old != value => {
+                let r = rate(old, value, elapsed).map(|r| format_rate(&r)).unwrap_or_default();
+                if r.is_empty() {
+                    println!("{} ~ {}: {} -> {}", now, k, display(old), display(value));
+                } else {
+                    println!("{} ~ {}: {} -> {} ({})", now, k, display(old), display(value), r);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, old) in prev {
+        if !current.contains_key(key) {
+            println!("{} - {} (was {})", now, display(&map.render_key(key)), display(old));
+        }
+    }
+}
+
+fn render_table(
+    map: &ResolvedMap,
+    current: &Snapshot,
+    prev: &Snapshot,
+    elapsed: f64,
+    top: usize,
+    (added, removed, changed): (usize, usize, usize),
+) {
+    let mut rows: Vec<(&Vec<u8>, &Value, Option<Value>)> = current
+        .iter()
+        .map(|(k, v)| (k, v, prev.get(k).and_then(|old| rate(old, v, elapsed))))
+        .collect();
+    rows.sort_by(|a, b| {
+        let (ra, rb) = (a.2.as_ref().map(magnitude).unwrap_or(0.0), b.2.as_ref().map(magnitude).unwrap_or(0.0));
+        rb.partial_cmp(&ra).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(b.0))
+    });
+
+    print!("\x1B[2J\x1B[1;1H");
+    println!(
+        "Map '{}' ({}) at {} - {} entries, +{} -{} ~{} (Ctrl+C to exit)\n",
+        map.name,
+        map.target,
+        chrono::Local::now().format("%H:%M:%S"),
+        current.len(),
+        added,
+        removed,
+        changed
+    );
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Key", "Value", "Rate"]);
+    for (key, value, rate) in rows.iter().take(top) {
+        table.add_row(Row::new(vec![
+            Cell::new(&display(&map.render_key(key))),
+            Cell::new(&display(value)),
+            Cell::new(&rate.as_ref().map(format_rate).unwrap_or_default()),
+        ]));
+    }
+    table.printstd();
+    if rows.len() > top {
+        println!("... {} more keys", rows.len() - top);
+    }
+}
@@ -3,4 +3,18 @@ pub mod load;
 pub mod unload;
 pub mod upload;
 pub mod inspect;
-pub mod remove; 
\ No newline at end of file
+pub mod remove; 
+pub mod map;
+pub mod map_watch;
+pub mod map_init;
+pub mod map_state;
+pub mod upgrade;
+pub mod hook;
+pub mod globals;
+pub mod apply;
+pub mod autostart;
+pub mod sweep;
+pub mod tail_calls;
+pub mod shared_maps;
+pub mod verify;
+pub mod compat;
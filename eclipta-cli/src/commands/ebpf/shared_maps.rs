@@ -0,0 +1,214 @@
+use crate::commands::ebpf::hook::sanitize;
+use crate::commands::ebpf::map::find_newest_loaded_map;
+use crate::commands::ebpf::map_state::{compare, map_type_name, LIBBPF_PIN_BY_NAME};
+use crate::db::programs::get_program_by_title;
+use crate::utils::bpftool::{map_pin, map_show, MapInfo, MapRef};
+use crate::utils::btf::Btf;
+use crate::utils::db::DbPool;
+use crate::utils::logger::{info, success, warn};
+use crate::utils::paths::default_pin_prefix;
+use crate::utils::state::{SharedMapRecord, State};
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// One `--share-map <name>=<pinned path|program title>` request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareMap {
+    pub map: String,
+    /// An absolute bpffs path, or the registry title of a loaded program
+    pub source: String,
+}
+
+impl ShareMap {
+    pub fn parse(spec: &str) -> Result<ShareMap> {
+        match spec.split_once('=') {
+            Some((map, source)) if !map.is_empty() && !source.is_empty() => {
+                Ok(ShareMap { map: map.to_string(), source: source.to_string() })
+            }
+            _ => Err(anyhow!("Invalid --share-map '{}', expected <map>=<pinned path or program title>", spec)),
+        }
+    }
+}
+
+/// A shared map resolved to the kernel map it refers to
+#[derive(Debug)]
+pub struct SharedMap {
+    pub share: ShareMap,
+    pub info: MapInfo,
+    /// Object of the source program, or the pin the map was named by
+    pub owner: PathBuf,
+}
+
+/// Maps the loader reopens instead of creating
+#[derive(Debug, Default)]
+pub struct SharePlan {
+    /// Directory holding a pin of every shared map, under the map's name, for the loader
+    pub pin_path: Option<PathBuf>,
+    pub maps: Vec<SharedMap>,
+}
+
+/// Resolve every share of `object` and check its definition can open the existing map.
+/// The loader only reopens maps declared with `LIBBPF_PIN_BY_NAME`, so others are refused
+pub async fn plan_shares(pool: &DbPool, object: &Path, specs: &[String]) -> Result<SharePlan> {
+    if specs.is_empty() {
+        return Ok(SharePlan::default());
+    }
+    let btf = Btf::from_elf(object)?
+        .ok_or_else(|| anyhow!("{} has no BTF; rebuild it with -g to share maps", object.display()))?;
+
+    let mut plan = SharePlan::default();
+    let mut problems = Vec::new();
+    for spec in specs {
+        let share = ShareMap::parse(spec)?;
+        if !btf.map_names().contains(&share.map) {
+            return Err(anyhow!("{} has no map named '{}'", object.display(), share.map));
+        }
+        if btf.map_uint(&share.map, "pinning") != Some(LIBBPF_PIN_BY_NAME) {
+            return Err(anyhow!(
+                "Map '{}' must be declared with __uint(pinning, LIBBPF_PIN_BY_NAME) to be shared",
+                share.map
+            ));
+        }
+        let (info, owner) = resolve_source(pool, object, &share).await?;
+
+        let mut diff = compare(&share.map, &info, None, &btf);
+        if let Some(new_type) = btf.map_uint(&share.map, "type").map(map_type_name) {
+            if new_type != info.map_type {
+                diff.push(format!("  - type: {}\n  + type: {}", info.map_type, new_type));
+            }
+        }
+        if !diff.is_empty() {
+            problems.push(format!("map '{}':\n{}", share.map, diff.join("\n")));
+            continue;
+        }
+        plan.maps.push(SharedMap { share, info, owner });
+    }
+    if !problems.is_empty() {
+        return Err(anyhow!("Shared maps don't match this object's definitions:\n{}", problems.join("\n")));
+    }
+    plan.pin_path = Some(staging_dir(object));
+    Ok(plan)
+}
+
+async fn resolve_source(pool: &DbPool, object: &Path, share: &ShareMap) -> Result<(MapInfo, PathBuf)> {
+    if share.source.starts_with('/') {
+        let pin = PathBuf::from(&share.source);
+        let info = map_show(&MapRef::Pinned(pin.clone())).await
+            .with_context(|| format!("No map is pinned at {}", pin.display()))?;
+        return Ok((info, pin));
+    }
+
+    // Any loaded version of the title will do; the newest map wins
+    let programs = get_program_by_title(pool, &share.source).await
+        .context("Failed to fetch programs from database")?;
+    if programs.is_empty() {
+        return Err(anyhow!("No program found with title '{}'", share.source));
+    }
+    let mut found: Option<(MapInfo, PathBuf)> = None;
+    for p in programs {
+        let path = PathBuf::from(&p.path);
+        if path == object {
+            continue;
+        }
+        if let Ok(info) = find_newest_loaded_map(&path, &share.map).await {
+            if found.as_ref().map_or(true, |(f, _)| info.id > f.id) {
+                found = Some((info, path));
+            }
+        }
+    }
+    found.ok_or_else(|| anyhow!("No loaded version of '{}' has a map named '{}'", share.source, share.map))
+}
+
+/// Per-object directory of share pins, so two objects never see each other's names. The
+/// loader also pins the object's other LIBBPF_PIN_BY_NAME maps here
+fn staging_dir(object: &Path) -> PathBuf {
+    let stem = object.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    default_pin_prefix().join("shared").join(sanitize(&stem))
+}
+
+impl SharePlan {
+    /// Pin every shared map into the staging directory under its own name
+    pub async fn stage(&self) -> Result<()> {
+        let Some(dir) = &self.pin_path else { return Ok(()) };
+        // Pins of an earlier load would make the loader reopen its maps instead of creating them
+        if dir.exists() {
+            std::fs::remove_dir_all(dir).with_context(|| format!("Failed to clear {}", dir.display()))?;
+        }
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        for m in &self.maps {
+            let pin = dir.join(&m.share.map);
+            map_pin(&MapRef::Id(m.info.id), &pin).await?;
+            info(&format!("Sharing map '{}' (id {}) from {}", m.share.map, m.info.id, m.share.source));
+        }
+        Ok(())
+    }
+
+    /// Drop the staging directory of a load that failed
+    pub fn unstage(&self) {
+        if let Some(dir) = &self.pin_path {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+
+    /// Check the loaded object really opened the shared maps. Runs before anything is
+    /// attached, so a program never starts with a private copy of a shared map
+    pub async fn confirm(&self, object: &Path) -> Result<()> {
+        for m in &self.maps {
+            let loaded = find_newest_loaded_map(object, &m.share.map).await?;
+            if loaded.id != m.info.id {
+                return Err(anyhow!(
+                    "Map '{}' was created anew (id {}) instead of reusing map {}",
+                    m.share.map, loaded.id, m.info.id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record who shares what once the object is attached
+    pub fn record(&self, object: &Path, st: &mut State) {
+        let Some(dir) = &self.pin_path else { return };
+        for m in &self.maps {
+            st.shared_maps.retain(|r| !(r.user == object && r.map == m.share.map));
+            st.shared_maps.push(SharedMapRecord {
+                map: m.share.map.clone(),
+                map_id: m.info.id,
+                source: m.share.source.clone(),
+                owner: m.owner.clone(),
+                user: object.to_path_buf(),
+                pin: dir.join(&m.share.map),
+            });
+            success(&format!("Map '{}' is shared with {}", m.share.map, m.share.source));
+        }
+    }
+}
+
+/// Forget the maps `object` borrowed and say which of its own maps stay alive for other
+/// programs. With `unpin` its staging directory goes too, including the pins the loader
+/// made there for its other maps, except pins another object names as a share source
+pub fn release_shares(st: &mut State, object: &Path, unpin: bool) {
+    let (released, kept): (Vec<_>, Vec<_>) = st.shared_maps.drain(..).partition(|r| r.user == object);
+    st.shared_maps = kept;
+    for r in &released {
+        info(&format!("Released shared map '{}' from {}", r.map, r.source));
+    }
+    if unpin {
+        let in_use = pins_in_use(st);
+        let dir = staging_dir(object);
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            if !in_use.contains(&entry.path()) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+        // Only succeeds once the directory is empty
+        let _ = std::fs::remove_dir(&dir);
+    }
+    for r in st.shared_maps.iter().filter(|r| r.owner == object) {
+        warn(&format!("Map '{}' stays in use by {}", r.map, r.user.display()));
+    }
+}
+
+/// Pins another loaded object still opens its shared maps through
+pub fn pins_in_use(st: &State) -> Vec<PathBuf> {
+    st.shared_maps.iter().flat_map(|r| [r.pin.clone(), r.owner.clone()]).collect()
+}
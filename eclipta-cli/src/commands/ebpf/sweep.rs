@@ -0,0 +1,183 @@
+use crate::commands::ebpf::load::{handle_load, LoadOptions};
+use crate::commands::ebpf::unload::{handle_unload, UnloadOptions};
+use crate::utils::bpftool::{loaded_programs_for, object_program_names};
+use crate::utils::logger::{error, info, success, warn};
+use crate::utils::paths::default_state_path;
+use crate::utils::schedule::ActiveWindow;
+use crate::utils::state::{load_state, save_state, LeaseRecord};
+use crate::utils::systemd::{install_units, oneshot_service};
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, Utc};
+use clap::Args;
+use std::path::PathBuf;
+
+const SERVICE_NAME: &str = "eclipta-sweep.service";
+const TIMER_NAME: &str = "eclipta-sweep.timer";
+
+#[derive(Args, Debug)]
+pub struct SweepOptions {
+    /// Keep sweeping every --interval instead of once
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Time between sweeps with --watch
+    #[arg(long, default_value = "30s")]
+    pub interval: String,
+
+    /// Write a systemd service and timer that sweep every minute
+    #[arg(long)]
+    pub install_timer: bool,
+
+    /// Directory the units are written to
+    #[arg(long, default_value = "/etc/systemd/system", requires = "install_timer")]
+    pub unit_dir: PathBuf,
+
+    /// Print the units instead of writing them
+    #[arg(long, requires = "install_timer")]
+    pub print: bool,
+
+    /// Reload systemd and start the timer after writing it
+    #[arg(long, requires = "install_timer")]
+    pub enable: bool,
+}
+
+/// Enforce the leases recorded by `load --ttl` / `--active-window`: unload expired
+/// programs, and unload or reload windowed ones as their windows close and open
+pub async fn handle_sweep(opts: SweepOptions) -> Result<()> {
+    if opts.install_timer {
+        return install_timer(&opts).await;
+    }
+    if !opts.watch {
+        return match sweep_once().await? {
+            0 => Ok(()),
+            n => Err(anyhow!("{} lease(s) could not be enforced", n)),
+        };
+    }
+
+    let interval = humantime::parse_duration(&opts.interval)
+        .map_err(|e| anyhow!("Invalid --interval '{}': {}", opts.interval, e))?;
+    info(&format!("Sweeping every {}... Press Ctrl+C to stop", humantime::format_duration(interval)));
+    loop {
+        // A failed lease is retried on the next sweep
+        if let Err(e) = sweep_once().await {
+            error(&format!("Sweep failed: {:#}", e));
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// One pass over the leases; returns how many failed
+async fn sweep_once() -> Result<usize> {
+    let path = default_state_path();
+    let leases = load_state(&path).leases;
+    let now = Utc::now().timestamp();
+
+    let mut kept = Vec::new();
+    let mut failed = 0;
+    for lease in &leases {
+        match enforce(lease.clone(), now).await {
+            Ok(next) => kept.extend(next),
+            Err(e) => {
+                error(&format!("{}: {:#}", lease.name, e));
+                kept.push(lease.clone());
+                failed += 1;
+            }
+        }
+    }
+
+    // Unloading rewrites the state file, and `load` may have added leases meanwhile
+    let mut st = load_state(&path);
+    st.leases.retain(|l| !leases.iter().any(|old| old.program == l.program));
+    st.leases.extend(kept);
+    save_state(&path, st).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(failed)
+}
+
+/// The lease after this sweep, or None once it is done
+async fn enforce(mut lease: LeaseRecord, now: i64) -> Result<Option<LeaseRecord>> {
+    if lease.remaining(now) == Some(0) {
+        if !lease.suspended {
+            unload(&lease).await?;
+        }
+        success(&format!("'{}' expired and was unloaded", lease.name));
+        return Ok(None);
+    }
+    let Some(spec) = lease.window.as_deref() else { return Ok(Some(lease)) };
+
+    let window = ActiveWindow::parse(spec)?;
+    let open = window.contains(Local::now());
+    if open && lease.suspended {
+        load(&lease).await?;
+        lease.suspended = false;
+        success(&format!("'{}' loaded, its window '{}' opened", lease.name, window));
+    } else if !open && !lease.suspended {
+        unload(&lease).await?;
+        lease.suspended = true;
+        success(&format!("'{}' unloaded, its window '{}' closed", lease.name, window));
+    }
+    Ok(Some(lease))
+}
+
+async fn unload(lease: &LeaseRecord) -> Result<()> {
+    let name = object_program_names(&lease.program).ok().and_then(|n| n.into_iter().next());
+    if name.is_none() {
+        warn(&format!("No program names found in {}", lease.program.display()));
+    }
+    let unloaded = handle_unload(UnloadOptions {
+        program: Some(lease.program.clone()),
+        id: None,
+        title: None,
+        name,
+        iface: lease.iface.clone(),
+        socket_fd: None,
+        tracepoint: None,
+        state_file: None,
+        unpin: true,
+        json: false,
+        verbose: false,
+    })
+    .await;
+    match unloaded {
+        // Unloaded by hand or by a reboot: the lease has nothing left to enforce
+        Err(e) if loaded_programs_for(&lease.program).await.is_ok_and(|p| p.is_empty()) => {
+            warn(&format!("'{}' is already gone ({:#}); releasing its lease", lease.name, e));
+            Ok(())
+        }
+        other => other,
+    }
+}
+
+async fn load(lease: &LeaseRecord) -> Result<()> {
+    handle_load(LoadOptions {
+        program: Some(lease.program.clone()),
+        id: None,
+        title: None,
+        iface: lease.iface.clone(),
+        socket_fd: None,
+        map_init: lease.map_init.clone(),
+        reuse_maps_from: None,
+        tail_calls: lease.tail_calls.clone(),
+        share_maps: lease.share_maps.clone(),
+        ttl: None,
+        active_window: None,
+    })
+    .await
+}
+
+async fn install_timer(opts: &SweepOptions) -> Result<()> {
+    let service = oneshot_service("Enforce eclipta program TTLs and active windows", "sweep", false, None)?;
+    let timer = format!(
+        "[Unit]\n\
+         Description=Run {} every minute\n\
+         \n\
+         [Timer]\n\
+         OnBootSec=30s\n\
+         OnUnitActiveSec=1min\n\
+         AccuracySec=5s\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        SERVICE_NAME
+    );
+    install_units(&opts.unit_dir, &[(SERVICE_NAME, service), (TIMER_NAME, timer)], opts.print, opts.enable.then_some(TIMER_NAME)).await
+}
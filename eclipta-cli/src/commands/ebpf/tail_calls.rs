@@ -0,0 +1,145 @@
+use crate::commands::ebpf::map::find_newest_loaded_map;
+use crate::utils::bpftool::{
+    kernel_name, loaded_programs_for, map_dump, map_list, map_pin, object_program_names, prog_array_set, prog_show,
+    MapRef, MapValue,
+};
+use crate::utils::logger::{info, success};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::path::Path;
+
+/// Programs named `tail__<map>__<index>` are wired into that slot without a `--tail-call`
+const CONVENTION_PREFIX: &str = "tail__";
+
+/// One `--tail-call <map>[<index>]=<program>` request
+#[derive(Debug, Clone, PartialEq)]
+pub struct TailCall {
+    pub map: String,
+    pub index: u32,
+    pub program: String,
+}
+
+impl TailCall {
+    pub fn parse(spec: &str) -> Result<TailCall> {
+        let invalid = || anyhow!("Invalid --tail-call '{}', expected <map>[<index>]=<program>", spec);
+        let (slot, program) = spec.split_once('=').ok_or_else(invalid)?;
+        let (map, index) = slot.strip_suffix(']').and_then(|s| s.split_once('[')).ok_or_else(invalid)?;
+        let index = index.trim().parse().map_err(|_| invalid())?;
+        if map.is_empty() || program.is_empty() {
+            return Err(invalid());
+        }
+        Ok(TailCall { map: map.to_string(), index, program: program.to_string() })
+    }
+}
+
+/// The tail calls to wire for an object: explicit specs first, then programs following the
+/// `tail__<map>__<index>` naming convention for slots no spec fills
+pub fn tail_calls_for(object: &Path, specs: &[String]) -> Result<Vec<TailCall>> {
+    let mut calls = specs.iter().map(|s| TailCall::parse(s)).collect::<Result<Vec<_>>>()?;
+    for name in object_program_names(object)? {
+        let Some((map, index)) = name.strip_prefix(CONVENTION_PREFIX).and_then(|r| r.rsplit_once("__")) else {
+            continue;
+        };
+        let Ok(index) = index.parse() else { continue };
+        if !calls.iter().any(|c| c.map == map && c.index == index) {
+            calls.push(TailCall { map: map.to_string(), index, program: name.clone() });
+        }
+    }
+    Ok(calls)
+}
+
+/// Insert the loaded target programs into the prog arrays of `object`. Each array is pinned
+/// under `<pin_dir>/tail_calls/`, since the kernel empties a prog array once no process or pin
+/// holds it; unloading the caller removes `pin_dir` and with it the targets
+pub async fn wire_tail_calls(object: &Path, calls: &[TailCall], pin_dir: &Path) -> Result<()> {
+    if calls.is_empty() {
+        return Ok(());
+    }
+    let mut progs = loaded_programs_for(object).await?;
+    progs.sort_by_key(|p| Reverse(p.id));
+
+    let mut pinned = Vec::new();
+    for call in calls {
+        let map = find_newest_loaded_map(object, &call.map).await?;
+        if map.map_type != "prog_array" {
+            return Err(anyhow!("Map '{}' is a {} map, not a prog_array", call.map, map.map_type));
+        }
+        if call.index >= map.max_entries {
+            return Err(anyhow!("Slot {} is outside '{}', which has {} entries", call.index, call.map, map.max_entries));
+        }
+        let target = progs.iter()
+            .find(|p| p.name == kernel_name(&call.program))
+            .ok_or_else(|| anyhow!("Tail call target '{}' is not loaded", call.program))?;
+        prog_array_set(&MapRef::Id(map.id), call.index, target.id).await?;
+        success(&format!("Tail call {}[{}] -> {} (prog {})", call.map, call.index, call.program, target.id));
+
+        if map.pinned.is_empty() && !pinned.contains(&map.id) {
+            let dir = pin_dir.join("tail_calls");
+            std::fs::create_dir_all(&dir)?;
+            let path = dir.join(&call.map);
+            // A pin left by an earlier load would keep the old array alive instead
+            let _ = std::fs::remove_file(&path);
+            map_pin(&MapRef::Id(map.id), &path).await?;
+            info(&format!("Pinned '{}' at {} to keep its entries", call.map, path.display()));
+            pinned.push(map.id);
+        }
+    }
+    Ok(())
+}
+
+/// One filled slot of a prog array used by a loaded program
+#[derive(Debug, Clone, Serialize)]
+pub struct TailCallEdge {
+    /// Programs of the object referencing the array
+    pub callers: Vec<String>,
+    pub map: String,
+    pub map_id: u32,
+    pub index: u32,
+    pub target_id: u32,
+    pub target: String,
+}
+
+/// The live tail-call graph of the newest loaded instance of `object`
+pub async fn tail_call_graph(object: &Path) -> Result<Vec<TailCallEdge>> {
+    let mut progs = loaded_programs_for(object).await?;
+    progs.sort_by_key(|p| Reverse(p.id));
+    let mut seen = Vec::new();
+    progs.retain(|p| {
+        let first = !seen.contains(&p.name);
+        seen.push(p.name.clone());
+        first
+    });
+
+    let mut edges = Vec::new();
+    for map in map_list().await? {
+        if map.map_type != "prog_array" {
+            continue;
+        }
+        let callers: Vec<String> = progs.iter()
+            .filter(|p| p.map_ids.contains(&map.id))
+            .map(|p| p.name.clone())
+            .collect();
+        if callers.is_empty() {
+            continue;
+        }
+        for entry in map_dump(&MapRef::Id(map.id)).await? {
+            let (Ok(key), MapValue::Single(value)) = (<[u8; 4]>::try_from(entry.key.as_slice()), &entry.value) else {
+                continue;
+            };
+            let Ok(value) = <[u8; 4]>::try_from(value.as_slice()) else { continue };
+            let target_id = u32::from_ne_bytes(value);
+            let target = prog_show(target_id).await.map(|p| p.name).unwrap_or_else(|_| "?".to_string());
+            edges.push(TailCallEdge {
+                callers: callers.clone(),
+                map: map.name.clone(),
+                map_id: map.id,
+                index: u32::from_ne_bytes(key),
+                target_id,
+                target,
+            });
+        }
+    }
+    edges.sort_by(|a, b| (&a.map, a.index).cmp(&(&b.map, b.index)));
+    Ok(edges)
+}
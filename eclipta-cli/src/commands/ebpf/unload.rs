@@ -1,6 +1,7 @@
 use crate::utils::logger::{success, info};
 use crate::utils::paths::default_state_path;
-use crate::utils::state::{load_state, save_state};
+use crate::commands::ebpf::shared_maps::{pins_in_use, release_shares};
+use crate::utils::state::{load_state, save_state, AttachmentRecord};
 use crate::db::programs::{get_program_by_id, get_program_by_title};
 use crate::utils::db::ensure_db_ready;
 use object::{Object, ObjectSection};
@@ -136,23 +137,38 @@ pub async fn handle_unload(opts: UnloadOptions) -> Result<()> {
         info(&format!("Attempting to unload program: {}", program_name)); 
     }
 
+    let state_file = opts.state_file.as_ref().cloned().unwrap_or_else(default_state_path);
+    let matches = |r: &AttachmentRecord| r.name == program_name || r.object.as_ref() == Some(&program_path);
+    // A pinned link holds its attachment, so dropping the pin is what detaches the program
+    for rec in load_state(&state_file).attachments.iter().filter(|r| matches(r)) {
+        if let Some(dir) = &rec.pin_dir {
+            let _ = std::fs::remove_dir_all(dir.join("links"));
+        }
+    }
+
     println!("Detaching eBPF program from kernel...");
     let detach_result = detach_program_from_kernel(&program_path, &requirements, &opts).await?;
 
     println!("Verifying kernel program detachment...");
     verify_kernel_detachment(&requirements, &opts).await?;
 
-    // Update state: remove records matching name
-    let state_file = opts.state_file.as_ref().cloned().unwrap_or_else(default_state_path);
+    // Update state: remove records matching name or object
     let mut st = load_state(&state_file);
-    let removed: Vec<_> = st.attachments.iter().filter(|r| r.name == program_name).cloned().collect();
-    st.attachments.retain(|r| r.name != program_name);
+    let removed: Vec<_> = st.attachments.iter().filter(|r| matches(r)).cloned().collect();
+    st.attachments.retain(|r| !matches(r));
+    release_shares(&mut st, &program_path, opts.unpin);
+    let in_use = pins_in_use(&st);
     let _ = save_state(&state_file, st);
 
+    // What `load` pinned only kept this program loaded
+    for dir in removed.iter().filter_map(|r| r.pin_dir.as_ref()) {
+        let _ = std::fs::remove_dir_all(dir);
+    }
     if opts.unpin {
         for rec in removed {
             if let Some(pp) = rec.pinned_prog { let _ = std::fs::remove_file(pp); }
-            for m in rec.pinned_maps { let _ = std::fs::remove_file(m); }
+            // Another program may still open a shared map through its pin
+            for m in rec.pinned_maps.into_iter().filter(|m| !in_use.contains(m)) { let _ = std::fs::remove_file(m); }
         }
     }
 
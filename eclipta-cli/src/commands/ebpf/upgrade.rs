@@ -0,0 +1,294 @@
+use crate::commands::ebpf::globals::{set_globals, GlobalSpec};
+use crate::commands::ebpf::hook::{
+    adopt_link, attach, attachments, detach, detect_hook, load_pinned, maps_dir, new_version_dir, pin_root,
+    pinned_versions, AttachMode, Attached, Hook,
+};
+use crate::commands::ebpf::map_init::{populate_maps, read_specs, MapInit};
+use crate::commands::ebpf::map_state::{plan_carry_over, restore_carry_over, MapCarryOver, MapPlan};
+use crate::commands::system::probe::require_object_caps;
+use crate::db::programs::{get_program_by_title, set_program_status, Program};
+use crate::utils::bpf_stats::RuntimeStats;
+use crate::utils::bpftool::{kernel_name, object_program_names, prog_pin, prog_show, ProgInfo};
+use crate::utils::db::{ensure_db_ready, DbPool};
+use crate::utils::logger::{info, success, warn};
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often the health check looks at the new program
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(Args, Debug, Clone)]
+pub struct UpgradeOptions {
+    /// Registered program title
+    #[arg(long)]
+    pub title: String,
+
+    /// Registered version to switch to
+    #[arg(long = "to-version")]
+    pub to_version: String,
+
+    /// Registered version being replaced (default: the active one, else the newest other version)
+    #[arg(long = "from-version")]
+    pub from_version: Option<String>,
+
+    /// Allow --from-version to equal --to-version, reloading the running version with new
+    /// --map-init or --global settings
+    #[arg(long)]
+    pub reload: bool,
+
+    /// Interface of XDP and TC programs
+    #[arg(long)]
+    pub iface: Option<String>,
+
+    /// cgroup v2 directory of cgroup programs
+    #[arg(long)]
+    pub cgroup: Option<PathBuf>,
+
+    /// How long the new version must stay attached and running before the old one is released
+    #[arg(long, default_value = "10s")]
+    pub health_window: String,
+
+    /// Don't require the new program to run during the window, for hooks without traffic
+    #[arg(long)]
+    pub allow_idle: bool,
+
+    /// Carry map state over from the old version, as `load --reuse-maps-from` does
+    #[arg(long)]
+    pub keep_maps: bool,
+
+    /// Fill a map before the swap, e.g. "blocked_ips=ips.csv" (repeatable)
+    #[arg(long = "map-init", value_name = "MAP=FILE")]
+    pub map_init: Vec<String>,
+
+    /// Set a non-const global variable before the swap, e.g. "debug=1" (repeatable)
+    #[arg(long = "global", value_name = "NAME=VALUE")]
+    pub globals: Vec<String>,
+}
+
+/// Everything written into the new version between loading and attaching it
+struct Prepare {
+    carry: Option<MapCarryOver>,
+    inits: Vec<MapInit>,
+    globals: Vec<GlobalSpec>,
+}
+
+/// Load `--to-version` next to the running version, swap it in on the same hook, watch it
+/// for the health window and put the old program back if it misbehaves
+pub async fn handle_upgrade(opts: UpgradeOptions) -> Result<()> {
+    let window = humantime::parse_duration(&opts.health_window)
+        .map_err(|e| anyhow!("Invalid --health-window '{}': {}", opts.health_window, e))?;
+    let pool = ensure_db_ready().await
+        .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
+
+    let versions = get_program_by_title(&pool, &opts.title).await
+        .context("Failed to fetch programs from database")?;
+    let find = |v: &str| {
+        versions.iter().find(|p| p.version == v).cloned()
+            .ok_or_else(|| anyhow!("No version '{}' of '{}' is registered", v, opts.title))
+    };
+    let new = find(&opts.to_version)?;
+    require_object_caps(&format!("upgrade '{}'", opts.title), Path::new(&new.path))?;
+    let old = match &opts.from_version {
+        Some(v) if *v == opts.to_version && !opts.reload => {
+            return Err(anyhow!("--from-version and --to-version are the same (pass --reload to reload it with new settings)"))
+        }
+        Some(v) => Some(find(v)?),
+        None => versions.iter().find(|p| p.id != new.id && p.status == "active")
+            .or_else(|| versions.iter().find(|p| p.id != new.id))
+            .cloned(),
+    };
+
+    let new_path = PathBuf::from(&new.path);
+    let hook = detect_hook(&new_path, opts.iface.as_deref(), opts.cgroup.as_deref())?;
+    let current = running_program(&hook, &new, old.as_ref()).await?;
+
+    let carry = match (opts.keep_maps, &old) {
+        (false, _) => None,
+        (true, None) => return Err(anyhow!("--keep-maps needs a previous version of '{}'", opts.title)),
+        (true, Some(o)) => {
+            let carry = plan_carry_over(Path::new(&o.path), &new_path).await?;
+            carry.report(&format!("v{}", o.version));
+            Some(carry)
+        }
+    };
+    let prepare = Prepare {
+        carry,
+        inits: read_specs(&opts.map_init)?,
+        globals: opts.globals.iter().map(|g| GlobalSpec::parse(g)).collect::<Result<_>>()?,
+    };
+
+    let root = pin_root(&opts.title);
+    std::fs::create_dir_all(&root)
+        .with_context(|| format!("Failed to create {} (is bpffs mounted at /sys/fs/bpf?)", root.display()))?;
+    let rollback_pin = root.join("rollback");
+    let _ = std::fs::remove_file(&rollback_pin);
+    match &current {
+        Some(cur) => {
+            prog_pin(cur.prog_id, &rollback_pin).await
+                .context("Failed to pin the running program for rollback")?;
+            info(&format!("Running program {} ({}) kept at {} for rollback", cur.prog_id, cur.name, rollback_pin.display()));
+        }
+        None => warn(&format!("Nothing from '{}' is attached to {}; a rollback leaves the hook empty", opts.title, hook)),
+    }
+    // A failed reload must not mark the version that keeps running as failed
+    let failed_status = if old.as_ref().is_some_and(|o| o.id == new.id) { "active" } else { "failed" };
+
+    let version_dir = new_version_dir(&opts.title, &new.version);
+    let swapped = match load_new(&new_path, &version_dir, &hook, &prepare).await {
+        Ok((pin, prog)) => attach(&hook, &pin, current.as_ref()).await.map(|_| prog),
+        Err(e) => Err(e),
+    };
+    let new_info = match swapped {
+        Ok(prog) => prog,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&version_dir);
+            let _ = std::fs::remove_file(&rollback_pin);
+            set_program_status(&pool, new.id, failed_status).await?;
+            return Err(e.context(format!("Upgrade to v{} failed before the swap; the running program is untouched", new.version)));
+        }
+    };
+
+    // From here on the new program has replaced the old one, so every failure rolls back
+    let found = attachments(&hook).await.ok()
+        .and_then(|attached| attached.into_iter().find(|a| a.prog_id == new_info.id));
+    let new_attached = found.clone().unwrap_or_else(|| Attached {
+        prog_id: new_info.id,
+        name: new_info.name.clone(),
+        mode: current.as_ref().map(|c| c.mode.clone()).unwrap_or_else(|| AttachMode::default_for(&hook)),
+    });
+    let healthy = match found {
+        Some(_) => {
+            success(&format!("v{} (prog {}) now attached to {}", new.version, new_info.id, hook));
+            info(&format!("Health check for {}...", humantime::format_duration(window)));
+            health_check(&hook, new_info.id, window, opts.allow_idle).await
+        }
+        None => Err(anyhow!("program {} did not show up on {} after attaching", new_info.id, hook)),
+    };
+    if let Err(reason) = healthy {
+        warn(&format!("Health check failed: {}", reason));
+        rollback(&hook, &rollback_pin, current.is_some(), &new_attached).await?;
+        let _ = std::fs::remove_dir_all(&version_dir);
+        let _ = std::fs::remove_file(&rollback_pin);
+        set_program_status(&pool, new.id, failed_status).await?;
+        return Err(anyhow!("Upgrade of '{}' to v{} rolled back: {}", opts.title, new.version, reason));
+    }
+
+    let _ = std::fs::remove_file(&rollback_pin);
+    if let Some(cur) = &current {
+        if let Err(e) = adopt_link(cur, &version_dir) {
+            warn(&format!("{:#}; releasing the old version's pins would detach v{}", e, new.version));
+        }
+    }
+    finish_registry(&pool, &opts.title, &new, old.as_ref(), current.as_ref()).await?;
+    success(&format!("'{}' is running v{}", opts.title, new.version));
+    Ok(())
+}
+
+/// Load the new object into bpffs and fill its maps and globals, ready to swap onto the hook
+async fn load_new(object: &Path, version_dir: &Path, hook: &Hook, prepare: &Prepare) -> Result<(PathBuf, ProgInfo)> {
+    let reuse: Vec<(String, PathBuf)> = prepare.carry.iter()
+        .flat_map(|c| c.maps.iter())
+        .filter_map(|(name, plan)| match plan {
+            MapPlan::ReusePinned(pin) => Some((name.clone(), pin.clone())),
+            _ => None,
+        })
+        .collect();
+    let (pin, prog) = load_pinned(object, version_dir, hook, &reuse).await?;
+    if let Some(carry) = &prepare.carry {
+        restore_carry_over(object, carry).await?;
+    }
+    populate_maps(object, &prepare.inits).await?;
+    set_globals(object, &maps_dir(version_dir), &prepare.globals).await?;
+    Ok((pin, prog))
+}
+
+async fn rollback(hook: &Hook, rollback_pin: &Path, had_previous: bool, new: &Attached) -> Result<()> {
+    if had_previous {
+        attach(hook, rollback_pin, Some(new)).await
+            .context("Rollback failed; the new version is still attached")?;
+        warn(&format!("Previous program is back on {}", hook));
+    } else {
+        detach(hook, new).await?;
+        warn(&format!("New program detached from {}", hook));
+    }
+    Ok(())
+}
+
+async fn finish_registry(pool: &DbPool, title: &str, new: &Program, old: Option<&Program>, replaced: Option<&Attached>) -> Result<()> {
+    // Pins of the replaced program would keep it loaded forever
+    if let Some(replaced) = replaced {
+        for v in pinned_versions(title).await {
+            if v.prog_ids.contains(&replaced.prog_id) {
+                let _ = std::fs::remove_dir_all(&v.dir);
+            }
+        }
+    }
+    if let Some(old) = old.filter(|o| o.id != new.id) {
+        set_program_status(pool, old.id, "deactive").await?;
+    }
+    set_program_status(pool, new.id, "active").await?;
+    Ok(())
+}
+
+/// What currently occupies the hook. Only a program of this title may be replaced
+async fn running_program(hook: &Hook, new: &Program, old: Option<&Program>) -> Result<Option<Attached>> {
+    let attached = attachments(hook).await?;
+    let mut names = object_program_names(Path::new(&new.path)).unwrap_or_default();
+    if let Some(o) = old {
+        names.extend(object_program_names(Path::new(&o.path)).unwrap_or_default());
+    }
+    if let Some(a) = attached.iter().find(|a| names.iter().any(|n| kernel_name(n) == a.name)) {
+        return Ok(Some(a.clone()));
+    }
+    match (hook, attached.first()) {
+        // Cgroups with multi attachments can hold unrelated programs side by side
+        (Hook::Cgroup { .. }, _) | (_, None) => Ok(None),
+        (_, Some(other)) => Err(anyhow!(
+            "{} is taken by program {} ({}), which is not from '{}'",
+            hook, other.prog_id, other.name, new.title
+        )),
+    }
+}
+
+/// The new program must stay attached for the whole window and, unless idle is allowed, run
+async fn health_check(hook: &Hook, prog_id: u32, window: Duration, allow_idle: bool) -> Result<()> {
+    // Runtime counters only move while stats are on
+    let stats = RuntimeStats::enable();
+    let before = prog_show(prog_id).await?;
+    let deadline = Instant::now() + window;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        tokio::time::sleep(CHECK_EVERY.min(deadline - now)).await;
+        if !attachments(hook).await?.iter().any(|a| a.prog_id == prog_id) {
+            return Err(anyhow!("program {} is no longer attached to {}", prog_id, hook));
+        }
+    }
+
+    let after = prog_show(prog_id).await
+        .map_err(|e| anyhow!("program {} disappeared: {}", prog_id, e))?;
+    let misses = after.recursion_misses.saturating_sub(before.recursion_misses);
+    if misses > 0 {
+        warn(&format!("{} runs were skipped for recursion during the window", misses));
+    }
+    if allow_idle {
+        return Ok(());
+    }
+    if !stats.enabled() {
+        warn("BPF runtime stats are off, so activity can't be checked (run as root or set kernel.bpf_stats_enabled=1)");
+        return Ok(());
+    }
+    let runs = after.run_cnt.saturating_sub(before.run_cnt);
+    if runs == 0 {
+        return Err(anyhow!(
+            "program {} did not run during the window (use --allow-idle for hooks without traffic)",
+            prog_id
+        ));
+    }
+    info(&format!("Program {} ran {} times during the window", prog_id, runs));
+    Ok(())
+}
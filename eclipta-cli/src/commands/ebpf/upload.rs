@@ -64,6 +64,24 @@ fn validate_elf_sections(path: &Path) -> Result<Vec<String>, String> {
     Ok(recognized)
 }
 
+/// Copy an object into the registry's storage directory under a unique name
+pub fn store_object(program: &Path, title: &str) -> std::io::Result<PathBuf> {
+    let storage_dir = Path::new("/var/lib/eclipta/programs");
+    if !storage_dir.exists() {
+        warn("Storage directory missing. Creating...");
+        fs::create_dir_all(storage_dir)?;
+    }
+
+    let unique_name = format!(
+        "{}-{}.o",
+        title.replace(' ', "_"),
+        Uuid::new_v4()
+    );
+    let dest_path = storage_dir.join(unique_name);
+    fs::copy(program, &dest_path)?;
+    Ok(dest_path)
+}
+
 pub async fn handle_upload(opts: UploadOptions) -> Result<(), Box<dyn std::error::Error>> {
     info("Starting upload process...");
 
@@ -89,26 +107,16 @@ pub async fn handle_upload(opts: UploadOptions) -> Result<(), Box<dyn std::error
     }
 
     // Step 3: Copy program into storage directory with unique name
-    let storage_dir = Path::new("/var/lib/eclipta/programs");
-    if !storage_dir.exists() {
-        warn("Storage directory missing. Creating...");
-        fs::create_dir_all(storage_dir)?;
-    }
-
-    let unique_name = format!(
-        "{}-{}.o",
-        opts.title.replace(' ', "_"),
-        Uuid::new_v4()
-    );
-    let dest_path = storage_dir.join(unique_name);
-
-    match fs::copy(&opts.program, &dest_path) {
-        Ok(_) => info(&format!("Program stored at {}", dest_path.display())),
+    let dest_path = match store_object(&opts.program, &opts.title) {
+        Ok(p) => {
+            info(&format!("Program stored at {}", p.display()));
+            p
+        }
         Err(e) => {
             error(&format!("Failed to copy program: {}", e));
             return Ok(());
         }
-    }
+    };
 
     // Step 4: Insert metadata into Postgres
     let pool = match ensure_db_ready().await {
@@ -0,0 +1,191 @@
+use crate::commands::ebpf::load::{load_program_by_type, resolve_object};
+use crate::commands::system::probe::check_object_caps;
+use crate::db::programs::list_programs;
+use crate::utils::bpftool::{kernel_name, loaded_programs_for};
+use crate::utils::db::ensure_db_ready;
+use crate::utils::logger::{error, info, success};
+use crate::utils::paths::default_pin_prefix;
+use anyhow::{anyhow, Result};
+use aya::EbpfLoader;
+use clap::Args;
+use prettytable::{format, row, Table};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct VerifyOptions {
+    /// Object file to verify
+    #[arg(short, long)]
+    pub program: Option<PathBuf>,
+
+    /// Registry id of the program to verify
+    #[arg(long)]
+    pub id: Option<i32>,
+
+    /// Registry title of the program to verify
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// Verify every object in the registry
+    #[arg(long, conflicts_with_all = ["program", "id", "title"])]
+    pub all: bool,
+
+    /// Print the results as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Verifier outcome for one program of an object
+#[derive(Debug, Serialize)]
+pub struct Verdict {
+    pub object: String,
+    /// "title vX" for registry programs
+    pub label: Option<String>,
+    pub program: String,
+    /// "passed", "failed" or "skipped"
+    pub status: &'static str,
+    pub prog_type: Option<String>,
+    /// Instructions after the kernel's rewrites
+    pub insns: Option<u64>,
+    pub verified_insns: Option<u64>,
+    pub jited_bytes: Option<u64>,
+    /// Load error, including the verifier log
+    pub error: Option<String>,
+}
+
+/// Load every program of the objects into the kernel verifier and unload them again,
+/// without attaching anything
+pub async fn handle_verify(opts: VerifyOptions) -> Result<()> {
+    let mut objects: Vec<(PathBuf, Option<String>)> = Vec::new();
+    if let (Some(path), None, None) = (&opts.program, opts.id, &opts.title) {
+        // --program alone never touches the registry, so no database is needed
+        objects.push((path.clone(), None));
+    } else if opts.all {
+        let pool = ensure_db_ready().await
+            .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
+        for p in list_programs(&pool).await? {
+            let path = PathBuf::from(&p.path);
+            if !objects.iter().any(|(o, _)| *o == path) {
+                objects.push((path, Some(format!("{} v{}", p.title, p.version))));
+            }
+        }
+        if objects.is_empty() {
+            info("The registry is empty; nothing to verify");
+            return Ok(());
+        }
+    } else {
+        let pool = ensure_db_ready().await
+            .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
+        let (path, registry) = resolve_object(&pool, opts.id, opts.title.as_deref(), opts.program.as_ref(), "verify").await?;
+        objects.push((path, registry.map(|p| format!("{} v{}", p.title, p.version))));
+    }
+
+    let mut verdicts = Vec::new();
+    for (path, label) in &objects {
+        if !opts.json {
+            info(&format!("Verifying {}...", label.as_deref().unwrap_or(&path.display().to_string())));
+        }
+        verdicts.extend(verify_object(path, label.as_deref()).await);
+    }
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&verdicts)?);
+    } else {
+        print_verdicts(&verdicts);
+    }
+
+    let failed = verdicts.iter().filter(|v| v.status == "failed").count();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} programs failed verification", failed, verdicts.len()));
+    }
+    if !opts.json {
+        success(&format!("All {} programs passed the verifier", verdicts.iter().filter(|v| v.status == "passed").count()));
+    }
+    Ok(())
+}
+
+async fn verify_object(path: &Path, label: Option<&str>) -> Vec<Verdict> {
+    let verdict = |program: &str, status, error: Option<String>| Verdict {
+        object: path.display().to_string(),
+        label: label.map(str::to_string),
+        program: program.to_string(),
+        status,
+        prog_type: None,
+        insns: None,
+        verified_insns: None,
+        jited_bytes: None,
+        error,
+    };
+    if !path.is_file() {
+        return vec![verdict("-", "failed", Some(format!("{} does not exist", path.display())))];
+    }
+    if let Err(e) = check_object_caps(path) {
+        return vec![verdict("-", "failed", Some(e.to_string()))];
+    }
+
+    // Maps pinned by name go to a scratch directory, so running programs' pins are never reopened
+    let scratch = default_pin_prefix().join("verify").join(std::process::id().to_string());
+    let _ = std::fs::create_dir_all(&scratch);
+    let mut loader = EbpfLoader::new();
+    loader.map_pin_path(&scratch);
+    let mut ebpf = match loader.load_file(path) {
+        Ok(ebpf) => ebpf,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&scratch);
+            return vec![verdict("-", "failed", Some(format!("Failed to load the object: {}", e)))];
+        }
+    };
+
+    let mut verdicts = Vec::new();
+    for (name, program) in ebpf.programs_mut() {
+        verdicts.push(match load_program_by_type(program) {
+            Err(e) => verdict(name, "failed", Some(e.to_string())),
+            Ok(()) if program.fd().is_err() => verdict(name, "skipped", Some("needs attach-time information to load".to_string())),
+            Ok(()) => verdict(name, "passed", None),
+        });
+    }
+
+    // Read the stats while the programs are still loaded; ours are the newest instances
+    let loaded = loaded_programs_for(path).await.unwrap_or_default();
+    for v in verdicts.iter_mut().filter(|v| v.status == "passed") {
+        if let Some(p) = loaded.iter().filter(|p| p.name == kernel_name(&v.program)).max_by_key(|p| p.id) {
+            v.prog_type = Some(p.prog_type.clone());
+            v.insns = Some(p.bytes_xlated / 8);
+            v.verified_insns = (p.verified_insns > 0).then_some(p.verified_insns);
+            v.jited_bytes = Some(p.bytes_jited);
+        }
+    }
+
+    drop(ebpf);
+    let _ = std::fs::remove_dir_all(&scratch);
+    verdicts
+}
+
+fn print_verdicts(verdicts: &[Verdict]) {
+    let num = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(row!["Object", "Program", "Type", "Result", "Insns", "Verified", "JIT bytes", "Note"]);
+    for v in verdicts {
+        let note = v.error.as_deref().and_then(|e| e.lines().next()).unwrap_or("");
+        table.add_row(row![
+            v.label.as_deref().unwrap_or(&v.object),
+            v.program,
+            v.prog_type.as_deref().unwrap_or("-"),
+            v.status,
+            num(v.insns),
+            num(v.verified_insns),
+            num(v.jited_bytes),
+            note
+        ]);
+    }
+    print!("{}", table);
+
+    // Verifier logs are long, so they follow the table
+    for v in verdicts.iter().filter(|v| v.status == "failed") {
+        if let Some(e) = &v.error {
+            error(&format!("{} / {}:", v.label.as_deref().unwrap_or(&v.object), v.program));
+            println!("{}", e);
+        }
+    }
+}
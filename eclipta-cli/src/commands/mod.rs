@@ -3,6 +3,8 @@ pub mod ebpf;
 pub mod network;
 
 pub mod run;
+pub mod profile;
+pub mod replay;
 pub mod version;
 pub mod welcome;
 pub mod store;
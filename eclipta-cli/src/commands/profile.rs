@@ -0,0 +1,225 @@
+use crate::events::symbolize::{Frame, Symbolizer};
+use crate::utils::caps::{self, Capability, SYMBOLIZE};
+use crate::utils::logger::{info, success, warn};
+use crate::utils::paths::default_profile_object;
+use anyhow::{anyhow, Context, Result};
+use aya::maps::{stack_trace::StackTraceMap, HashMap as BpfHashMap, MapData};
+use aya::programs::perf_event::{perf_sw_ids, PerfEventScope, PerfTypeId, SamplePolicy};
+use aya::programs::PerfEvent;
+use aya::util::online_cpus;
+use aya::EbpfLoader;
+use clap::Args;
+use nix::sys::resource::{setrlimit, Resource, RLIM_INFINITY};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tokio::signal;
+
+#[derive(Args, Debug)]
+pub struct ProfileOptions {
+    /// How long to sample, e.g. "30s" or "2m" (Ctrl+C stops early)
+    #[arg(short, long, default_value = "30s")]
+    pub duration: String,
+
+    /// Only sample this process
+    #[arg(long)]
+    pub pid: Option<u32>,
+
+    /// Samples per second on each CPU
+    #[arg(short, long, default_value_t = 99)]
+    pub freq: u64,
+
+    /// Folded stack output, one "frame;frame;... count" line per stack
+    #[arg(long, default_value = "profile.folded")]
+    pub folded: PathBuf,
+
+    /// SVG flame graph output
+    #[arg(long, default_value = "profile.svg")]
+    pub svg: PathBuf,
+
+    /// Skip writing the SVG flame graph
+    #[arg(long)]
+    pub no_svg: bool,
+
+    /// Sampling program to load (defaults to $ECLIPTA_PROFILE_BIN or ./bin/profile.o)
+    #[arg(short, long)]
+    pub program: Option<PathBuf>,
+
+    /// Give up every capability not needed for symbolizing and writing the output once sampling starts
+    #[arg(long)]
+    pub drop_caps: bool,
+}
+
+/// Key of the in-kernel `counts` map; must match `struct sample_key` in profile.c
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct SampleKey {
+    pid: u32,
+    kstack_id: i32,
+    ustack_id: i32,
+    comm: [u8; 16],
+}
+
+unsafe impl aya::Pod for SampleKey {}
+
+pub async fn handle_profile(opts: ProfileOptions) -> Result<()> {
+    caps::require("sample stacks", &[("profile_cpu".to_string(), "perf_event")], false)?;
+    let duration = humantime::parse_duration(&opts.duration)
+        .map_err(|e| anyhow!("Invalid --duration '{}': {}", opts.duration, e))?;
+    if opts.freq == 0 {
+        return Err(anyhow!("--freq must be greater than zero"));
+    }
+    if let Some(pid) = opts.pid {
+        if !PathBuf::from(format!("/proc/{}", pid)).exists() {
+            return Err(anyhow!("No process with pid {}", pid));
+        }
+    }
+
+    let program_path = opts.program.clone().unwrap_or_else(default_profile_object);
+    if !program_path.exists() {
+        return Err(anyhow!(
+            "Missing profiler program at {} (build it with: make -C examples/ebpf install)",
+            program_path.display()
+        ));
+    }
+
+    // Bump memlock to avoid failures on older kernels
+    let _ = setrlimit(Resource::RLIMIT_MEMLOCK, RLIM_INFINITY, RLIM_INFINITY);
+
+    let target_pid = opts.pid.unwrap_or(0);
+    let mut bpf = EbpfLoader::new()
+        .set_global("target_pid", &target_pid, true)
+        .load_file(&program_path)
+        .with_context(|| format!("Failed to load {}", program_path.display()))?;
+
+    let program: &mut PerfEvent = bpf
+        .program_mut("profile_cpu")
+        .ok_or_else(|| anyhow!("Program 'profile_cpu' not found in {}", program_path.display()))?
+        .try_into()
+        .map_err(|_| anyhow!("Program 'profile_cpu' is not a perf_event program"))?;
+    program.load()?;
+
+    let cpus = online_cpus().map_err(|(_, e)| anyhow!("Failed to list online CPUs: {}", e))?;
+    for cpu in &cpus {
+        program
+            .attach(
+                PerfTypeId::Software,
+                perf_sw_ids::PERF_COUNT_SW_CPU_CLOCK as u64,
+                PerfEventScope::AllProcessesOneCpu { cpu: *cpu },
+                SamplePolicy::Frequency(opts.freq),
+                true,
+            )
+            .with_context(|| format!("Failed to attach sampler on CPU {}", cpu))?;
+    }
+
+    if opts.drop_caps {
+        // The outputs often go to the invoking user's directory
+        let keep: Vec<Capability> = SYMBOLIZE.iter().copied().chain([Capability::DacOverride]).collect();
+        match caps::drop_caps(&keep) {
+            Ok(()) => info("Dropped capabilities not needed for symbolizing"),
+            Err(e) => warn(&format!("Failed to drop capabilities: {:#}", e)),
+        }
+    }
+
+    let target = match opts.pid {
+        Some(pid) => format!("pid {}", pid),
+        None => "all processes".to_string(),
+    };
+    info(&format!(
+        "Sampling {} at {} Hz on {} CPUs for {} (Ctrl+C to stop early)",
+        target,
+        opts.freq,
+        cpus.len(),
+        humantime::format_duration(duration)
+    ));
+
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = signal::ctrl_c() => info("Stopping early"),
+    }
+
+    let counts: BpfHashMap<MapData, SampleKey, u64> = BpfHashMap::try_from(
+        bpf.take_map("counts").ok_or_else(|| anyhow!("Map 'counts' not found"))?,
+    )?;
+    let traces: StackTraceMap<MapData> = StackTraceMap::try_from(
+        bpf.take_map("stacks").ok_or_else(|| anyhow!("Map 'stacks' not found"))?,
+    )?;
+
+    let folded = fold_samples(&counts, &traces);
+    if folded.is_empty() {
+        warn("No samples collected");
+        return Ok(());
+    }
+
+    let mut lines: Vec<String> = folded.iter().map(|(stack, n)| format!("{} {}", stack, n)).collect();
+    lines.sort();
+    let total: u64 = folded.values().sum();
+
+    let mut out = BufWriter::new(
+        File::create(&opts.folded).with_context(|| format!("Failed to create {}", opts.folded.display()))?,
+    );
+    for line in &lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    success(&format!(
+        "Wrote {} samples in {} stacks to {}",
+        total,
+        lines.len(),
+        opts.folded.display()
+    ));
+
+    if !opts.no_svg {
+        let mut flame = inferno::flamegraph::Options::default();
+        flame.title = format!("eclipta profile: {} ({} Hz, {})", target, opts.freq, humantime::format_duration(duration));
+        flame.count_name = "samples".to_string();
+        let svg = File::create(&opts.svg).with_context(|| format!("Failed to create {}", opts.svg.display()))?;
+        inferno::flamegraph::from_lines(&mut flame, lines.iter().map(String::as_str), BufWriter::new(svg))
+            .map_err(|e| anyhow!("Failed to render flame graph: {}", e))?;
+        success(&format!("Wrote flame graph to {}", opts.svg.display()));
+    }
+
+    Ok(())
+}
+
+/// Symbolize every sampled stack and merge them into folded `comm;user;kernel` lines
+fn fold_samples(
+    counts: &BpfHashMap<MapData, SampleKey, u64>,
+    traces: &StackTraceMap<MapData>,
+) -> HashMap<String, u64> {
+    let mut symbolizer = Symbolizer::new().with_kernel_symbols().unwrap_or_else(|e| {
+        warn(&format!("Kernel frames will not be symbolized: {}", e));
+        Symbolizer::new()
+    });
+    let ips = |id: i32| -> Vec<u64> {
+        if id < 0 {
+            return Vec::new();
+        }
+        traces
+            .get(&(id as u32), 0)
+            .map(|t| t.frames().iter().map(|f| f.ip).collect())
+            .unwrap_or_default()
+    };
+
+    let mut folded: HashMap<String, u64> = HashMap::new();
+    for entry in counts.iter() {
+        let Ok((key, count)) = entry else { continue };
+        let end = key.comm.iter().position(|b| *b == 0).unwrap_or(key.comm.len());
+        let comm = String::from_utf8_lossy(&key.comm[..end]).replace(';', "_");
+
+        let user = symbolizer.user_frames(key.pid, &ips(key.ustack_id));
+        let kernel = symbolizer.kernel_frames(&ips(key.kstack_id));
+
+        // Outermost frame first; kernel frames sit on top of user frames and get the
+        // "_[k]" suffix flame graph tools use to color them
+        let mut parts = vec![comm];
+        parts.extend(user.iter().rev().map(Frame::name));
+        parts.extend(kernel.iter().rev().map(|f| format!("{}_[k]", f.name())));
+        if parts.len() == 1 {
+            parts.push("[unknown]".to_string());
+        }
+        *folded.entry(parts.join(";")).or_default() += count;
+    }
+    folded
+}
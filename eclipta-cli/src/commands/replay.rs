@@ -0,0 +1,84 @@
+use crate::events::capture::CaptureReader;
+use crate::events::decode::{Decoder, Layout};
+use crate::events::{EventSink, StreamOptions};
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ReplayOptions {
+    /// Capture file written by `eclipta run --record`
+    pub capture: PathBuf,
+
+    /// Decode records with this layout instead of the one stored in the capture
+    #[arg(long, conflicts_with = "execve_format")]
+    pub layout: Option<String>,
+
+    /// Decode records as { u32 pid; char comm[16]; } instead of the stored decoder
+    #[arg(long)]
+    pub execve_format: bool,
+
+    /// Print the capture header and exit
+    #[arg(long)]
+    pub info: bool,
+
+    #[command(flatten)]
+    pub stream: StreamOptions,
+}
+
+/// Feed a recorded capture through the same decode, filter, aggregation and output
+/// path as `run`, without root or kernel access
+pub async fn handle_replay(opts: ReplayOptions) -> Result<()> {
+    let mut reader = CaptureReader::open(&opts.capture)?;
+
+    if opts.info {
+        print_capture_info(&opts, &reader);
+        return Ok(());
+    }
+
+    let decoder = if opts.execve_format {
+        Decoder::Layout(Layout::exec())
+    } else if let Some(spec) = opts.layout.as_deref() {
+        Decoder::Layout(Layout::parse(spec).map_err(|e| anyhow!("Invalid --layout: {}", e))?)
+    } else {
+        reader.header.decoder.clone()
+    };
+
+    let mut sink = EventSink::new(&opts.stream, &decoder.fields())?;
+    let mut count = 0u64;
+    while let Some(rec) = reader.next_record()? {
+        sink.push_at(decoder.decode(rec.cpu, &rec.data), rec.ts_ns);
+        count += 1;
+    }
+
+    sink.finish().await;
+    eprintln!("Replayed {} records from {}", count, opts.capture.display());
+    Ok(())
+}
+
+fn print_capture_info(opts: &ReplayOptions, reader: &CaptureReader) {
+    let header = &reader.header;
+    let created = DateTime::from_timestamp(header.created_at, 0)
+        .map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let decoder = match &header.decoder {
+        Decoder::Text => "text".to_string(),
+        Decoder::Layout(layout) => layout.spec(),
+    };
+
+    println!("Capture: {}", opts.capture.display());
+    println!("  Recorded: {}", created);
+    println!(
+        "  Object: {}",
+        header.object.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "-".to_string())
+    );
+    println!("  Program: {}", header.program);
+    println!("  Tracepoint: {}", header.tracepoint);
+    println!("  Map: {}", header.map);
+    println!("  Decoder: {}", decoder);
+    println!(
+        "  BTF: {}",
+        if reader.btf.is_empty() { "not embedded".to_string() } else { format!("{} bytes", reader.btf.len()) }
+    );
+}
@@ -1,18 +1,16 @@
-use aya::{EbpfLoader, maps::perf::PerfEventArray, util::online_cpus, programs::TracePoint};
+use aya::{EbpfLoader, maps::{perf::PerfEventArray, stack_trace::StackTraceMap}, util::online_cpus, programs::TracePoint};
 use bytes::BytesMut;
 use clap::Args;
-use std::{convert::TryInto, path::PathBuf, time::Duration, mem};
-use tokio::{signal, time};
+use std::{convert::TryInto, path::PathBuf, time::Duration};
+use tokio::{signal, sync::mpsc, time};
+use crate::events::{EventSink, StreamOptions};
+use crate::events::capture::{read_object_btf, CaptureHeader, CaptureWriter};
+use crate::events::decode::{Decoder, Layout};
+use crate::events::stacks::{StackFields, StackOptions, StackResolver};
+use crate::utils::caps::{self, Capability, SYMBOLIZE};
+use crate::utils::otlp::now_ns;
 use crate::utils::paths::default_bin_object;
 use nix::sys::resource::{setrlimit, Resource, RLIM_INFINITY};
-use nix::unistd::Uid;
-
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub struct ExecEvent {
-    pub pid: u32,
-    pub comm: [u8; 16],
-}
 
 #[derive(Args, Debug)]
 pub struct RunOptions {
@@ -35,19 +33,76 @@ pub struct RunOptions {
     #[arg(long)]
     pub execve_format: bool,
 
+    /// Record layout for decoding events, e.g. "pid:u32,port:u16,comm:char[16]"
+    #[arg(long, conflicts_with = "execve_format")]
+    pub layout: Option<String>,
+
+    #[command(flatten)]
+    pub stream: StreamOptions,
+
+    #[command(flatten)]
+    pub stacks: StackOptions,
+
+    /// Save raw records to a capture file for `eclipta replay` (requires --map)
+    #[arg(long, value_name = "FILE", requires = "map")]
+    pub record: Option<PathBuf>,
+
+    /// Give up every capability once the program is attached and the buffers are open
+    #[arg(long)]
+    pub drop_caps: bool,
+
     #[arg(long)]
     pub verbose: bool,
 }
 
 pub async fn handle_run(opts: RunOptions) {
-    if !Uid::effective().is_root() {
-        eprintln!("This command must be run as root. Try: sudo eclipta run ...");
+    if let Err(e) = caps::require("run a tracepoint program", &[(opts.name.clone(), "tracepoint")], false) {
+        eprintln!("{}", e);
         return;
     }
 
     // Bump memlock to avoid failures on older kernels
     let _ = setrlimit(Resource::RLIMIT_MEMLOCK, RLIM_INFINITY, RLIM_INFINITY);
 
+    let decoder = if opts.execve_format {
+        Decoder::Layout(Layout::exec())
+    } else if let Some(spec) = opts.layout.as_deref() {
+        match Layout::parse(spec) {
+            Ok(layout) => Decoder::Layout(layout),
+            Err(e) => {
+                eprintln!("Invalid --layout: {}", e);
+                return;
+            }
+        }
+    } else {
+        Decoder::Text
+    };
+
+    if opts.stacks.stack_map.is_some() && opts.map.is_none() {
+        eprintln!("--stack-map needs --map to stream the events carrying stack IDs");
+        return;
+    }
+    let stack_fields = match StackFields::new(&opts.stacks, &decoder.fields()) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut fields = decoder.fields();
+    if let Some(sf) = &stack_fields {
+        fields.extend(sf.fields());
+    }
+
+    // Validate the filter and aggregation before touching the kernel so typos fail fast
+    let mut sink = match EventSink::new(&opts.stream, &fields) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
     let program_path = opts.program.clone().unwrap_or_else(default_bin_object);
     if !program_path.exists() {
         eprintln!("Missing compiled eBPF program at {}", program_path.display());
@@ -115,8 +170,58 @@ pub async fn handle_run(opts: RunOptions) {
             }
         };
 
+        let mut stacks = match (stack_fields, opts.stacks.stack_map.as_ref()) {
+            (Some(sf), Some(stack_map)) => {
+                let map = match bpf.take_map(stack_map) {
+                    Some(m) => m,
+                    None => {
+                        eprintln!("Map '{}' not found in ELF", stack_map);
+                        return;
+                    }
+                };
+                let traces: StackTraceMap<_> = match map.try_into() {
+                    Ok(t) => t,
+                    Err(_) => {
+                        eprintln!("Map '{}' is not a StackTraceMap", stack_map);
+                        return;
+                    }
+                };
+                Some(StackResolver::new(traces, sf))
+            }
+            _ => None,
+        };
+
         println!("Streaming events from '{}' (Ctrl+C to exit)", map_name);
 
+        let mut capture = match opts.record.as_ref() {
+            Some(path) => {
+                let header = CaptureHeader {
+                    created_at: chrono::Utc::now().timestamp(),
+                    object: std::fs::canonicalize(&program_path).ok(),
+                    program: opts.name.clone(),
+                    tracepoint: opts.tracepoint.clone(),
+                    map: map_name.clone(),
+                    decoder: decoder.clone(),
+                };
+                let btf = read_object_btf(&program_path).unwrap_or_default();
+                match CaptureWriter::create(path, &header, btf.as_deref()) {
+                    Ok(w) => {
+                        println!("Recording raw events to {}", path.display());
+                        Some(w)
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // Per-CPU readers forward raw records; a single consumer decodes and filters
+        // so sampling and rate limits apply to the stream as a whole
+        let (tx, mut rx) = mpsc::channel::<(u32, u64, BytesMut)>(4096);
+
         for cpu_id in online_cpus().unwrap_or_default() {
             let mut buf = match perf_array.open(cpu_id, None) {
                 Ok(b) => b,
@@ -126,31 +231,16 @@ pub async fn handle_run(opts: RunOptions) {
                 }
             };
 
-            let execve_fmt = opts.execve_format;
+            let tx = tx.clone();
             tokio::spawn(async move {
                 let mut bufs = vec![BytesMut::with_capacity(1024)];
                 loop {
                     match buf.read_events(&mut bufs) {
                         Ok(events) => {
+                            let ts = now_ns();
                             for rec in &bufs[..events.read] {
-                                if execve_fmt && rec.len() >= mem::size_of::<ExecEvent>() {
-                                    let ptr = rec.as_ptr() as *const ExecEvent;
-                                    let ev = unsafe { *ptr };
-                                    let comm = std::str::from_utf8(&ev.comm)
-                                        .unwrap_or("")
-                                        .trim_end_matches(char::from(0))
-                                        .to_string();
-                                    println!("exec pid={} comm={}", ev.pid, comm);
-                                } else if let Ok(s) = std::str::from_utf8(&rec) {
-                                    println!("{}", s.trim_end_matches(char::from(0)));
-                                } else {
-                                    println!(
-                                        "{}",
-                                        rec.iter()
-                                            .map(|b| format!("{:02x}", b))
-                                            .collect::<Vec<_>>()
-                                            .join("")
-                                    );
+                                if tx.send((cpu_id, ts, rec.clone())).await.is_err() {
+                                    return;
                                 }
                             }
                             if events.lost > 0 {
@@ -163,11 +253,61 @@ pub async fn handle_run(opts: RunOptions) {
                 }
             });
         }
+        drop(tx);
+        if opts.drop_caps {
+            // Symbolizing stacks still reads other processes' memory maps
+            drop_caps_after_attach(if stacks.is_some() { SYMBOLIZE } else { &[] }, opts.verbose);
+        }
+
+        let ctrl_c = signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        let mut refresh = time::interval(sink.interval());
+        loop {
+            tokio::select! {
+                rec = rx.recv() => {
+                    let Some((cpu, ts, rec)) = rec else { break };
+                    if let Some(w) = capture.as_mut() {
+                        if let Err(e) = w.write(ts, cpu, &rec) {
+                            eprintln!("Failed to write capture, recording stopped: {}", e);
+                            capture = None;
+                        }
+                    }
+                    let mut ev = decoder.decode(cpu, &rec);
+                    if let Some(resolver) = stacks.as_mut() {
+                        resolver.resolve(&mut ev);
+                    }
+                    sink.push(ev);
+                }
+                _ = refresh.tick(), if sink.is_aggregating() => sink.refresh(),
+                _ = &mut ctrl_c => break,
+            }
+        }
+
+        sink.finish().await;
+        if let (Some(w), Some(path)) = (capture, opts.record.as_ref()) {
+            match w.finish() {
+                Ok(n) => println!("Recorded {} events to {}", n, path.display()),
+                Err(e) => eprintln!("Failed to finalize capture {}: {}", path.display(), e),
+            }
+        }
+        return;
     } else {
+        if opts.drop_caps {
+            drop_caps_after_attach(&[], opts.verbose);
+        }
         println!("Attached. No map provided for streaming. Waiting (Ctrl+C to exit)...");
     }
 
     if let Err(e) = signal::ctrl_c().await {
         eprintln!("Failed to wait for Ctrl+C: {}", e);
     }
-} 
\ No newline at end of file
+} 
+
+/// Failing to drop only forgoes the hardening, so the run goes on
+fn drop_caps_after_attach(keep: &[Capability], verbose: bool) {
+    match caps::drop_caps(keep) {
+        Ok(()) if verbose => println!("✓ Dropped capabilities"),
+        Ok(()) => {}
+        Err(e) => eprintln!("Failed to drop capabilities: {}", e),
+    }
+}
@@ -0,0 +1,327 @@
+use crate::db::metrics::{is_counter, list_retention, prune_metrics, query_history, set_retention, HistoryBucket, DEFAULT_RETENTION_SECS, METRICS};
+use crate::db::programs::list_programs;
+use crate::utils::db::ensure_db_ready;
+use crate::utils::logger::{info, success};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
+use prettytable::{format, row, Table};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Buckets per query when --step is not given
+const TARGET_BUCKETS: f64 = 60.0;
+
+#[derive(Args, Debug)]
+pub struct HistoryOptions {
+    #[command(subcommand)]
+    pub command: Option<HistoryCommand>,
+
+    /// Registered program ID (default: every program)
+    #[arg(long)]
+    pub id: Option<i32>,
+
+    /// Metric to show: run_cnt, run_time_ns, recursion_misses, events_per_sec, avg_ns_per_run, memlock_bytes, loaded or map_entries
+    #[arg(long, default_value = "run_cnt")]
+    pub metric: String,
+
+    /// Start of the range, as a duration before now, e.g. "1h" or "7d"
+    #[arg(long, default_value = "1h")]
+    pub since: String,
+
+    /// End of the range, as a duration before now (default: now)
+    #[arg(long)]
+    pub until: Option<String>,
+
+    /// Bucket width for downsampling, e.g. "1m" (default: about 60 buckets over the range)
+    #[arg(long)]
+    pub step: Option<String>,
+
+    /// Value per bucket: avg, min, max, last or rate (default: rate for counters, avg otherwise)
+    #[arg(long)]
+    pub agg: Option<String>,
+
+    /// Output format: table, csv or json
+    #[arg(long, default_value = "table")]
+    pub format: String,
+
+    /// Write the output to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommand {
+    /// Show retention policies, or set one with --keep
+    Retention {
+        /// How long samples are kept, e.g. "7d"
+        #[arg(long)]
+        keep: Option<String>,
+
+        /// Metric the policy applies to; "*" is the default for all others
+        #[arg(long, default_value = "*")]
+        metric: String,
+    },
+    /// Delete samples older than their retention now instead of on the next recording
+    Prune,
+}
+
+/// One output row after aggregation
+struct Point {
+    time: DateTime<Utc>,
+    program_id: i32,
+    map_name: String,
+    value: f64,
+    samples: i64,
+}
+
+pub async fn handle_history(opts: HistoryOptions) -> Result<()> {
+    let pool = ensure_db_ready().await.map_err(|e| anyhow!("Database error: {}", e))?;
+
+    match &opts.command {
+        Some(HistoryCommand::Retention { keep, metric }) => {
+            if let Some(keep) = keep {
+                if metric != "*" && !METRICS.iter().any(|(m, _)| m == metric) {
+                    return Err(anyhow!("Unknown metric '{}'", metric));
+                }
+                let secs = parse_duration("--keep", keep)?.as_secs() as i64;
+                if secs == 0 {
+                    return Err(anyhow!("--keep must be at least one second"));
+                }
+                set_retention(&pool, metric, secs).await?;
+                success(&format!("Keeping {} samples for {}", metric, keep));
+                return Ok(());
+            }
+            let policies = list_retention(&pool).await?;
+            if !policies.iter().any(|(m, _)| m == "*") {
+                println!("* {} (built-in default)", humantime::format_duration(Duration::from_secs(DEFAULT_RETENTION_SECS as u64)));
+            }
+            for (metric, secs) in policies {
+                println!("{} {}", metric, humantime::format_duration(Duration::from_secs(secs as u64)));
+            }
+            return Ok(());
+        }
+        Some(HistoryCommand::Prune) => {
+            let deleted = prune_metrics(&pool).await?;
+            success(&format!("Deleted {} samples past their retention", deleted));
+            return Ok(());
+        }
+        None => {}
+    }
+
+    if !METRICS.iter().any(|(m, _)| *m == opts.metric) {
+        let known: Vec<&str> = METRICS.iter().map(|(m, _)| *m).collect();
+        return Err(anyhow!("Unknown metric '{}' (expected one of {})", opts.metric, known.join(", ")));
+    }
+    let agg = opts.agg.clone().unwrap_or_else(|| if is_counter(&opts.metric) { "rate" } else { "avg" }.to_string());
+    if !matches!(agg.as_str(), "avg" | "min" | "max" | "last" | "rate") {
+        return Err(anyhow!("Unknown --agg '{}' (expected avg, min, max, last or rate)", agg));
+    }
+
+    let now = Utc::now();
+    let since = now - chrono::Duration::from_std(parse_duration("--since", &opts.since)?)?;
+    let until = match &opts.until {
+        Some(u) => now - chrono::Duration::from_std(parse_duration("--until", u)?)?,
+        None => now,
+    };
+    if since >= until {
+        return Err(anyhow!("--since must be further back than --until"));
+    }
+    let step = match &opts.step {
+        Some(s) => parse_duration("--step", s)?.as_secs_f64(),
+        None => ((until - since).num_seconds() as f64 / TARGET_BUCKETS).max(1.0).ceil(),
+    };
+    if step <= 0.0 {
+        return Err(anyhow!("--step must be greater than zero"));
+    }
+
+    let buckets = query_history(&pool, &opts.metric, opts.id, since, until, step).await?;
+    let points = aggregate(&buckets, &agg);
+    let titles: HashMap<i32, String> = list_programs(&pool).await?.into_iter().map(|p| (p.id, p.title)).collect();
+
+    let rendered = match opts.format.as_str() {
+        "table" => render_table(&points, &titles, &opts.metric, &agg),
+        "csv" => render_csv(&points, &titles),
+        "json" => render_json(&points, &titles, &opts.metric, &agg, step)?,
+        other => return Err(anyhow!("Unknown --format '{}' (expected table, csv or json)", other)),
+    };
+    match &opts.output {
+        Some(path) => {
+            std::fs::write(path, rendered)?;
+            info(&format!("Wrote {} points to {}", points.len(), path.display()));
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn parse_duration(flag: &str, value: &str) -> Result<Duration> {
+    humantime::parse_duration(value).map_err(|e| anyhow!("Invalid {} '{}': {}", flag, value, e))
+}
+
+/// Pick the requested value per bucket; rate compares the last value of consecutive buckets
+fn aggregate(buckets: &[HistoryBucket], agg: &str) -> Vec<Point> {
+    let mut points = Vec::new();
+    let mut prev: Option<&HistoryBucket> = None;
+    for b in buckets {
+        let same_series = prev.is_some_and(|p| p.program_id == b.program_id && p.map_name == b.map_name);
+        let value = match agg {
+            "min" => Some(b.min),
+            "max" => Some(b.max),
+            "last" => Some(b.last),
+            "rate" => match prev.filter(|_| same_series) {
+                Some(p) => {
+                    let secs = (b.bucket - p.bucket).num_milliseconds() as f64 / 1000.0;
+                    // A reload restarts the kernel counters from zero
+                    let delta = if b.last >= p.last { b.last - p.last } else { b.last };
+                    (secs > 0.0).then(|| delta / secs)
+                }
+                None => None,
+            },
+            _ => Some(b.avg),
+        };
+        if let Some(value) = value {
+            points.push(Point {
+                time: b.bucket,
+                program_id: b.program_id,
+                map_name: b.map_name.clone(),
+                value,
+                samples: b.samples,
+            });
+        }
+        prev = Some(b);
+    }
+    points
+}
+
+fn title_of(titles: &HashMap<i32, String>, id: i32) -> &str {
+    titles.get(&id).map(|t| t.as_str()).unwrap_or("-")
+}
+
+fn render_table(points: &[Point], titles: &HashMap<i32, String>, metric: &str, agg: &str) -> String {
+    if points.is_empty() {
+        return format!("No {} samples in range (record some with: eclipta status --record)\n", metric);
+    }
+    let with_maps = points.iter().any(|p| !p.map_name.is_empty());
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    if with_maps {
+        table.set_titles(row!["Time", "ID", "Program", "Map", format!("{}({})", agg, metric), "Samples"]);
+    } else {
+        table.set_titles(row!["Time", "ID", "Program", format!("{}({})", agg, metric), "Samples"]);
+    }
+    for p in points {
+        let time = p.time.format("%Y-%m-%d %H:%M:%S").to_string();
+        let value = format_value(p.value);
+        if with_maps {
+            table.add_row(row![time, p.program_id, title_of(titles, p.program_id), p.map_name, r->value, r->p.samples]);
+        } else {
+            table.add_row(row![time, p.program_id, title_of(titles, p.program_id), r->value, r->p.samples]);
+        }
+    }
+    table.to_string()
+}
+
+fn format_value(v: f64) -> String {
+    if v.fract() == 0.0 && v.abs() < 1e15 {
+        format!("{}", v as i64)
+    } else {
+        format!("{:.2}", v)
+    }
+}
+
+fn render_csv(points: &[Point], titles: &HashMap<i32, String>) -> String {
+    let mut out = String::from("time,program_id,title,map,value,samples\n");
+    for p in points {
+        out.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            p.time.to_rfc3339(),
+            p.program_id,
+            csv_field(title_of(titles, p.program_id)),
+            csv_field(&p.map_name),
+            p.value,
+            p.samples
+        ));
+    }
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn render_json(points: &[Point], titles: &HashMap<i32, String>, metric: &str, agg: &str, step: f64) -> Result<String> {
+    let points: Vec<_> = points
+        .iter()
+        .map(|p| {
+            let mut point = json!({
+                "time": p.time.to_rfc3339(),
+                "program_id": p.program_id,
+                "title": title_of(titles, p.program_id),
+                "value": p.value,
+                "samples": p.samples,
+            });
+            if !p.map_name.is_empty() {
+                point["map"] = json!(p.map_name);
+            }
+            point
+        })
+        .collect();
+    let doc = json!({ "metric": metric, "agg": agg, "step_seconds": step, "points": points });
+    Ok(format!("{}\n", serde_json::to_string_pretty(&doc)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn bucket(program_id: i32, map_name: &str, secs: i64, last: f64) -> HistoryBucket {
+        HistoryBucket {
+            program_id,
+            map_name: map_name.to_string(),
+            bucket: Utc.timestamp_opt(secs, 0).unwrap(),
+            avg: last - 1.0,
+            min: last - 2.0,
+            max: last + 1.0,
+            last,
+            samples: 3,
+        }
+    }
+
+    fn values(points: &[Point]) -> Vec<(i32, f64)> {
+        points.iter().map(|p| (p.program_id, p.value)).collect()
+    }
+
+    #[test]
+    fn picks_the_requested_value() {
+        let buckets = [bucket(1, "", 0, 10.0), bucket(1, "", 60, 20.0)];
+        assert_eq!(values(&aggregate(&buckets, "avg")), vec![(1, 9.0), (1, 19.0)]);
+        assert_eq!(values(&aggregate(&buckets, "min")), vec![(1, 8.0), (1, 18.0)]);
+        assert_eq!(values(&aggregate(&buckets, "max")), vec![(1, 11.0), (1, 21.0)]);
+        assert_eq!(values(&aggregate(&buckets, "last")), vec![(1, 10.0), (1, 20.0)]);
+        assert_eq!(aggregate(&buckets, "last")[1].samples, 3);
+    }
+
+    #[test]
+    fn rate_stays_within_a_series_and_survives_reloads() {
+        let buckets = [
+            bucket(1, "", 0, 100.0),
+            bucket(1, "", 10, 200.0),
+            // Counter reset by a reload
+            bucket(1, "", 20, 50.0),
+            bucket(2, "", 20, 1000.0),
+            bucket(2, "", 30, 1100.0),
+            bucket(2, "events", 30, 5.0),
+        ];
+        // The first bucket of every series has nothing to compare with
+        assert_eq!(values(&aggregate(&buckets, "rate")), vec![(1, 10.0), (1, 5.0), (2, 10.0)]);
+        assert!(aggregate(&[], "rate").is_empty());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod check_db;
 pub mod migrate;
+pub mod history;
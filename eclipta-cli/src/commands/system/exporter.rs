@@ -0,0 +1,581 @@
+use crate::commands::ebpf::hook::registry_prog_ids;
+use crate::commands::ebpf::map::ResolvedMap;
+use crate::commands::ebpf::map_watch::aggregate;
+use crate::commands::system::status::{check_bpf_support, get_system_resources};
+use crate::db::programs::{list_programs, Program};
+use crate::utils::bpf_stats::RuntimeStats;
+use crate::utils::bpftool::{kernel_name, map_dump, map_list, MapInfo, MapRef};
+use crate::utils::db::{ensure_db_ready, DbPool};
+use crate::utils::logger::{info, success, warn};
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::signal;
+
+#[derive(Args, Debug)]
+pub struct ExporterOptions {
+    /// Address to serve /metrics on
+    #[arg(long, default_value = "127.0.0.1:9435")]
+    pub listen: String,
+
+    /// Write metrics to this .prom file for node_exporter's textfile collector instead of listening
+    #[arg(long)]
+    pub textfile: Option<PathBuf>,
+
+    /// How often the textfile is rewritten, e.g. "15s"
+    #[arg(long, default_value = "15s")]
+    pub interval: String,
+
+    /// Write the textfile once and exit
+    #[arg(long, requires = "textfile")]
+    pub once: bool,
+
+    /// JSON file selecting map values to export as labeled series
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// How long map entry counts are reused between scrapes, e.g. "1m"; counting dumps the map
+    #[arg(long, default_value = "1m")]
+    pub entries_interval: String,
+}
+
+/// `--config` file: `{"maps": [ ... ]}`
+#[derive(Debug, Deserialize, Default)]
+struct ExporterConfig {
+    #[serde(default)]
+    maps: Vec<MapMetric>,
+}
+
+/// Export every entry of one map as a sample of `metric`
+#[derive(Debug, Deserialize)]
+struct MapMetric {
+    /// Registry program owning the map: title or id
+    program: String,
+    map: String,
+    metric: String,
+    #[serde(default)]
+    help: Option<String>,
+    /// "gauge" or "counter"
+    #[serde(rename = "type", default = "default_metric_type")]
+    kind: String,
+    /// Label for scalar keys; struct keys get one `<key_label>_<field>` label per field
+    #[serde(default = "default_key_label")]
+    key_label: String,
+    /// Dotted path of the number inside struct values; without it every numeric field
+    /// becomes its own series with a `field` label
+    #[serde(default)]
+    value_field: Option<String>,
+}
+
+fn default_metric_type() -> String {
+    "gauge".to_string()
+}
+
+fn default_key_label() -> String {
+    "key".to_string()
+}
+
+/// State kept across scrapes
+struct Exporter {
+    config: ExporterConfig,
+    stats: RuntimeStats,
+    pool: DbPool,
+    entry_counts: EntryCounts,
+}
+
+/// Entry counts by map ID, so every map isn't dumped on every scrape
+struct EntryCounts {
+    ttl: Duration,
+    /// `None` for maps that cannot be dumped, such as ring buffers
+    counts: HashMap<u32, (Instant, Option<usize>)>,
+}
+
+impl EntryCounts {
+    async fn get(&mut self, id: u32) -> Option<usize> {
+        if let Some((at, count)) = self.counts.get(&id) {
+            if at.elapsed() < self.ttl {
+                return *count;
+            }
+        }
+        let count = map_dump(&MapRef::Id(id)).await.ok().map(|entries| entries.len());
+        self.counts.insert(id, (Instant::now(), count));
+        count
+    }
+
+    /// Forget maps that were unloaded since the last scrape
+    fn retain(&mut self, maps: &[MapInfo]) {
+        self.counts.retain(|id, _| maps.iter().any(|m| m.id == *id));
+    }
+}
+
+pub async fn handle_exporter(opts: ExporterOptions) -> Result<()> {
+    let config = match &opts.config {
+        Some(path) => load_config(path)?,
+        None => ExporterConfig::default(),
+    };
+    let pool = ensure_db_ready().await
+        .map_err(|e| anyhow!("Failed to initialize database: {}", e))?;
+    let ttl = humantime::parse_duration(&opts.entries_interval)
+        .map_err(|e| anyhow!("Invalid --entries-interval '{}': {}", opts.entries_interval, e))?;
+    let stats = RuntimeStats::enable();
+    if !stats.enabled() {
+        warn("BPF runtime stats are off (run as root or set kernel.bpf_stats_enabled=1); run counters stay at zero");
+    }
+
+    let entry_counts = EntryCounts { ttl, counts: HashMap::new() };
+    let mut exporter = Exporter { config, stats, pool, entry_counts };
+    match &opts.textfile {
+        Some(path) => run_textfile(path, &opts, &mut exporter).await,
+        None => serve(&opts.listen, &mut exporter).await,
+    }
+}
+
+fn load_config(path: &Path) -> Result<ExporterConfig> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: ExporterConfig =
+        serde_json::from_str(&text).with_context(|| format!("Invalid exporter config {}", path.display()))?;
+    for m in &config.maps {
+        if !valid_metric_name(&m.metric) {
+            return Err(anyhow!("Invalid metric name '{}' in {}", m.metric, path.display()));
+        }
+        if !matches!(m.kind.as_str(), "gauge" | "counter") {
+            return Err(anyhow!("Metric '{}' has type '{}'; use gauge or counter", m.metric, m.kind));
+        }
+    }
+    Ok(config)
+}
+
+async fn serve(listen: &str, exporter: &mut Exporter) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    info(&format!("Serving metrics on http://{}/metrics (Ctrl+C to exit)", listen));
+
+    let ctrl_c = signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut ctrl_c => break,
+        };
+        let (stream, _) = match accepted {
+            Ok(a) => a,
+            Err(e) => {
+                // E.g. out of fds; back off instead of spinning, the next scrape may work
+                warn(&format!("Failed to accept a scrape: {}", e));
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        // Scrapes come every few seconds at most, so they are served one at a time
+        if let Err(e) = handle_connection(stream, exporter).await {
+            warn(&format!("Scrape failed: {}", e));
+        }
+    }
+    Ok(())
+}
+
+async fn handle_connection(mut stream: TcpStream, exporter: &mut Exporter) -> Result<()> {
+    let mut buf = vec![0u8; 4096];
+    let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .map_err(|_| anyhow!("client sent no request"))??;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let mut parts = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let (status, content_type, body) = match (method, path.split('?').next().unwrap_or_default()) {
+        ("GET", "/metrics") => match collect(exporter).await {
+            Ok(body) => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", body),
+            Err(e) => ("500 Internal Server Error", "text/plain", format!("{}\n", e)),
+        },
+        ("GET", "/") => (
+            "200 OK",
+            "text/html",
+            "<html><body><a href=\"/metrics\">metrics</a></body></html>\n".to_string(),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn run_textfile(path: &Path, opts: &ExporterOptions, exporter: &mut Exporter) -> Result<()> {
+    let interval = humantime::parse_duration(&opts.interval)
+        .map_err(|e| anyhow!("Invalid --interval '{}': {}", opts.interval, e))?;
+    if interval.is_zero() {
+        return Err(anyhow!("--interval must be greater than zero"));
+    }
+    if !opts.once {
+        info(&format!(
+            "Writing metrics to {} every {} (Ctrl+C to exit)",
+            path.display(),
+            humantime::format_duration(interval)
+        ));
+    }
+
+    let ctrl_c = signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = &mut ctrl_c => break,
+        }
+        let body = collect(exporter).await?;
+        write_atomically(path, &body)?;
+        if opts.once {
+            success(&format!("Wrote metrics to {}", path.display()));
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The textfile collector may read at any moment, so never let it see a half-written file
+fn write_atomically(path: &Path, body: &str) -> Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+    std::fs::write(&tmp, body).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Gather everything into Prometheus text exposition format
+async fn collect(exporter: &mut Exporter) -> Result<String> {
+    let started = Instant::now();
+    let mut out = Metrics::default();
+    let stats = &mut exporter.stats;
+
+    system_metrics(&mut out, stats);
+
+    let programs = list_programs(&exporter.pool).await?;
+    stats.sample(Duration::ZERO).await?;
+    let maps = map_list().await?;
+    exporter.entry_counts.retain(&maps);
+    for program in &programs {
+        program_metrics(&mut out, program, stats, &maps, &mut exporter.entry_counts).await;
+    }
+    for m in &exporter.config.maps {
+        if let Err(e) = map_value_metrics(&mut out, m, &programs, stats, &maps).await {
+            warn(&format!("Skipping metric '{}': {}", m.metric, e));
+        }
+    }
+
+    out.family("eclipta_scrape_duration_seconds", "Time spent collecting these metrics", "gauge");
+    out.sample("eclipta_scrape_duration_seconds", &[], started.elapsed().as_secs_f64());
+    Ok(out.render())
+}
+
+fn system_metrics(out: &mut Metrics, stats: &RuntimeStats) {
+    if let Ok(res) = get_system_resources() {
+        let gauges = [
+            ("eclipta_system_cpu_cores", "Online CPU cores", res.cpu_cores as f64),
+            ("eclipta_system_memory_total_bytes", "Total memory", res.memory_total_gb * 1024.0 * 1024.0 * 1024.0),
+            ("eclipta_system_memory_available_bytes", "Available memory", res.memory_available_gb * 1024.0 * 1024.0 * 1024.0),
+            ("eclipta_system_uptime_seconds", "Host uptime", res.uptime_seconds as f64),
+        ];
+        for (name, help, value) in gauges {
+            out.family(name, help, "gauge");
+            out.sample(name, &[], value);
+        }
+    }
+
+    if let Ok(bpf) = check_bpf_support() {
+        out.family("eclipta_bpf_support", "BPF features available on this host (1 = yes)", "gauge");
+        let features = [
+            ("bpf_fs_mounted", bpf.bpf_fs_mounted),
+            ("debug_fs_mounted", bpf.debug_fs_mounted),
+            ("cap_sys_admin", bpf.cap_sys_admin),
+            ("cap_bpf", bpf.cap_bpf),
+            ("cap_perfmon", bpf.cap_perfmon),
+            ("cap_net_admin", bpf.cap_net_admin),
+            ("bpf_verifier_available", bpf.bpf_verifier_available),
+            ("btf_support", bpf.btf_support),
+        ];
+        for (feature, on) in features {
+            out.sample("eclipta_bpf_support", &[("feature".to_string(), feature.to_string())], on as u8 as f64);
+        }
+    }
+
+    out.family("eclipta_bpf_stats_enabled", "Whether the kernel counts program run time", "gauge");
+    out.sample("eclipta_bpf_stats_enabled", &[], stats.enabled() as u8 as f64);
+}
+
+async fn program_metrics(
+    out: &mut Metrics,
+    program: &Program,
+    stats: &RuntimeStats,
+    maps: &[MapInfo],
+    entry_counts: &mut EntryCounts,
+) {
+    let ids = registry_prog_ids(program).await;
+    let s = stats.for_programs(&ids);
+    let labels = program_labels(program);
+
+    out.family("eclipta_program_loaded", "Kernel programs currently loaded from the object", "gauge");
+    out.sample("eclipta_program_loaded", &labels, s.prog_ids.len() as f64);
+    if s.prog_ids.is_empty() {
+        return;
+    }
+
+    let counters = [
+        ("eclipta_program_run_count_total", "Times the program ran", s.run_cnt as f64),
+        ("eclipta_program_run_time_seconds_total", "Time spent running the program", s.run_time_ns as f64 / 1e9),
+        ("eclipta_program_recursion_misses_total", "Runs skipped because of recursion", s.recursion_misses as f64),
+    ];
+    for (name, help, value) in counters {
+        out.family(name, help, "counter");
+        out.sample(name, &labels, value);
+    }
+    out.family("eclipta_program_memlock_bytes", "Locked memory used by the program", "gauge");
+    out.sample("eclipta_program_memlock_bytes", &labels, s.bytes_memlock as f64);
+
+    let mut map_ids: Vec<u32> = stats.programs(&ids).iter().flat_map(|p| p.map_ids.iter().copied()).collect();
+    map_ids.sort_unstable();
+    map_ids.dedup();
+    out.family("eclipta_map_entries", "Entries currently stored in the map", "gauge");
+    out.family("eclipta_map_max_entries", "Capacity of the map", "gauge");
+    for info in maps.iter().filter(|m| map_ids.contains(&m.id)) {
+        let mut map_labels = labels.clone();
+        map_labels.push(("map".to_string(), info.name.clone()));
+        map_labels.push(("map_type".to_string(), info.map_type.clone()));
+        out.sample("eclipta_map_max_entries", &map_labels, info.max_entries as f64);
+        // Ring buffers and perf arrays cannot be dumped
+        if let Some(count) = entry_counts.get(info.id).await {
+            out.sample("eclipta_map_entries", &map_labels, count as f64);
+        }
+    }
+}
+
+fn program_labels(program: &Program) -> Labels {
+    vec![
+        ("program_id".to_string(), program.id.to_string()),
+        ("title".to_string(), program.title.clone()),
+        ("version".to_string(), program.version.clone()),
+    ]
+}
+
+async fn map_value_metrics(
+    out: &mut Metrics,
+    m: &MapMetric,
+    programs: &[Program],
+    stats: &RuntimeStats,
+    maps: &[MapInfo],
+) -> Result<()> {
+    let program = programs
+        .iter()
+        .find(|p| p.id.to_string() == m.program || p.title == m.program)
+        .ok_or_else(|| anyhow!("no registry program '{}'", m.program))?;
+    let ids = registry_prog_ids(program).await;
+    let map_ids: Vec<u32> = stats.programs(&ids).iter().flat_map(|p| p.map_ids.iter().copied()).collect();
+    let info = maps
+        .iter()
+        .filter(|i| map_ids.contains(&i.id) && i.name == kernel_name(&m.map))
+        .max_by_key(|i| i.id)
+        .ok_or_else(|| anyhow!("map '{}' of '{}' is not loaded", m.map, m.program))?
+        .clone();
+
+    let map = ResolvedMap::new(MapRef::Id(info.id), info, m.map.clone(), Some(Path::new(&program.path)));
+    let entries = map_dump(&map.target).await?;
+
+    out.family(
+        &m.metric,
+        m.help.as_deref().unwrap_or(&format!("Values of map {} in {}", m.map, program.title)),
+        &m.kind,
+    );
+    for entry in entries {
+        let mut labels = program_labels(program);
+        labels.extend(key_labels(&map.render_key(&entry.key), &m.key_label));
+        let value = aggregate(&map, &entry.value);
+        let value = match &m.value_field {
+            Some(path) => path.split('.').try_fold(&value, |v, field| v.get(field)).cloned().unwrap_or(Value::Null),
+            None => value,
+        };
+        match &value {
+            Value::Object(fields) => {
+                for (field, v) in fields {
+                    if let Some(n) = as_number(v) {
+                        let mut labels = labels.clone();
+                        labels.push(("field".to_string(), field.clone()));
+                        out.sample(&m.metric, &labels, n);
+                    }
+                }
+            }
+            v => {
+                if let Some(n) = as_number(v) {
+                    out.sample(&m.metric, &labels, n);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One `<key_label>_<field>` label per struct field, so fields can't collide with the
+/// program labels, or a single `key_label` for scalar keys
+fn key_labels(key: &Value, key_label: &str) -> Labels {
+    let text = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    match key {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(k, v)| (sanitize_label(&format!("{}_{}", key_label, k)), text(v)))
+            .collect(),
+        other => vec![(sanitize_label(key_label), text(other))],
+    }
+}
+
+fn as_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(*b as u8 as f64),
+        _ => None,
+    }
+}
+
+fn sanitize_label(name: &str) -> String {
+    let mut s: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if s.is_empty() || s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    s
+}
+
+fn valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Label name/value pairs of one sample
+type Labels = Vec<(String, String)>;
+
+/// Samples grouped by metric family, as the exposition format requires
+#[derive(Default)]
+struct Metrics {
+    families: Vec<(String, String, String, Vec<String>)>,
+}
+
+impl Metrics {
+    fn family(&mut self, name: &str, help: &str, kind: &str) {
+        if !self.families.iter().any(|f| f.0 == name) {
+            self.families.push((name.to_string(), help.to_string(), kind.to_string(), Vec::new()));
+        }
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(String, String)], value: f64) {
+        let Some(family) = self.families.iter_mut().find(|f| f.0 == name) else { return };
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            let pairs: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+            format!("{{{}}}", pairs.join(","))
+        };
+        family.3.push(format!("{}{} {}", name, labels, format_value(value)));
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, help, kind, samples) in &self.families {
+            if samples.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, escape_help(help), name, kind));
+            for s in samples {
+                out.push_str(s);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// Label values escape backslash, double quote and newline
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// HELP text only allows escaping backslash and newline; parsers reject `\"`
+fn escape_help(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// The exposition format spells infinities +Inf and -Inf
+fn format_value(v: f64) -> String {
+    if v.is_infinite() {
+        return if v > 0.0 { "+Inf" } else { "-Inf" }.to_string();
+    }
+    v.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn struct_key_fields_are_prefixed() {
+        let labels = key_labels(&json!({ "title": "nginx", "pid": 7 }), "key");
+        assert_eq!(
+            labels,
+            vec![("key_title".to_string(), "nginx".to_string()), ("key_pid".to_string(), "7".to_string())]
+        );
+        assert_eq!(key_labels(&json!(42), "port"), vec![("port".to_string(), "42".to_string())]);
+        assert_eq!(key_labels(&json!({ "a.b": 1 }), "k")[0].0, "k_a_b");
+    }
+
+    #[test]
+    fn renders_exposition_format() {
+        let mut out = Metrics::default();
+        out.family("eclipta_test", "A \"test\" metric", "gauge");
+        out.family("eclipta_empty", "Never sampled", "gauge");
+        out.sample("eclipta_test", &[("title".to_string(), "a\"b".to_string())], 1.5);
+        out.sample("eclipta_test", &[], 2.0);
+        assert_eq!(
+            out.render(),
+            "# HELP eclipta_test A \"test\" metric\n# TYPE eclipta_test gauge\neclipta_test{title=\"a\\\"b\"} 1.5\neclipta_test 2\n"
+        );
+    }
+
+    #[test]
+    fn help_escapes_only_backslash_and_newline() {
+        assert_eq!(escape_help("a \"b\" c\\d\ne"), "a \"b\" c\\\\d\\ne");
+        assert_eq!(escape("a \"b\""), "a \\\"b\\\"");
+    }
+
+    #[test]
+    fn infinities_use_exposition_spelling() {
+        let mut out = Metrics::default();
+        out.family("eclipta_inf", "Infinite", "gauge");
+        out.sample("eclipta_inf", &[], f64::INFINITY);
+        out.sample("eclipta_inf", &[], f64::NEG_INFINITY);
+        out.sample("eclipta_inf", &[], f64::NAN);
+        assert_eq!(out.render(), "# HELP eclipta_inf Infinite\n# TYPE eclipta_inf gauge\neclipta_inf +Inf\neclipta_inf -Inf\neclipta_inf NaN\n");
+    }
+
+    #[test]
+    fn label_and_metric_names() {
+        assert_eq!(sanitize_label("9lives"), "_9lives");
+        assert_eq!(sanitize_label("src-ip"), "src_ip");
+        assert!(valid_metric_name("eclipta:conns_total"));
+        assert!(!valid_metric_name("1conns"));
+        assert!(!valid_metric_name("conns-total"));
+    }
+}
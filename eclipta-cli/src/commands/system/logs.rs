@@ -1,4 +1,5 @@
 use crate::utils::logger::{info, error};
+use crate::utils::otlp::{now_ns, LogRecord, OtlpExporter, OtlpOptions};
 use aya::{Ebpf, maps::perf::PerfEventArray, util::online_cpus};
 use bytes::BytesMut;
 use clap::Args;
@@ -12,6 +13,9 @@ pub struct LogOptions {
 
     #[arg(short, long, default_value = "trace_execve_events")]
     pub map: String,
+
+    #[command(flatten)]
+    pub otlp: OtlpOptions,
 }
 
 pub async fn handle_logs(opts: LogOptions) {
@@ -44,6 +48,14 @@ pub async fn handle_logs(opts: LogOptions) {
         }
     };
 
+    let otlp = match OtlpExporter::start(&opts.otlp) {
+        Ok(otlp) => otlp,
+        Err(e) => {
+            error(&format!("Invalid OTLP settings: {}", e));
+            return;
+        }
+    };
+
     info("Listening for perf event logs...\nPress Ctrl+C to exit.\n");
 
     for cpu_id in online_cpus().unwrap() {
@@ -55,6 +67,7 @@ pub async fn handle_logs(opts: LogOptions) {
             }
         };
 
+        let sender = otlp.as_ref().map(|o| o.sender());
         task::spawn(async move {
             let mut buffers = vec![BytesMut::with_capacity(1024)];
             loop {
@@ -63,6 +76,11 @@ pub async fn handle_logs(opts: LogOptions) {
                         for buf in &buffers[..events.read] {
                             let event = String::from_utf8_lossy(&buf);
                             println!("🟢 {}", event);
+                            if let Some(sender) = &sender {
+                                let mut attributes = serde_json::Map::new();
+                                attributes.insert("cpu".to_string(), serde_json::Value::from(cpu_id));
+                                sender.log(LogRecord { time_ns: now_ns(), body: event.to_string(), attributes });
+                            }
                         }
                         if events.lost > 0 {
                             error(&format!("Lost {} events due to buffer overflow", events.lost));
@@ -82,5 +100,8 @@ pub async fn handle_logs(opts: LogOptions) {
         error(&format!("Failed to wait for Ctrl+C: {}", e));
     }
 
+    if let Some(otlp) = otlp {
+        otlp.shutdown().await;
+    }
     println!("\n🛑 Exiting logs...");
 }
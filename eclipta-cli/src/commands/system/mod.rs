@@ -1,4 +1,8 @@
 pub mod monitor;
 pub mod status;
 pub mod watch_cpu;
-pub mod logs;
\ No newline at end of file
+pub mod logs;
+pub mod trace_pipe;
+pub mod exporter;
+pub mod probe;
+pub mod doctor;
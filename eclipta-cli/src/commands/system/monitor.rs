@@ -15,7 +15,7 @@ use tui::{
 };
 
 use crate::utils::paths::default_state_path;
-use crate::utils::state::load_state;
+use crate::utils::state::{lease_for, load_state};
 use crate::utils::db::ensure_db_ready;
 use crate::db::programs::list_programs;
 use serde_json::Value;
@@ -30,6 +30,8 @@ struct AttachmentRow {
     status: String,
     pinned: String,
     created: String,
+    /// Remaining TTL or window state of `load --ttl` / `--active-window` programs
+    ttl: String,
 }
 
 fn proc_alive(pid: u32) -> bool {
@@ -139,6 +141,10 @@ pub async fn handle_monitor() -> io::Result<()> {
         }
 
         let st = load_state(&default_state_path());
+        let now = Utc::now().timestamp();
+        let lease_text = |name: &str| {
+            st.leases.iter().find(|l| l.name == name).map(|l| l.describe(now)).unwrap_or_else(|| "-".to_string())
+        };
 
         let (prog_index, link_index) = match get_live_bpf_indices().await {
             Ok(v) => v,
@@ -174,6 +180,7 @@ pub async fn handle_monitor() -> io::Result<()> {
                     status: status.to_string(),
                     pinned: pinned.to_string(),
                     created,
+                    ttl: lease_text(&r.name),
                 }
             })
             .collect();
@@ -184,6 +191,7 @@ pub async fn handle_monitor() -> io::Result<()> {
                 let any_attached = link_index.iter().next();
                 for p in programs {
                     if state_names.contains(&p.title) { continue; }
+                    let ttl = lease_for(&st, p.id, &p.title).map(|l| l.describe(now)).unwrap_or_else(|| "-".to_string());
                     let (pid_str, hook_str, status_str) = if let Some(l) = any_attached {
                         let pid_str = l.pid.map(|x| x.to_string()).unwrap_or("-".to_string());
                         let hook_str = render_hook(l);
@@ -199,11 +207,30 @@ pub async fn handle_monitor() -> io::Result<()> {
                         status: status_str,
                         pinned: "n/a".to_string(),
                         created: "-".to_string(),
+                        ttl,
                     });
                 }
             }
         }
 
+        // Leases of objects loaded by path have no other row
+        for l in &st.leases {
+            if rows_data.iter().any(|r| r.name == l.name) { continue; }
+            rows_data.push(AttachmentRow {
+                name: l.name.clone(),
+                kind: "lease".to_string(),
+                hook: l.iface.clone().unwrap_or_else(|| "-".to_string()),
+                pid: "-".to_string(),
+                status: if l.suspended { "offline" } else { "online" }.to_string(),
+                pinned: "n/a".to_string(),
+                created: DateTime::from_timestamp(l.loaded_at, 0)
+                    .unwrap_or(DateTime::<Utc>::UNIX_EPOCH)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+                ttl: l.describe(now),
+            });
+        }
+
         terminal.draw(|f| {
             let size = f.size();
             let chunks = Layout::default()
@@ -213,7 +240,7 @@ pub async fn handle_monitor() -> io::Result<()> {
 
             let header = Row::new(
                 vec![
-                    "Program", "Kind", "Hook", "PID", "Status", "Pinned", "Created",
+                    "Program", "Kind", "Hook", "PID", "Status", "Pinned", "Created", "TTL",
                 ]
                 .into_iter()
                 .map(|h| Cell::from(Span::styled(h, Style::default().fg(Color::Yellow))))
@@ -246,6 +273,7 @@ pub async fn handle_monitor() -> io::Result<()> {
                         Cell::from(status_span),
                         Cell::from(pinned_span),
                         Cell::from(a.created.clone()),
+                        Cell::from(a.ttl.clone()),
                     ])
                 })
                 .collect();
@@ -265,6 +293,7 @@ pub async fn handle_monitor() -> io::Result<()> {
                     Constraint::Length(10),
                     Constraint::Length(8),
                     Constraint::Length(20),
+                    Constraint::Length(24),
                 ])
                 .column_spacing(1);
 
@@ -0,0 +1,388 @@
+use crate::commands::ebpf::map_state::map_type_name;
+use crate::utils::bpftool::bpftool_json;
+use crate::utils::btf::{Btf, BtfType};
+use crate::utils::caps;
+use crate::utils::logger::{info, success, warn};
+use crate::utils::paths::default_probe_path;
+use anyhow::{anyhow, Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Local, Utc};
+use clap::Args;
+use nix::sys::resource::{getrlimit, Resource, RLIM_INFINITY};
+use object::{Object, ObjectSection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// `BPF_JMP | BPF_CALL`
+const CALL_OPCODE: u8 = 0x85;
+
+/// Helper names by id, as in `___BPF_FUNC_MAPPER` of include/uapi/linux/bpf.h
+const HELPERS: &[&str] = &[
+    "unspec", "map_lookup_elem", "map_update_elem", "map_delete_elem", "probe_read", "ktime_get_ns",
+    "trace_printk", "get_prandom_u32", "get_smp_processor_id", "skb_store_bytes", "l3_csum_replace",
+    "l4_csum_replace", "tail_call", "clone_redirect", "get_current_pid_tgid", "get_current_uid_gid",
+    "get_current_comm", "get_cgroup_classid", "skb_vlan_push", "skb_vlan_pop", "skb_get_tunnel_key",
+    "skb_set_tunnel_key", "perf_event_read", "redirect", "get_route_realm", "perf_event_output",
+    "skb_load_bytes", "get_stackid", "csum_diff", "skb_get_tunnel_opt", "skb_set_tunnel_opt",
+    "skb_change_proto", "skb_change_type", "skb_under_cgroup", "get_hash_recalc", "get_current_task",
+    "probe_write_user", "current_task_under_cgroup", "skb_change_tail", "skb_pull_data", "csum_update",
+    "set_hash_invalid", "get_numa_node_id", "skb_change_head", "xdp_adjust_head", "probe_read_str",
+    "get_socket_cookie", "get_socket_uid", "set_hash", "setsockopt", "skb_adjust_room", "redirect_map",
+    "sk_redirect_map", "sock_map_update", "xdp_adjust_meta", "perf_event_read_value",
+    "perf_prog_read_value", "getsockopt", "override_return", "sock_ops_cb_flags_set",
+    "msg_redirect_map", "msg_apply_bytes", "msg_cork_bytes", "msg_pull_data", "bind", "xdp_adjust_tail",
+    "skb_get_xfrm_state", "get_stack", "skb_load_bytes_relative", "fib_lookup", "sock_hash_update",
+    "msg_redirect_hash", "sk_redirect_hash", "lwt_push_encap", "lwt_seg6_store_bytes",
+    "lwt_seg6_adjust_srh", "lwt_seg6_action", "rc_repeat", "rc_keydown", "skb_cgroup_id",
+    "get_current_cgroup_id", "get_local_storage", "sk_select_reuseport", "skb_ancestor_cgroup_id",
+    "sk_lookup_tcp", "sk_lookup_udp", "sk_release", "map_push_elem", "map_pop_elem", "map_peek_elem",
+    "msg_push_data", "msg_pop_data", "rc_pointer_rel", "spin_lock", "spin_unlock", "sk_fullsock",
+    "tcp_sock", "skb_ecn_set_ce", "get_listener_sock", "skc_lookup_tcp", "tcp_check_syncookie",
+    "sysctl_get_name", "sysctl_get_current_value", "sysctl_get_new_value", "sysctl_set_new_value",
+    "strtol", "strtoul", "sk_storage_get", "sk_storage_delete", "send_signal", "tcp_gen_syncookie",
+    "skb_output", "probe_read_user", "probe_read_kernel", "probe_read_user_str", "probe_read_kernel_str",
+    "tcp_send_ack", "send_signal_thread", "jiffies64", "read_branch_records", "get_ns_current_pid_tgid",
+    "xdp_output", "get_netns_cookie", "get_current_ancestor_cgroup_id", "sk_assign", "ktime_get_boot_ns",
+    "seq_printf", "seq_write", "sk_cgroup_id", "sk_ancestor_cgroup_id", "ringbuf_output",
+    "ringbuf_reserve", "ringbuf_submit", "ringbuf_discard", "ringbuf_query", "csum_level",
+    "skc_to_tcp6_sock", "skc_to_tcp_sock", "skc_to_tcp_timewait_sock", "skc_to_tcp_request_sock",
+    "skc_to_udp6_sock", "get_task_stack", "load_hdr_opt", "store_hdr_opt", "reserve_hdr_opt",
+    "inode_storage_get", "inode_storage_delete", "d_path", "copy_from_user", "snprintf_btf",
+    "seq_printf_btf", "skb_cgroup_classid", "redirect_neigh", "per_cpu_ptr", "this_cpu_ptr",
+    "redirect_peer", "task_storage_get", "task_storage_delete", "get_current_task_btf", "bprm_opts_set",
+    "ktime_get_coarse_ns", "ima_inode_hash", "sock_from_file", "check_mtu", "for_each_map_elem",
+    "snprintf", "sys_bpf", "btf_find_by_name_kind", "sys_close", "timer_init", "timer_set_callback",
+    "timer_start", "timer_cancel", "get_func_ip", "get_attach_cookie", "task_pt_regs",
+    "get_branch_snapshot", "trace_vprintk", "skc_to_unix_sock", "kallsyms_lookup_name", "find_vma",
+    "loop", "strncmp", "get_func_arg", "get_func_ret", "get_func_arg_cnt", "get_retval", "set_retval",
+    "xdp_get_buff_len", "xdp_load_bytes", "xdp_store_bytes", "copy_from_user_task", "skb_set_tstamp",
+    "ima_file_hash", "kptr_xchg", "map_lookup_percpu_elem", "skc_to_mptcp_sock", "dynptr_from_mem",
+    "ringbuf_reserve_dynptr", "ringbuf_submit_dynptr", "ringbuf_discard_dynptr", "dynptr_read",
+    "dynptr_write", "dynptr_data", "tcp_raw_gen_syncookie_ipv4", "tcp_raw_gen_syncookie_ipv6",
+    "tcp_raw_check_syncookie_ipv4", "tcp_raw_check_syncookie_ipv6", "ktime_get_tai_ns",
+    "user_ringbuf_drain", "cgrp_storage_get", "cgrp_storage_delete",
+];
+
+#[derive(Args, Debug)]
+pub struct ProbeOptions {
+    /// Show the cached results instead of probing again
+    #[arg(long)]
+    pub cached: bool,
+
+    /// Print the results as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// List the helpers available to each program type
+    #[arg(short, long)]
+    pub verbose: bool,
+}
+
+/// What the running kernel supports, as found by `eclipta probe`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KernelFeatures {
+    pub kernel: String,
+    /// Results are only trusted on the boot they were probed on
+    pub boot_id: String,
+    pub probed_at: DateTime<Utc>,
+    pub program_types: BTreeMap<String, bool>,
+    pub map_types: BTreeMap<String, bool>,
+    /// Helpers each program type may call, without the `bpf_` prefix
+    pub helpers: BTreeMap<String, Vec<String>>,
+    /// Values of `enum bpf_attach_type` in the kernel's BTF, e.g. "cgroup_inet_ingress"
+    pub attach_types: Vec<String>,
+    /// Instruction set and verifier features, e.g. "bounded_loops"
+    pub misc: BTreeMap<String, bool>,
+    /// bpf_jit_enable: 0 interpreter only, 1 JIT, 2 JIT with debug output
+    pub jit_enable: Option<u32>,
+    pub jit_harden: Option<u32>,
+    /// 0 allowed, 1 disabled until reboot, 2 disabled but root may re-enable it
+    pub unprivileged_bpf_disabled: Option<u32>,
+    /// none, integrity or confidentiality
+    pub lockdown: Option<String>,
+    /// RLIMIT_MEMLOCK in bytes; None is unlimited
+    pub memlock_soft: Option<u64>,
+    pub memlock_hard: Option<u64>,
+}
+
+pub async fn handle_probe(opts: ProbeOptions) -> Result<()> {
+    let features = if opts.cached {
+        KernelFeatures::cached().ok_or_else(|| anyhow!("No probe results for this boot; run `eclipta probe`"))?
+    } else {
+        if !opts.json {
+            info("Probing kernel BPF features with test loads...");
+        }
+        let features = probe().await?;
+        features.save()?;
+        features
+    };
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&features)?);
+    } else {
+        print_features(&features, opts.verbose);
+    }
+    Ok(())
+}
+
+impl KernelFeatures {
+    /// Results probed on this boot of this kernel, if any
+    pub fn cached() -> Option<KernelFeatures> {
+        let data = std::fs::read_to_string(default_probe_path()).ok()?;
+        let features: KernelFeatures = serde_json::from_str(&data).ok()?;
+        (features.boot_id == boot_id() && features.kernel == kernel_release()).then_some(features)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = default_probe_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Program types, helpers and map types `object` uses that this kernel lacks. Only what
+    /// the probe positively found missing counts, so unknown names never block a load
+    pub fn missing_for(&self, object: &Path) -> Result<Vec<String>> {
+        let data = std::fs::read(object).context("Failed to read eBPF object")?;
+        let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+        let mut missing = Vec::new();
+        for section in obj.sections() {
+            let Ok(name) = section.name() else { continue };
+            let Some(prog_type) = section_prog_type(name) else { continue };
+            if self.program_types.get(prog_type) == Some(&false) {
+                missing.push(format!("the {} program type (section {})", prog_type, name));
+                continue;
+            }
+            let Some(available) = self.helpers.get(prog_type).filter(|h| !h.is_empty()) else { continue };
+            for helper in helper_calls(section.data()?).into_iter().filter_map(|id| HELPERS.get(id as usize)) {
+                if !available.iter().any(|h| h == helper) {
+                    missing.push(format!("helper bpf_{} for {} programs (section {})", helper, prog_type, name));
+                }
+            }
+        }
+        if let Some(btf) = Btf::from_elf(object)? {
+            for map in btf.map_names() {
+                let Some(map_type) = btf.map_uint(&map, "type").map(map_type_name) else { continue };
+                if self.map_types.get(&map_type) == Some(&false) {
+                    missing.push(format!("the {} map type (map '{}')", map_type, map));
+                }
+            }
+        }
+        Ok(missing)
+    }
+}
+
+/// Refuse an object needing something the cached probe found missing. Without a probe of this
+/// boot nothing is checked, and the kernel reports the failure at load time instead
+pub fn check_object_support(object: &Path) -> Result<()> {
+    let Some(features) = KernelFeatures::cached() else { return Ok(()) };
+    let missing = features.missing_for(object)?;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Your kernel ({}) lacks what {} needs:\n  {}\n(found by `eclipta probe` at {}; probe again after enabling features)",
+        features.kernel,
+        object.display(),
+        missing.join("\n  "),
+        features.probed_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+    ))
+}
+
+/// Refuse an object when this process lacks a capability its programs need, naming each one
+pub fn check_object_caps(object: &Path) -> Result<()> {
+    require_object_caps(&format!("load {}", object.display()), object)
+}
+
+/// Like `check_object_caps`, for commands that `what` describes, e.g. "upgrade 'filter'"
+pub fn require_object_caps(what: &str, object: &Path) -> Result<()> {
+    let data = std::fs::read(object).context("Failed to read eBPF object")?;
+    let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+    let programs: Vec<(String, &str)> = obj
+        .sections()
+        .filter_map(|s| {
+            let name = s.name().ok()?;
+            Some((name.to_string(), section_prog_type(name)?))
+        })
+        .collect();
+    caps::require(what, &programs, true)
+}
+
+async fn probe() -> Result<KernelFeatures> {
+    let v = bpftool_json(&["feature".to_string(), "probe".to_string(), "kernel".to_string()]).await?;
+    let flags = |section: &str, suffix: &str| -> BTreeMap<String, bool> {
+        v.get(section)
+            .and_then(Value::as_object)
+            .map(|o| {
+                o.iter()
+                    .filter_map(|(k, val)| {
+                        let name = k.strip_prefix("have_")?.strip_suffix(suffix)?;
+                        Some((name.to_string(), val.as_bool()?))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let helpers = v.get("helpers")
+        .and_then(Value::as_object)
+        .map(|o| {
+            o.iter()
+                .filter_map(|(k, list)| {
+                    let prog_type = k.strip_suffix("_available_helpers")?;
+                    let names = list.as_array()?
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|h| h.trim_start_matches("bpf_").to_string())
+                        .collect();
+                    Some((prog_type.to_string(), names))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let (memlock_soft, memlock_hard) = match getrlimit(Resource::RLIMIT_MEMLOCK) {
+        Ok((soft, hard)) => ((soft != RLIM_INFINITY).then_some(soft), (hard != RLIM_INFINITY).then_some(hard)),
+        Err(_) => (None, None),
+    };
+    Ok(KernelFeatures {
+        kernel: kernel_release(),
+        boot_id: boot_id(),
+        probed_at: Utc::now(),
+        program_types: flags("program_types", "_prog_type"),
+        map_types: flags("map_types", "_map_type"),
+        helpers,
+        attach_types: attach_types(),
+        misc: flags("misc", ""),
+        jit_enable: read_u32("/proc/sys/net/core/bpf_jit_enable"),
+        jit_harden: read_u32("/proc/sys/net/core/bpf_jit_harden"),
+        unprivileged_bpf_disabled: read_u32("/proc/sys/kernel/unprivileged_bpf_disabled"),
+        lockdown: lockdown_mode(),
+        memlock_soft,
+        memlock_hard,
+    })
+}
+
+/// bpftool's program type for an ELF section name, following libbpf's section conventions
+pub fn section_prog_type(section: &str) -> Option<&'static str> {
+    let mut parts = section.split('/');
+    let kind = parts.next().unwrap_or(section);
+    Some(match kind {
+        "xdp" | "xdp_drop" | "xdp.frags" => "xdp",
+        "tc" | "tcx" | "classifier" | "tc_ingress" | "tc_egress" => "sched_cls",
+        "action" => "sched_act",
+        "kprobe" | "kretprobe" | "uprobe" | "uretprobe" | "ksyscall" | "kretsyscall" | "usdt" => "kprobe",
+        "tracepoint" | "tp" => "tracepoint",
+        "raw_tracepoint" | "raw_tp" => "raw_tracepoint",
+        "socket" | "socket_filter" => "socket_filter",
+        "perf_event" => "perf_event",
+        "cgroup_skb" => "cgroup_skb",
+        "sockops" => "sock_ops",
+        "sk_skb" => "sk_skb",
+        "sk_msg" => "sk_msg",
+        "sk_lookup" => "sk_lookup",
+        "flow_dissector" => "flow_dissector",
+        "fentry" | "fexit" | "fmod_ret" | "tp_btf" | "iter" => "tracing",
+        "lsm" => "lsm",
+        "cgroup" => match parts.next()? {
+            "skb" => "cgroup_skb",
+            "sock" | "sock_create" | "sock_release" | "post_bind4" | "post_bind6" => "cgroup_sock",
+            "dev" => "cgroup_device",
+            "sysctl" => "cgroup_sysctl",
+            "getsockopt" | "setsockopt" => "cgroup_sockopt",
+            _ => "cgroup_sock_addr",
+        },
+        _ => return None,
+    })
+}
+
+/// Ids of the helpers a program section calls
+fn helper_calls(code: &[u8]) -> BTreeSet<u32> {
+    code.chunks_exact(8)
+        // src_reg 0 is a helper; 1 and 2 are calls to subprograms and kfuncs
+        .filter(|insn| insn[0] == CALL_OPCODE && insn[1] >> 4 == 0)
+        .filter_map(|insn| u32::try_from(LittleEndian::read_i32(&insn[4..])).ok())
+        .collect()
+}
+
+fn attach_types() -> Vec<String> {
+    let Ok(btf) = Btf::from_file(Path::new("/sys/kernel/btf/vmlinux")) else { return Vec::new() };
+    let values = btf.types().find_map(|(_, t)| match t {
+        BtfType::Enum { name, values, .. } if name == "bpf_attach_type" => Some(values),
+        _ => None,
+    });
+    values
+        .map(|values| values.iter().filter_map(|(n, _)| n.strip_prefix("BPF_")).map(str::to_lowercase).collect())
+        .unwrap_or_default()
+}
+
+/// The bracketed entry of /sys/kernel/security/lockdown, e.g. "none [integrity] confidentiality"
+fn lockdown_mode() -> Option<String> {
+    let s = std::fs::read_to_string("/sys/kernel/security/lockdown").ok()?;
+    let start = s.find('[')? + 1;
+    let end = s[start..].find(']')? + start;
+    Some(s[start..end].to_string())
+}
+
+pub fn kernel_release() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/osrelease").map(|s| s.trim().to_string()).unwrap_or_default()
+}
+
+fn boot_id() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/random/boot_id").map(|s| s.trim().to_string()).unwrap_or_default()
+}
+
+fn read_u32(path: &str) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn print_features(f: &KernelFeatures, verbose: bool) {
+    success(&format!("Kernel {} (probed {})", f.kernel, f.probed_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")));
+
+    let jit = match f.jit_enable {
+        Some(0) => "off (interpreter only)".to_string(),
+        Some(1) => "on".to_string(),
+        Some(n) => format!("on (mode {})", n),
+        None => "unknown".to_string(),
+    };
+    println!("  JIT: {}{}", jit, f.jit_harden.filter(|h| *h > 0).map(|h| format!(", hardening {}", h)).unwrap_or_default());
+    let unprivileged = match f.unprivileged_bpf_disabled {
+        Some(0) => "allowed",
+        Some(1) => "disabled until reboot",
+        Some(2) => "disabled (root may re-enable)",
+        _ => "unknown",
+    };
+    println!("  Unprivileged BPF: {}", unprivileged);
+    println!("  Lockdown: {}", f.lockdown.as_deref().unwrap_or("not available"));
+    let limit = |l: Option<u64>| l.map(|b| format!("{} KiB", b / 1024)).unwrap_or_else(|| "unlimited".to_string());
+    println!("  RLIMIT_MEMLOCK: {} soft, {} hard", limit(f.memlock_soft), limit(f.memlock_hard));
+    if f.lockdown.as_deref() == Some("confidentiality") {
+        warn("Lockdown in confidentiality mode blocks bpf_probe_read and similar helpers");
+    }
+
+    summarize("Program types", &f.program_types);
+    summarize("Map types", &f.map_types);
+    summarize("Features", &f.misc);
+    println!("  Attach types known to the kernel: {}", f.attach_types.len());
+
+    println!("  Helpers per program type:");
+    for (prog_type, helpers) in &f.helpers {
+        if verbose {
+            println!("    {} ({}): {}", prog_type, helpers.len(), helpers.join(", "));
+        } else {
+            println!("    {}: {}", prog_type, helpers.len());
+        }
+    }
+}
+
+fn summarize(what: &str, flags: &BTreeMap<String, bool>) {
+    let missing: Vec<&str> = flags.iter().filter(|(_, ok)| !**ok).map(|(n, _)| n.as_str()).collect();
+    if missing.is_empty() {
+        println!("  {}: all {} supported", what, flags.len());
+    } else {
+        println!("  {}: {} of {} supported; missing: {}", what, flags.len() - missing.len(), flags.len(), missing.join(", "));
+    }
+}
@@ -1,5 +1,14 @@
 use crate::utils::db::ensure_db_ready;
-use crate::db::programs::{get_program_by_id, list_programs};
+use crate::utils::bpf_stats::RuntimeStats;
+use crate::utils::caps::{CapSets, Capability};
+use crate::commands::ebpf::hook::registry_prog_ids;
+use crate::utils::bpftool::{map_dump, map_list, MapInfo, MapRef};
+use crate::utils::logger::warn;
+use crate::utils::paths::default_state_path;
+use crate::utils::state::{lease_for, load_state};
+use crate::utils::otlp::{MetricKind, MetricPoint, OtlpExporter, OtlpOptions};
+use crate::db::metrics::{insert_samples, prune_metrics, MetricSample};
+use crate::db::programs::{get_program_by_id, list_programs, Program};
 use clap::Args;
 use std::fs;
 use std::process::Command;
@@ -30,6 +39,18 @@ pub struct StatusOptions {
     /// Output format: table, json, or summary
     #[arg(long, default_value = "table")]
     pub format: String,
+
+    /// Window for measuring run rates, e.g. "1s" or "500ms"
+    #[arg(long, default_value = "1s")]
+    pub sample: String,
+
+    /// Store a sample of every program's metrics in the history table (each refresh with --watch)
+    #[arg(long)]
+    pub record: bool,
+
+    /// Also send the metrics to an OTLP collector (every refresh with --watch)
+    #[command(flatten)]
+    pub otlp: OtlpOptions,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,6 +62,9 @@ pub struct ProgramStatus {
     pub kernel_status: KernelStatus,
     pub attachment_status: AttachmentStatus,
     pub performance_metrics: PerformanceMetrics,
+    /// Remaining TTL or window state from `load --ttl` / `--active-window`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lease: Option<String>,
     pub last_updated: DateTime<Utc>,
 }
 
@@ -61,12 +85,23 @@ pub struct AttachmentStatus {
     pub hook_point: Option<String>,
 }
 
+/// Kernel runtime stats of the program's loaded instances (BPF_ENABLE_STATS)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PerformanceMetrics {
+    /// False when the kernel is not counting, so the numbers below stay at zero
+    pub stats_enabled: bool,
+    pub run_cnt: Option<u64>,
+    pub run_time_ns: Option<u64>,
+    pub recursion_misses: Option<u64>,
+    /// Same as `run_cnt`; kept for JSON consumers of earlier releases
     pub events_processed: Option<u64>,
+    /// Same as `recursion_misses`; kept for JSON consumers of earlier releases
+    pub error_count: Option<u64>,
+    pub events_per_sec: Option<f64>,
+    pub avg_ns_per_run: Option<f64>,
+    /// Percent of one CPU spent in the program during the sample window
     pub cpu_usage: Option<f64>,
     pub memory_usage: Option<u64>,
-    pub error_count: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -83,6 +118,15 @@ pub struct BpfSupport {
     pub bpf_fs_mounted: bool,
     pub debug_fs_mounted: bool,
     pub cap_sys_admin: bool,
+    /// CAP_BPF or CAP_SYS_ADMIN: programs and maps can be loaded
+    #[serde(default)]
+    pub cap_bpf: bool,
+    /// CAP_PERFMON or CAP_SYS_ADMIN: tracing programs can be loaded
+    #[serde(default)]
+    pub cap_perfmon: bool,
+    /// Network programs can be loaded and attached
+    #[serde(default)]
+    pub cap_net_admin: bool,
     pub bpf_verifier_available: bool,
     pub btf_support: bool,
 }
@@ -96,26 +140,56 @@ pub struct SystemResources {
 }
 
 pub async fn run_status(opts: StatusOptions) -> Result<()> {
+    let window = humantime::parse_duration(&opts.sample)
+        .map_err(|e| anyhow!("Invalid --sample '{}': {}", opts.sample, e))?;
+    let mut stats = RuntimeStats::enable();
+    if !stats.enabled() {
+        warn("BPF runtime stats are off (run as root or set kernel.bpf_stats_enabled=1); run counts will be zero");
+    }
+
+    let otlp = OtlpExporter::start(&opts.otlp)?;
+
     if opts.watch {
-        return run_status_watch(opts).await;
+        let result = run_status_watch(opts, &mut stats, window, otlp.as_ref()).await;
+        // Deliver the last refresh's metrics before exiting on Ctrl+C
+        if let Some(otlp) = otlp {
+            otlp.shutdown().await;
+        }
+        return result;
     }
 
+    stats.sample(window).await?;
     if let Some(program_id) = opts.id {
-        show_program_status(program_id, opts.detailed, &opts.format).await?;
+        show_program_status(program_id, opts.detailed, &opts.format, &stats, otlp.as_ref()).await?;
     } else {
-        show_system_status(&opts).await?;
+        show_system_status(&opts, &stats, otlp.as_ref()).await?;
+    }
+    if opts.record {
+        record_history(&stats, opts.id).await?;
     }
 
+    if let Some(otlp) = otlp {
+        otlp.shutdown().await;
+    }
     Ok(())
 }
 
-async fn show_program_status(program_id: i32, detailed: bool, format: &str) -> Result<()> {
+async fn show_program_status(
+    program_id: i32,
+    detailed: bool,
+    format: &str,
+    stats: &RuntimeStats,
+    otlp: Option<&OtlpExporter>,
+) -> Result<()> {
     let pool = ensure_db_ready().await
         .map_err(|e| anyhow!("Database error: {}", e))?;
     let program = get_program_by_id(&pool, program_id).await?
         .ok_or_else(|| anyhow!("Program with ID {} not found", program_id))?;
 
-    let program_status = build_program_status(&program).await?;
+    let program_status = build_program_status(&program, stats).await?;
+    if let Some(otlp) = otlp {
+        export_program_metrics(otlp, &program_status);
+    }
 
     match format {
         "json" => {
@@ -132,14 +206,14 @@ async fn show_program_status(program_id: i32, detailed: bool, format: &str) -> R
     Ok(())
 }
 
-async fn show_system_status(opts: &StatusOptions) -> Result<()> {
+async fn show_system_status(opts: &StatusOptions, stats: &RuntimeStats, otlp: Option<&OtlpExporter>) -> Result<()> {
     let pool = ensure_db_ready().await
         .map_err(|e| anyhow!("Database error: {}", e))?;
     let programs = list_programs(&pool).await?;
     
     let mut program_statuses = Vec::new();
     for program in &programs {
-        if let Some(status) = build_program_status(program).await.ok() {
+        if let Ok(status) = build_program_status(program, stats).await {
             if let Some(ref status_filter) = opts.status {
                 if status.db_status == *status_filter {
                     program_statuses.push(status);
@@ -151,6 +225,12 @@ async fn show_system_status(opts: &StatusOptions) -> Result<()> {
     }
 
     let system_status = build_system_status(&program_statuses).await?;
+    if let Some(otlp) = otlp {
+        export_system_metrics(otlp, &system_status);
+        for status in &program_statuses {
+            export_program_metrics(otlp, status);
+        }
+    }
 
     match opts.format.as_str() {
         "json" => {
@@ -171,10 +251,12 @@ async fn show_system_status(opts: &StatusOptions) -> Result<()> {
     Ok(())
 }
 
-async fn build_program_status(program: &crate::db::programs::Program) -> Result<ProgramStatus> {
+async fn build_program_status(program: &crate::db::programs::Program, stats: &RuntimeStats) -> Result<ProgramStatus> {
     let kernel_status = get_kernel_status(&program.title).await?;
     let attachment_status = get_attachment_status(&program.title).await?;
-    let performance_metrics = get_performance_metrics(&program.title).await?;
+    let performance_metrics = get_performance_metrics(program, stats).await;
+    let lease = lease_for(&load_state(&default_state_path()), program.id, &program.title)
+        .map(|l| l.describe(Utc::now().timestamp()));
 
     Ok(ProgramStatus {
         id: program.id,
@@ -184,6 +266,7 @@ async fn build_program_status(program: &crate::db::programs::Program) -> Result<
         kernel_status,
         attachment_status,
         performance_metrics,
+        lease,
         last_updated: Utc::now(),
     })
 }
@@ -312,15 +395,158 @@ async fn get_attachment_status(_program_name: &str) -> Result<AttachmentStatus>
     })
 }
 
-async fn get_performance_metrics(_program_name: &str) -> Result<PerformanceMetrics> {
-    // This would integrate with actual eBPF program metrics
-    // For now, return placeholder data
-    Ok(PerformanceMetrics {
-        events_processed: Some(0),
-        cpu_usage: Some(0.0),
-        memory_usage: Some(0),
-        error_count: Some(0),
-    })
+async fn get_performance_metrics(program: &crate::db::programs::Program, stats: &RuntimeStats) -> PerformanceMetrics {
+    // Only kernel programs pinned for this registry entry, not whatever shares its function names
+    let ids = registry_prog_ids(program).await;
+    let s = stats.for_programs(&ids);
+    if s.prog_ids.is_empty() {
+        return PerformanceMetrics {
+            stats_enabled: stats.enabled(),
+            run_cnt: None,
+            run_time_ns: None,
+            recursion_misses: None,
+            events_processed: None,
+            error_count: None,
+            events_per_sec: None,
+            avg_ns_per_run: None,
+            cpu_usage: None,
+            memory_usage: None,
+        };
+    }
+
+    PerformanceMetrics {
+        stats_enabled: stats.enabled(),
+        run_cnt: Some(s.run_cnt),
+        run_time_ns: Some(s.run_time_ns),
+        recursion_misses: Some(s.recursion_misses),
+        events_processed: Some(s.run_cnt),
+        error_count: Some(s.recursion_misses),
+        events_per_sec: s.events_per_sec,
+        avg_ns_per_run: s.avg_ns_per_run,
+        cpu_usage: s.cpu_percent,
+        memory_usage: Some(s.bytes_memlock),
+    }
+}
+
+/// Write the current sample to program_metrics, then drop samples past their retention
+async fn record_history(stats: &RuntimeStats, program_id: Option<i32>) -> Result<()> {
+    let pool = ensure_db_ready().await
+        .map_err(|e| anyhow!("Database error: {}", e))?;
+    let programs = match program_id {
+        Some(id) => get_program_by_id(&pool, id).await?.into_iter().collect(),
+        None => list_programs(&pool).await?,
+    };
+    let maps = map_list().await.unwrap_or_default();
+
+    let mut samples = Vec::new();
+    for program in &programs {
+        samples.extend(program_samples(program, stats, &maps).await);
+    }
+    insert_samples(&pool, Utc::now(), &samples).await?;
+    prune_metrics(&pool).await?;
+    Ok(())
+}
+
+async fn program_samples(program: &Program, stats: &RuntimeStats, maps: &[MapInfo]) -> Vec<MetricSample> {
+    let ids = registry_prog_ids(program).await;
+    let s = stats.for_programs(&ids);
+    let sample = |metric: &str, map_name: &str, value: f64| MetricSample {
+        program_id: program.id,
+        metric: metric.to_string(),
+        map_name: map_name.to_string(),
+        value,
+    };
+
+    let mut samples = vec![sample("loaded", "", s.prog_ids.len() as f64)];
+    if s.prog_ids.is_empty() {
+        return samples;
+    }
+    samples.push(sample("run_cnt", "", s.run_cnt as f64));
+    samples.push(sample("run_time_ns", "", s.run_time_ns as f64));
+    samples.push(sample("recursion_misses", "", s.recursion_misses as f64));
+    samples.push(sample("memlock_bytes", "", s.bytes_memlock as f64));
+    if let Some(rate) = s.events_per_sec {
+        samples.push(sample("events_per_sec", "", rate));
+    }
+    if let Some(avg) = s.avg_ns_per_run {
+        samples.push(sample("avg_ns_per_run", "", avg));
+    }
+
+    let mut map_ids: Vec<u32> = stats.programs(&ids).iter().flat_map(|p| p.map_ids.iter().copied()).collect();
+    map_ids.sort_unstable();
+    map_ids.dedup();
+    for info in maps.iter().filter(|m| map_ids.contains(&m.id)) {
+        // Ring buffers and perf arrays cannot be dumped
+        if let Ok(entries) = map_dump(&MapRef::Id(info.id)).await {
+            samples.push(sample("map_entries", &info.name, entries.len() as f64));
+        }
+    }
+    samples
+}
+
+fn metric(name: &str, description: &str, unit: &str, kind: MetricKind, value: f64, attributes: &serde_json::Map<String, serde_json::Value>) -> MetricPoint {
+    MetricPoint {
+        name: name.to_string(),
+        description: description.to_string(),
+        unit: unit.to_string(),
+        kind,
+        attributes: attributes.clone(),
+        value,
+    }
+}
+
+/// OTLP counterparts of the status columns: counters as sums, rates and sizes as gauges
+fn export_program_metrics(otlp: &OtlpExporter, status: &ProgramStatus) {
+    let mut attrs = serde_json::Map::new();
+    attrs.insert("program.id".to_string(), status.id.into());
+    attrs.insert("program.title".to_string(), status.title.clone().into());
+    attrs.insert("program.version".to_string(), status.version.clone().into());
+
+    let flag = |b: bool| if b { 1.0 } else { 0.0 };
+    otlp.metric(metric("eclipta.program.loaded", "Program is loaded in the kernel", "1", MetricKind::Gauge, flag(status.kernel_status.loaded), &attrs));
+    otlp.metric(metric("eclipta.program.attached", "Program is attached to its hook", "1", MetricKind::Gauge, flag(status.attachment_status.attached), &attrs));
+
+    let m = &status.performance_metrics;
+    let counters = [
+        ("eclipta.program.runs", "Times the program ran", "{run}", m.run_cnt),
+        ("eclipta.program.run_time", "Time spent running the program", "ns", m.run_time_ns),
+        ("eclipta.program.recursion_misses", "Runs skipped because the program was already running", "{run}", m.recursion_misses),
+    ];
+    for (name, description, unit, value) in counters {
+        if let Some(v) = value {
+            otlp.metric(metric(name, description, unit, MetricKind::Sum, v as f64, &attrs));
+        }
+    }
+    let gauges = [
+        ("eclipta.program.events_per_second", "Runs per second in the sample window", "{run}/s", m.events_per_sec),
+        ("eclipta.program.avg_run_time", "Average cost of one run in the sample window", "ns", m.avg_ns_per_run),
+        ("eclipta.program.cpu_usage", "Percent of one CPU spent in the program", "%", m.cpu_usage),
+        ("eclipta.program.memlock", "Locked memory of the program", "By", m.memory_usage.map(|b| b as f64)),
+    ];
+    for (name, description, unit, value) in gauges {
+        if let Some(v) = value {
+            otlp.metric(metric(name, description, unit, MetricKind::Gauge, v, &attrs));
+        }
+    }
+}
+
+fn export_system_metrics(otlp: &OtlpExporter, system: &SystemStatus) {
+    let mut attrs = serde_json::Map::new();
+    attrs.insert("os.kernel.version".to_string(), system.kernel_version.clone().into());
+
+    let r = &system.system_resources;
+    let gib = 1024.0 * 1024.0 * 1024.0;
+    let gauges = [
+        ("eclipta.system.cpu_cores", "Online CPU cores", "{core}", r.cpu_cores as f64),
+        ("eclipta.system.memory.total", "Total memory", "By", r.memory_total_gb * gib),
+        ("eclipta.system.memory.available", "Available memory", "By", r.memory_available_gb * gib),
+        ("eclipta.system.uptime", "System uptime", "s", r.uptime_seconds as f64),
+        ("eclipta.programs.loaded", "Registered programs loaded in the kernel", "{program}", system.loaded_programs_count as f64),
+        ("eclipta.programs.attached", "Registered programs attached to a hook", "{program}", system.active_programs_count as f64),
+    ];
+    for (name, description, unit, value) in gauges {
+        otlp.metric(metric(name, description, unit, MetricKind::Gauge, value, &attrs));
+    }
 }
 
 async fn build_system_status(program_statuses: &[ProgramStatus]) -> Result<SystemStatus> {
@@ -350,11 +576,12 @@ fn get_kernel_version() -> Result<String> {
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-fn check_bpf_support() -> Result<BpfSupport> {
+pub fn check_bpf_support() -> Result<BpfSupport> {
     let bpf_fs_mounted = fs::metadata("/sys/fs/bpf").is_ok();
     let debug_fs_mounted = fs::metadata("/sys/kernel/debug").is_ok();
-    let uid = nix::unistd::Uid::effective();
-    let cap_sys_admin = uid.is_root();
+    // Capabilities rather than the uid, so setcap'd binaries and containers are judged right
+    let caps = CapSets::current().ok();
+    let allows = |cap| caps.is_some_and(|c| c.allows(cap));
     
     // Check BTF support
     let btf_support = fs::metadata("/sys/kernel/btf/vmlinux").is_ok();
@@ -365,13 +592,16 @@ fn check_bpf_support() -> Result<BpfSupport> {
     Ok(BpfSupport {
         bpf_fs_mounted,
         debug_fs_mounted,
-        cap_sys_admin,
+        cap_sys_admin: allows(Capability::SysAdmin),
+        cap_bpf: allows(Capability::Bpf),
+        cap_perfmon: allows(Capability::Perfmon),
+        cap_net_admin: allows(Capability::NetAdmin),
         bpf_verifier_available,
         btf_support,
     })
 }
 
-fn get_system_resources() -> Result<SystemResources> {
+pub fn get_system_resources() -> Result<SystemResources> {
     let cpu_cores = std::thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(1);
@@ -414,7 +644,7 @@ fn get_system_resources() -> Result<SystemResources> {
 
     let uptime_seconds = if let Ok(uptime) = fs::read_to_string("/proc/uptime") {
         if let Some(seconds_str) = uptime.split_whitespace().next() {
-            seconds_str.parse::<u64>().unwrap_or(0)
+            seconds_str.parse::<f64>().map(|s| s as u64).unwrap_or(0)
         } else {
             0
         }
@@ -438,6 +668,23 @@ fn print_program_summary(status: &ProgramStatus) {
     println!("Database Status: {}", status.db_status);
     println!("Kernel Status: {}", if status.kernel_status.loaded { "LOADED" } else { "NOT LOADED" });
     println!("Attachment Status: {}", if status.attachment_status.attached { "ATTACHED" } else { "NOT ATTACHED" });
+    if let Some(lease) = &status.lease {
+        println!("Lease: {}", lease);
+    }
+    let m = &status.performance_metrics;
+    if let Some(runs) = m.run_cnt {
+        println!("Runs: {} ({}), {} recursion misses",
+            runs, format_rate(m.events_per_sec), m.recursion_misses.unwrap_or(0));
+        println!("Run Time: {} ns total, {}", m.run_time_ns.unwrap_or(0), format_ns_per_run(m.avg_ns_per_run));
+    }
+}
+
+fn format_rate(rate: Option<f64>) -> String {
+    rate.map(|r| format!("{:.1}/s", r)).unwrap_or_else(|| "-".to_string())
+}
+
+fn format_ns_per_run(avg: Option<f64>) -> String {
+    avg.map(|ns| format!("{:.0} ns/run", ns)).unwrap_or_else(|| "- ns/run".to_string())
 }
 
 fn print_program_table(status: &ProgramStatus, detailed: bool) {
@@ -445,6 +692,9 @@ fn print_program_table(status: &ProgramStatus, detailed: bool) {
     println!("┌─────────────────────────────────────────────────────────────┐");
     println!("│ Program ID: {} │ Title: {}", status.id, status.title);
     println!("│ Version: {} │ Status: {}", status.version, status.db_status);
+    if let Some(lease) = &status.lease {
+        println!("│ Lease: {}", lease);
+    }
     println!("└─────────────────────────────────────────────────────────────┘");
 
     if detailed {
@@ -472,19 +722,32 @@ fn print_program_table(status: &ProgramStatus, detailed: bool) {
             println!("  Hook Point: {}", hook);
         }
 
+        let m = &status.performance_metrics;
         println!("\n\x1b[1;36mPerformance Metrics:\x1b[0m");
-        if let Some(events) = status.performance_metrics.events_processed {
-            println!("  Events Processed: {}", events);
+        if !m.stats_enabled {
+            println!("  Runtime stats disabled (needs root or kernel.bpf_stats_enabled=1)");
+        }
+        if let Some(runs) = m.run_cnt {
+            println!("  Runs: {}", runs);
+        }
+        if let Some(ns) = m.run_time_ns {
+            println!("  Run Time: {} ns", ns);
+        }
+        if let Some(misses) = m.recursion_misses {
+            println!("  Recursion Misses: {}", misses);
+        }
+        if let Some(rate) = m.events_per_sec {
+            println!("  Events/sec: {:.1}", rate);
         }
-        if let Some(cpu) = status.performance_metrics.cpu_usage {
+        if let Some(avg) = m.avg_ns_per_run {
+            println!("  Avg per Run: {:.0} ns", avg);
+        }
+        if let Some(cpu) = m.cpu_usage {
             println!("  CPU Usage: {:.2}%", cpu);
         }
-        if let Some(mem) = status.performance_metrics.memory_usage {
+        if let Some(mem) = m.memory_usage {
             println!("  Memory Usage: {} bytes", mem);
         }
-        if let Some(errors) = status.performance_metrics.error_count {
-            println!("  Error Count: {}", errors);
-        }
     }
 }
 
@@ -494,6 +757,9 @@ fn print_system_summary(system: &SystemStatus, programs: &[ProgramStatus]) {
     println!("BPF Support: {}", if system.bpf_support.bpf_fs_mounted { "✅ Available" } else { "❌ Not Available" });
     println!("Loaded Programs: {}/{}", system.active_programs_count, system.loaded_programs_count);
     println!("Total Programs: {}", programs.len());
+    let total_rate: f64 = programs.iter().filter_map(|p| p.performance_metrics.events_per_sec).sum();
+    let total_cpu: f64 = programs.iter().filter_map(|p| p.performance_metrics.cpu_usage).sum();
+    println!("Program Runs: {:.1}/s, {:.3}% of one CPU", total_rate, total_cpu);
 }
 
 fn print_system_table(system: &SystemStatus, programs: &[ProgramStatus]) {
@@ -511,6 +777,9 @@ fn print_system_table(system: &SystemStatus, programs: &[ProgramStatus]) {
     println!("  BPF Filesystem: {}", if system.bpf_support.bpf_fs_mounted { "✅ Mounted" } else { "❌ Not Mounted" });
     println!("  Debug Filesystem: {}", if system.bpf_support.debug_fs_mounted { "✅ Mounted" } else { "❌ Not Mounted" });
     println!("  CAP_SYS_ADMIN: {}", if system.bpf_support.cap_sys_admin { "✅ Available" } else { "❌ Missing" });
+    println!("  CAP_BPF: {}", if system.bpf_support.cap_bpf { "✅ Available" } else { "❌ Missing" });
+    println!("  CAP_PERFMON: {}", if system.bpf_support.cap_perfmon { "✅ Available" } else { "❌ Missing" });
+    println!("  CAP_NET_ADMIN: {}", if system.bpf_support.cap_net_admin { "✅ Available" } else { "❌ Missing" });
     println!("  BTF Support: {}", if system.bpf_support.btf_support { "✅ Available" } else { "❌ Not Available" });
     println!("  BPF Verifier: {}", if system.bpf_support.bpf_verifier_available { "✅ Available" } else { "❌ Not Available" });
 
@@ -521,40 +790,65 @@ fn print_system_table(system: &SystemStatus, programs: &[ProgramStatus]) {
 
     if !programs.is_empty() {
         println!("\n\x1b[1;36mProgram Details:\x1b[0m");
-        println!("┌─────┬─────────────────────┬──────────┬──────────┬─────────────┐");
-        println!("│ ID  │ Title               │ Status   │ Kernel   │ Attached   │");
-        println!("├─────┼─────────────────────┼──────────┼──────────┼─────────────┤");
+        println!("┌─────┬─────────────────────┬──────────┬──────────┬─────────────┬────────────┬────────────┬──────────┬──────────────────────┐");
+        println!("│ ID  │ Title               │ Status   │ Kernel   │ Attached   │ Events/s   │ ns/run     │ Misses   │ TTL                  │");
+        println!("├─────┼─────────────────────┼──────────┼──────────┼─────────────┼────────────┼────────────┼──────────┼──────────────────────┤");
         
         for program in programs {
             let kernel_status = if program.kernel_status.loaded { "✅" } else { "❌" };
             let attached_status = if program.attachment_status.attached { "✅" } else { "❌" };
-            println!("│ {:3} │ {:19} │ {:8} │ {:8} │ {:10} │",
+            let m = &program.performance_metrics;
+            println!("│ {:3} │ {:19} │ {:8} │ {:8} │ {:10} │ {:>10} │ {:>10} │ {:>8} │ {:20} │",
                 program.id,
                 if program.title.len() > 19 { &program.title[..19] } else { &program.title },
                 program.db_status,
                 kernel_status,
-                attached_status
+                attached_status,
+                m.events_per_sec.map(|r| format!("{:.1}", r)).unwrap_or_else(|| "-".to_string()),
+                m.avg_ns_per_run.map(|ns| format!("{:.0}", ns)).unwrap_or_else(|| "-".to_string()),
+                m.recursion_misses.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string()),
+                program.lease.as_deref().unwrap_or("-")
             );
         }
-        println!("└─────┴─────────────────────┴──────────┴──────────┴─────────────┘");
+        println!("└─────┴─────────────────────┴──────────┴──────────┴─────────────┴────────────┴────────────┴──────────┴──────────────────────┘");
     }
 }
 
-async fn run_status_watch(opts: StatusOptions) -> Result<()> {
+async fn run_status_watch(
+    opts: StatusOptions,
+    stats: &mut RuntimeStats,
+    window: std::time::Duration,
+    otlp: Option<&OtlpExporter>,
+) -> Result<()> {
     println!("\x1b[1;35mWatching system status... Press Ctrl+C to stop\x1b[0m");
-    
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
     loop {
-        // Clear screen
-        print!("\x1B[2J\x1B[1;1H");
-        
-        // Show current status
-        if let Some(program_id) = opts.id {
-            show_program_status(program_id, opts.detailed, &opts.format).await?;
-        } else {
-            show_system_status(&opts).await?;
+        let refresh = async {
+            // Rates cover the time since the previous refresh
+            stats.sample(window).await?;
+
+            // Clear screen
+            print!("\x1B[2J\x1B[1;1H");
+
+            // Show current status
+            if let Some(program_id) = opts.id {
+                show_program_status(program_id, opts.detailed, &opts.format, stats, otlp).await?;
+            } else {
+                show_system_status(&opts, stats, otlp).await?;
+            }
+            if opts.record {
+                record_history(stats, opts.id).await?;
+            }
+
+            // Wait before next update
+            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            Ok::<(), anyhow::Error>(())
+        };
+        tokio::select! {
+            result = refresh => result?,
+            _ = &mut ctrl_c => return Ok(()),
         }
-        
-        // Wait before next update
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
     }
 }
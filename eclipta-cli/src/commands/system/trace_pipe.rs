@@ -0,0 +1,328 @@
+use crate::db::programs::{get_program_by_id, get_program_by_title, list_programs};
+use crate::events::decode::{DecodedEvent, FieldType, FieldValue};
+use crate::events::{EventSink, StreamOptions};
+use crate::utils::bpftool::loaded_programs_for;
+use crate::utils::db::ensure_db_ready;
+use crate::utils::logger::{info, warn};
+use crate::utils::paths::trace_pipe_path;
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use object::{Object, ObjectSection};
+use regex::Regex;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tokio::signal;
+use tokio::sync::mpsc;
+
+#[derive(Args, Debug)]
+pub struct TracePipeOptions {
+    /// Only show output of this registered program ID
+    #[arg(long, conflicts_with_all = ["title", "program"])]
+    pub id: Option<i32>,
+
+    /// Only show output of registered programs with this title
+    #[arg(long, conflicts_with_all = ["id", "program"])]
+    pub title: Option<String>,
+
+    /// Only show output of this eBPF object file
+    #[arg(short, long, conflicts_with_all = ["id", "title"])]
+    pub program: Option<PathBuf>,
+
+    /// Show every trace_pipe line, not just output from eclipta programs
+    #[arg(long)]
+    pub all: bool,
+
+    #[command(flatten)]
+    pub stream: StreamOptions,
+}
+
+/// A `bpf_printk` format string from an object's .rodata, compiled to a matcher
+struct PrintkFormat {
+    program: String,
+    pattern: Regex,
+}
+
+pub async fn handle_trace_pipe(opts: TracePipeOptions) -> Result<()> {
+    let path = trace_pipe_path().ok_or_else(|| {
+        anyhow!("trace_pipe not found; mount tracefs with: mount -t tracefs nodev /sys/kernel/tracing")
+    })?;
+
+    let formats = if opts.all { None } else { Some(load_formats(&opts).await?) };
+    if let Some(f) = &formats {
+        if f.is_empty() {
+            warn("No bpf_printk format strings found in eclipta programs; use --all to show everything");
+        }
+    }
+
+    let mut sink = EventSink::new(&opts.stream, &trace_fields())?;
+    let file = std::fs::File::open(&path)
+        .with_context(|| format!("Failed to open {} (are you root?)", path.display()))?;
+    let mut lines = spawn_reader(file);
+
+    info(&format!("Reading {} (Ctrl+C to exit)", path.display()));
+
+    let ctrl_c = signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut refresh = tokio::time::interval(sink.interval());
+    loop {
+        tokio::select! {
+            line = lines.recv() => {
+                let Some(line) = line else { break };
+                let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+                let Some(mut ev) = parse_trace_line(&line) else { continue };
+                if let Some(formats) = &formats {
+                    let msg = ev.get("msg").map(|m| m.to_string()).unwrap_or_default();
+                    let Some(matched) = formats.iter().find(|f| f.pattern.is_match(&msg)) else { continue };
+                    ev.fields.push(("program".to_string(), FieldValue::Str(matched.program.clone())));
+                } else {
+                    ev.fields.push(("program".to_string(), FieldValue::Str(String::new())));
+                }
+                sink.push(ev);
+            }
+            _ = refresh.tick(), if sink.is_aggregating() => sink.refresh(),
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    sink.finish().await;
+    Ok(())
+}
+
+/// Read trace_pipe on a plain thread so a blocked read can't hold up runtime shutdown on Ctrl+C
+fn spawn_reader(file: std::fs::File) -> mpsc::Receiver<std::io::Result<String>> {
+    let (tx, rx) = mpsc::channel(1024);
+    std::thread::spawn(move || {
+        for line in BufReader::new(file).lines() {
+            let failed = line.is_err();
+            if tx.blocking_send(line).is_err() || failed {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn trace_fields() -> Vec<(String, FieldType)> {
+    [
+        ("cpu", FieldType::Number),
+        ("task", FieldType::Text),
+        ("pid", FieldType::Number),
+        ("ts", FieldType::Text),
+        ("msg", FieldType::Text),
+        ("program", FieldType::Text),
+    ]
+    .into_iter()
+    .map(|(n, t)| (n.to_string(), t))
+    .collect()
+}
+
+/// Parse `<task>-<pid> [cpu] <flags> <ts>: <func>: <msg>` as printed by the kernel
+fn parse_trace_line(line: &str) -> Option<DecodedEvent> {
+    let (head, rest) = line.split_once(": ")?;
+    // The message follows the event name, e.g. "bpf_trace_printk: " on current kernels
+    let msg = rest.split_once(": ").map(|(_, m)| m).unwrap_or(rest);
+
+    let open = head.rfind('[')?;
+    let close = open + head[open..].find(']')?;
+    let cpu: u32 = head[open + 1..close].trim().parse().ok()?;
+
+    let task_pid = head[..open].trim_end();
+    // Newer kernels may print the tgid as "(  123)" between task and cpu
+    let task_pid = match task_pid.rfind(" (") {
+        Some(i) if task_pid.ends_with(')') => task_pid[..i].trim_end(),
+        _ => task_pid,
+    };
+    let (task, pid) = task_pid.trim_start().rsplit_once('-')?;
+    let pid: u64 = pid.parse().ok()?;
+    let ts = head[close + 1..].split_whitespace().last()?.to_string();
+
+    Some(DecodedEvent {
+        cpu,
+        label: Some("printk".to_string()),
+        fields: vec![
+            ("cpu".to_string(), FieldValue::UInt(cpu as u64)),
+            ("task".to_string(), FieldValue::Str(task.to_string())),
+            ("pid".to_string(), FieldValue::UInt(pid)),
+            ("ts".to_string(), FieldValue::Str(ts)),
+            ("msg".to_string(), FieldValue::Str(msg.to_string())),
+        ],
+        stacks: Vec::new(),
+    })
+}
+
+/// Collect (program title, object path) pairs for the selected programs
+async fn selected_objects(opts: &TracePipeOptions) -> Result<Vec<(String, PathBuf)>> {
+    if let Some(path) = &opts.program {
+        let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        return Ok(vec![(name, path.clone())]);
+    }
+
+    let pool = ensure_db_ready()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to database (use --all or --program): {}", e))?;
+
+    let programs = if let Some(id) = opts.id {
+        let p = get_program_by_id(&pool, id)
+            .await?
+            .ok_or_else(|| anyhow!("No program found with id {}", id))?;
+        vec![p]
+    } else if let Some(title) = &opts.title {
+        let p = get_program_by_title(&pool, title).await?;
+        if p.is_empty() {
+            return Err(anyhow!("No program found with title '{}'", title));
+        }
+        p
+    } else {
+        // Only programs that are currently loaded can be writing to trace_pipe
+        let mut loaded = Vec::new();
+        for p in list_programs(&pool).await? {
+            if loaded_programs_for(Path::new(&p.path)).await.is_ok_and(|l| !l.is_empty()) {
+                loaded.push(p);
+            }
+        }
+        loaded
+    };
+
+    Ok(programs
+        .into_iter()
+        .map(|p| (p.title, PathBuf::from(p.path)))
+        .collect())
+}
+
+async fn load_formats(opts: &TracePipeOptions) -> Result<Vec<PrintkFormat>> {
+    let mut formats = Vec::new();
+    for (title, path) in selected_objects(opts).await? {
+        match printk_formats(&path) {
+            Ok(strings) => {
+                for s in strings {
+                    if let Ok(pattern) = Regex::new(&format_to_regex(&s)) {
+                        formats.push(PrintkFormat { program: title.clone(), pattern });
+                    }
+                }
+            }
+            Err(e) => warn(&format!("Skipping {}: {}", path.display(), e)),
+        }
+    }
+    Ok(formats)
+}
+
+/// NUL-terminated strings from the object's read-only data, where clang puts printk formats
+fn printk_formats(path: &Path) -> Result<Vec<String>> {
+    let data = std::fs::read(path).context("Failed to read eBPF object")?;
+    let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+    let mut out = Vec::new();
+    for section in obj.sections() {
+        let Ok(name) = section.name() else { continue };
+        if !name.starts_with(".rodata") {
+            continue;
+        }
+        for raw in section.data()?.split(|b| *b == 0) {
+            if raw.len() < 2 || !raw.iter().all(|b| b.is_ascii_graphic() || *b == b' ' || *b == b'\n' || *b == b'\t') {
+                continue;
+            }
+            out.push(String::from_utf8_lossy(raw).trim_end_matches('\n').to_string());
+        }
+    }
+    Ok(out)
+}
+
+/// Translate a printf-style format into an anchored regex matching its output
+fn format_to_regex(fmt: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            re.push_str(&regex::escape(&c.to_string()));
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            re.push('%');
+            continue;
+        }
+        // Skip flags, width, precision and length modifiers
+        while let Some(&n) = chars.peek() {
+            if "-+ #0123456789.hlzjt".contains(n) {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let mut conversion = chars.next();
+        if conversion == Some('p') {
+            // The extensions bpf_trace_printk knows: %pK, %px, %pB, %pi4/%pI6 and %pks/%pus
+            match chars.peek().copied() {
+                Some('K' | 'x' | 'B') => {
+                    chars.next();
+                }
+                Some('i' | 'I') => {
+                    chars.next();
+                    chars.next_if(|c| *c == '4' || *c == '6');
+                }
+                Some('k' | 'u') => {
+                    chars.next();
+                    // A string read from kernel or user memory
+                    if chars.next_if_eq(&'s').is_some() {
+                        conversion = Some('s');
+                    }
+                }
+                _ => {}
+            }
+        }
+        re.push_str(match conversion {
+            Some('d') | Some('i') => r"-?\d+",
+            Some('u') => r"\d+",
+            Some('x') | Some('X') => r"(0x)?[0-9a-fA-F]+",
+            Some('p') => r"\S+",
+            Some('c') => r".",
+            _ => r".*",
+        });
+    }
+    re.push('$');
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(ev: &DecodedEvent, name: &str) -> String {
+        ev.get(name).map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn trace_lines_split_into_fields() {
+        let ev = parse_trace_line("           <...>-1234    [002] d..31  1234.567890: bpf_trace_printk: hello 5").unwrap();
+        assert_eq!(ev.cpu, 2);
+        assert_eq!(field(&ev, "task"), "<...>");
+        assert_eq!(field(&ev, "pid"), "1234");
+        assert_eq!(field(&ev, "ts"), "1234.567890");
+        assert_eq!(field(&ev, "msg"), "hello 5");
+
+        // Task names with dashes, the tgid column of newer kernels, colons in the message
+        let ev = parse_trace_line(" kworker/u8:2-my-99  (   42) [000] ..... 7.000001: bpf_trace_printk: key: value").unwrap();
+        assert_eq!(field(&ev, "task"), "kworker/u8:2-my");
+        assert_eq!(field(&ev, "pid"), "99");
+        assert_eq!(field(&ev, "msg"), "key: value");
+
+        assert!(parse_trace_line("# tracer: nop").is_none());
+        assert!(parse_trace_line("curl-12 [x] .... 1.0: bpf_trace_printk: hi").is_none());
+        assert!(parse_trace_line("").is_none());
+    }
+
+    #[test]
+    fn formats_match_their_output() {
+        let re = |fmt: &str| Regex::new(&format_to_regex(fmt)).unwrap();
+        assert_eq!(format_to_regex("a.b %d%%"), r"^a\.b -?\d+%$");
+
+        let drop = re("drop %pI4 port=%u len=%-5lld flags=%#x");
+        assert!(drop.is_match("drop 10.0.0.1 port=80 len=-12 flags=0x1f"));
+        assert!(!drop.is_match("drop 10.0.0.1 port=-80 len=12 flags=0x1f"));
+        assert!(!drop.is_match("prefix drop 10.0.0.1 port=80 len=12 flags=1f"));
+
+        assert!(re("comm=%s cpu=%c").is_match("comm=curl bash cpu=3"));
+        assert!(re("hex %X").is_match("hex BEEF"));
+        assert!(re("path %pus").is_match("path /tmp/a b"));
+        assert!(!re("exact").is_match("exactly"));
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres, Row};
+
+/// A registered program loaded by `eclipta autostart run`, with where it attaches
+#[derive(Debug, Clone)]
+pub struct AutostartEntry {
+    pub program_id: i32,
+    pub title: String,
+    pub version: String,
+    pub path: String,
+    pub iface: Option<String>,
+    pub cgroup: Option<String>,
+    /// `name=value` specs as taken by `upgrade --global`
+    pub globals: Vec<String>,
+    /// Titles that must be running before this one loads
+    pub after: Vec<String>,
+    /// 'ok', 'failed' or 'skipped' after the last run
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+/// Flag one version of a title for autostart; any other version of the title is unflagged
+pub async fn set_autostart(
+    pool: &Pool<Postgres>,
+    program_id: i32,
+    iface: Option<&str>,
+    cgroup: Option<&str>,
+    globals: &[String],
+    after: &[String],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        DELETE FROM program_autostart
+        WHERE program_id IN (
+            SELECT id FROM ebpf_programs
+            WHERE title = (SELECT title FROM ebpf_programs WHERE id = $1) AND id <> $1
+        )
+        "#,
+    )
+    .bind(program_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO program_autostart (program_id, iface, cgroup, globals, after)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (program_id) DO UPDATE
+        SET iface = EXCLUDED.iface, cgroup = EXCLUDED.cgroup,
+            globals = EXCLUDED.globals, after = EXCLUDED.after
+        "#,
+    )
+    .bind(program_id)
+    .bind(iface)
+    .bind(cgroup)
+    .bind(globals)
+    .bind(after)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// Unflag every version of a title; returns how many were flagged
+pub async fn clear_autostart(pool: &Pool<Postgres>, title: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM program_autostart WHERE program_id IN (SELECT id FROM ebpf_programs WHERE title = $1)",
+    )
+    .bind(title)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn list_autostart(pool: &Pool<Postgres>) -> Result<Vec<AutostartEntry>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT a.program_id, p.title, p.version, p.path, a.iface, a.cgroup, a.globals, a.after,
+               a.last_status, a.last_error, a.last_run_at
+        FROM program_autostart a
+        JOIN ebpf_programs p ON p.id = a.program_id
+        ORDER BY p.title
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| AutostartEntry {
+            program_id: row.get("program_id"),
+            title: row.get("title"),
+            version: row.get("version"),
+            path: row.get("path"),
+            iface: row.get("iface"),
+            cgroup: row.get("cgroup"),
+            globals: row.get("globals"),
+            after: row.get("after"),
+            last_status: row.get("last_status"),
+            last_error: row.get("last_error"),
+            last_run_at: row.get("last_run_at"),
+        })
+        .collect())
+}
+
+pub async fn record_autostart_result(
+    pool: &Pool<Postgres>,
+    program_id: i32,
+    status: &str,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE program_autostart
+        SET last_status = $2, last_error = $3, last_run_at = CURRENT_TIMESTAMP
+        WHERE program_id = $1
+        "#,
+    )
+    .bind(program_id)
+    .bind(status)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres, Row};
+
+/// Retention used for metrics without a policy of their own when no '*' policy is set
+pub const DEFAULT_RETENTION_SECS: i64 = 30 * 24 * 3600;
+
+/// Metric names stored by the sampler; the bool marks cumulative counters
+pub const METRICS: &[(&str, bool)] = &[
+    ("run_cnt", true),
+    ("run_time_ns", true),
+    ("recursion_misses", true),
+    ("events_per_sec", false),
+    ("avg_ns_per_run", false),
+    ("memlock_bytes", false),
+    ("loaded", false),
+    ("map_entries", false),
+];
+
+pub fn is_counter(metric: &str) -> bool {
+    METRICS.iter().any(|(name, counter)| *name == metric && *counter)
+}
+
+/// One value of one program (or one of its maps) at sampling time
+#[derive(Debug, Clone)]
+pub struct MetricSample {
+    pub program_id: i32,
+    pub metric: String,
+    /// Empty for program-level metrics
+    pub map_name: String,
+    pub value: f64,
+}
+
+/// Samples of one series folded into a time bucket
+#[derive(Debug, Clone)]
+pub struct HistoryBucket {
+    pub program_id: i32,
+    pub map_name: String,
+    pub bucket: DateTime<Utc>,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    pub last: f64,
+    pub samples: i64,
+}
+
+/// Store a whole sampling round with one statement, one array per column
+pub async fn insert_samples(
+    pool: &Pool<Postgres>,
+    recorded_at: DateTime<Utc>,
+    samples: &[MetricSample],
+) -> Result<(), sqlx::Error> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+    let program_ids: Vec<i32> = samples.iter().map(|s| s.program_id).collect();
+    let metrics: Vec<&str> = samples.iter().map(|s| s.metric.as_str()).collect();
+    let map_names: Vec<&str> = samples.iter().map(|s| s.map_name.as_str()).collect();
+    let values: Vec<f64> = samples.iter().map(|s| s.value).collect();
+    sqlx::query(
+        r#"
+        INSERT INTO program_metrics (program_id, metric, map_name, value, recorded_at)
+        SELECT program_id, metric, map_name, value, $5
+        FROM UNNEST($1::INT[], $2::TEXT[], $3::TEXT[], $4::DOUBLE PRECISION[])
+            AS s(program_id, metric, map_name, value)
+        "#,
+    )
+    .bind(&program_ids)
+    .bind(&metrics)
+    .bind(&map_names)
+    .bind(&values)
+    .bind(recorded_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Samples of `metric` in [since, until), downsampled into `step_secs` wide buckets
+pub async fn query_history(
+    pool: &Pool<Postgres>,
+    metric: &str,
+    program_id: Option<i32>,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    step_secs: f64,
+) -> Result<Vec<HistoryBucket>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            program_id,
+            map_name,
+            to_timestamp(floor(extract(epoch FROM recorded_at) / $5) * $5) AS bucket,
+            avg(value) AS avg,
+            min(value) AS min,
+            max(value) AS max,
+            (array_agg(value ORDER BY recorded_at DESC))[1] AS last,
+            count(*) AS samples
+        FROM program_metrics
+        WHERE metric = $1
+          AND ($2::INT IS NULL OR program_id = $2)
+          AND recorded_at >= $3
+          AND recorded_at < $4
+        GROUP BY program_id, map_name, bucket
+        ORDER BY program_id, map_name, bucket
+        "#,
+    )
+    .bind(metric)
+    .bind(program_id)
+    .bind(since)
+    .bind(until)
+    .bind(step_secs)
+    .fetch_all(pool)
+    .await?;
+
+    let buckets = rows
+        .iter()
+        .map(|row| HistoryBucket {
+            program_id: row.get("program_id"),
+            map_name: row.get("map_name"),
+            bucket: row.get("bucket"),
+            avg: row.get("avg"),
+            min: row.get("min"),
+            max: row.get("max"),
+            last: row.get("last"),
+            samples: row.get("samples"),
+        })
+        .collect();
+
+    Ok(buckets)
+}
+
+/// Retention policies as (metric, seconds kept); '*' is the default for every other metric
+pub async fn list_retention(pool: &Pool<Postgres>) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let rows = sqlx::query("SELECT metric, keep_seconds FROM metric_retention ORDER BY metric")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.iter().map(|row| (row.get("metric"), row.get("keep_seconds"))).collect())
+}
+
+pub async fn set_retention(pool: &Pool<Postgres>, metric: &str, keep_secs: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO metric_retention (metric, keep_seconds) VALUES ($1, $2)
+        ON CONFLICT (metric) DO UPDATE SET keep_seconds = EXCLUDED.keep_seconds
+        "#,
+    )
+    .bind(metric)
+    .bind(keep_secs)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete samples older than their metric's policy, falling back to '*' and then the built-in default
+pub async fn prune_metrics(pool: &Pool<Postgres>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM program_metrics m
+        WHERE m.recorded_at < CURRENT_TIMESTAMP - COALESCE(
+            (SELECT keep_seconds FROM metric_retention r WHERE r.metric = m.metric),
+            (SELECT keep_seconds FROM metric_retention r WHERE r.metric = '*'),
+            $1
+        ) * INTERVAL '1 second'
+        "#,
+    )
+    .bind(DEFAULT_RETENTION_SECS)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
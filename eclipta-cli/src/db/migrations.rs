@@ -1,4 +1,4 @@
-use sqlx::{Pool, Postgres, Row};
+use sqlx::{PgConnection, Pool, Postgres, Row};
 use crate::utils::logger::{success, info};
 
 pub async fn run_migrations(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
@@ -32,26 +32,117 @@ pub async fn run_migrations(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
 
     if !table_exists {
         info("Creating ebpf_programs table...");
-        create_ebpf_programs_table(pool).await?;
-        create_updated_at_trigger(pool).await?;
+        // The table, its trigger and the record land together or not at all
+        let mut tx = pool.begin().await?;
+        create_ebpf_programs_table(&mut tx).await?;
+        create_updated_at_trigger(&mut tx).await?;
         
         // Record migration
         sqlx::query(
             "INSERT INTO migrations (name) VALUES ($1)"
         )
         .bind("001_create_ebpf_programs_table")
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
         
         success("Database migration completed successfully!");
-    } else {
+    }
+
+    let applied = apply_pending_migrations(pool).await?;
+    if table_exists && applied == 0 {
         info("Database is up to date");
     }
 
     Ok(())
 }
 
-async fn create_ebpf_programs_table(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+/// Schema changes applied in order after the initial table, each recorded by name
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "002_add_map_init",
+        "ALTER TABLE ebpf_programs ADD COLUMN IF NOT EXISTS map_init TEXT[] NOT NULL DEFAULT '{}'",
+    ),
+    (
+        "003_create_program_metrics",
+        r#"
+        CREATE TABLE IF NOT EXISTS program_metrics (
+            id BIGSERIAL PRIMARY KEY,
+            program_id INT NOT NULL REFERENCES ebpf_programs(id) ON DELETE CASCADE,
+            metric TEXT NOT NULL,
+            map_name TEXT NOT NULL DEFAULT '',
+            value DOUBLE PRECISION NOT NULL,
+            recorded_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    ),
+    (
+        "004_index_program_metrics",
+        "CREATE INDEX IF NOT EXISTS program_metrics_lookup ON program_metrics (metric, program_id, recorded_at)",
+    ),
+    (
+        "005_create_metric_retention",
+        r#"
+        CREATE TABLE IF NOT EXISTS metric_retention (
+            metric TEXT PRIMARY KEY,
+            keep_seconds BIGINT NOT NULL CHECK (keep_seconds > 0)
+        )
+        "#,
+    ),
+    (
+        "006_create_program_autostart",
+        r#"
+        CREATE TABLE IF NOT EXISTS program_autostart (
+            program_id INT PRIMARY KEY REFERENCES ebpf_programs(id) ON DELETE CASCADE,
+            iface TEXT,
+            cgroup TEXT,
+            globals TEXT[] NOT NULL DEFAULT '{}',
+            after TEXT[] NOT NULL DEFAULT '{}',
+            last_status TEXT,
+            last_error TEXT,
+            last_run_at TIMESTAMPTZ
+        )
+        "#,
+    ),
+];
+
+/// Names of the migrations not yet recorded in the migrations table
+pub async fn pending_migrations(pool: &Pool<Postgres>) -> Result<Vec<&'static str>, sqlx::Error> {
+    let mut pending = Vec::new();
+    for (name, _) in MIGRATIONS {
+        let done: bool = sqlx::query("SELECT EXISTS (SELECT 1 FROM migrations WHERE name = $1)")
+            .bind(name)
+            .fetch_one(pool)
+            .await
+            .map(|row| row.get::<bool, _>(0))?;
+        if !done {
+            pending.push(*name);
+        }
+    }
+    Ok(pending)
+}
+
+/// Apply every migration not yet recorded in the migrations table; returns how many ran.
+/// Each one commits together with its record, so a failure never leaves a change unrecorded
+/// and a concurrent run applying the same migration rolls back on the unique name
+pub async fn apply_pending_migrations(pool: &Pool<Postgres>) -> Result<usize, sqlx::Error> {
+    let pending = pending_migrations(pool).await?;
+    let mut applied = 0;
+    for (name, sql) in MIGRATIONS.iter().filter(|(name, _)| pending.contains(name)) {
+        info(&format!("Applying migration {}...", name));
+        let mut tx = pool.begin().await?;
+        sqlx::query(sql).execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO migrations (name) VALUES ($1)")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+async fn create_ebpf_programs_table(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
         CREATE TABLE ebpf_programs (
@@ -70,13 +161,13 @@ async fn create_ebpf_programs_table(pool: &Pool<Postgres>) -> Result<(), sqlx::E
         )
         "#
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     Ok(())
 }
 
-async fn create_updated_at_trigger(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+async fn create_updated_at_trigger(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
     // Create the function
     sqlx::query(
         r#"
@@ -89,7 +180,7 @@ async fn create_updated_at_trigger(pool: &Pool<Postgres>) -> Result<(), sqlx::Er
         $$ LANGUAGE plpgsql;
         "#
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Create the trigger
@@ -98,7 +189,7 @@ async fn create_updated_at_trigger(pool: &Pool<Postgres>) -> Result<(), sqlx::Er
         DROP TRIGGER IF EXISTS set_updated_at_trigger ON ebpf_programs;
         "#
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     sqlx::query(
@@ -109,7 +200,7 @@ async fn create_updated_at_trigger(pool: &Pool<Postgres>) -> Result<(), sqlx::Er
         EXECUTE FUNCTION set_updated_at();
         "#
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     Ok(())
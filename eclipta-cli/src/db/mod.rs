@@ -1,2 +1,4 @@
 pub mod programs;
 pub mod migrations;
+pub mod metrics;
+pub mod autostart;
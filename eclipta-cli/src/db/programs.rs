@@ -139,3 +139,33 @@ pub async fn get_program_by_title(
     Ok(programs)
 }
 
+/// `map=file` specs used to pre-populate maps whenever the program is loaded
+pub async fn get_map_init(pool: &Pool<Postgres>, program_id: i32) -> Result<Vec<String>, sqlx::Error> {
+    let row = sqlx::query("SELECT map_init FROM ebpf_programs WHERE id = $1")
+        .bind(program_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|row| row.get("map_init")).unwrap_or_default())
+}
+
+pub async fn set_map_init(pool: &Pool<Postgres>, program_id: i32, specs: &[String]) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE ebpf_programs SET map_init = $1 WHERE id = $2")
+        .bind(specs)
+        .bind(program_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Registry status: 'active', 'deactive' or 'failed'
+pub async fn set_program_status(pool: &Pool<Postgres>, program_id: i32, status: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE ebpf_programs SET status = $1 WHERE id = $2")
+        .bind(status)
+        .bind(program_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use crate::events::decode::{DecodedEvent, FieldType};
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+
+const BAR_WIDTH: u64 = 40;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistScale {
+    Log2,
+    Linear(i64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggKind {
+    Count,
+    Sum(String),
+    Hist { field: String, scale: HistScale },
+}
+
+impl AggKind {
+    /// Parse `count`, `sum(field)`, `hist(field)` (log2) or `lhist(field, step)` (linear)
+    pub fn parse(spec: &str) -> Result<AggKind> {
+        let spec = spec.trim();
+        if spec == "count" {
+            return Ok(AggKind::Count);
+        }
+        let (func, args) = spec
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(|| anyhow!("Unknown aggregation '{}' (expected count, sum(field), hist(field) or lhist(field, step))", spec))?;
+        let args: Vec<&str> = args.split(',').map(str::trim).collect();
+        match (func.trim(), args.as_slice()) {
+            ("sum", [field]) if !field.is_empty() => Ok(AggKind::Sum(field.to_string())),
+            ("hist", [field]) if !field.is_empty() => Ok(AggKind::Hist {
+                field: field.to_string(),
+                scale: HistScale::Log2,
+            }),
+            ("lhist", [field, step]) if !field.is_empty() => {
+                let step: i64 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| anyhow!("lhist step must be a positive integer, got '{}'", step))?;
+                Ok(AggKind::Hist {
+                    field: field.to_string(),
+                    scale: HistScale::Linear(step),
+                })
+            }
+            _ => Err(anyhow!("Unknown aggregation '{}' (expected count, sum(field), hist(field) or lhist(field, step))", spec)),
+        }
+    }
+
+    fn field(&self) -> Option<&str> {
+        match self {
+            AggKind::Count => None,
+            AggKind::Sum(f) => Some(f),
+            AggKind::Hist { field, .. } => Some(field),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            AggKind::Count => "COUNT".to_string(),
+            AggKind::Sum(f) => format!("SUM({})", f),
+            AggKind::Hist { field, .. } => format!("HIST({})", field),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: BTreeMap<i64, u64>,
+    total: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, value: i64, scale: &HistScale) {
+        let bucket = match scale {
+            HistScale::Log2 if value < 0 => i64::MIN,
+            HistScale::Log2 if value < 2 => value,
+            HistScale::Log2 => 1i64 << (63 - value.leading_zeros()),
+            // Widen so buckets at the ends of the i64 range can't overflow
+            HistScale::Linear(step) => {
+                let bucket = (value as i128).div_euclid(*step as i128) * *step as i128;
+                bucket.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+            }
+        };
+        *self.buckets.entry(bucket).or_default() += 1;
+        self.total += 1;
+    }
+
+    fn render(&self, scale: &HistScale, out: &mut String) {
+        let max = self.buckets.values().copied().max().unwrap_or(0).max(1);
+        for (bucket, count) in &self.buckets {
+            let label = match scale {
+                HistScale::Log2 if *bucket == i64::MIN => "(..., 0)".to_string(),
+                HistScale::Log2 if *bucket < 2 => format!("[{}]", bucket),
+                HistScale::Log2 => format!("[{}, {})", bucket, bucket.saturating_mul(2)),
+                HistScale::Linear(step) => format!("[{}, {})", bucket, *bucket as i128 + *step as i128),
+            };
+            let bar = "@".repeat((count * BAR_WIDTH / max) as usize);
+            out.push_str(&format!(
+                "{:<24} {:>10} |{:<width$}|\n",
+                label,
+                count,
+                bar,
+                width = BAR_WIDTH as usize
+            ));
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Cell {
+    Scalar(i128),
+    Hist(Histogram),
+}
+
+impl Cell {
+    fn weight(&self) -> i128 {
+        match self {
+            Cell::Scalar(v) => *v,
+            Cell::Hist(h) => h.total as i128,
+        }
+    }
+}
+
+/// In-memory aggregation keyed by the values of the `--by` fields
+#[derive(Debug)]
+pub struct Aggregator {
+    kind: AggKind,
+    by: Vec<String>,
+    cells: HashMap<Vec<String>, Cell>,
+    events: u64,
+}
+
+impl Aggregator {
+    /// Build an aggregator, checking the referenced fields against the decoder's field set
+    pub fn new(spec: &str, by: &[String], fields: &[(String, FieldType)]) -> Result<Aggregator> {
+        let kind = AggKind::parse(spec)?;
+        let available = || fields.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", ");
+
+        if let Some(field) = kind.field() {
+            match fields.iter().find(|(n, _)| n == field) {
+                Some((_, FieldType::Number)) => {}
+                Some(_) => return Err(anyhow!("Cannot aggregate non-numeric field '{}'", field)),
+                None => return Err(anyhow!("Unknown field '{}' in --aggregate (available: {})", field, available())),
+            }
+        }
+        for key in by {
+            if !fields.iter().any(|(n, _)| n == key) {
+                return Err(anyhow!("Unknown field '{}' in --by (available: {})", key, available()));
+            }
+        }
+
+        Ok(Aggregator {
+            kind,
+            by: by.to_vec(),
+            cells: HashMap::new(),
+            events: 0,
+        })
+    }
+
+    pub fn record(&mut self, ev: &DecodedEvent) {
+        let key: Vec<String> = self
+            .by
+            .iter()
+            .map(|f| ev.get(f).map(|v| v.to_string()).unwrap_or_default())
+            .collect();
+        let value = self
+            .kind
+            .field()
+            .and_then(|f| ev.get(f))
+            .and_then(|v| v.as_i128());
+        if self.kind.field().is_some() && value.is_none() {
+            return;
+        }
+        self.events += 1;
+
+        match &self.kind {
+            AggKind::Count => {
+                let cell = self.cells.entry(key).or_insert(Cell::Scalar(0));
+                if let Cell::Scalar(v) = cell {
+                    *v += 1;
+                }
+            }
+            AggKind::Sum(_) => {
+                let cell = self.cells.entry(key).or_insert(Cell::Scalar(0));
+                if let Cell::Scalar(v) = cell {
+                    *v += value.unwrap_or(0);
+                }
+            }
+            AggKind::Hist { scale, .. } => {
+                let cell = self.cells.entry(key).or_insert_with(|| Cell::Hist(Histogram::default()));
+                if let Cell::Hist(h) = cell {
+                    let v = value.unwrap_or(0).clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+                    h.record(v, scale);
+                }
+            }
+        }
+    }
+
+    /// Render the `top` heaviest keys as a table (or one histogram per key)
+    pub fn render(&self, top: usize) -> String {
+        let mut rows: Vec<(&Vec<String>, &Cell)> = self.cells.iter().collect();
+        rows.sort_by(|a, b| b.1.weight().cmp(&a.1.weight()).then_with(|| a.0.cmp(b.0)));
+        let shown = rows.len().min(top);
+
+        let mut out = String::new();
+        if rows.is_empty() {
+            out.push_str("(no events aggregated yet)\n");
+            return out;
+        }
+
+        match &self.kind {
+            AggKind::Hist { scale, .. } => {
+                for (key, cell) in rows.iter().take(top) {
+                    if let Cell::Hist(h) = cell {
+                        out.push_str(&format!("{} [{}]:\n", self.kind.title(), self.render_key(key)));
+                        h.render(scale, &mut out);
+                        out.push('\n');
+                    }
+                }
+            }
+            _ => {
+                let key_header = if self.by.is_empty() {
+                    "KEY".to_string()
+                } else {
+                    self.by.join(",").to_uppercase()
+                };
+                let width = rows
+                    .iter()
+                    .take(top)
+                    .map(|(k, _)| self.render_key(k).len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(key_header.len());
+                out.push_str(&format!("{:<width$}  {:>14}\n", key_header, self.kind.title(), width = width));
+                for (key, cell) in rows.iter().take(top) {
+                    out.push_str(&format!("{:<width$}  {:>14}\n", self.render_key(key), cell.weight(), width = width));
+                }
+            }
+        }
+
+        if rows.len() > shown {
+            out.push_str(&format!("... {} more keys\n", rows.len() - shown));
+        }
+        out.push_str(&format!("{} events aggregated into {} keys\n", self.events, rows.len()));
+        out
+    }
+
+    fn render_key(&self, key: &[String]) -> String {
+        if self.by.is_empty() {
+            "*".to_string()
+        } else {
+            key.join(",")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::decode::FieldValue;
+
+    fn fields() -> Vec<(String, FieldType)> {
+        vec![
+            ("cpu".to_string(), FieldType::Number),
+            ("comm".to_string(), FieldType::Text),
+            ("bytes".to_string(), FieldType::Number),
+        ]
+    }
+
+    fn event(comm: &str, bytes: FieldValue) -> DecodedEvent {
+        DecodedEvent {
+            cpu: 0,
+            label: None,
+            fields: vec![
+                ("cpu".to_string(), FieldValue::UInt(0)),
+                ("comm".to_string(), FieldValue::Str(comm.to_string())),
+                ("bytes".to_string(), bytes),
+            ],
+            stacks: Vec::new(),
+        }
+    }
+
+    fn aggregator(spec: &str, by: &[&str]) -> Aggregator {
+        let by: Vec<String> = by.iter().map(|s| s.to_string()).collect();
+        Aggregator::new(spec, &by, &fields()).unwrap()
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(AggKind::parse(" count ").unwrap(), AggKind::Count);
+        assert_eq!(AggKind::parse("sum(bytes)").unwrap(), AggKind::Sum("bytes".to_string()));
+        assert_eq!(
+            AggKind::parse("lhist(bytes, 10)").unwrap(),
+            AggKind::Hist { field: "bytes".to_string(), scale: HistScale::Linear(10) }
+        );
+        for spec in ["avg(bytes)", "sum()", "lhist(bytes)", "lhist(bytes, 0)", "hist(bytes"] {
+            assert!(AggKind::parse(spec).is_err(), "{} should not parse", spec);
+        }
+    }
+
+    #[test]
+    fn checks_fields() {
+        let by = vec!["comm".to_string()];
+        assert!(Aggregator::new("sum(comm)", &[], &fields()).is_err());
+        assert!(Aggregator::new("sum(missing)", &[], &fields()).is_err());
+        assert!(Aggregator::new("count", &["missing".to_string()], &fields()).is_err());
+        assert!(Aggregator::new("hist(bytes)", &by, &fields()).is_ok());
+    }
+
+    #[test]
+    fn counts_and_sums_by_key() {
+        let mut count = aggregator("count", &["comm"]);
+        let mut sum = aggregator("sum(bytes)", &["comm"]);
+        for (comm, bytes) in [("sh", 10), ("bash", 5), ("sh", 7)] {
+            count.record(&event(comm, FieldValue::UInt(bytes)));
+            sum.record(&event(comm, FieldValue::UInt(bytes)));
+        }
+        sum.record(&event("sh", FieldValue::Str("n/a".to_string())));
+
+        let rendered = count.render(10);
+        let sh = rendered.lines().position(|l| l.starts_with("sh ")).unwrap();
+        let bash = rendered.lines().position(|l| l.starts_with("bash ")).unwrap();
+        assert!(sh < bash, "{}", rendered);
+        assert!(rendered.contains("3 events aggregated into 2 keys"));
+
+        let rendered = sum.render(1);
+        assert!(rendered.lines().any(|l| l.starts_with("sh ") && l.ends_with(" 17")), "{}", rendered);
+        assert!(rendered.contains("... 1 more keys"));
+        assert!(rendered.contains("3 events aggregated"));
+    }
+
+    #[test]
+    fn log2_buckets() {
+        let mut hist = Histogram::default();
+        for v in [-5, 0, 1, 2, 3, 4, 1000] {
+            hist.record(v, &HistScale::Log2);
+        }
+        let buckets: Vec<(i64, u64)> = hist.buckets.into_iter().collect();
+        assert_eq!(buckets, vec![(i64::MIN, 1), (0, 1), (1, 1), (2, 2), (4, 1), (512, 1)]);
+    }
+
+    #[test]
+    fn linear_buckets_at_the_ends_of_the_range_do_not_overflow() {
+        let mut agg = aggregator("lhist(bytes, 1000)", &[]);
+        agg.record(&event("a", FieldValue::UInt(u64::MAX)));
+        agg.record(&event("a", FieldValue::Int(i64::MAX)));
+        agg.record(&event("a", FieldValue::Int(i64::MIN)));
+        agg.record(&event("a", FieldValue::Int(-1)));
+
+        let rendered = agg.render(10);
+        assert!(rendered.contains("[9223372036854775000, 9223372036854776000)"), "{}", rendered);
+        assert!(rendered.contains("[-1000, 0)"), "{}", rendered);
+        assert!(rendered.contains("4 events aggregated"));
+
+        let mut hist = Histogram::default();
+        hist.record(i64::MIN, &HistScale::Linear(3));
+        assert_eq!(hist.buckets.keys().copied().collect::<Vec<_>>(), vec![i64::MIN]);
+    }
+}
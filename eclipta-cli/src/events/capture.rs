@@ -0,0 +1,208 @@
+use crate::events::decode::Decoder;
+use anyhow::{anyhow, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use object::{Object, ObjectSection};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"ECAP";
+const VERSION: u16 = 1;
+/// Upper bounds for lengths read from a capture, so a corrupt file can't force a huge allocation
+const MAX_HEADER_LEN: usize = 1 << 20;
+const MAX_BTF_LEN: usize = 64 << 20;
+/// Perf samples are limited to a u16 size by the kernel; leave room for anything larger
+const MAX_RECORD_LEN: usize = 1 << 20;
+
+/// Describes where a capture came from and how its records are laid out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub created_at: i64,
+    pub object: Option<PathBuf>,
+    pub program: String,
+    pub tracepoint: String,
+    pub map: String,
+    pub decoder: Decoder,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureRecord {
+    /// Wall-clock time the record was read from the perf buffer, in ns since the epoch
+    pub ts_ns: u64,
+    pub cpu: u32,
+    pub data: Vec<u8>,
+}
+
+/// `.ecap` layout: magic, version, JSON header, raw `.BTF` blob, then records of
+/// `ts_ns:u64 cpu:u32 len:u32 data[len]`, all little endian
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    pub records: u64,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path, header: &CaptureHeader, btf: Option<&[u8]>) -> Result<CaptureWriter> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create capture file {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let header_json = serde_json::to_vec(header)?;
+        let btf = btf.unwrap_or_default();
+
+        out.write_all(MAGIC)?;
+        out.write_u16::<LittleEndian>(VERSION)?;
+        out.write_u32::<LittleEndian>(header_json.len() as u32)?;
+        out.write_all(&header_json)?;
+        out.write_u32::<LittleEndian>(btf.len() as u32)?;
+        out.write_all(btf)?;
+
+        Ok(CaptureWriter { out, records: 0 })
+    }
+
+    pub fn write(&mut self, ts_ns: u64, cpu: u32, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_RECORD_LEN {
+            return Err(anyhow!("Record of {} bytes exceeds the {} byte capture limit", data.len(), MAX_RECORD_LEN));
+        }
+        self.out.write_u64::<LittleEndian>(ts_ns)?;
+        self.out.write_u32::<LittleEndian>(cpu)?;
+        self.out.write_u32::<LittleEndian>(data.len() as u32)?;
+        self.out.write_all(data)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<u64> {
+        self.out.flush()?;
+        Ok(self.records)
+    }
+}
+
+pub struct CaptureReader {
+    input: BufReader<File>,
+    pub header: CaptureHeader,
+    pub btf: Vec<u8>,
+}
+
+impl CaptureReader {
+    pub fn open(path: &Path) -> Result<CaptureReader> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open capture file {}", path.display()))?;
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic).context("Capture file is truncated")?;
+        if &magic != MAGIC {
+            return Err(anyhow!("{} is not an eclipta capture (.ecap) file", path.display()));
+        }
+        let version = input.read_u16::<LittleEndian>()?;
+        if version != VERSION {
+            return Err(anyhow!("Unsupported capture version {} (expected {})", version, VERSION));
+        }
+
+        let header_len = read_len(&mut input, MAX_HEADER_LEN, "header")?;
+        let mut header_json = vec![0u8; header_len];
+        input.read_exact(&mut header_json)?;
+        let header: CaptureHeader = serde_json::from_slice(&header_json)
+            .context("Capture header is corrupt")?;
+
+        let btf_len = read_len(&mut input, MAX_BTF_LEN, "BTF blob")?;
+        let mut btf = vec![0u8; btf_len];
+        input.read_exact(&mut btf)?;
+
+        Ok(CaptureReader { input, header, btf })
+    }
+
+    /// Next record, or `None` at end of file
+    pub fn next_record(&mut self) -> Result<Option<CaptureRecord>> {
+        let ts_ns = match self.input.read_u64::<LittleEndian>() {
+            Ok(ts) => ts,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let cpu = self.input.read_u32::<LittleEndian>()?;
+        let len = read_len(&mut self.input, MAX_RECORD_LEN, "record")?;
+        let mut data = vec![0u8; len];
+        self.input
+            .read_exact(&mut data)
+            .context("Capture file ends in the middle of a record")?;
+        Ok(Some(CaptureRecord { ts_ns, cpu, data }))
+    }
+}
+
+/// A u32 length prefix, rejected when it is larger than `max`
+fn read_len(input: &mut impl Read, max: usize, what: &str) -> Result<usize> {
+    let len = input.read_u32::<LittleEndian>()? as usize;
+    if len > max {
+        return Err(anyhow!("Capture {} length {} exceeds {} bytes; the file is corrupt", what, len, max));
+    }
+    Ok(len)
+}
+
+/// Raw `.BTF` section of an eBPF object, if it has one
+pub fn read_object_btf(path: &Path) -> Result<Option<Vec<u8>>> {
+    let data = std::fs::read(path).context("Failed to read eBPF object")?;
+    let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+    match obj.section_by_name(".BTF") {
+        Some(section) => Ok(Some(section.data()?.to_vec())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::decode::Layout;
+
+    fn header() -> CaptureHeader {
+        CaptureHeader {
+            created_at: 1_700_000_000,
+            object: Some(PathBuf::from("/tmp/exec.o")),
+            program: "handle_exec".to_string(),
+            tracepoint: "syscalls:sys_enter_execve".to_string(),
+            map: "EVENTS".to_string(),
+            decoder: Decoder::Layout(Layout::exec()),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("eclipta-{}-{}.ecap", name, std::process::id()))
+    }
+
+    #[test]
+    fn write_then_replay_round_trips() {
+        let path = temp_path("roundtrip");
+        let mut writer = CaptureWriter::create(&path, &header(), Some(b"btf")).unwrap();
+        writer.write(42, 1, b"abc").unwrap();
+        writer.write(43, 3, &[]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let mut reader = CaptureReader::open(&path).unwrap();
+        assert_eq!(reader.header.program, "handle_exec");
+        assert_eq!(reader.header.decoder.fields(), header().decoder.fields());
+        assert_eq!(reader.btf, b"btf");
+
+        let first = reader.next_record().unwrap().unwrap();
+        assert_eq!((first.ts_ns, first.cpu, first.data.as_slice()), (42, 1, &b"abc"[..]));
+        let second = reader.next_record().unwrap().unwrap();
+        assert_eq!((second.ts_ns, second.cpu, second.data.len()), (43, 3, 0));
+        assert!(reader.next_record().unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn oversized_record_length_is_rejected() {
+        let path = temp_path("oversized");
+        let writer = CaptureWriter::create(&path, &header(), None).unwrap();
+        writer.finish().unwrap();
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_u64::<LittleEndian>(1).unwrap();
+        file.write_u32::<LittleEndian>(0).unwrap();
+        file.write_u32::<LittleEndian>(u32::MAX).unwrap();
+        drop(file);
+
+        let mut reader = CaptureReader::open(&path).unwrap();
+        let err = reader.next_record().unwrap_err();
+        assert!(err.to_string().contains("exceeds"), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
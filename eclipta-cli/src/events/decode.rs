@@ -0,0 +1,397 @@
+use crate::events::symbolize::Frame;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Layout of the `{ u32 pid; char comm[16]; }` records used by `run --execve-format`
+pub const EXEC_LAYOUT: &str = "pid:u32,comm:char[16]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    /// NUL-padded C string, e.g. `char comm[16]`
+    Char(usize),
+    /// Opaque bytes rendered as hex
+    Bytes(usize),
+}
+
+impl FieldKind {
+    fn parse(s: &str) -> Option<FieldKind> {
+        let s = s.trim();
+        if let Some((base, rest)) = s.split_once('[') {
+            let len: usize = rest.strip_suffix(']')?.trim().parse().ok()?;
+            if len == 0 {
+                return None;
+            }
+            return match base.trim() {
+                "char" => Some(FieldKind::Char(len)),
+                "u8" => Some(FieldKind::Bytes(len)),
+                _ => None,
+            };
+        }
+        match s {
+            "u8" => Some(FieldKind::U8),
+            "u16" => Some(FieldKind::U16),
+            "u32" => Some(FieldKind::U32),
+            "u64" => Some(FieldKind::U64),
+            "i8" => Some(FieldKind::I8),
+            "i16" => Some(FieldKind::I16),
+            "i32" => Some(FieldKind::I32),
+            "i64" => Some(FieldKind::I64),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            FieldKind::U8 | FieldKind::I8 => 1,
+            FieldKind::U16 | FieldKind::I16 => 2,
+            FieldKind::U32 | FieldKind::I32 => 4,
+            FieldKind::U64 | FieldKind::I64 => 8,
+            FieldKind::Char(n) | FieldKind::Bytes(n) => *n,
+        }
+    }
+
+    fn align(&self) -> usize {
+        match self {
+            FieldKind::Char(_) | FieldKind::Bytes(_) => 1,
+            other => other.size(),
+        }
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldKind::Char(_) => FieldType::Text,
+            FieldKind::Bytes(_) => FieldType::Bytes,
+            _ => FieldType::Number,
+        }
+    }
+}
+
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldKind::U8 => write!(f, "u8"),
+            FieldKind::U16 => write!(f, "u16"),
+            FieldKind::U32 => write!(f, "u32"),
+            FieldKind::U64 => write!(f, "u64"),
+            FieldKind::I8 => write!(f, "i8"),
+            FieldKind::I16 => write!(f, "i16"),
+            FieldKind::I32 => write!(f, "i32"),
+            FieldKind::I64 => write!(f, "i64"),
+            FieldKind::Char(n) => write!(f, "char[{}]", n),
+            FieldKind::Bytes(n) => write!(f, "u8[{}]", n),
+        }
+    }
+}
+
+/// Coarse type of a decoded field, used to type-check filter expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Number,
+    Text,
+    Bytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldDef {
+    pub name: String,
+    pub kind: FieldKind,
+    pub offset: usize,
+}
+
+/// C-struct layout of a perf record, laid out with natural alignment like `#[repr(C)]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Layout {
+    pub label: Option<String>,
+    pub fields: Vec<FieldDef>,
+}
+
+impl Layout {
+    /// Parse a spec such as `pid:u32,comm:char[16]`
+    pub fn parse(spec: &str) -> Result<Layout> {
+        let mut fields: Vec<FieldDef> = Vec::new();
+        let mut offset = 0usize;
+
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, ty) = part
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Layout field '{}' must be 'name:type'", part))?;
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(anyhow!("Invalid layout field name '{}'", name));
+            }
+            if name == "cpu" {
+                return Err(anyhow!("Layout field 'cpu' clashes with the built-in cpu field"));
+            }
+            if fields.iter().any(|f| f.name == name) {
+                return Err(anyhow!("Duplicate layout field '{}'", name));
+            }
+            let kind = FieldKind::parse(ty).ok_or_else(|| {
+                anyhow!(
+                    "Unknown type '{}' for field '{}' (expected u8..u64, i8..i64, char[N] or u8[N])",
+                    ty.trim(),
+                    name
+                )
+            })?;
+            let align = kind.align();
+            offset = (offset + align - 1) / align * align;
+            fields.push(FieldDef { name: name.to_string(), kind, offset });
+            offset += kind.size();
+        }
+
+        if fields.is_empty() {
+            return Err(anyhow!("Layout '{}' has no fields", spec));
+        }
+
+        Ok(Layout { label: None, fields })
+    }
+
+    pub fn exec() -> Layout {
+        let mut layout = Layout::parse(EXEC_LAYOUT).expect("built-in exec layout is valid");
+        layout.label = Some("exec".to_string());
+        layout
+    }
+
+    /// Minimum record size needed to decode every field
+    pub fn size(&self) -> usize {
+        self.fields.iter().map(|f| f.offset + f.kind.size()).max().unwrap_or(0)
+    }
+
+    pub fn spec(&self) -> String {
+        self.fields
+            .iter()
+            .map(|f| format!("{}:{}", f.name, f.kind))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Int(i64),
+    UInt(u64),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl FieldValue {
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            FieldValue::Int(v) => Some(*v as i128),
+            FieldValue::UInt(v) => Some(*v as i128),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Int(v) => write!(f, "{}", v),
+            FieldValue::UInt(v) => write!(f, "{}", v),
+            FieldValue::Str(s) => write!(f, "{}", s),
+            FieldValue::Bytes(b) => write!(f, "{}", hex(b)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedEvent {
+    pub cpu: u32,
+    pub label: Option<String>,
+    pub fields: Vec<(String, FieldValue)>,
+    /// Symbolized stacks, innermost frame first; their folded form is also in `fields`
+    pub stacks: Vec<(String, Vec<Frame>)>,
+}
+
+impl DecodedEvent {
+    pub fn get(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// One JSON object per event; byte fields are hex encoded
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut obj = serde_json::Map::new();
+        if let Some(label) = &self.label {
+            obj.insert("event".to_string(), serde_json::Value::from(label.clone()));
+        }
+        for (name, value) in &self.fields {
+            if let Some((_, frames)) = self.stacks.iter().find(|(n, _)| n == name) {
+                let frames: Vec<String> = frames.iter().map(|f| f.to_string()).collect();
+                obj.insert(name.clone(), serde_json::Value::from(frames));
+                continue;
+            }
+            let v = match value {
+                FieldValue::Int(n) => serde_json::Value::from(*n),
+                FieldValue::UInt(n) => serde_json::Value::from(*n),
+                FieldValue::Str(s) => serde_json::Value::from(s.clone()),
+                FieldValue::Bytes(b) => serde_json::Value::from(hex(b)),
+            };
+            obj.insert(name.clone(), v);
+        }
+        serde_json::Value::Object(obj)
+    }
+}
+
+impl fmt::Display for DecodedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Text records keep the plain `run` output: the message itself
+        if self.label.is_none() && self.fields.len() == 2 {
+            if let Some(msg) = self.get("msg") {
+                return write!(f, "{}", msg);
+            }
+        }
+        let body = self
+            .fields
+            .iter()
+            .filter(|(n, _)| n != "cpu" && !self.stacks.iter().any(|(s, _)| s == n))
+            .map(|(n, v)| format!("{}={}", n, v))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match &self.label {
+            Some(label) => write!(f, "{} {}", label, body)?,
+            None => write!(f, "{}", body)?,
+        }
+        for (name, frames) in &self.stacks {
+            write!(f, "\n  {}:", name)?;
+            if frames.is_empty() {
+                write!(f, "\n    [unavailable]")?;
+            }
+            for frame in frames {
+                write!(f, "\n    {}", frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns raw perf records into named fields
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Decoder {
+    /// Records are NUL-terminated strings; non-UTF-8 payloads are kept as bytes
+    Text,
+    Layout(Layout),
+}
+
+impl Decoder {
+    /// Fields every decoded event carries, in output order
+    pub fn fields(&self) -> Vec<(String, FieldType)> {
+        let mut out = vec![("cpu".to_string(), FieldType::Number)];
+        match self {
+            Decoder::Text => out.push(("msg".to_string(), FieldType::Text)),
+            Decoder::Layout(layout) => {
+                for f in &layout.fields {
+                    out.push((f.name.clone(), f.kind.field_type()));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn decode(&self, cpu: u32, rec: &[u8]) -> DecodedEvent {
+        let mut fields = vec![("cpu".to_string(), FieldValue::UInt(cpu as u64))];
+        match self {
+            Decoder::Text => {
+                let msg = match std::str::from_utf8(rec) {
+                    Ok(s) => FieldValue::Str(s.trim_end_matches(char::from(0)).to_string()),
+                    Err(_) => FieldValue::Bytes(rec.to_vec()),
+                };
+                fields.push(("msg".to_string(), msg));
+                DecodedEvent { cpu, label: None, fields, stacks: Vec::new() }
+            }
+            Decoder::Layout(layout) if rec.len() >= layout.size() => {
+                for f in &layout.fields {
+                    let raw = &rec[f.offset..f.offset + f.kind.size()];
+                    fields.push((f.name.clone(), decode_field(f.kind, raw)));
+                }
+                DecodedEvent { cpu, label: layout.label.clone(), fields, stacks: Vec::new() }
+            }
+            // Short records cannot be laid out; keep them as bytes so they still show up
+            Decoder::Layout(_) => {
+                fields.push(("msg".to_string(), FieldValue::Bytes(rec.to_vec())));
+                DecodedEvent { cpu, label: None, fields, stacks: Vec::new() }
+            }
+        }
+    }
+}
+
+fn decode_field(kind: FieldKind, raw: &[u8]) -> FieldValue {
+    let mut buf = [0u8; 8];
+    let n = raw.len().min(8);
+    buf[..n].copy_from_slice(&raw[..n]);
+    let unsigned = u64::from_le_bytes(buf);
+    match kind {
+        FieldKind::U8 | FieldKind::U16 | FieldKind::U32 | FieldKind::U64 => FieldValue::UInt(unsigned),
+        FieldKind::I8 => FieldValue::Int(raw[0] as i8 as i64),
+        FieldKind::I16 => FieldValue::Int(unsigned as u16 as i16 as i64),
+        FieldKind::I32 => FieldValue::Int(unsigned as u32 as i32 as i64),
+        FieldKind::I64 => FieldValue::Int(unsigned as i64),
+        FieldKind::Char(_) => {
+            let end = raw.iter().position(|b| *b == 0).unwrap_or(raw.len());
+            FieldValue::Str(String::from_utf8_lossy(&raw[..end]).to_string())
+        }
+        FieldKind::Bytes(_) => FieldValue::Bytes(raw.to_vec()),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_fields_are_naturally_aligned() {
+        let layout = Layout::parse("flag:u8, pid:u32, comm:char[3], ts:u64").unwrap();
+        let offsets: Vec<usize> = layout.fields.iter().map(|f| f.offset).collect();
+        assert_eq!(offsets, vec![0, 4, 8, 16]);
+        assert_eq!(layout.size(), 24);
+        assert_eq!(layout.spec(), "flag:u8,pid:u32,comm:char[3],ts:u64");
+    }
+
+    #[test]
+    fn layout_rejects_bad_specs() {
+        for spec in ["", "pid", "pid:u128", "pid:u32,pid:u64", "cpu:u32", "a-b:u8", "buf:char[0]"] {
+            assert!(Layout::parse(spec).is_err(), "{} should not parse", spec);
+        }
+    }
+
+    #[test]
+    fn exec_layout_decodes_pid_and_comm() {
+        let mut rec = 4242u32.to_le_bytes().to_vec();
+        rec.extend_from_slice(b"bash\0\0\0\0\0\0\0\0\0\0\0\0");
+        let ev = Decoder::Layout(Layout::exec()).decode(2, &rec);
+        assert_eq!(ev.label.as_deref(), Some("exec"));
+        assert_eq!(ev.get("pid"), Some(&FieldValue::UInt(4242)));
+        assert_eq!(ev.get("comm"), Some(&FieldValue::Str("bash".to_string())));
+        assert_eq!(ev.to_string(), "exec pid=4242 comm=bash");
+    }
+
+    #[test]
+    fn signed_fields_are_sign_extended() {
+        let layout = Layout::parse("a:i8,b:i16,c:i32").unwrap();
+        let rec = [0xff, 0, 0xfe, 0xff, 0xfd, 0xff, 0xff, 0xff];
+        let ev = Decoder::Layout(layout).decode(0, &rec);
+        assert_eq!(ev.get("a"), Some(&FieldValue::Int(-1)));
+        assert_eq!(ev.get("b"), Some(&FieldValue::Int(-2)));
+        assert_eq!(ev.get("c"), Some(&FieldValue::Int(-3)));
+    }
+
+    #[test]
+    fn short_records_fall_back_to_bytes() {
+        let ev = Decoder::Layout(Layout::exec()).decode(0, &[1, 2]);
+        assert_eq!(ev.label, None);
+        assert_eq!(ev.get("msg"), Some(&FieldValue::Bytes(vec![1, 2])));
+    }
+}
@@ -0,0 +1,439 @@
+use crate::events::decode::{DecodedEvent, FieldType, FieldValue};
+use regex::Regex;
+use std::fmt;
+
+/// Error raised while parsing a `--filter` expression, pointing at the offending span
+#[derive(Debug)]
+pub struct FilterError {
+    pub expr: String,
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.end.saturating_sub(self.start).max(1);
+        writeln!(f, "invalid filter: {}", self.message)?;
+        writeln!(f, "  {}", self.expr)?;
+        write!(f, "  {}{}", " ".repeat(self.start), "^".repeat(width))
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Int(i128),
+    Str(String),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare { field: String, op: CmpOp, value: Literal },
+    Regex { field: String, negate: bool, re: Regex },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(i128),
+    Str(String),
+    Op(CmpOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    start: usize,
+    end: usize,
+}
+
+/// A compiled filter expression, e.g. `pid == 1234 && comm =~ "nginx.*"`
+#[derive(Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// Parse `source` and check every referenced field against the decoder's field set
+    pub fn parse(source: &str, fields: &[(String, FieldType)]) -> Result<Filter, FilterError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { source, tokens, pos: 0, fields };
+        let expr = parser.parse_or()?;
+        if let Some(t) = parser.tokens.get(parser.pos) {
+            return Err(parser.error(t.start, t.end, "unexpected token after end of expression"));
+        }
+        Ok(Filter { expr })
+    }
+
+    pub fn matches(&self, ev: &DecodedEvent) -> bool {
+        eval(&self.expr, ev)
+    }
+}
+
+fn eval(expr: &Expr, ev: &DecodedEvent) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, ev) && eval(b, ev),
+        Expr::Or(a, b) => eval(a, ev) || eval(b, ev),
+        Expr::Not(e) => !eval(e, ev),
+        Expr::Regex { field, negate, re } => match ev.get(field) {
+            Some(FieldValue::Str(s)) => re.is_match(s) != *negate,
+            Some(other) => re.is_match(&other.to_string()) != *negate,
+            None => false,
+        },
+        Expr::Compare { field, op, value } => {
+            let Some(actual) = ev.get(field) else { return false };
+            let ord = match (actual, value) {
+                (v, Literal::Int(n)) => match v.as_i128() {
+                    Some(a) => a.cmp(n),
+                    None => return false,
+                },
+                (FieldValue::Str(s), Literal::Str(lit)) => s.as_str().cmp(lit.as_str()),
+                (FieldValue::Bytes(b), Literal::Str(lit)) => {
+                    crate::events::decode::hex(b).as_str().cmp(lit.as_str())
+                }
+                _ => return false,
+            };
+            match op {
+                CmpOp::Eq => ord.is_eq(),
+                CmpOp::Ne => ord.is_ne(),
+                CmpOp::Lt => ord.is_lt(),
+                CmpOp::Le => ord.is_le(),
+                CmpOp::Gt => ord.is_gt(),
+                CmpOp::Ge => ord.is_ge(),
+                CmpOp::Match | CmpOp::NotMatch => unreachable!("regex ops compile to Expr::Regex"),
+            }
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, FilterError> {
+    let err = |start: usize, end: usize, message: String| FilterError {
+        expr: source.to_string(),
+        start,
+        end,
+        message,
+    };
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let two = |tok: Tok| Token { tok, start, end: start + 2 };
+        let one = |tok: Tok| Token { tok, start, end: start + 1 };
+
+        let token = match (c, next) {
+            ('&', Some('&')) => { i += 2; two(Tok::And) }
+            ('|', Some('|')) => { i += 2; two(Tok::Or) }
+            ('=', Some('=')) => { i += 2; two(Tok::Op(CmpOp::Eq)) }
+            ('=', Some('~')) => { i += 2; two(Tok::Op(CmpOp::Match)) }
+            ('!', Some('=')) => { i += 2; two(Tok::Op(CmpOp::Ne)) }
+            ('!', Some('~')) => { i += 2; two(Tok::Op(CmpOp::NotMatch)) }
+            ('<', Some('=')) => { i += 2; two(Tok::Op(CmpOp::Le)) }
+            ('>', Some('=')) => { i += 2; two(Tok::Op(CmpOp::Ge)) }
+            ('<', _) => { i += 1; one(Tok::Op(CmpOp::Lt)) }
+            ('>', _) => { i += 1; one(Tok::Op(CmpOp::Gt)) }
+            ('!', _) => { i += 1; one(Tok::Not) }
+            ('(', _) => { i += 1; one(Tok::LParen) }
+            (')', _) => { i += 1; one(Tok::RParen) }
+            ('"', _) => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                let mut closed = false;
+                while j < chars.len() {
+                    match chars[j].1 {
+                        '\\' if j + 1 < chars.len() => {
+                            value.push(match chars[j + 1].1 {
+                                'n' => '\n',
+                                't' => '\t',
+                                other => other,
+                            });
+                            j += 2;
+                        }
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        other => {
+                            value.push(other);
+                            j += 1;
+                        }
+                    }
+                }
+                if !closed {
+                    return Err(err(start, source.len(), "unterminated string literal".to_string()));
+                }
+                let end = chars[j].0 + 1;
+                i = j + 1;
+                Token { tok: Tok::Str(value), start, end }
+            }
+            (c, _) if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let mut j = i + 1;
+                while j < chars.len() && (chars[j].1.is_ascii_alphanumeric() || chars[j].1 == '_') {
+                    j += 1;
+                }
+                let end = chars.get(j).map(|(p, _)| *p).unwrap_or(source.len());
+                let text = source[start..end].replace('_', "");
+                let (negative, digits) = match text.strip_prefix('-') {
+                    Some(rest) => (true, rest.to_string()),
+                    None => (false, text.clone()),
+                };
+                let parsed = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+                    Some(hex) => i128::from_str_radix(hex, 16),
+                    None => digits.parse::<i128>(),
+                };
+                let value = parsed.map_err(|_| err(start, end, format!("invalid number '{}'", text)))?;
+                i = j;
+                Token { tok: Tok::Int(if negative { -value } else { value }), start, end }
+            }
+            (c, _) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut j = i + 1;
+                while j < chars.len() && (chars[j].1.is_ascii_alphanumeric() || chars[j].1 == '_') {
+                    j += 1;
+                }
+                let end = chars.get(j).map(|(p, _)| *p).unwrap_or(source.len());
+                i = j;
+                let word = &source[start..end];
+                let tok = match word {
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    _ => Tok::Ident(word.to_string()),
+                };
+                Token { tok, start, end }
+            }
+            _ => {
+                return Err(err(start, start + c.len_utf8(), format!("unexpected character '{}'", c)));
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    fields: &'a [(String, FieldType)],
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, start: usize, end: usize, message: impl Into<String>) -> FilterError {
+        FilterError {
+            expr: self.source.to_string(),
+            start,
+            end,
+            message: message.into(),
+        }
+    }
+
+    fn eof_error(&self, message: &str) -> FilterError {
+        let at = self.source.len();
+        self.error(at, at + 1, message)
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, FilterError> {
+        let mut lhs = self.parse_and()?;
+        while self.peek() == Some(&Tok::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, FilterError> {
+        let mut lhs = self.parse_unary()?;
+        while self.peek() == Some(&Tok::And) {
+            self.pos += 1;
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, FilterError> {
+        if self.peek() == Some(&Tok::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.peek() == Some(&Tok::LParen) {
+            let open = self.next().unwrap();
+            let inner = self.parse_or()?;
+            return match self.next() {
+                Some(Token { tok: Tok::RParen, .. }) => Ok(inner),
+                _ => Err(self.error(open.start, open.end, "unclosed '('")),
+            };
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, FilterError> {
+        let field_tok = self.next().ok_or_else(|| self.eof_error("expected a field name"))?;
+        let Tok::Ident(field) = field_tok.tok.clone() else {
+            return Err(self.error(field_tok.start, field_tok.end, "expected a field name"));
+        };
+        let field_type = self
+            .fields
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, ty)| *ty)
+            .ok_or_else(|| {
+                let available = self.fields.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", ");
+                self.error(
+                    field_tok.start,
+                    field_tok.end,
+                    format!("unknown field '{}' (available: {})", field, available),
+                )
+            })?;
+
+        let op_tok = self
+            .next()
+            .ok_or_else(|| self.eof_error(&format!("expected an operator after '{}'", field)))?;
+        let Tok::Op(op) = op_tok.tok else {
+            return Err(self.error(op_tok.start, op_tok.end, "expected one of ==, !=, <, <=, >, >=, =~, !~"));
+        };
+
+        let value_tok = self.next().ok_or_else(|| self.eof_error("expected a value"))?;
+        let value = match value_tok.tok.clone() {
+            Tok::Int(n) => Literal::Int(n),
+            Tok::Str(s) => Literal::Str(s),
+            _ => return Err(self.error(value_tok.start, value_tok.end, "expected a number or a quoted string")),
+        };
+
+        if matches!(op, CmpOp::Match | CmpOp::NotMatch) {
+            let Literal::Str(pattern) = value else {
+                return Err(self.error(value_tok.start, value_tok.end, "regex operators need a quoted pattern"));
+            };
+            let re = Regex::new(&pattern).map_err(|e| {
+                self.error(value_tok.start, value_tok.end, format!("invalid regex: {}", e))
+            })?;
+            return Ok(Expr::Regex { field, negate: op == CmpOp::NotMatch, re });
+        }
+
+        match (field_type, &value) {
+            (FieldType::Number, Literal::Str(_)) => Err(self.error(
+                value_tok.start,
+                value_tok.end,
+                format!("field '{}' is numeric; compare it with a number", field),
+            )),
+            (FieldType::Text, Literal::Int(_)) | (FieldType::Bytes, Literal::Int(_)) => Err(self.error(
+                value_tok.start,
+                value_tok.end,
+                format!("field '{}' is a string; compare it with a quoted string", field),
+            )),
+            _ => Ok(Expr::Compare { field, op, value }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Vec<(String, FieldType)> {
+        vec![
+            ("cpu".to_string(), FieldType::Number),
+            ("pid".to_string(), FieldType::Number),
+            ("comm".to_string(), FieldType::Text),
+        ]
+    }
+
+    fn event(pid: u64, comm: &str) -> DecodedEvent {
+        DecodedEvent {
+            cpu: 0,
+            label: None,
+            fields: vec![
+                ("cpu".to_string(), FieldValue::UInt(0)),
+                ("pid".to_string(), FieldValue::UInt(pid)),
+                ("comm".to_string(), FieldValue::Str(comm.to_string())),
+            ],
+            stacks: Vec::new(),
+        }
+    }
+
+    fn matches(expr: &str, ev: &DecodedEvent) -> bool {
+        Filter::parse(expr, &fields()).unwrap().matches(ev)
+    }
+
+    #[test]
+    fn comparisons_and_regexes() {
+        let ev = event(1234, "nginx-worker");
+        assert!(matches("pid == 1234", &ev));
+        assert!(matches("pid >= 0x400 && pid < 2_000", &ev));
+        assert!(matches(r#"comm =~ "^nginx""#, &ev));
+        assert!(!matches(r#"comm !~ "nginx""#, &ev));
+        assert!(matches(r#"comm == "nginx-worker""#, &ev));
+        assert!(!matches("pid != 1234", &ev));
+        assert!(matches("pid > -1", &ev));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let ev = event(1, "sh");
+        assert!(matches(r#"pid == 1 || pid == 2 && comm == "bash""#, &ev));
+        assert!(!matches(r#"(pid == 1 || pid == 2) && comm == "bash""#, &ev));
+        assert!(matches(r#"not comm == "bash" and pid == 1"#, &ev));
+        assert!(!matches("!(pid == 1)", &ev));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let err = Filter::parse("pid == 1 && uid == 0", &fields()).unwrap_err();
+        assert_eq!((err.start, err.end), (12, 15));
+        assert!(err.message.contains("unknown field 'uid'"));
+
+        let err = Filter::parse(r#"pid == "x""#, &fields()).unwrap_err();
+        assert_eq!(err.start, 7);
+        assert!(err.message.contains("numeric"));
+
+        let err = Filter::parse("comm == 3", &fields()).unwrap_err();
+        assert!(err.message.contains("quoted string"));
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for expr in ["", "pid", "pid ==", "(pid == 1", "pid == 1 pid", r#"comm == "open"#, "pid == 1 $", r#"comm =~ "(""#] {
+            assert!(Filter::parse(expr, &fields()).is_err(), "{} should not parse", expr);
+        }
+    }
+}
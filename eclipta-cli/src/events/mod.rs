@@ -0,0 +1,212 @@
+pub mod aggregate;
+pub mod capture;
+pub mod decode;
+pub mod filter;
+pub mod stacks;
+pub mod symbolize;
+pub mod throttle;
+
+use crate::events::aggregate::Aggregator;
+use crate::events::decode::{DecodedEvent, FieldType};
+use crate::events::filter::Filter;
+use crate::events::throttle::{RateLimiter, Sampler};
+use crate::utils::otlp::{now_ns, LogRecord, OtlpExporter, OtlpOptions};
+use anyhow::{anyhow, Result};
+use clap::Args;
+use std::time::Duration;
+
+/// Options shared by every command that prints a stream of decoded events
+#[derive(Args, Debug, Clone, Default)]
+pub struct StreamOptions {
+    /// Only show events matching an expression, e.g. 'pid == 1234 && comm =~ "nginx.*"'
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Keep one out of every N events that pass the filter
+    #[arg(long, value_name = "N")]
+    pub sample: Option<u64>,
+
+    /// Print at most N events per second, e.g. "500/s"
+    #[arg(long, value_name = "N/s")]
+    pub rate_limit: Option<String>,
+
+    /// Aggregate instead of printing events: count, sum(field), hist(field) or lhist(field, step)
+    #[arg(long, value_name = "AGG")]
+    pub aggregate: Option<String>,
+
+    /// Fields to group aggregations by, comma separated
+    #[arg(long, value_delimiter = ',', requires = "aggregate")]
+    pub by: Vec<String>,
+
+    /// Refresh interval for the aggregation table, e.g. "1s" or "500ms"
+    #[arg(long, default_value = "1s")]
+    pub interval: String,
+
+    /// Number of keys shown in the aggregation table
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    /// Output format for events: text or json (one object per line)
+    #[arg(long, default_value = "text")]
+    pub format: String,
+
+    /// Also send printed events to an OTLP collector as log records
+    #[command(flatten)]
+    pub otlp: OtlpOptions,
+}
+
+/// Filter, sampling and rate limiting applied to decoded events, in that order
+#[derive(Debug)]
+pub struct Pipeline {
+    filter: Option<Filter>,
+    sampler: Option<Sampler>,
+    limiter: Option<RateLimiter>,
+}
+
+impl Pipeline {
+    pub fn new(opts: &StreamOptions, fields: &[(String, FieldType)]) -> Result<Pipeline> {
+        let filter = match opts.filter.as_deref() {
+            Some(expr) => Some(Filter::parse(expr, fields).map_err(|e| anyhow!("{}", e))?),
+            None => None,
+        };
+        let sampler = opts.sample.map(Sampler::new).transpose()?;
+        let limiter = opts.rate_limit.as_deref().map(RateLimiter::parse).transpose()?;
+        Ok(Pipeline { filter, sampler, limiter })
+    }
+
+    /// Apply the filter and sampler only
+    pub fn select(&mut self, ev: &DecodedEvent) -> bool {
+        if let Some(filter) = &self.filter {
+            if !filter.matches(ev) {
+                return false;
+            }
+        }
+        match &mut self.sampler {
+            Some(sampler) => sampler.keep(),
+            None => true,
+        }
+    }
+
+    pub fn admit(&mut self, ev: &DecodedEvent) -> bool {
+        if !self.select(ev) {
+            return false;
+        }
+        match &mut self.limiter {
+            Some(limiter) => limiter.allow(),
+            None => true,
+        }
+    }
+
+    /// Events dropped by the rate limiter so far
+    pub fn suppressed(&self) -> u64 {
+        self.limiter.as_ref().map(|l| l.suppressed).unwrap_or(0)
+    }
+}
+
+/// End of the event pipeline: prints admitted events or folds them into an aggregation
+#[derive(Debug)]
+pub struct EventSink {
+    pipeline: Pipeline,
+    aggregator: Option<Aggregator>,
+    interval: Duration,
+    top: usize,
+    json: bool,
+    otlp: Option<OtlpExporter>,
+}
+
+impl EventSink {
+    /// `fields` is the decoded field set that filters and aggregations are checked against
+    pub fn new(opts: &StreamOptions, fields: &[(String, FieldType)]) -> Result<EventSink> {
+        let json = match opts.format.as_str() {
+            "text" => false,
+            "json" => true,
+            other => return Err(anyhow!("Unknown --format '{}' (expected text or json)", other)),
+        };
+        let pipeline = Pipeline::new(opts, fields)?;
+        let aggregator = match opts.aggregate.as_deref() {
+            Some(spec) => Some(Aggregator::new(spec, &opts.by, fields)?),
+            None => None,
+        };
+        let interval = humantime::parse_duration(&opts.interval)
+            .map_err(|e| anyhow!("Invalid --interval '{}': {}", opts.interval, e))?;
+        if interval.is_zero() {
+            return Err(anyhow!("--interval must be greater than zero"));
+        }
+        Ok(EventSink {
+            pipeline,
+            aggregator,
+            interval,
+            top: opts.top,
+            json,
+            otlp: OtlpExporter::start(&opts.otlp)?,
+        })
+    }
+
+    pub fn push(&mut self, ev: DecodedEvent) {
+        self.push_at(ev, now_ns());
+    }
+
+    /// Like `push`, with the time the event happened, e.g. from a capture
+    pub fn push_at(&mut self, ev: DecodedEvent, time_ns: u64) {
+        match &mut self.aggregator {
+            // Rate limiting only protects the terminal; aggregations see every selected event
+            Some(agg) => {
+                if self.pipeline.select(&ev) {
+                    agg.record(&ev);
+                }
+            }
+            None => {
+                if self.pipeline.admit(&ev) {
+                    if self.json {
+                        println!("{}", ev.to_json());
+                    } else {
+                        println!("{}", ev);
+                    }
+                    if let Some(otlp) = &self.otlp {
+                        otlp.log(log_record(&ev, time_ns));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_aggregating(&self) -> bool {
+        self.aggregator.is_some()
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Redraw the aggregation table in place
+    pub fn refresh(&self) {
+        if let Some(agg) = &self.aggregator {
+            print!("\x1B[2J\x1B[1;1H");
+            println!("Top {} (every {}, Ctrl+C for summary)\n", self.top, humantime::format_duration(self.interval));
+            print!("{}", agg.render(self.top));
+        }
+    }
+
+    /// Print the final aggregation and deliver anything still queued for the collector
+    pub async fn finish(self) {
+        if let Some(agg) = &self.aggregator {
+            println!("\nFinal summary:\n");
+            print!("{}", agg.render(usize::MAX));
+        }
+        if self.pipeline.suppressed() > 0 {
+            eprintln!("Rate limit suppressed {} events", self.pipeline.suppressed());
+        }
+        if let Some(otlp) = self.otlp {
+            otlp.shutdown().await;
+        }
+    }
+}
+
+/// One log record per event: the text rendering as body, every field as an attribute
+fn log_record(ev: &DecodedEvent, time_ns: u64) -> LogRecord {
+    let attributes = match ev.to_json() {
+        serde_json::Value::Object(fields) => fields,
+        _ => serde_json::Map::new(),
+    };
+    LogRecord { time_ns, body: ev.to_string(), attributes }
+}
@@ -0,0 +1,132 @@
+use crate::events::decode::{DecodedEvent, FieldType, FieldValue};
+use crate::events::symbolize::{fold, Frame, Symbolizer};
+use crate::utils::logger::warn;
+use anyhow::{anyhow, Result};
+use aya::maps::{stack_trace::StackTraceMap, MapData};
+use clap::Args;
+
+/// Options for resolving stack IDs carried in events through a stack trace map
+#[derive(Args, Debug, Clone, Default)]
+pub struct StackOptions {
+    /// BPF_MAP_TYPE_STACK_TRACE map to resolve stack IDs against
+    #[arg(long, value_name = "MAP")]
+    pub stack_map: Option<String>,
+
+    /// Event field holding a kernel stack ID (default: kstack_id, if the layout has it)
+    #[arg(long, value_name = "FIELD", requires = "stack_map")]
+    pub kstack_field: Option<String>,
+
+    /// Event field holding a user stack ID (default: ustack_id, if the layout has it)
+    #[arg(long, value_name = "FIELD", requires = "stack_map")]
+    pub ustack_field: Option<String>,
+
+    /// Event field holding the process ID used to symbolize user stacks
+    #[arg(long, value_name = "FIELD", default_value = "pid")]
+    pub pid_field: String,
+}
+
+/// Which event fields carry stack IDs, checked against the decoder's field set
+#[derive(Debug, Clone)]
+pub struct StackFields {
+    kernel: Option<String>,
+    user: Option<String>,
+    pid: String,
+}
+
+impl StackFields {
+    /// `None` when no stack map was requested
+    pub fn new(opts: &StackOptions, fields: &[(String, FieldType)]) -> Result<Option<StackFields>> {
+        if opts.stack_map.is_none() {
+            return Ok(None);
+        }
+        let numeric = |name: &str| fields.iter().any(|(n, t)| n == name && *t == FieldType::Number);
+        let pick = |explicit: &Option<String>, default: &str, flag: &str| -> Result<Option<String>> {
+            match explicit {
+                Some(name) if numeric(name) => Ok(Some(name.clone())),
+                Some(name) => Err(anyhow!("{} '{}' is not a numeric field of the event layout", flag, name)),
+                None if numeric(default) => Ok(Some(default.to_string())),
+                None => Ok(None),
+            }
+        };
+
+        let kernel = pick(&opts.kstack_field, "kstack_id", "--kstack-field")?;
+        let user = pick(&opts.ustack_field, "ustack_id", "--ustack-field")?;
+        if kernel.is_none() && user.is_none() {
+            return Err(anyhow!(
+                "--stack-map needs a stack ID field in --layout (kstack_id, ustack_id, or set --kstack-field/--ustack-field)"
+            ));
+        }
+        if user.is_some() && !numeric(&opts.pid_field) {
+            return Err(anyhow!(
+                "User stacks need a numeric '{}' field to find the process (set --pid-field)",
+                opts.pid_field
+            ));
+        }
+        Ok(Some(StackFields { kernel, user, pid: opts.pid_field.clone() }))
+    }
+
+    /// Folded stack fields added to every event, usable in filters and `--by`
+    pub fn fields(&self) -> Vec<(String, FieldType)> {
+        let mut out = Vec::new();
+        if self.kernel.is_some() {
+            out.push(("kstack".to_string(), FieldType::Text));
+        }
+        if self.user.is_some() {
+            out.push(("ustack".to_string(), FieldType::Text));
+        }
+        out
+    }
+}
+
+/// Looks up stack IDs in the stack trace map and attaches symbolized frames to events
+pub struct StackResolver {
+    map: StackTraceMap<MapData>,
+    fields: StackFields,
+    symbolizer: Symbolizer,
+}
+
+impl StackResolver {
+    pub fn new(map: StackTraceMap<MapData>, fields: StackFields) -> StackResolver {
+        let symbolizer = if fields.kernel.is_some() {
+            Symbolizer::new().with_kernel_symbols().unwrap_or_else(|e| {
+                warn(&format!("Kernel frames will not be symbolized: {}", e));
+                Symbolizer::new()
+            })
+        } else {
+            Symbolizer::new()
+        };
+        StackResolver { map, fields, symbolizer }
+    }
+
+    pub fn resolve(&mut self, ev: &mut DecodedEvent) {
+        if let Some(field) = self.fields.kernel.clone() {
+            let frames = self.lookup(ev, &field).map(|ips| self.symbolizer.kernel_frames(&ips));
+            attach(ev, "kstack", frames);
+        }
+        if let Some(field) = self.fields.user.clone() {
+            let pid = ev.get(&self.fields.pid).and_then(|v| v.as_i128()).unwrap_or(0) as u32;
+            let frames = self.lookup(ev, &field).map(|ips| self.symbolizer.user_frames(pid, &ips));
+            attach(ev, "ustack", frames);
+        }
+    }
+
+    /// Instruction pointers for the stack ID in `field`, innermost frame first
+    fn lookup(&self, ev: &DecodedEvent, field: &str) -> Option<Vec<u64>> {
+        // bpf_get_stackid() returns a negative errno when the stack could not be stored
+        let id = ev.get(field)?.as_i128()? as u32;
+        if (id as i32) < 0 {
+            return None;
+        }
+        let trace = self.map.get(&id, 0).ok()?;
+        Some(trace.frames().iter().map(|f| f.ip).collect())
+    }
+}
+
+fn attach(ev: &mut DecodedEvent, name: &str, frames: Option<Vec<Frame>>) {
+    let folded = match &frames {
+        Some(frames) => fold(frames),
+        None => "[unavailable]".to_string(),
+    };
+    ev.fields.push((name.to_string(), FieldValue::Str(folded)));
+    ev.stacks.push((name.to_string(), frames.unwrap_or_default()));
+}
@@ -0,0 +1,346 @@
+use anyhow::{anyhow, Context, Result};
+use object::{Object, ObjectKind, ObjectSegment, ObjectSymbol, SymbolKind};
+use std::collections::HashMap;
+use std::fmt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Cached processes before the ones that exited are dropped
+const MAX_PROCESSES: usize = 1024;
+
+/// One resolved instruction pointer of a stack trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub addr: u64,
+    pub symbol: Option<String>,
+    pub offset: u64,
+    /// Kernel module or file name of the mapping the address falls into
+    pub module: Option<String>,
+}
+
+impl Frame {
+    fn unknown(addr: u64) -> Frame {
+        Frame { addr, symbol: None, offset: 0, module: None }
+    }
+
+    /// Function name without offset, as used in folded stacks; raw address if unresolved
+    pub fn name(&self) -> String {
+        self.symbol.clone().unwrap_or_else(|| format!("0x{:x}", self.addr))
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.symbol {
+            Some(sym) => write!(f, "{}+0x{:x}", sym, self.offset)?,
+            None => write!(f, "0x{:x}", self.addr)?,
+        }
+        if let Some(module) = &self.module {
+            write!(f, " ({})", module)?;
+        }
+        Ok(())
+    }
+}
+
+/// Join frames (innermost first) into the `outer;...;inner` form used by flame graph tools
+pub fn fold(frames: &[Frame]) -> String {
+    frames.iter().rev().map(Frame::name).collect::<Vec<_>>().join(";")
+}
+
+/// Kernel symbol table from `/proc/kallsyms`, sorted by address
+#[derive(Debug, Default)]
+pub struct KernelSymbols {
+    symbols: Vec<(u64, String, Option<String>)>,
+}
+
+impl KernelSymbols {
+    pub fn load() -> Result<KernelSymbols> {
+        let text = std::fs::read_to_string("/proc/kallsyms").context("Failed to read /proc/kallsyms")?;
+        let mut symbols = Vec::new();
+        for line in text.lines() {
+            let mut parts = line.split_whitespace();
+            let (Some(addr), Some(kind), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
+            };
+            // Only text symbols can show up in a stack
+            if !matches!(kind, "t" | "T" | "w" | "W") {
+                continue;
+            }
+            let Ok(addr) = u64::from_str_radix(addr, 16) else { continue };
+            if addr == 0 {
+                continue;
+            }
+            let module = parts
+                .next()
+                .map(|m| m.trim_start_matches('[').trim_end_matches(']').to_string());
+            symbols.push((addr, name.to_string(), module));
+        }
+        if symbols.is_empty() {
+            // kptr_restrict hides every address from unprivileged readers
+            return Err(anyhow!("/proc/kallsyms has no usable addresses (run as root or lower kernel.kptr_restrict)"));
+        }
+        symbols.sort_by_key(|(addr, _, _)| *addr);
+        Ok(KernelSymbols { symbols })
+    }
+
+    pub fn resolve(&self, addr: u64) -> Frame {
+        let idx = self.symbols.partition_point(|(a, _, _)| *a <= addr);
+        if idx == 0 {
+            return Frame::unknown(addr);
+        }
+        let (start, name, module) = &self.symbols[idx - 1];
+        Frame {
+            addr,
+            symbol: Some(name.clone()),
+            offset: addr - start,
+            module: module.clone(),
+        }
+    }
+}
+
+/// Function symbols of one ELF file plus its loadable segments
+#[derive(Debug)]
+struct ElfSymbols {
+    /// ET_EXEC binaries are mapped at their link addresses, everything else is relocated
+    absolute: bool,
+    /// (file offset, file size, virtual address) per segment
+    segments: Vec<(u64, u64, u64)>,
+    /// (address, size, name), sorted by address
+    symbols: Vec<(u64, u64, String)>,
+}
+
+impl ElfSymbols {
+    fn load(path: &Path) -> Result<ElfSymbols> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+
+        let segments = obj
+            .segments()
+            .map(|s| {
+                let (offset, size) = s.file_range();
+                (offset, size, s.address())
+            })
+            .collect();
+
+        let mut symbols: Vec<(u64, u64, String)> = obj
+            .symbols()
+            .chain(obj.dynamic_symbols())
+            .filter(|s| s.kind() == SymbolKind::Text && s.address() != 0)
+            .filter_map(|s| Some((s.address(), s.size(), s.name().ok()?.to_string())))
+            .filter(|(_, _, name)| !name.is_empty())
+            .collect();
+        symbols.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)));
+        symbols.dedup_by_key(|(addr, _, _)| *addr);
+
+        Ok(ElfSymbols {
+            absolute: obj.kind() == ObjectKind::Executable,
+            segments,
+            symbols,
+        })
+    }
+
+    /// Translate a file offset into the ELF's own virtual address space
+    fn file_offset_to_vaddr(&self, file_offset: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|(off, size, _)| file_offset >= *off && file_offset < off + size)
+            .map(|(off, _, vaddr)| vaddr + (file_offset - off))
+    }
+
+    fn lookup(&self, vaddr: u64) -> Option<(&str, u64)> {
+        let idx = self.symbols.partition_point(|(a, _, _)| *a <= vaddr);
+        if idx == 0 {
+            return None;
+        }
+        let (start, size, name) = &self.symbols[idx - 1];
+        if *size > 0 && vaddr >= start + size {
+            return None;
+        }
+        Some((name.as_str(), vaddr - start))
+    }
+}
+
+/// An executable mapping from `/proc/<pid>/maps`
+#[derive(Debug, Clone)]
+struct Mapping {
+    start: u64,
+    end: u64,
+    offset: u64,
+    path: String,
+}
+
+fn read_maps(pid: u32) -> Result<Vec<Mapping>> {
+    let text = std::fs::read_to_string(format!("/proc/{}/maps", pid))
+        .with_context(|| format!("Failed to read /proc/{}/maps", pid))?;
+    let mut maps = Vec::new();
+    for line in text.lines() {
+        // start-end perms offset dev inode path
+        let mut parts = line.splitn(6, char::is_whitespace);
+        let (Some(range), Some(perms), Some(offset)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let path = parts.nth(2).unwrap_or("").trim();
+        if !perms.contains('x') || !path.starts_with('/') {
+            continue;
+        }
+        let Some((start, end)) = range.split_once('-') else { continue };
+        let (Ok(start), Ok(end), Ok(offset)) = (
+            u64::from_str_radix(start, 16),
+            u64::from_str_radix(end, 16),
+            u64::from_str_radix(offset, 16),
+        ) else {
+            continue;
+        };
+        maps.push(Mapping {
+            start,
+            end,
+            offset,
+            path: path.trim_end_matches(" (deleted)").to_string(),
+        });
+    }
+    Ok(maps)
+}
+
+/// Device and inode of a process's root directory, telling container filesystems apart
+type RootId = Option<(u64, u64)>;
+
+/// What is cached per process: its root and executable mappings
+#[derive(Debug, Default)]
+struct Process {
+    root: RootId,
+    maps: Vec<Mapping>,
+}
+
+impl Process {
+    fn read(pid: u32) -> Process {
+        let root = std::fs::metadata(format!("/proc/{}/root", pid)).ok().map(|m| (m.dev(), m.ino()));
+        Process { root, maps: read_maps(pid).unwrap_or_default() }
+    }
+}
+
+/// Resolves kernel and user stack frames, caching symbol tables and process mappings
+#[derive(Debug, Default)]
+pub struct Symbolizer {
+    kernel: Option<KernelSymbols>,
+    processes: HashMap<u32, Process>,
+    /// Keyed by root as well as path, since the same path differs between containers
+    elfs: HashMap<(RootId, PathBuf), Option<Arc<ElfSymbols>>>,
+}
+
+impl Symbolizer {
+    pub fn new() -> Symbolizer {
+        Symbolizer::default()
+    }
+
+    /// Load kernel symbols up front; without them kernel frames are printed as addresses
+    pub fn with_kernel_symbols(mut self) -> Result<Symbolizer> {
+        self.kernel = Some(KernelSymbols::load()?);
+        Ok(self)
+    }
+
+    pub fn kernel_frames(&self, ips: &[u64]) -> Vec<Frame> {
+        ips.iter()
+            .map(|ip| match &self.kernel {
+                Some(k) => k.resolve(*ip),
+                None => Frame::unknown(*ip),
+            })
+            .collect()
+    }
+
+    pub fn user_frames(&mut self, pid: u32, ips: &[u64]) -> Vec<Frame> {
+        // Processes that already exited keep their raw addresses
+        if !self.processes.contains_key(&pid) {
+            if self.processes.len() >= MAX_PROCESSES {
+                self.evict_exited();
+            }
+            self.processes.insert(pid, Process::read(pid));
+        }
+        ips.iter().map(|ip| self.resolve_user(pid, *ip)).collect()
+    }
+
+    /// Drop cached processes that no longer exist, so a reused pid is read afresh
+    fn evict_exited(&mut self) {
+        self.processes.retain(|pid, _| Path::new(&format!("/proc/{}", pid)).exists());
+    }
+
+    fn resolve_user(&mut self, pid: u32, addr: u64) -> Frame {
+        let Some((root, mapping)) = self.processes.get(&pid).and_then(|p| {
+            let mapping = p.maps.iter().find(|m| addr >= m.start && addr < m.end)?;
+            Some((p.root, mapping.clone()))
+        }) else {
+            return Frame::unknown(addr);
+        };
+
+        let module = Path::new(&mapping.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string());
+        let mut frame = Frame { module, ..Frame::unknown(addr) };
+
+        let Some(elf) = self.elf(pid, root, &mapping.path) else { return frame };
+        let vaddr = if elf.absolute {
+            Some(addr)
+        } else {
+            elf.file_offset_to_vaddr(addr - mapping.start + mapping.offset)
+        };
+        if let Some((name, offset)) = vaddr.and_then(|v| elf.lookup(v)) {
+            frame.symbol = Some(name.to_string());
+            frame.offset = offset;
+        }
+        frame
+    }
+
+    fn elf(&mut self, pid: u32, root: RootId, path: &str) -> Option<Arc<ElfSymbols>> {
+        let key = (root, PathBuf::from(path));
+        if let Some(cached) = self.elfs.get(&key) {
+            return cached.clone();
+        }
+        // Go through the process root so binaries inside containers resolve too. The host
+        // file of the same name may be a different binary, so a miss stays unresolved
+        let in_root = PathBuf::from(format!("/proc/{}/root{}", pid, path));
+        let loaded = ElfSymbols::load(&in_root).ok().map(Arc::new);
+        self.elfs.insert(key, loaded.clone());
+        loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_outermost_first() {
+        let frames = vec![
+            Frame { addr: 0x10, symbol: Some("inner".to_string()), offset: 4, module: None },
+            Frame::unknown(0x20),
+            Frame { addr: 0x30, symbol: Some("main".to_string()), offset: 0, module: Some("app".to_string()) },
+        ];
+        assert_eq!(fold(&frames), "main;0x20;inner");
+        assert_eq!(frames[0].to_string(), "inner+0x4");
+        assert_eq!(frames[2].to_string(), "main+0x0 (app)");
+    }
+
+    #[test]
+    fn reads_own_executable_mappings() {
+        let maps = read_maps(std::process::id()).unwrap();
+        let exe = std::fs::read_link("/proc/self/exe").unwrap();
+        assert!(maps.iter().any(|m| Path::new(&m.path) == exe), "{:?}", maps);
+    }
+
+    #[test]
+    fn exited_processes_are_evicted() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+
+        let mut symbolizer = Symbolizer::new();
+        let own = std::process::id();
+        assert_eq!(symbolizer.user_frames(dead, &[0x1000]), vec![Frame::unknown(0x1000)]);
+        symbolizer.user_frames(own, &[]);
+        assert!(symbolizer.processes[&own].root.is_some());
+
+        symbolizer.evict_exited();
+        assert!(!symbolizer.processes.contains_key(&dead));
+        assert!(symbolizer.processes.contains_key(&own));
+    }
+}
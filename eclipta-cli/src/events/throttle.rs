@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use std::time::Instant;
+
+/// Keeps one event out of every `every`
+#[derive(Debug)]
+pub struct Sampler {
+    every: u64,
+    seen: u64,
+}
+
+impl Sampler {
+    pub fn new(every: u64) -> Result<Sampler> {
+        if every == 0 {
+            return Err(anyhow!("--sample must be at least 1"));
+        }
+        Ok(Sampler { every, seen: 0 })
+    }
+
+    pub fn keep(&mut self) -> bool {
+        let keep = self.seen == 0;
+        self.seen = (self.seen + 1) % self.every;
+        keep
+    }
+}
+
+/// Token bucket allowing `rate` events per second with a one-second burst
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+    pub suppressed: u64,
+}
+
+impl RateLimiter {
+    /// Parse a limit such as `500/s`, `500` or `6000/m`
+    pub fn parse(spec: &str) -> Result<RateLimiter> {
+        let spec = spec.trim();
+        let (count, per_secs) = match spec.split_once('/') {
+            Some((n, unit)) => {
+                let secs = match unit.trim() {
+                    "s" | "sec" => 1.0,
+                    "m" | "min" => 60.0,
+                    other => return Err(anyhow!("Unknown rate unit '{}' in '{}' (use /s or /m)", other, spec)),
+                };
+                (n.trim(), secs)
+            }
+            None => (spec, 1.0),
+        };
+        let count: f64 = count
+            .parse::<u64>()
+            .map_err(|_| anyhow!("Invalid rate limit '{}', expected N/s", spec))? as f64;
+        if count == 0.0 {
+            return Err(anyhow!("Rate limit must be greater than zero"));
+        }
+        let rate = count / per_secs;
+        Ok(RateLimiter {
+            rate,
+            tokens: rate.max(1.0),
+            last: Instant::now(),
+            suppressed: 0,
+        })
+    }
+
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            self.suppressed += 1;
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampler_keeps_one_in_n() {
+        let mut sampler = Sampler::new(3).unwrap();
+        let kept: Vec<bool> = (0..7).map(|_| sampler.keep()).collect();
+        assert_eq!(kept, vec![true, false, false, true, false, false, true]);
+        assert!(Sampler::new(0).is_err());
+    }
+
+    #[test]
+    fn rate_limit_specs() {
+        assert_eq!(RateLimiter::parse("500/s").unwrap().rate, 500.0);
+        assert_eq!(RateLimiter::parse(" 20 ").unwrap().rate, 20.0);
+        assert_eq!(RateLimiter::parse("6000/m").unwrap().rate, 100.0);
+        for spec in ["0/s", "10/h", "abc", "-5/s"] {
+            assert!(RateLimiter::parse(spec).is_err(), "{} should not parse", spec);
+        }
+    }
+
+    #[test]
+    fn rate_limiter_allows_one_second_burst() {
+        let mut limiter = RateLimiter::parse("5/s").unwrap();
+        let allowed = (0..20).filter(|_| limiter.allow()).count();
+        assert_eq!(allowed, 5);
+        assert_eq!(limiter.suppressed, 15);
+    }
+
+    #[test]
+    fn rate_limiter_refills_over_time() {
+        let mut limiter = RateLimiter::parse("10/s").unwrap();
+        while limiter.allow() {}
+        limiter.last -= std::time::Duration::from_millis(500);
+        let allowed = (0..20).filter(|_| limiter.allow()).count();
+        assert!((5..=6).contains(&allowed), "allowed {}", allowed);
+    }
+}
@@ -1,15 +1,20 @@
 mod commands;
 mod utils;
 mod db;
+mod events;
 
 
 use clap::{Parser, Subcommand};
 
 // SYSTEM COMMANDS
 use crate::commands::system::{
+    doctor::{handle_doctor, DoctorOptions},
+    exporter::{handle_exporter, ExporterOptions},
     logs::{handle_logs, LogOptions},
     monitor::handle_monitor,
+    probe::{handle_probe, ProbeOptions},
     status::run_status,
+    trace_pipe::{handle_trace_pipe, TracePipeOptions},
     watch_cpu::{handle_watch_cpu, WatchCpuOptions},
 };
 
@@ -21,6 +26,13 @@ use crate::commands::ebpf::{
     upload::{handle_upload, UploadOptions},
     list::handle_list,
     remove::{handle_remove, RemoveOptions},
+    map::{handle_map, MapOptions},
+    upgrade::{handle_upgrade, UpgradeOptions},
+    apply::{handle_apply, ApplyOptions},
+    autostart::{handle_autostart, AutostartOptions},
+    sweep::{handle_sweep, SweepOptions},
+    verify::{handle_verify, VerifyOptions},
+    compat::{handle_compat, CompatOptions},
 };
 
 // NETWORK COMMANDS
@@ -37,10 +49,13 @@ use crate::commands::network::{
 
 // STORE / DB COMMANDS
 use crate::commands::store::check_db::{handle_check_db, CheckDbOptions};
+use crate::commands::store::history::{handle_history, HistoryOptions};
 use crate::commands::store::migrate::{handle_migrate, MigrateOptions};
 
 // OTHER GLOBAL COMMANDS
 use crate::commands::{
+    profile::{handle_profile, ProfileOptions},
+    replay::{handle_replay, ReplayOptions},
     run::{handle_run, RunOptions},
     version::{handle_version, VersionOptions},
     welcome::run_welcome,
@@ -58,10 +73,20 @@ struct Cli {
 enum Commands {
     Welcome,
     Status(commands::system::status::StatusOptions),
+    Probe(ProbeOptions),
+    Doctor(DoctorOptions),
     Load(commands::ebpf::load::LoadOptions),
     Logs(LogOptions),
+    TracePipe(TracePipeOptions),
     Unload(UnloadOptions),
+    Upgrade(UpgradeOptions),
+    Apply(ApplyOptions),
+    Autostart(AutostartOptions),
+    Sweep(SweepOptions),
+    Verify(VerifyOptions),
+    Compat(CompatOptions),
     Inspect(InspectOptions),
+    Map(MapOptions),
     // Daemon,  // Temporarily disabled
     Monitor,
     PingAll,
@@ -70,8 +95,12 @@ enum Commands {
     Alerts,
     Version(VersionOptions),
     Run(RunOptions),
+    Replay(ReplayOptions),
+    Profile(ProfileOptions),
+    Exporter(ExporterOptions),
     CheckDb(CheckDbOptions),
     Migrate(MigrateOptions),
+    History(HistoryOptions),
     Upload(UploadOptions),
     List,
     Remove(RemoveOptions), 
@@ -90,18 +119,28 @@ async fn handle_command(cmd: Commands) -> Result<(), Box<dyn std::error::Error>>
     match cmd {
         Commands::Welcome => run_welcome(),
         Commands::Status(opts) => run_status(opts).await?,
+        Commands::Probe(opts) => handle_probe(opts).await?,
+        Commands::Doctor(opts) => handle_doctor(opts).await?,
         Commands::Load(opts) => handle_load(opts).await?,
         Commands::Unload(opts) => {
             if let Err(e) = handle_unload(opts).await {
                 eprintln!("[UNLOAD ERROR] {}", e);
             }
         }
+        Commands::Upgrade(opts) => handle_upgrade(opts).await?,
+        Commands::Apply(opts) => handle_apply(opts).await?,
+        Commands::Autostart(opts) => handle_autostart(opts).await?,
+        Commands::Sweep(opts) => handle_sweep(opts).await?,
+        Commands::Verify(opts) => handle_verify(opts).await?,
+        Commands::Compat(opts) => handle_compat(opts).await?,
         Commands::Inspect(opts) => {
             if let Err(e) = handle_inspect(opts).await {
                 eprintln!("[INSPECT ERROR] {}", e);
             }
         }
+        Commands::Map(opts) => handle_map(opts).await?,
         Commands::Logs(opts) => handle_logs(opts).await,
+        Commands::TracePipe(opts) => handle_trace_pipe(opts).await?,
         // Commands::Daemon => handle_daemon().await,  // Temporarily disabled
         Commands::Monitor => handle_monitor().await?,
         Commands::PingAll => handle_ping_all().await,
@@ -110,8 +149,12 @@ async fn handle_command(cmd: Commands) -> Result<(), Box<dyn std::error::Error>>
         Commands::Alerts => handle_alerts().await?,
         Commands::Version(opts) => handle_version(opts).await?,
         Commands::Run(opts) => handle_run(opts).await,
+        Commands::Replay(opts) => handle_replay(opts).await?,
+        Commands::Profile(opts) => handle_profile(opts).await?,
+        Commands::Exporter(opts) => handle_exporter(opts).await?,
         Commands::CheckDb(opts) => handle_check_db(opts).await?,
         Commands::Migrate(opts) => handle_migrate(opts).await?,
+        Commands::History(opts) => handle_history(opts).await?,
         Commands::Upload(opts) => {
             if let Err(e) = handle_upload(opts).await {
                 eprintln!("[UPLOAD ERROR] {}", e);
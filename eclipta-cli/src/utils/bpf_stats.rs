@@ -0,0 +1,100 @@
+use crate::utils::bpftool::{prog_list, ProgInfo};
+use anyhow::Result;
+use aya::sys::{enable_stats, Stats};
+use std::os::fd::OwnedFd;
+use std::time::{Duration, Instant};
+
+const STATS_SYSCTL: &str = "/proc/sys/kernel/bpf_stats_enabled";
+
+/// Totals and rates of a group of kernel programs between the last two samples
+#[derive(Debug, Clone, Default)]
+pub struct ProgramStats {
+    pub prog_ids: Vec<u32>,
+    pub run_cnt: u64,
+    pub run_time_ns: u64,
+    pub recursion_misses: u64,
+    pub bytes_memlock: u64,
+    /// Runs per second; `None` until two samples exist
+    pub events_per_sec: Option<f64>,
+    /// Average cost of one run in the sampling window
+    pub avg_ns_per_run: Option<f64>,
+    /// Share of one CPU spent running the programs, in percent
+    pub cpu_percent: Option<f64>,
+}
+
+/// Keeps kernel BPF runtime stats switched on and remembers the last two `prog show` samples
+pub struct RuntimeStats {
+    /// BPF_ENABLE_STATS fd; stats stay on while it is open
+    _guard: Option<OwnedFd>,
+    enabled: bool,
+    prev: Option<(Instant, Vec<ProgInfo>)>,
+    cur: Option<(Instant, Vec<ProgInfo>)>,
+}
+
+impl RuntimeStats {
+    /// Turn stats on for the lifetime of the returned value, unless the sysctl already has them on
+    pub fn enable() -> RuntimeStats {
+        let sysctl_on = std::fs::read_to_string(STATS_SYSCTL).map(|v| v.trim() == "1").unwrap_or(false);
+        let guard = if sysctl_on { None } else { enable_stats(Stats::RunTime).ok() };
+        RuntimeStats { enabled: sysctl_on || guard.is_some(), _guard: guard, prev: None, cur: None }
+    }
+
+    /// Whether the kernel is counting run time; without root and the sysctl off it is not
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Take a new sample. The first call samples twice, `window` apart, so rates exist right away
+    pub async fn sample(&mut self, window: Duration) -> Result<()> {
+        if self.cur.is_none() {
+            self.cur = Some((Instant::now(), prog_list().await?));
+            tokio::time::sleep(window).await;
+        }
+        self.prev = self.cur.take();
+        self.cur = Some((Instant::now(), prog_list().await?));
+        Ok(())
+    }
+
+    /// Kernel programs in the latest sample with one of the given IDs
+    pub fn programs(&self, ids: &[u32]) -> Vec<&ProgInfo> {
+        match &self.cur {
+            Some((_, cur)) => cur.iter().filter(|p| ids.contains(&p.id)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Stats of the kernel programs with the given IDs, e.g. from `registry_prog_ids`
+    pub fn for_programs(&self, ids: &[u32]) -> ProgramStats {
+        let matches = |p: &&ProgInfo| ids.contains(&p.id);
+        let Some((cur_at, cur)) = &self.cur else { return ProgramStats::default() };
+
+        let mut stats = ProgramStats::default();
+        for p in cur.iter().filter(matches) {
+            stats.prog_ids.push(p.id);
+            stats.run_cnt += p.run_cnt;
+            stats.run_time_ns += p.run_time_ns;
+            stats.recursion_misses += p.recursion_misses;
+            stats.bytes_memlock += p.bytes_memlock;
+        }
+
+        if let Some((prev_at, prev)) = &self.prev {
+            // Only programs present in both samples; a reload restarts the counters
+            let (mut runs, mut time) = (0u64, 0u64);
+            for p in cur.iter().filter(matches) {
+                if let Some(old) = prev.iter().find(|o| o.id == p.id) {
+                    runs += p.run_cnt.saturating_sub(old.run_cnt);
+                    time += p.run_time_ns.saturating_sub(old.run_time_ns);
+                }
+            }
+            let elapsed = cur_at.duration_since(*prev_at).as_secs_f64();
+            if elapsed > 0.0 {
+                stats.events_per_sec = Some(runs as f64 / elapsed);
+                stats.cpu_percent = Some(time as f64 / (elapsed * 1e9) * 100.0);
+            }
+            if runs > 0 {
+                stats.avg_ns_per_run = Some(time as f64 / runs as f64);
+            }
+        }
+        stats
+    }
+}
@@ -0,0 +1,357 @@
+use anyhow::{anyhow, Context, Result};
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// bpftool rejects batch lines with more arguments than this
+const BATCH_MAX_ARGS: usize = 4096;
+
+/// A map in the kernel, addressed the way bpftool expects
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapRef {
+    Id(u32),
+    Pinned(PathBuf),
+}
+
+impl MapRef {
+    fn args(&self) -> Vec<String> {
+        match self {
+            MapRef::Id(id) => vec!["id".to_string(), id.to_string()],
+            MapRef::Pinned(path) => vec!["pinned".to_string(), path.display().to_string()],
+        }
+    }
+}
+
+impl fmt::Display for MapRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapRef::Id(id) => write!(f, "map id {}", id),
+            MapRef::Pinned(path) => write!(f, "map pinned at {}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgInfo {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub prog_type: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub tag: String,
+    #[serde(default)]
+    pub map_ids: Vec<u32>,
+    #[serde(default)]
+    pub loaded_at: i64,
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    #[serde(default)]
+    pub bytes_memlock: u64,
+    #[serde(default)]
+    pub bytes_xlated: u64,
+    #[serde(default)]
+    pub bytes_jited: u64,
+    /// Instructions the verifier walked, reported by kernels since 5.16
+    #[serde(default)]
+    pub verified_insns: u64,
+    /// Runtime stats, only counted while kernel.bpf_stats_enabled is on
+    #[serde(default)]
+    pub run_time_ns: u64,
+    #[serde(default)]
+    pub run_cnt: u64,
+    #[serde(default)]
+    pub recursion_misses: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MapInfo {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub map_type: String,
+    #[serde(default)]
+    pub name: String,
+    pub bytes_key: u32,
+    pub bytes_value: u32,
+    pub max_entries: u32,
+    #[serde(default)]
+    pub pinned: Vec<String>,
+}
+
+impl MapInfo {
+    pub fn is_percpu(&self) -> bool {
+        self.map_type.starts_with("percpu") || self.map_type.contains("_percpu")
+    }
+
+    /// Plain arrays have fixed slots that can be overwritten but never deleted. Arrays of
+    /// fds (prog_array, perf_event_array, cgroup_array) are emptied by deleting their keys
+    pub fn is_array(&self) -> bool {
+        matches!(self.map_type.as_str(), "array" | "percpu_array")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapValue {
+    Single(Vec<u8>),
+    /// One value per possible CPU, in CPU order
+    PerCpu(Vec<(u32, Vec<u8>)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapEntry {
+    pub key: Vec<u8>,
+    pub value: MapValue,
+}
+
+/// Run `bpftool -j <args>` and parse its JSON output
+pub async fn bpftool_json(args: &[String]) -> Result<Value> {
+    let output = Command::new("bpftool")
+        .arg("-j")
+        .args(args)
+        .output()
+        .await
+        .context("Failed to execute bpftool (is it installed and in PATH?)")?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        return Err(anyhow!("bpftool {}: {}", args.join(" "), error_message(&output)));
+    }
+    if stdout.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&stdout).context("Failed to parse bpftool JSON output")
+}
+
+/// Run several bpftool commands in one process with `bpftool batch`, stopping at the first failure
+pub async fn bpftool_batch(commands: &[Vec<String>]) -> Result<()> {
+    let mut script = String::new();
+    for args in commands {
+        let line: Vec<String> = args
+            .iter()
+            .map(|a| if a.contains(char::is_whitespace) { format!("\"{}\"", a) } else { a.clone() })
+            .collect();
+        script.push_str(&line.join(" "));
+        script.push('\n');
+    }
+
+    let mut child = Command::new("bpftool")
+        .args(["-j", "batch", "file", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to execute bpftool (is it installed and in PATH?)")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("bpftool batch: {}", error_message(&output)));
+    }
+    Ok(())
+}
+
+/// bpftool reports errors as {"error": "..."} in JSON mode; batches print an array of results
+fn error_message(output: &Output) -> String {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json = serde_json::from_str::<Value>(&stdout).ok();
+    let error = match &json {
+        Some(Value::Array(results)) => results.iter().find_map(|r| r.get("error")),
+        Some(v) => v.get("error"),
+        None => None,
+    };
+    error
+        .and_then(|e| e.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| String::from_utf8_lossy(&output.stderr).trim().to_string())
+}
+
+pub async fn prog_list() -> Result<Vec<ProgInfo>> {
+    let v = bpftool_json(&["prog".to_string(), "show".to_string()]).await?;
+    Ok(serde_json::from_value(v).unwrap_or_default())
+}
+
+pub async fn prog_show(id: u32) -> Result<ProgInfo> {
+    let v = bpftool_json(&["prog".to_string(), "show".to_string(), "id".to_string(), id.to_string()]).await?;
+    serde_json::from_value(v).with_context(|| format!("Unexpected bpftool output for prog id {}", id))
+}
+
+pub async fn prog_show_pinned(path: &Path) -> Result<ProgInfo> {
+    let v = bpftool_json(&["prog".to_string(), "show".to_string(), "pinned".to_string(), path.display().to_string()]).await?;
+    serde_json::from_value(v).with_context(|| format!("Unexpected bpftool output for {}", path.display()))
+}
+
+/// Pin a loaded program so it outlives its current attachment
+pub async fn prog_pin(id: u32, path: &Path) -> Result<()> {
+    bpftool_json(&["prog".to_string(), "pin".to_string(), "id".to_string(), id.to_string(), path.display().to_string()])
+        .await
+        .map(|_| ())
+}
+
+/// Pin a map so it outlives the process that created it
+pub async fn map_pin(map: &MapRef, path: &Path) -> Result<()> {
+    let mut args = vec!["map".to_string(), "pin".to_string()];
+    args.extend(map.args());
+    args.push(path.display().to_string());
+    bpftool_json(&args).await.map(|_| ())
+}
+
+/// Put program `prog_id` into slot `index` of a prog array; bpftool passes it as a program fd
+pub async fn prog_array_set(map: &MapRef, index: u32, prog_id: u32) -> Result<()> {
+    let mut args = vec!["map".to_string(), "update".to_string()];
+    args.extend(map.args());
+    args.extend(hex_args("key", &index.to_ne_bytes()));
+    args.extend(["value".to_string(), "id".to_string(), prog_id.to_string()]);
+    bpftool_json(&args).await.map(|_| ())
+}
+
+/// The kernel truncates object names to BPF_OBJ_NAME_LEN - 1 characters
+pub fn kernel_name(name: &str) -> &str {
+    match name.char_indices().nth(15) {
+        Some((i, _)) => &name[..i],
+        None => name,
+    }
+}
+
+/// Function names of the programs in an eBPF object (global text symbols outside .text)
+pub fn object_program_names(path: &Path) -> Result<Vec<String>> {
+    let data = std::fs::read(path).context("Failed to read eBPF object")?;
+    let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+    let mut names = Vec::new();
+    for sym in obj.symbols() {
+        if sym.kind() != SymbolKind::Text || !sym.is_global() {
+            continue;
+        }
+        let in_program_section = sym
+            .section_index()
+            .and_then(|i| obj.section_by_index(i).ok())
+            .and_then(|s| s.name().ok().map(|n| n != ".text"))
+            .unwrap_or(false);
+        if let (true, Ok(name)) = (in_program_section, sym.name()) {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Kernel programs whose names match the programs of an eBPF object
+pub async fn loaded_programs_for(path: &Path) -> Result<Vec<ProgInfo>> {
+    let names = object_program_names(path)?;
+    Ok(prog_list()
+        .await?
+        .into_iter()
+        .filter(|p| names.iter().any(|n| kernel_name(n) == p.name))
+        .collect())
+}
+
+pub async fn map_list() -> Result<Vec<MapInfo>> {
+    let v = bpftool_json(&["map".to_string(), "show".to_string()]).await?;
+    Ok(serde_json::from_value(v).unwrap_or_default())
+}
+
+pub async fn map_show(map: &MapRef) -> Result<MapInfo> {
+    let mut args = vec!["map".to_string(), "show".to_string()];
+    args.extend(map.args());
+    let v = bpftool_json(&args).await?;
+    serde_json::from_value(v).with_context(|| format!("Unexpected bpftool output for {}", map))
+}
+
+pub async fn map_dump(map: &MapRef) -> Result<Vec<MapEntry>> {
+    let mut args = vec!["map".to_string(), "dump".to_string()];
+    args.extend(map.args());
+    let v = bpftool_json(&args).await?;
+    Ok(v.as_array().map(|entries| entries.iter().filter_map(parse_entry).collect()).unwrap_or_default())
+}
+
+/// Value stored under `key`, or `None` if the key is absent
+pub async fn map_lookup(map: &MapRef, key: &[u8]) -> Result<Option<MapEntry>> {
+    let mut args = vec!["map".to_string(), "lookup".to_string()];
+    args.extend(map.args());
+    args.extend(hex_args("key", key));
+    match bpftool_json(&args).await {
+        Ok(v) => Ok(parse_entry(&v)),
+        Err(e) if is_not_found(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write `value` under `key`; per-CPU maps get the same value on every CPU.
+/// `flag` is one of bpftool's `any`, `exist` or `noexist`
+pub async fn map_update(map: &MapRef, key: &[u8], value: &[u8], flag: &str) -> Result<()> {
+    let mut args = vec!["map".to_string(), "update".to_string()];
+    args.extend(map.args());
+    args.extend(hex_args("key", key));
+    args.extend(hex_args("value", value));
+    args.push(flag.to_string());
+    bpftool_json(&args).await.map(|_| ())
+}
+
+/// Write many entries with one bpftool process; entries too large for a batch line go one by one
+pub async fn map_update_batch(map: &MapRef, entries: &[(&[u8], &[u8])], flag: &str) -> Result<()> {
+    let mut commands = Vec::new();
+    for (key, value) in entries {
+        let mut args = vec!["map".to_string(), "update".to_string()];
+        args.extend(map.args());
+        args.extend(hex_args("key", key));
+        args.extend(hex_args("value", value));
+        args.push(flag.to_string());
+        if args.len() < BATCH_MAX_ARGS {
+            commands.push(args);
+        } else {
+            map_update(map, key, value, flag).await?;
+        }
+    }
+    if commands.is_empty() {
+        return Ok(());
+    }
+    bpftool_batch(&commands).await
+}
+
+/// Delete `key`; returns false if it was not present
+pub async fn map_delete(map: &MapRef, key: &[u8]) -> Result<bool> {
+    let mut args = vec!["map".to_string(), "delete".to_string()];
+    args.extend(map.args());
+    args.extend(hex_args("key", key));
+    match bpftool_json(&args).await {
+        Ok(_) => Ok(true),
+        Err(e) if is_not_found(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    let msg = e.to_string();
+    msg.contains("No such file or directory") || msg.contains("not found")
+}
+
+fn hex_args(kind: &str, bytes: &[u8]) -> Vec<String> {
+    let mut args = vec![kind.to_string(), "hex".to_string()];
+    args.extend(bytes.iter().map(|b| format!("{:02x}", b)));
+    args
+}
+
+/// bpftool prints bytes as arrays of "0x.." strings
+fn parse_bytes(v: &Value) -> Option<Vec<u8>> {
+    v.as_array()?
+        .iter()
+        .map(|b| u8::from_str_radix(b.as_str()?.trim_start_matches("0x"), 16).ok())
+        .collect()
+}
+
+fn parse_entry(v: &Value) -> Option<MapEntry> {
+    let key = parse_bytes(v.get("key")?)?;
+    if let Some(values) = v.get("values").and_then(|vs| vs.as_array()) {
+        let per_cpu = values
+            .iter()
+            .filter_map(|c| Some((c.get("cpu")?.as_u64()? as u32, parse_bytes(c.get("value")?)?)))
+            .collect();
+        return Some(MapEntry { key, value: MapValue::PerCpu(per_cpu) });
+    }
+    let value = parse_bytes(v.get("value")?)?;
+    Some(MapEntry { key, value: MapValue::Single(value) })
+}
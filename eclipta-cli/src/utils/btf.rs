@@ -0,0 +1,918 @@
+use anyhow::{anyhow, Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use object::{Object, ObjectSection};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+const BTF_MAGIC: u16 = 0xeb9f;
+
+const KIND_INT: u32 = 1;
+const KIND_PTR: u32 = 2;
+const KIND_ARRAY: u32 = 3;
+const KIND_STRUCT: u32 = 4;
+const KIND_UNION: u32 = 5;
+const KIND_ENUM: u32 = 6;
+const KIND_FWD: u32 = 7;
+const KIND_TYPEDEF: u32 = 8;
+const KIND_VOLATILE: u32 = 9;
+const KIND_CONST: u32 = 10;
+const KIND_RESTRICT: u32 = 11;
+const KIND_FUNC: u32 = 12;
+const KIND_FUNC_PROTO: u32 = 13;
+const KIND_VAR: u32 = 14;
+const KIND_DATASEC: u32 = 15;
+const KIND_FLOAT: u32 = 16;
+const KIND_DECL_TAG: u32 = 17;
+const KIND_TYPE_TAG: u32 = 18;
+const KIND_ENUM64: u32 = 19;
+
+/// How deep `size_of`, `format`, `encode` and friends follow nested types before giving
+/// up, so malformed or cyclic BTF can't recurse forever
+pub(crate) const MAX_DEPTH: u32 = 32;
+
+const INT_SIGNED: u32 = 1 << 0;
+const INT_CHAR: u32 = 1 << 1;
+const INT_BOOL: u32 = 1 << 2;
+
+/// Index into `Btf::types`; 0 is `void`
+pub type TypeId = u32;
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub ty: TypeId,
+    pub bit_offset: u32,
+    /// Non-zero for bitfields
+    pub bit_size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum BtfType {
+    Void,
+    Int { name: String, size: u32, signed: bool, char: bool, bool: bool, bits: u32 },
+    Ptr(TypeId),
+    Array { elem: TypeId, len: u32 },
+    Struct { name: String, size: u32, members: Vec<Member> },
+    Union { name: String, size: u32, members: Vec<Member> },
+    Enum { name: String, size: u32, signed: bool, values: Vec<(String, i64)> },
+    Fwd(String),
+    Typedef { name: String, ty: TypeId },
+    /// const, volatile, restrict and type tags: transparent for layout purposes
+    Qualifier(TypeId),
+    Float { name: String, size: u32 },
+    Var { name: String, ty: TypeId },
+    Datasec { name: String, vars: Vec<(TypeId, u32, u32)> },
+    /// Functions, prototypes and decl tags, which never describe data
+    Other,
+}
+
+/// Parsed `.BTF` section of an eBPF object
+#[derive(Debug, Clone)]
+pub struct Btf {
+    types: Vec<BtfType>,
+    strings: Vec<u8>,
+}
+
+impl Btf {
+    /// BTF of an eBPF object file, or `None` if it was built without `-g`
+    pub fn from_elf(path: &Path) -> Result<Option<Btf>> {
+        let data = std::fs::read(path).context("Failed to read eBPF object")?;
+        let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+        match obj.section_by_name(".BTF") {
+            Some(section) => Ok(Some(Btf::parse(section.data()?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Kernel BTF: a raw blob like /sys/kernel/btf/vmlinux or a btfhub `.btf` file, or an
+    /// ELF image such as vmlinux with a `.BTF` section
+    pub fn from_file(path: &Path) -> Result<Btf> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if data.len() >= 2 && LittleEndian::read_u16(&data) == BTF_MAGIC {
+            return Btf::parse(&data);
+        }
+        let obj = object::File::parse(&*data).with_context(|| format!("{} is neither BTF nor ELF", path.display()))?;
+        let section = obj.section_by_name(".BTF")
+            .ok_or_else(|| anyhow!("{} has no .BTF section", path.display()))?;
+        Btf::parse(section.data()?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Btf> {
+        if data.len() < 24 || LittleEndian::read_u16(data) != BTF_MAGIC {
+            return Err(anyhow!("Not a little-endian BTF blob"));
+        }
+        let hdr_len = LittleEndian::read_u32(&data[4..]) as usize;
+        let type_off = LittleEndian::read_u32(&data[8..]) as usize;
+        let type_len = LittleEndian::read_u32(&data[12..]) as usize;
+        let str_off = LittleEndian::read_u32(&data[16..]) as usize;
+        let str_len = LittleEndian::read_u32(&data[20..]) as usize;
+
+        let types_data = data
+            .get(hdr_len + type_off..hdr_len + type_off + type_len)
+            .ok_or_else(|| anyhow!("BTF type section out of bounds"))?;
+        let strings = data
+            .get(hdr_len + str_off..hdr_len + str_off + str_len)
+            .ok_or_else(|| anyhow!("BTF string section out of bounds"))?;
+        let name = |off: u32| -> String {
+            let rest = strings.get(off as usize..).unwrap_or_default();
+            let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+            String::from_utf8_lossy(&rest[..end]).to_string()
+        };
+
+        let mut types = vec![BtfType::Void];
+        let mut pos = 0usize;
+        while pos + 12 <= types_data.len() {
+            let rec = &types_data[pos..];
+            let name_off = LittleEndian::read_u32(rec);
+            let info = LittleEndian::read_u32(&rec[4..]);
+            let size_or_type = LittleEndian::read_u32(&rec[8..]);
+            let vlen = (info & 0xffff) as usize;
+            let kind = (info >> 24) & 0x1f;
+            let kind_flag = info >> 31 == 1;
+            let extra = &rec[12..];
+            let need = |n: usize| -> Result<()> {
+                if extra.len() < n {
+                    Err(anyhow!("Truncated BTF type record"))
+                } else {
+                    Ok(())
+                }
+            };
+
+            let (ty, extra_len) = match kind {
+                KIND_INT => {
+                    need(4)?;
+                    let enc = LittleEndian::read_u32(extra);
+                    let encoding = (enc >> 24) & 0x0f;
+                    (
+                        BtfType::Int {
+                            name: name(name_off),
+                            size: size_or_type,
+                            signed: encoding & INT_SIGNED != 0,
+                            char: encoding & INT_CHAR != 0,
+                            bool: encoding & INT_BOOL != 0,
+                            bits: enc & 0xff,
+                        },
+                        4,
+                    )
+                }
+                KIND_PTR => (BtfType::Ptr(size_or_type), 0),
+                KIND_ARRAY => {
+                    need(12)?;
+                    (
+                        BtfType::Array {
+                            elem: LittleEndian::read_u32(extra),
+                            len: LittleEndian::read_u32(&extra[8..]),
+                        },
+                        12,
+                    )
+                }
+                KIND_STRUCT | KIND_UNION => {
+                    need(12 * vlen)?;
+                    let members = (0..vlen)
+                        .map(|i| {
+                            let m = &extra[i * 12..];
+                            let offset = LittleEndian::read_u32(&m[8..]);
+                            let (bit_offset, bit_size) = if kind_flag {
+                                (offset & 0xff_ffff, offset >> 24)
+                            } else {
+                                (offset, 0)
+                            };
+                            Member {
+                                name: name(LittleEndian::read_u32(m)),
+                                ty: LittleEndian::read_u32(&m[4..]),
+                                bit_offset,
+                                bit_size,
+                            }
+                        })
+                        .collect();
+                    let name = name(name_off);
+                    let ty = if kind == KIND_STRUCT {
+                        BtfType::Struct { name, size: size_or_type, members }
+                    } else {
+                        BtfType::Union { name, size: size_or_type, members }
+                    };
+                    (ty, 12 * vlen)
+                }
+                KIND_ENUM => {
+                    need(8 * vlen)?;
+                    let values = (0..vlen)
+                        .map(|i| {
+                            let e = &extra[i * 8..];
+                            let v = LittleEndian::read_u32(&e[4..]);
+                            let v = if kind_flag { v as i32 as i64 } else { v as i64 };
+                            (name(LittleEndian::read_u32(e)), v)
+                        })
+                        .collect();
+                    (
+                        BtfType::Enum { name: name(name_off), size: size_or_type, signed: kind_flag, values },
+                        8 * vlen,
+                    )
+                }
+                KIND_ENUM64 => {
+                    need(12 * vlen)?;
+                    let values = (0..vlen)
+                        .map(|i| {
+                            let e = &extra[i * 12..];
+                            let lo = LittleEndian::read_u32(&e[4..]) as u64;
+                            let hi = LittleEndian::read_u32(&e[8..]) as u64;
+                            (name(LittleEndian::read_u32(e)), ((hi << 32) | lo) as i64)
+                        })
+                        .collect();
+                    (
+                        BtfType::Enum { name: name(name_off), size: size_or_type, signed: kind_flag, values },
+                        12 * vlen,
+                    )
+                }
+                KIND_FWD => (BtfType::Fwd(name(name_off)), 0),
+                KIND_TYPEDEF => (BtfType::Typedef { name: name(name_off), ty: size_or_type }, 0),
+                KIND_VOLATILE | KIND_CONST | KIND_RESTRICT | KIND_TYPE_TAG => (BtfType::Qualifier(size_or_type), 0),
+                KIND_FUNC => (BtfType::Other, 0),
+                KIND_FUNC_PROTO => (BtfType::Other, 8 * vlen),
+                KIND_VAR => (BtfType::Var { name: name(name_off), ty: size_or_type }, 4),
+                KIND_DATASEC => {
+                    need(12 * vlen)?;
+                    let vars = (0..vlen)
+                        .map(|i| {
+                            let v = &extra[i * 12..];
+                            (
+                                LittleEndian::read_u32(v),
+                                LittleEndian::read_u32(&v[4..]),
+                                LittleEndian::read_u32(&v[8..]),
+                            )
+                        })
+                        .collect();
+                    (BtfType::Datasec { name: name(name_off), vars }, 12 * vlen)
+                }
+                KIND_FLOAT => (BtfType::Float { name: name(name_off), size: size_or_type }, 0),
+                KIND_DECL_TAG => (BtfType::Other, 4),
+                other => return Err(anyhow!("Unsupported BTF kind {}", other)),
+            };
+            types.push(ty);
+            pos += 12 + extra_len;
+        }
+
+        Ok(Btf { types, strings: strings.to_vec() })
+    }
+
+    pub fn get(&self, id: TypeId) -> Option<&BtfType> {
+        self.types.get(id as usize)
+    }
+
+    /// Every type with its id, `void` included
+    pub fn types(&self) -> impl Iterator<Item = (TypeId, &BtfType)> {
+        self.types.iter().enumerate().map(|(id, t)| (id as TypeId, t))
+    }
+
+    /// String at `offset` of the string section, as referenced by `.BTF.ext`
+    pub fn string(&self, offset: u32) -> String {
+        let rest = self.strings.get(offset as usize..).unwrap_or_default();
+        let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        String::from_utf8_lossy(&rest[..end]).to_string()
+    }
+
+    /// Follow typedefs and qualifiers down to the type that defines the layout
+    pub fn resolve(&self, mut id: TypeId) -> TypeId {
+        for _ in 0..32 {
+            match self.get(id) {
+                Some(BtfType::Typedef { ty, .. }) | Some(BtfType::Qualifier(ty)) => id = *ty,
+                _ => break,
+            }
+        }
+        id
+    }
+
+    pub fn size_of(&self, id: TypeId) -> Option<usize> {
+        self.size_at(id, 0)
+    }
+
+    fn size_at(&self, id: TypeId, depth: u32) -> Option<usize> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match self.get(self.resolve(id))? {
+            BtfType::Int { size, .. }
+            | BtfType::Struct { size, .. }
+            | BtfType::Union { size, .. }
+            | BtfType::Enum { size, .. }
+            | BtfType::Float { size, .. } => Some(*size as usize),
+            BtfType::Ptr(_) => Some(8),
+            BtfType::Array { elem, len } => self.size_at(*elem, depth + 1)?.checked_mul(*len as usize),
+            _ => None,
+        }
+    }
+
+    /// C spelling of a type, e.g. `struct event`, `__u32`, `char[16]`
+    pub fn type_name(&self, id: TypeId) -> String {
+        self.name_at(id, 0)
+    }
+
+    fn name_at(&self, id: TypeId, depth: u32) -> String {
+        if depth > MAX_DEPTH {
+            return "?".to_string();
+        }
+        match self.get(id) {
+            None | Some(BtfType::Void) => "void".to_string(),
+            Some(BtfType::Int { name, .. }) | Some(BtfType::Float { name, .. }) => name.clone(),
+            Some(BtfType::Typedef { name, .. }) => name.clone(),
+            Some(BtfType::Ptr(t)) => format!("{} *", self.name_at(*t, depth + 1)),
+            Some(BtfType::Array { elem, len }) => format!("{}[{}]", self.name_at(*elem, depth + 1), len),
+            Some(BtfType::Struct { name, .. }) => format!("struct {}", or_anon(name)),
+            Some(BtfType::Union { name, .. }) => format!("union {}", or_anon(name)),
+            Some(BtfType::Enum { name, .. }) => format!("enum {}", or_anon(name)),
+            Some(BtfType::Fwd(name)) => name.clone(),
+            Some(BtfType::Qualifier(t)) => self.name_at(*t, depth + 1),
+            Some(BtfType::Var { name, .. }) | Some(BtfType::Datasec { name, .. }) => name.clone(),
+            Some(BtfType::Other) => "?".to_string(),
+        }
+    }
+
+    /// Key and value types of a BTF-defined map (`__type(key, ...)` / `__type(value, ...)`)
+    pub fn map_types(&self, map: &str) -> Option<(TypeId, TypeId)> {
+        let def = self.map_def(map)?;
+        let BtfType::Struct { members, .. } = self.get(def)? else { return None };
+        let field = |wanted: &str| {
+            members.iter().find(|m| m.name == wanted).and_then(|m| match self.get(self.resolve(m.ty)) {
+                Some(BtfType::Ptr(t)) => Some(*t),
+                _ => None,
+            })
+        };
+        Some((field("key")?, field("value")?))
+    }
+
+    /// Names of all maps declared in the `.maps` section
+    pub fn map_names(&self) -> Vec<String> {
+        self.maps_section()
+            .map(|vars| {
+                vars.iter()
+                    .filter_map(|(var, _, _)| match self.get(*var) {
+                        Some(BtfType::Var { name, .. }) => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Struct type describing the definition of `map` in `.maps`
+    pub fn map_def(&self, map: &str) -> Option<TypeId> {
+        self.maps_section()?.iter().find_map(|(var, _, _)| match self.get(*var) {
+            Some(BtfType::Var { name, ty }) if name == map => Some(self.resolve(*ty)),
+            _ => None,
+        })
+    }
+
+    /// Data section, byte offset and type of a global variable
+    pub fn global(&self, var: &str) -> Option<(String, u32, TypeId)> {
+        self.types.iter().find_map(|t| match t {
+            BtfType::Datasec { name, vars } if name != ".maps" => vars.iter().find_map(|(id, offset, _)| match self.get(*id) {
+                Some(BtfType::Var { name: n, ty }) if n == var => Some((name.clone(), *offset, *ty)),
+                _ => None,
+            }),
+            _ => None,
+        })
+    }
+
+    fn maps_section(&self) -> Option<&Vec<(TypeId, u32, u32)>> {
+        self.types.iter().find_map(|t| match t {
+            BtfType::Datasec { name, vars } if name == ".maps" => Some(vars),
+            _ => None,
+        })
+    }
+
+    /// Integer member of a map definition, e.g. `__uint(max_entries, 1024)` or `__uint(pinning, ...)`
+    pub fn map_uint(&self, map: &str, member: &str) -> Option<u32> {
+        let BtfType::Struct { members, .. } = self.get(self.map_def(map)?)? else { return None };
+        let m = members.iter().find(|m| m.name == member)?;
+        // __uint(name, val) is encoded as `int (*name)[val]`
+        match self.get(self.resolve(m.ty))? {
+            BtfType::Ptr(t) => match self.get(self.resolve(*t))? {
+                BtfType::Array { len, .. } => Some(*len),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Render raw bytes of type `id` as JSON: structs as objects, char arrays as strings
+    pub fn format(&self, id: TypeId, data: &[u8]) -> Value {
+        self.format_at(id, data, 0)
+    }
+
+    fn format_at(&self, id: TypeId, data: &[u8], depth: u32) -> Value {
+        if depth > MAX_DEPTH {
+            return Value::from(hex(data));
+        }
+        let id = self.resolve(id);
+        match self.get(id) {
+            Some(BtfType::Int { size, signed, char, bool, .. }) => {
+                let raw = read_uint(data, *size as usize);
+                if *bool {
+                    Value::from(raw != 0)
+                } else if *char && *size == 1 {
+                    Value::from((raw as u8 as char).to_string())
+                } else if *signed {
+                    Value::from(sign_extend(raw, *size as usize))
+                } else {
+                    Value::from(raw)
+                }
+            }
+            Some(BtfType::Enum { size, signed, values, .. }) => {
+                let raw = read_uint(data, *size as usize);
+                let v = if *signed { sign_extend(raw, *size as usize) } else { raw as i64 };
+                match values.iter().find(|(_, val)| *val == v) {
+                    Some((name, _)) => Value::from(name.clone()),
+                    None => Value::from(v),
+                }
+            }
+            Some(BtfType::Ptr(_)) => Value::from(format!("0x{:x}", read_uint(data, 8))),
+            Some(BtfType::Float { size: 4, .. }) if data.len() >= 4 => {
+                Value::from(LittleEndian::read_f32(data) as f64)
+            }
+            Some(BtfType::Float { size: 8, .. }) if data.len() >= 8 => Value::from(LittleEndian::read_f64(data)),
+            Some(BtfType::Array { elem, len }) => {
+                if self.is_char(*elem) {
+                    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                    return Value::from(String::from_utf8_lossy(&data[..end]).to_string());
+                }
+                let Some(step) = self.size_of(*elem).filter(|s| *s > 0) else {
+                    return Value::from(hex(data));
+                };
+                Value::Array(
+                    (0..*len as usize)
+                        .map(|i| data.get(i * step..(i + 1) * step).unwrap_or_default())
+                        .map(|chunk| self.format_at(*elem, chunk, depth + 1))
+                        .collect(),
+                )
+            }
+            Some(BtfType::Struct { members, .. }) | Some(BtfType::Union { members, .. }) => {
+                let mut obj = Map::new();
+                for (i, m) in members.iter().enumerate() {
+                    let name = if m.name.is_empty() { format!("_{}", i) } else { m.name.clone() };
+                    let v = if m.bit_size > 0 || m.bit_offset % 8 != 0 {
+                        Value::from(read_bits(data, m.bit_offset, m.bit_size.max(1)))
+                    } else {
+                        let start = (m.bit_offset / 8) as usize;
+                        let end = start + self.size_of(m.ty).unwrap_or(0);
+                        match data.get(start..end) {
+                            Some(chunk) => self.format_at(m.ty, chunk, depth + 1),
+                            None => Value::Null,
+                        }
+                    };
+                    obj.insert(name, v);
+                }
+                Value::Object(obj)
+            }
+            _ => Value::from(hex(data)),
+        }
+    }
+
+    /// Encode a JSON value (or a bare string such as `0x10` or `nginx`) as bytes of type `id`
+    pub fn encode(&self, id: TypeId, value: &Value) -> Result<Vec<u8>> {
+        let id = self.resolve(id);
+        let size = self
+            .size_of(id)
+            .ok_or_else(|| anyhow!("Cannot encode values of type {}", self.type_name(id)))?;
+        let mut out = vec![0u8; size];
+        self.encode_into(id, value, &mut out, 0)?;
+        Ok(out)
+    }
+
+    fn encode_into(&self, id: TypeId, value: &Value, out: &mut [u8], depth: u32) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(anyhow!("Type {} nests more than {} levels deep", self.type_name(id), MAX_DEPTH));
+        }
+        let id = self.resolve(id);
+        match self.get(id) {
+            Some(BtfType::Int { size, char, bool, .. }) => {
+                let v = match value {
+                    Value::Bool(b) if *bool => *b as i128,
+                    Value::String(s) if *char && s.chars().count() == 1 => s.chars().next().unwrap_or('\0') as i128,
+                    other => parse_int(other)?,
+                };
+                write_int(out, *size as usize, v, &self.type_name(id))
+            }
+            Some(BtfType::Enum { size, values, .. }) => {
+                let v = match value {
+                    Value::String(s) => match values.iter().find(|(n, _)| n == s) {
+                        Some((_, v)) => *v as i128,
+                        None => parse_int(value)?,
+                    },
+                    other => parse_int(other)?,
+                };
+                write_int(out, *size as usize, v, &self.type_name(id))
+            }
+            Some(BtfType::Ptr(_)) => write_int(out, 8, parse_int(value)?, "pointer"),
+            Some(BtfType::Float { size, .. }) => {
+                let f = match value {
+                    Value::Number(n) => n.as_f64(),
+                    Value::String(s) => s.parse().ok(),
+                    _ => None,
+                }
+                .ok_or_else(|| anyhow!("Expected a number, got {}", value))?;
+                match size {
+                    4 => LittleEndian::write_f32(out, f as f32),
+                    8 => LittleEndian::write_f64(out, f),
+                    // half floats and long doubles have no Rust counterpart to convert through
+                    _ => return Err(anyhow!("Cannot encode {}-byte floats ({})", size, self.type_name(id))),
+                }
+                Ok(())
+            }
+            Some(BtfType::Array { elem, len }) => {
+                if self.is_char(*elem) {
+                    let Value::String(s) = value else {
+                        return Err(anyhow!("Expected a string for {}, got {}", self.type_name(id), value));
+                    };
+                    if s.len() > out.len() {
+                        return Err(anyhow!("String '{}' is longer than {} bytes", s, out.len()));
+                    }
+                    out[..s.len()].copy_from_slice(s.as_bytes());
+                    return Ok(());
+                }
+                let Value::Array(items) = value else {
+                    return Err(anyhow!("Expected an array for {}, got {}", self.type_name(id), value));
+                };
+                if items.len() > *len as usize {
+                    return Err(anyhow!("Too many elements for {}", self.type_name(id)));
+                }
+                let step = self.size_of(*elem).unwrap_or(0);
+                for (i, item) in items.iter().enumerate() {
+                    self.encode_into(*elem, item, &mut out[i * step..(i + 1) * step], depth + 1)?;
+                }
+                Ok(())
+            }
+            Some(BtfType::Struct { members, .. }) | Some(BtfType::Union { members, .. }) => {
+                let Value::Object(fields) = value else {
+                    return Err(anyhow!("Expected an object for {}, got {}", self.type_name(id), value));
+                };
+                for key in fields.keys() {
+                    if !members.iter().any(|m| &m.name == key) {
+                        let names: Vec<&str> = members.iter().map(|m| m.name.as_str()).collect();
+                        return Err(anyhow!(
+                            "{} has no field '{}' (fields: {})",
+                            self.type_name(id),
+                            key,
+                            names.join(", ")
+                        ));
+                    }
+                }
+                // Missing fields stay zero
+                for m in members {
+                    let Some(v) = fields.get(&m.name) else { continue };
+                    if m.bit_size > 0 || m.bit_offset % 8 != 0 {
+                        let bits = m.bit_size.max(1);
+                        write_bits(out, m.bit_offset, bits, parse_int(v)?)
+                            .map_err(|e| anyhow!("Field '{}': {}", m.name, e))?;
+                        continue;
+                    }
+                    let start = (m.bit_offset / 8) as usize;
+                    let end = start + self.size_of(m.ty).unwrap_or(0);
+                    let slot = out
+                        .get_mut(start..end)
+                        .ok_or_else(|| anyhow!("Field '{}' lies outside the type", m.name))?;
+                    self.encode_into(m.ty, v, slot, depth + 1)?;
+                }
+                Ok(())
+            }
+            _ => Err(anyhow!("Cannot encode values of type {}", self.type_name(id))),
+        }
+    }
+
+    /// Flattened memory layout of a type, one "+offset name: type" line per leaf field.
+    /// Two types with the same layout lines can share the same bytes
+    pub fn layout(&self, id: TypeId) -> Vec<String> {
+        let mut out = Vec::new();
+        self.layout_into(id, "", 0, &mut out, 0);
+        out
+    }
+
+    fn layout_into(&self, id: TypeId, path: &str, bit_base: u32, out: &mut Vec<String>, depth: u32) {
+        let id = self.resolve(id);
+        match self.get(id) {
+            Some(BtfType::Struct { members, .. }) | Some(BtfType::Union { members, .. }) if depth <= MAX_DEPTH => {
+                for (i, m) in members.iter().enumerate() {
+                    let name = if m.name.is_empty() { format!("_{}", i) } else { m.name.clone() };
+                    let path = if path.is_empty() { name } else { format!("{}.{}", path, name) };
+                    let bits = bit_base + m.bit_offset;
+                    if m.bit_size > 0 {
+                        out.push(format!("+{}:{} {}: {}", bits / 8, bits % 8, path, self.type_name(self.resolve(m.ty))));
+                    } else {
+                        self.layout_into(m.ty, &path, bits, out, depth + 1);
+                    }
+                }
+            }
+            _ => {
+                let path = if path.is_empty() { "(whole)" } else { path };
+                let size = self.size_of(id).unwrap_or(0);
+                out.push(format!("+{} {}: {} ({} bytes)", bit_base / 8, path, self.type_name(id), size));
+            }
+        }
+    }
+
+    fn is_char(&self, id: TypeId) -> bool {
+        matches!(self.get(self.resolve(id)), Some(BtfType::Int { size: 1, char: true, .. }))
+            || matches!(self.get(self.resolve(id)), Some(BtfType::Int { name, size: 1, .. }) if name == "char")
+    }
+}
+
+fn or_anon(name: &str) -> &str {
+    if name.is_empty() {
+        "<anon>"
+    } else {
+        name
+    }
+}
+
+fn read_uint(data: &[u8], size: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    let n = size.min(8).min(data.len());
+    buf[..n].copy_from_slice(&data[..n]);
+    u64::from_le_bytes(buf)
+}
+
+fn sign_extend(raw: u64, size: usize) -> i64 {
+    let bits = (size.min(8) * 8) as u32;
+    if bits == 64 {
+        return raw as i64;
+    }
+    let shift = 64 - bits;
+    ((raw << shift) as i64) >> shift
+}
+
+fn read_bits(data: &[u8], offset: u32, size: u32) -> u64 {
+    (0..size.min(64)).fold(0u64, |acc, i| {
+        let bit = offset + i;
+        let set = data.get((bit / 8) as usize).map(|b| (b >> (bit % 8)) & 1).unwrap_or(0);
+        acc | ((set as u64) << i)
+    })
+}
+
+/// Write `v` into a bitfield of `size` bits, failing if it does not fit
+fn write_bits(out: &mut [u8], offset: u32, size: u32, v: i128) -> Result<()> {
+    if !fits_bits(v, size.min(64)) {
+        return Err(anyhow!("{} does not fit in {} bits", v, size));
+    }
+    let value = v as u64;
+    for i in 0..size.min(64) {
+        let bit = offset + i;
+        if let Some(b) = out.get_mut((bit / 8) as usize) {
+            if (value >> i) & 1 == 1 {
+                *b |= 1 << (bit % 8);
+            } else {
+                *b &= !(1 << (bit % 8));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Signed or unsigned values of `bits` bits both fit, as C accepts either for a field
+fn fits_bits(v: i128, bits: u32) -> bool {
+    if bits == 0 {
+        v == 0
+    } else if bits == 64 {
+        v >= i64::MIN as i128 && v <= u64::MAX as i128
+    } else {
+        v >= -(1i128 << (bits - 1)) && v < (1i128 << bits)
+    }
+}
+
+/// Write `v` as a little-endian integer of `size` bytes, failing if it does not fit
+pub fn write_int(out: &mut [u8], size: usize, v: i128, ty: &str) -> Result<()> {
+    if !fits_bits(v, (size.min(8) * 8) as u32) {
+        return Err(anyhow!("{} does not fit in {}", v, ty));
+    }
+    let bytes = (v as u64).to_le_bytes();
+    let n = size.min(8).min(out.len());
+    out[..n].copy_from_slice(&bytes[..n]);
+    Ok(())
+}
+
+/// Integers from JSON numbers or strings such as `42`, `-1` or `0x2a`
+pub fn parse_int(value: &Value) -> Result<i128> {
+    match value {
+        Value::Number(n) => n
+            .as_i64()
+            .map(|v| v as i128)
+            .or_else(|| n.as_u64().map(|v| v as i128))
+            .ok_or_else(|| anyhow!("Expected an integer, got {}", n)),
+        Value::String(s) => {
+            let s = s.trim();
+            let (neg, digits) = match s.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, s),
+            };
+            let v = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+                Some(h) => i128::from_str_radix(h, 16),
+                None => digits.parse::<i128>(),
+            }
+            .map_err(|_| anyhow!("Expected an integer, got '{}'", s))?;
+            Ok(if neg { -v } else { v })
+        }
+        Value::Bool(b) => Ok(*b as i128),
+        other => Err(anyhow!("Expected an integer, got {}", other)),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Little-endian BTF blobs for tests
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct BtfBuilder {
+        types: Vec<u8>,
+        strings: Vec<u8>,
+        count: u32,
+    }
+
+    impl BtfBuilder {
+        pub fn new() -> BtfBuilder {
+            BtfBuilder { strings: vec![0], ..Default::default() }
+        }
+
+        /// Offset of `s` in the string section, adding it
+        pub fn string(&mut self, s: &str) -> u32 {
+            if s.is_empty() {
+                return 0;
+            }
+            let off = self.strings.len() as u32;
+            self.strings.extend_from_slice(s.as_bytes());
+            self.strings.push(0);
+            off
+        }
+
+        fn push(&mut self, name: &str, kind: u32, vlen: u32, kind_flag: bool, size_or_type: u32, extra: &[u32]) -> TypeId {
+            let name = self.string(name);
+            let info = (kind << 24) | vlen | ((kind_flag as u32) << 31);
+            for word in [name, info, size_or_type].iter().chain(extra) {
+                self.types.extend_from_slice(&word.to_le_bytes());
+            }
+            self.count += 1;
+            self.count
+        }
+
+        pub fn int(&mut self, name: &str, size: u32, signed: bool) -> TypeId {
+            let enc = if signed { INT_SIGNED << 24 } else { 0 } | (size * 8);
+            self.push(name, KIND_INT, 0, false, size, &[enc])
+        }
+
+        pub fn float(&mut self, name: &str, size: u32) -> TypeId {
+            self.push(name, KIND_FLOAT, 0, false, size, &[])
+        }
+
+        pub fn ptr(&mut self, ty: TypeId) -> TypeId {
+            self.push("", KIND_PTR, 0, false, ty, &[])
+        }
+
+        pub fn array(&mut self, elem: TypeId, len: u32) -> TypeId {
+            self.push("", KIND_ARRAY, 0, false, 0, &[elem, elem, len])
+        }
+
+        pub fn typedef(&mut self, name: &str, ty: TypeId) -> TypeId {
+            self.push(name, KIND_TYPEDEF, 0, false, ty, &[])
+        }
+
+        /// Members as (name, type, bit offset, bitfield size or 0)
+        pub fn structure(&mut self, name: &str, size: u32, members: &[(&str, TypeId, u32, u32)]) -> TypeId {
+            self.aggregate(KIND_STRUCT, name, size, members)
+        }
+
+        pub fn union(&mut self, name: &str, size: u32, members: &[(&str, TypeId, u32, u32)]) -> TypeId {
+            self.aggregate(KIND_UNION, name, size, members)
+        }
+
+        fn aggregate(&mut self, kind: u32, name: &str, size: u32, members: &[(&str, TypeId, u32, u32)]) -> TypeId {
+            let bitfields = members.iter().any(|m| m.3 > 0);
+            let mut extra = Vec::new();
+            for (m, ty, offset, bits) in members {
+                extra.extend([self.string(m), *ty, if bitfields { (bits << 24) | offset } else { *offset }]);
+            }
+            self.push(name, kind, members.len() as u32, bitfields, size, &extra)
+        }
+
+        pub fn enumeration(&mut self, name: &str, size: u32, values: &[(&str, i32)]) -> TypeId {
+            let mut extra = Vec::new();
+            for (n, v) in values {
+                extra.extend([self.string(n), *v as u32]);
+            }
+            self.push(name, KIND_ENUM, values.len() as u32, false, size, &extra)
+        }
+
+        pub fn build(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            out.extend_from_slice(&BTF_MAGIC.to_le_bytes());
+            out.extend_from_slice(&[1, 0]);
+            for word in [24, 0, self.types.len() as u32, self.types.len() as u32, self.strings.len() as u32] {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+            out.extend_from_slice(&self.types);
+            out.extend_from_slice(&self.strings);
+            out
+        }
+
+        pub fn parse(&self) -> Btf {
+            Btf::parse(&self.build()).unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::BtfBuilder;
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn structs_round_trip_through_json() {
+        let mut b = BtfBuilder::new();
+        let u32_t = b.int("__u32", 4, false);
+        let char_t = b.int("char", 1, true);
+        let comm = b.array(char_t, 8);
+        let ev = b.structure("event", 12, &[("pid", u32_t, 0, 0), ("comm", comm, 32, 0)]);
+        let btf = b.parse();
+
+        assert_eq!(btf.type_name(ev), "struct event");
+        assert_eq!(btf.size_of(ev), Some(12));
+        let bytes = btf.encode(ev, &json!({ "pid": 7, "comm": "nginx" })).unwrap();
+        assert_eq!(&bytes[..4], &[7, 0, 0, 0]);
+        assert_eq!(btf.format(ev, &bytes), json!({ "pid": 7, "comm": "nginx" }));
+        assert!(btf.encode(ev, &json!({ "uid": 1 })).is_err());
+    }
+
+    #[test]
+    fn only_4_and_8_byte_floats_encode() {
+        let mut b = BtfBuilder::new();
+        let f32_t = b.float("float", 4);
+        let f64_t = b.float("double", 8);
+        let half = b.float("_Float16", 2);
+        let long_double = b.float("long double", 16);
+        let btf = b.parse();
+
+        assert_eq!(btf.encode(f32_t, &json!(1.5)).unwrap(), 1.5f32.to_le_bytes());
+        assert_eq!(btf.encode(f64_t, &json!(-2.25)).unwrap(), (-2.25f64).to_le_bytes());
+        assert!(btf.encode(half, &json!(1.0)).is_err());
+        assert!(btf.encode(long_double, &json!(1.0)).is_err());
+    }
+
+    #[test]
+    fn bitfields_reject_values_that_do_not_fit() {
+        let mut b = BtfBuilder::new();
+        let u8_t = b.int("__u8", 1, false);
+        let flags = b.structure("flags", 1, &[("low", u8_t, 0, 3), ("high", u8_t, 3, 5)]);
+        let btf = b.parse();
+
+        assert_eq!(btf.encode(flags, &json!({ "low": 7, "high": 31 })).unwrap(), vec![0xff]);
+        assert_eq!(btf.encode(flags, &json!({ "low": -1 })).unwrap(), vec![0x07]);
+        let err = btf.encode(flags, &json!({ "low": 8 })).unwrap_err();
+        assert!(err.to_string().contains("does not fit in 3 bits"), "{}", err);
+        assert!(btf.encode(flags, &json!({ "high": 32 })).is_err());
+    }
+
+    #[test]
+    fn integers_must_fit_their_size() {
+        let mut out = [0u8; 2];
+        assert!(write_int(&mut out, 2, 65535, "__u16").is_ok());
+        assert!(write_int(&mut out, 2, -32768, "__s16").is_ok());
+        assert!(write_int(&mut out, 2, 65536, "__u16").is_err());
+        assert!(write_int(&mut out, 2, -32769, "__s16").is_err());
+        assert!(!fits_bits(1, 0));
+    }
+
+    #[test]
+    fn array_sizes_that_overflow_are_unknown() {
+        let mut b = BtfBuilder::new();
+        let u64_t = b.int("__u64", 8, false);
+        let huge = b.array(u64_t, u32::MAX);
+        let huger = b.array(huge, u32::MAX);
+        let btf = b.parse();
+        assert_eq!(btf.size_of(huge), Some(8 * u32::MAX as usize));
+        assert_eq!(btf.size_of(huger), None);
+        assert!(btf.encode(huger, &json!([])).is_err());
+    }
+
+    #[test]
+    fn cyclic_types_stop_at_the_depth_limit() {
+        let mut b = BtfBuilder::new();
+        // Type 1 is an array of itself, type 2 a pointer to itself
+        let looped = b.array(1, 2);
+        let ptr = b.ptr(2);
+        let holder = b.structure("holder", 4, &[("inner", 3, 0, 0)]);
+        let btf = b.parse();
+
+        assert_eq!(btf.size_of(looped), None);
+        assert!(btf.type_name(ptr).starts_with("? *"));
+        // A struct containing itself: formatting and encoding give up instead of recursing
+        assert_eq!(btf.format(holder, &[1, 2, 3, 4]).to_string().matches("inner").count(), MAX_DEPTH as usize + 1);
+        assert!(btf.encode(holder, &json!({ "inner": { "inner": {} } })).is_ok());
+        let mut deep = json!({});
+        for _ in 0..=MAX_DEPTH {
+            deep = json!({ "inner": deep });
+        }
+        assert!(btf.encode(holder, &deep).is_err());
+        assert!(btf.layout(holder).len() <= 1);
+    }
+}
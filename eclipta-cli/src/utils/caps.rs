@@ -0,0 +1,356 @@
+use crate::utils::paths::default_pin_prefix;
+use anyhow::{anyhow, Context, Result};
+use nix::libc;
+use nix::unistd::{getegid, geteuid, getgroups, getpid, gettid};
+use std::collections::{BTreeMap, HashSet};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The capabilities eclipta checks for, numbered as in include/uapi/linux/capability.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    DacOverride = 1,
+    DacReadSearch = 2,
+    NetAdmin = 12,
+    SysPtrace = 19,
+    SysAdmin = 21,
+    SysResource = 24,
+    Syslog = 34,
+    Perfmon = 38,
+    Bpf = 39,
+}
+
+impl Capability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::DacOverride => "CAP_DAC_OVERRIDE",
+            Capability::DacReadSearch => "CAP_DAC_READ_SEARCH",
+            Capability::NetAdmin => "CAP_NET_ADMIN",
+            Capability::SysPtrace => "CAP_SYS_PTRACE",
+            Capability::SysAdmin => "CAP_SYS_ADMIN",
+            Capability::SysResource => "CAP_SYS_RESOURCE",
+            Capability::Syslog => "CAP_SYSLOG",
+            Capability::Perfmon => "CAP_PERFMON",
+            Capability::Bpf => "CAP_BPF",
+        }
+    }
+
+    fn bit(self) -> u64 {
+        1 << self as u32
+    }
+}
+
+/// What symbolizing stacks reads: /proc/kallsyms addresses and other users' /proc/<pid>/maps
+pub const SYMBOLIZE: &[Capability] = &[Capability::Syslog, Capability::SysPtrace, Capability::DacReadSearch];
+
+/// Program types the kernel only loads with CAP_PERFMON (`is_perfmon_prog_type`)
+const PERFMON_TYPES: &[&str] = &[
+    "kprobe", "tracepoint", "perf_event", "raw_tracepoint", "raw_tracepoint_writable", "tracing", "lsm",
+    "struct_ops", "ext",
+];
+
+/// Program types that need CAP_SYS_ADMIN on top: LSM hooks, struct_ops maps and freplace
+/// targets are only opened to it
+const SYS_ADMIN_TYPES: &[&str] = &["lsm", "struct_ops", "ext"];
+
+/// Program types needing CAP_NET_ADMIN to load (`is_net_admin_prog_type`) or to attach
+const NET_ADMIN_TYPES: &[&str] = &[
+    "sched_cls", "sched_act", "xdp", "lwt_in", "lwt_out", "lwt_xmit", "lwt_seg6local", "sk_skb", "sk_msg",
+    "flow_dissector", "cgroup_skb", "cgroup_device", "cgroup_sock", "cgroup_sock_addr", "cgroup_sockopt",
+    "cgroup_sysctl", "sock_ops", "sk_lookup", "ext",
+];
+
+/// The capability sets of this process's main thread, from /proc/self/status
+#[derive(Debug, Clone, Copy)]
+pub struct CapSets {
+    pub effective: u64,
+    pub permitted: u64,
+}
+
+impl CapSets {
+    pub fn current() -> Result<CapSets> {
+        CapSets::read("/proc/self/status")
+    }
+
+    fn read(path: &str) -> Result<CapSets> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        CapSets::parse(&text).with_context(|| format!("Unexpected format of {}", path))
+    }
+
+    fn parse(status: &str) -> Result<CapSets> {
+        let set = |key: &str| {
+            status.lines()
+                .find_map(|l| l.strip_prefix(key))
+                .and_then(|v| u64::from_str_radix(v.trim(), 16).ok())
+                .ok_or_else(|| anyhow!("no valid {} line", key))
+        };
+        Ok(CapSets { effective: set("CapEff:")?, permitted: set("CapPrm:")? })
+    }
+
+    pub fn has(&self, cap: Capability) -> bool {
+        self.effective & cap.bit() != 0
+    }
+
+    /// CAP_SYS_ADMIN still grants what CAP_BPF and CAP_PERFMON split off from it in 5.8
+    pub fn allows(&self, cap: Capability) -> bool {
+        match cap {
+            Capability::Bpf | Capability::Perfmon => self.has(cap) || self.has(Capability::SysAdmin),
+            _ => self.has(cap),
+        }
+    }
+}
+
+/// Whether the kernel knows CAP_BPF and CAP_PERFMON (5.8 and later)
+pub fn kernel_has_cap_bpf() -> bool {
+    last_cap() >= Capability::Bpf as u32
+}
+
+fn last_cap() -> u32 {
+    std::fs::read_to_string("/proc/sys/kernel/cap_last_cap")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(Capability::Bpf as u32)
+}
+
+/// Capabilities loading and attaching a program of `prog_type` (bpftool naming) takes, with
+/// what each one is for
+pub fn required_for(prog_type: &str) -> Vec<(Capability, &'static str)> {
+    let mut caps = vec![(Capability::Bpf, "load")];
+    if PERFMON_TYPES.contains(&prog_type) {
+        caps.push((Capability::Perfmon, "load"));
+    }
+    if NET_ADMIN_TYPES.contains(&prog_type) {
+        caps.push((Capability::NetAdmin, "load and attach"));
+    }
+    if SYS_ADMIN_TYPES.contains(&prog_type) {
+        caps.push((Capability::SysAdmin, "load and attach"));
+    }
+    caps
+}
+
+/// Whether this user can create pins under `default_pin_prefix()` by file permissions alone.
+/// systemd mounts /sys/fs/bpf root-only (mode 0700), so usually only CAP_DAC_OVERRIDE gets in
+pub fn pin_prefix_writable() -> bool {
+    let prefix = default_pin_prefix();
+    // Deepest first; a directory that can't be searched doesn't show up as existing
+    let existing: Vec<&Path> = prefix.ancestors().filter(|p| p.exists()).collect();
+    let Some(deepest) = existing.first() else { return false };
+    existing.iter().all(|dir| permits(dir, 0o1)) && permits(deepest, 0o2)
+}
+
+/// Whether the mode bits of `path` grant this process's effective user `bits` (4 read, 2 write,
+/// 1 execute)
+fn permits(path: &Path, bits: u32) -> bool {
+    let Ok(meta) = std::fs::metadata(path) else { return false };
+    let in_group = meta.gid() == getegid().as_raw()
+        || getgroups().unwrap_or_default().iter().any(|g| g.as_raw() == meta.gid());
+    let shift = if meta.uid() == geteuid().as_raw() {
+        6
+    } else if in_group {
+        3
+    } else {
+        0
+    };
+    (meta.mode() >> shift) & bits == bits
+}
+
+/// Each capability this process lacks for `programs` (label, program type), with what for.
+/// With `pins`, creating pins under the pin prefix counts too
+pub fn missing_for(programs: &[(String, &str)], pins: bool) -> Result<Vec<(Capability, String)>> {
+    let sets = CapSets::current()?;
+    let modern = kernel_has_cap_bpf();
+    let mut missing: BTreeMap<Capability, Vec<String>> = BTreeMap::new();
+    for (label, prog_type) in programs {
+        for (cap, action) in required_for(prog_type) {
+            // Before CAP_BPF existed, CAP_SYS_ADMIN is the only way in
+            let cap = match cap {
+                Capability::Bpf | Capability::Perfmon if !modern => Capability::SysAdmin,
+                cap => cap,
+            };
+            if !sets.allows(cap) {
+                let need = format!("{} {} programs ({})", action, prog_type, label);
+                let entry = missing.entry(cap).or_default();
+                if !entry.contains(&need) {
+                    entry.push(need);
+                }
+            }
+        }
+    }
+    if pins && !sets.allows(Capability::DacOverride) && !pin_prefix_writable() {
+        missing.entry(Capability::DacOverride).or_default()
+            .push(format!("create pins under {}", default_pin_prefix().display()));
+    }
+    Ok(missing
+        .into_iter()
+        .map(|(cap, needs)| {
+            let alternative = match cap {
+                Capability::Bpf | Capability::Perfmon => " (or CAP_SYS_ADMIN)",
+                Capability::SysAdmin if !modern => " (the kernel predates CAP_BPF)",
+                Capability::DacOverride => " (or write access to the pin directory)",
+                _ => "",
+            };
+            let permitted = if sets.permitted & cap.bit() != 0 { "; permitted but not effective" } else { "" };
+            (cap, format!("{}{} to {}{}", cap.name(), alternative, needs.join(", "), permitted))
+        })
+        .collect())
+}
+
+/// Fail with every capability `what` lacks and the setcap line that grants them
+pub fn require(what: &str, programs: &[(String, &str)], pins: bool) -> Result<()> {
+    let missing = missing_for(programs, pins)?;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = missing.iter().map(|(cap, _)| cap.name().to_lowercase()).collect();
+    let lines: Vec<&str> = missing.iter().map(|(_, line)| line.as_str()).collect();
+    let exe = std::env::current_exe().map(|p| p.display().to_string()).unwrap_or_else(|_| "eclipta".to_string());
+    let mut hint = format!("Run as root, or grant them with: sudo setcap {}+ep {}", names.join(","), exe);
+    if missing.iter().any(|(cap, _)| *cap == Capability::DacOverride) {
+        hint.push_str(&format!(
+            "\nInstead of CAP_DAC_OVERRIDE, the pin directory can be handed to this user: \
+             sudo mkdir -p {0} && sudo chown {1} {0} && sudo chmod o+x {2}",
+            default_pin_prefix().display(),
+            geteuid(),
+            default_pin_prefix().parent().map(|p| p.display().to_string()).unwrap_or_default()
+        ));
+    }
+    Err(anyhow!("Missing capabilities to {}:\n  {}\n{}", what, lines.join("\n  "), hint))
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+static KEEP: AtomicU64 = AtomicU64::new(0);
+static LAST_CAP: AtomicU32 = AtomicU32::new(0);
+
+/// Shrink the calling thread's bounding, effective, permitted and inheritable sets to KEEP.
+/// Only makes syscalls, so it is safe to run in a signal handler
+fn drop_own_caps() -> bool {
+    let keep = KEEP.load(Ordering::SeqCst);
+    // The bounding set goes first, while CAP_SETPCAP is still held, so a later exec
+    // can't hand the capabilities back
+    for cap in 0..=LAST_CAP.load(Ordering::SeqCst) {
+        if keep & (1 << cap) == 0 {
+            unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0) };
+        }
+    }
+    let mut header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+    let data = [
+        CapData { effective: keep as u32, permitted: keep as u32, inheritable: 0 },
+        CapData { effective: (keep >> 32) as u32, permitted: (keep >> 32) as u32, inheritable: 0 },
+    ];
+    unsafe { libc::syscall(libc::SYS_capset, &mut header as *mut CapHeader, data.as_ptr()) == 0 }
+}
+
+extern "C" fn on_drop_signal(_: libc::c_int) {
+    // The interrupted code may be about to read errno
+    let errno = unsafe { *libc::__errno_location() };
+    drop_own_caps();
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// Give up every capability but `keep`, in all threads, once programs are loaded and attached.
+/// Their fds keep working; only new kernel operations are refused afterwards
+pub fn drop_caps(keep: &[Capability]) -> Result<()> {
+    let keep = keep.iter().fold(0, |mask, c| mask | c.bit()) & CapSets::current()?.permitted;
+    KEEP.store(keep, Ordering::SeqCst);
+    LAST_CAP.store(last_cap(), Ordering::SeqCst);
+    if !drop_own_caps() {
+        return Err(anyhow!("capset failed: {}", std::io::Error::last_os_error()));
+    }
+
+    // Capabilities belong to threads and capset only changes the caller's, so every other
+    // runtime thread is signalled to drop its own. The handler stays installed for good: a
+    // signal still queued for a slow thread would otherwise meet the default action, which
+    // terminates the process
+    let signal = libc::SIGRTMIN();
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_drop_signal as extern "C" fn(libc::c_int) as usize;
+    action.sa_flags = libc::SA_RESTART;
+    if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+        return Err(anyhow!("Failed to install the capability drop handler: {}", std::io::Error::last_os_error()));
+    }
+
+    // Threads spawned meanwhile inherit from their creator, so new ones are signalled as they
+    // appear. A thread is signalled once; until its handler runs it just keeps showing up here
+    let mut signalled = HashSet::new();
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let holding = loop {
+        let holding = threads_holding(keep)?;
+        if holding.is_empty() || Instant::now() >= deadline {
+            break holding;
+        }
+        for tid in holding.iter().filter(|tid| signalled.insert(**tid)) {
+            unsafe { libc::syscall(libc::SYS_tgkill, getpid().as_raw(), *tid, signal) };
+        }
+        std::thread::sleep(Duration::from_millis(2));
+    };
+
+    if !holding.is_empty() {
+        return Err(anyhow!("Threads {:?} still hold capabilities", holding));
+    }
+    Ok(())
+}
+
+/// Threads of this process whose effective or permitted set has more than `keep`
+fn threads_holding(keep: u64) -> Result<Vec<i32>> {
+    let me = gettid().as_raw();
+    let mut tids = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/task").context("Failed to list threads")? {
+        let Some(tid) = entry?.file_name().to_str().and_then(|s| s.parse::<i32>().ok()) else { continue };
+        // A thread that exited in between has no status left to read
+        let Ok(sets) = CapSets::read(&format!("/proc/self/task/{}/status", tid)) else { continue };
+        if tid != me && (sets.effective | sets.permitted) & !keep != 0 {
+            tids.push(tid);
+        }
+    }
+    Ok(tids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_lines_give_the_sets() {
+        let status = "Name:\teclipta\nCapInh:\t0000000000000000\nCapPrm:\t000000c000001000\nCapEff:\t0000008000001000\n";
+        let sets = CapSets::parse(status).unwrap();
+        assert_eq!((sets.effective, sets.permitted), (0x80_0000_1000, 0xc0_0000_1000));
+        assert!(sets.has(Capability::Bpf) && sets.has(Capability::NetAdmin));
+        assert!(!sets.has(Capability::Perfmon));
+        assert!(!sets.allows(Capability::Perfmon));
+
+        let admin = CapSets::parse("CapPrm:\t0000000000200000\nCapEff:\t0000000000200000").unwrap();
+        assert!(admin.allows(Capability::Bpf) && admin.allows(Capability::Perfmon));
+        assert!(!admin.allows(Capability::NetAdmin));
+
+        assert!(CapSets::parse("CapEff:\t0000000000000000\n").is_err());
+        assert!(CapSets::parse("CapPrm:\tzz\nCapEff:\t0\n").is_err());
+    }
+
+    #[test]
+    fn program_types_need_their_capabilities() {
+        let caps = |t: &str| required_for(t).into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+        assert_eq!(caps("socket_filter"), vec![Capability::Bpf]);
+        assert_eq!(caps("kprobe"), vec![Capability::Bpf, Capability::Perfmon]);
+        assert_eq!(caps("xdp"), vec![Capability::Bpf, Capability::NetAdmin]);
+        assert_eq!(caps("cgroup_skb"), vec![Capability::Bpf, Capability::NetAdmin]);
+        assert_eq!(caps("lsm"), vec![Capability::Bpf, Capability::Perfmon, Capability::SysAdmin]);
+        assert_eq!(caps("struct_ops"), vec![Capability::Bpf, Capability::Perfmon, Capability::SysAdmin]);
+        assert_eq!(caps("ext"), vec![Capability::Bpf, Capability::Perfmon, Capability::NetAdmin, Capability::SysAdmin]);
+    }
+}
@@ -0,0 +1,595 @@
+use crate::utils::btf::{Btf, BtfType, TypeId, MAX_DEPTH};
+use anyhow::{anyhow, Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use object::{Object, ObjectSection};
+use std::collections::HashMap;
+use std::mem::discriminant;
+use std::path::Path;
+
+const BTF_EXT_MAGIC: u16 = 0xeb9f;
+/// Size of a `.BTF.ext` header that carries the CO-RE relocation offsets
+const CORE_RELO_HDR_LEN: usize = 32;
+
+/// `enum bpf_core_relo_kind`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReloKind {
+    FieldByteOffset,
+    FieldByteSize,
+    FieldExists,
+    FieldSigned,
+    FieldLshiftU64,
+    FieldRshiftU64,
+    TypeIdLocal,
+    TypeIdTarget,
+    TypeExists,
+    TypeSize,
+    EnumvalExists,
+    EnumvalValue,
+    TypeMatches,
+}
+
+impl ReloKind {
+    fn from_raw(kind: u32) -> Option<ReloKind> {
+        use ReloKind::*;
+        const KINDS: [ReloKind; 13] = [
+            FieldByteOffset, FieldByteSize, FieldExists, FieldSigned, FieldLshiftU64, FieldRshiftU64,
+            TypeIdLocal, TypeIdTarget, TypeExists, TypeSize, EnumvalExists, EnumvalValue, TypeMatches,
+        ];
+        KINDS.get(kind as usize).copied()
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ReloKind::FieldByteOffset => "field offset",
+            ReloKind::FieldByteSize => "field size",
+            ReloKind::FieldExists => "field exists",
+            ReloKind::FieldSigned => "field signedness",
+            ReloKind::FieldLshiftU64 | ReloKind::FieldRshiftU64 => "bitfield shift",
+            ReloKind::TypeIdLocal => "local type id",
+            ReloKind::TypeIdTarget => "target type id",
+            ReloKind::TypeExists => "type exists",
+            ReloKind::TypeSize => "type size",
+            ReloKind::EnumvalExists => "enum value exists",
+            ReloKind::EnumvalValue => "enum value",
+            ReloKind::TypeMatches => "type matches",
+        }
+    }
+
+    /// Existence checks evaluate to 0 on a miss instead of breaking the program
+    pub fn is_guard(&self) -> bool {
+        matches!(self, ReloKind::FieldExists | ReloKind::TypeExists | ReloKind::EnumvalExists | ReloKind::TypeMatches)
+    }
+
+    fn is_field(&self) -> bool {
+        matches!(
+            self,
+            ReloKind::FieldByteOffset
+                | ReloKind::FieldByteSize
+                | ReloKind::FieldExists
+                | ReloKind::FieldSigned
+                | ReloKind::FieldLshiftU64
+                | ReloKind::FieldRshiftU64
+        )
+    }
+
+    fn is_enum(&self) -> bool {
+        matches!(self, ReloKind::EnumvalExists | ReloKind::EnumvalValue)
+    }
+}
+
+/// One `struct bpf_core_relo` record
+#[derive(Debug, Clone)]
+pub struct CoreRelo {
+    /// ELF section of the program the instruction belongs to
+    pub section: String,
+    pub insn_off: u32,
+    pub type_id: TypeId,
+    /// Colon-separated access indices, e.g. "0:1:2"
+    pub access: String,
+    pub kind: ReloKind,
+}
+
+/// BTF and CO-RE relocations of an eBPF object
+pub struct CoreObject {
+    pub btf: Btf,
+    pub relos: Vec<CoreRelo>,
+}
+
+impl CoreObject {
+    /// Relocations are empty when the object has no `.BTF.ext`, i.e. it does not use CO-RE
+    pub fn from_elf(path: &Path) -> Result<CoreObject> {
+        let data = std::fs::read(path).context("Failed to read eBPF object")?;
+        let obj = object::File::parse(&*data).context("Failed to parse ELF file")?;
+        let btf = obj.section_by_name(".BTF")
+            .ok_or_else(|| anyhow!("{} has no BTF; rebuild it with -g", path.display()))?;
+        let btf = Btf::parse(btf.data()?)?;
+        let relos = match obj.section_by_name(".BTF.ext") {
+            Some(ext) => parse_relos(ext.data()?, &btf)?,
+            None => Vec::new(),
+        };
+        Ok(CoreObject { btf, relos })
+    }
+
+    /// What a relocation accesses, e.g. `struct task_struct.se.vruntime` or `enum bpf_func_id::BPF_FUNC_loop`
+    pub fn describe(&self, relo: &CoreRelo) -> String {
+        let mut out = self.btf.type_name(relo.type_id);
+        let Ok(access) = parse_access(&relo.access) else { return out };
+        if relo.kind.is_enum() {
+            if let (Some(BtfType::Enum { values, .. }), Some(i)) = (self.btf.get(self.btf.resolve(relo.type_id)), access.first()) {
+                if let Some((name, _)) = values.get(*i as usize) {
+                    out.push_str(&format!("::{}", name));
+                }
+            }
+            return out;
+        }
+        if !relo.kind.is_field() {
+            return out;
+        }
+        let mut id = self.btf.resolve(relo.type_id);
+        for &i in access.iter().skip(1) {
+            match self.btf.get(id) {
+                Some(BtfType::Struct { members, .. }) | Some(BtfType::Union { members, .. }) => {
+                    let Some(m) = members.get(i as usize) else { break };
+                    if !m.name.is_empty() {
+                        out.push('.');
+                        out.push_str(&m.name);
+                    }
+                    id = self.btf.resolve(m.ty);
+                }
+                Some(BtfType::Array { elem, .. }) => {
+                    out.push_str(&format!("[{}]", i));
+                    id = self.btf.resolve(*elem);
+                }
+                _ => break,
+            }
+        }
+        out
+    }
+
+    /// Whether `relo` resolves against a kernel's BTF, following libbpf's matching rules:
+    /// candidates share the kind and the name up to a `___flavor` suffix, and fields are
+    /// matched by name, looking through anonymous structs and unions
+    pub fn resolve(&self, relo: &CoreRelo, target: &TargetBtf) -> Result<(), String> {
+        if relo.kind == ReloKind::TypeIdLocal {
+            return Ok(());
+        }
+        let local = self.btf.get(relo.type_id).ok_or("relocation names an unknown local type")?;
+        let name = plain_name(local)
+            .map(essential_name)
+            .ok_or_else(|| format!("{} is anonymous and can't be relocated", self.btf.type_name(relo.type_id)))?;
+        let candidates = target.candidates(name, local);
+        if candidates.is_empty() {
+            return Err(format!("{} does not exist", self.btf.type_name(relo.type_id)));
+        }
+        let access = parse_access(&relo.access)?;
+
+        if relo.kind.is_field() {
+            let mut last = String::new();
+            for c in candidates {
+                match self.match_field(relo.type_id, &access, &target.btf, c) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => last = e,
+                }
+            }
+            return Err(last);
+        }
+        if relo.kind.is_enum() {
+            let Some(BtfType::Enum { values, .. }) = self.btf.get(self.btf.resolve(relo.type_id)) else {
+                return Err("enum relocation on a non-enum type".to_string());
+            };
+            let value = access.first()
+                .and_then(|i| values.get(*i as usize))
+                .map(|(n, _)| n.as_str())
+                .ok_or("enum relocation names a missing value")?;
+            let found = candidates.iter().any(|c| {
+                matches!(target.btf.get(target.btf.resolve(*c)), Some(BtfType::Enum { values, .. }) if values.iter().any(|(n, _)| n == value))
+            });
+            return match found {
+                true => Ok(()),
+                false => Err(format!("{} has no value {}", self.btf.type_name(relo.type_id), value)),
+            };
+        }
+        if relo.kind == ReloKind::TypeMatches {
+            return match candidates.iter().any(|c| types_match(&self.btf, relo.type_id, &target.btf, *c, 0)) {
+                true => Ok(()),
+                false => Err(format!("no {} in this kernel has the same layout", self.btf.type_name(relo.type_id))),
+            };
+        }
+
+        let compat: Vec<TypeId> = candidates.into_iter()
+            .filter(|c| types_compat(&self.btf, relo.type_id, &target.btf, *c, 0))
+            .collect();
+        if compat.is_empty() {
+            return Err(format!("{} has an incompatible definition in this kernel", self.btf.type_name(relo.type_id)));
+        }
+        if relo.kind == ReloKind::TypeSize {
+            // Every candidate has to agree on the size, or the loader can't pick one
+            let mut sizes: Vec<usize> = Vec::new();
+            for c in &compat {
+                let size = target.btf.size_of(*c)
+                    .ok_or_else(|| format!("{} has no size in this kernel", target.btf.type_name(*c)))?;
+                if !sizes.contains(&size) {
+                    sizes.push(size);
+                }
+            }
+            if sizes.len() > 1 {
+                return Err(format!("{} is ambiguous: candidates are {:?} bytes", self.btf.type_name(relo.type_id), sizes));
+            }
+        }
+        Ok(())
+    }
+
+    fn match_field(&self, local_id: TypeId, access: &[u32], target: &Btf, target_id: TypeId) -> Result<(), String> {
+        let local = &self.btf;
+        let mut l = local.resolve(local_id);
+        let mut t = target.resolve(target_id);
+        // The first index steps over the root pointer and never needs matching
+        for &i in access.iter().skip(1) {
+            match local.get(l) {
+                Some(BtfType::Struct { members, .. }) | Some(BtfType::Union { members, .. }) => {
+                    let m = members.get(i as usize).ok_or("access string is out of bounds")?;
+                    l = local.resolve(m.ty);
+                    if m.name.is_empty() {
+                        // The next named field is looked up through the kernel's own anonymous members
+                        continue;
+                    }
+                    let ty = find_member(target, t, &m.name)
+                        .ok_or_else(|| format!("{} has no field '{}'", target.type_name(t), m.name))?;
+                    t = target.resolve(ty);
+                }
+                Some(BtfType::Array { elem, .. }) => {
+                    let Some(BtfType::Array { elem: target_elem, len }) = target.get(t) else {
+                        return Err(format!("{} is not an array in this kernel", target.type_name(t)));
+                    };
+                    // Zero-length arrays are flexible array members
+                    if *len > 0 && i >= *len {
+                        return Err(format!("index {} is past the end of {}", i, target.type_name(t)));
+                    }
+                    l = local.resolve(*elem);
+                    t = target.resolve(*target_elem);
+                }
+                _ => return Err("access string walks into a non-composite type".to_string()),
+            }
+        }
+        if !compatible(local, l, target, t) {
+            return Err(format!("field is {} here but {} in this kernel", local.type_name(l), target.type_name(t)));
+        }
+        Ok(())
+    }
+}
+
+/// Kernel BTF indexed by essential type name
+pub struct TargetBtf {
+    pub btf: Btf,
+    by_name: HashMap<String, Vec<TypeId>>,
+}
+
+impl TargetBtf {
+    pub fn new(btf: Btf) -> TargetBtf {
+        let mut by_name: HashMap<String, Vec<TypeId>> = HashMap::new();
+        for (id, t) in btf.types() {
+            if let Some(name) = plain_name(t) {
+                by_name.entry(essential_name(name).to_string()).or_default().push(id);
+            }
+        }
+        TargetBtf { btf, by_name }
+    }
+
+    fn candidates(&self, name: &str, local: &BtfType) -> Vec<TypeId> {
+        self.by_name
+            .get(name)
+            .map(|ids| {
+                ids.iter()
+                    .copied()
+                    .filter(|id| self.btf.get(*id).is_some_and(|t| discriminant(t) == discriminant(local)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn parse_relos(data: &[u8], btf: &Btf) -> Result<Vec<CoreRelo>> {
+    if data.len() < 8 || LittleEndian::read_u16(data) != BTF_EXT_MAGIC {
+        return Err(anyhow!("Not a little-endian .BTF.ext section"));
+    }
+    let hdr_len = LittleEndian::read_u32(&data[4..]) as usize;
+    // Objects from compilers predating CO-RE have a shorter header and no relocations
+    if hdr_len < CORE_RELO_HDR_LEN || data.len() < CORE_RELO_HDR_LEN {
+        return Ok(Vec::new());
+    }
+    let off = hdr_len + LittleEndian::read_u32(&data[24..]) as usize;
+    let len = LittleEndian::read_u32(&data[28..]) as usize;
+    let block = data.get(off..off + len).ok_or_else(|| anyhow!("CO-RE relocations out of bounds"))?;
+    if block.len() < 4 {
+        return Ok(Vec::new());
+    }
+    let record_size = LittleEndian::read_u32(block) as usize;
+    if record_size < 16 {
+        return Err(anyhow!("Unexpected CO-RE relocation record size {}", record_size));
+    }
+
+    let mut relos = Vec::new();
+    let mut pos = 4;
+    while pos + 8 <= block.len() {
+        let section = btf.string(LittleEndian::read_u32(&block[pos..]));
+        let count = LittleEndian::read_u32(&block[pos + 4..]) as usize;
+        pos += 8;
+        let records = block.get(pos..pos + count * record_size)
+            .ok_or_else(|| anyhow!("Truncated CO-RE relocations for section {}", section))?;
+        for r in records.chunks(record_size) {
+            // Kinds newer than this list are skipped rather than guessed at
+            let Some(kind) = ReloKind::from_raw(LittleEndian::read_u32(&r[12..])) else { continue };
+            relos.push(CoreRelo {
+                section: section.clone(),
+                insn_off: LittleEndian::read_u32(r),
+                type_id: LittleEndian::read_u32(&r[4..]),
+                access: btf.string(LittleEndian::read_u32(&r[8..])),
+                kind,
+            });
+        }
+        pos += count * record_size;
+    }
+    Ok(relos)
+}
+
+fn parse_access(access: &str) -> Result<Vec<u32>, String> {
+    access.split(':')
+        .map(|i| i.parse().map_err(|_| format!("malformed access string '{}'", access)))
+        .collect()
+}
+
+fn plain_name(t: &BtfType) -> Option<&str> {
+    let name = match t {
+        BtfType::Struct { name, .. }
+        | BtfType::Union { name, .. }
+        | BtfType::Enum { name, .. }
+        | BtfType::Typedef { name, .. }
+        | BtfType::Int { name, .. }
+        | BtfType::Float { name, .. } => name,
+        BtfType::Fwd(name) => name,
+        _ => return None,
+    };
+    (!name.is_empty()).then_some(name.as_str())
+}
+
+/// `task_struct___v58` and `task_struct` are both looked up as `task_struct`
+fn essential_name(name: &str) -> &str {
+    name.find("___").map(|i| &name[..i]).unwrap_or(name)
+}
+
+/// Type of the member called `name`, searching anonymous members too
+fn find_member(btf: &Btf, id: TypeId, name: &str) -> Option<TypeId> {
+    let (BtfType::Struct { members, .. } | BtfType::Union { members, .. }) = btf.get(id)? else { return None };
+    members.iter().find_map(|m| {
+        if m.name == name {
+            Some(m.ty)
+        } else if m.name.is_empty() {
+            find_member(btf, btf.resolve(m.ty), name)
+        } else {
+            None
+        }
+    })
+}
+
+/// Field types the program can keep reading the same way: the same kind, arrays compared by element
+fn compatible(local: &Btf, l: TypeId, target: &Btf, t: TypeId) -> bool {
+    match (local.get(l), target.get(t)) {
+        (Some(BtfType::Array { elem: le, .. }), Some(BtfType::Array { elem: te, .. })) => {
+            compatible(local, local.resolve(*le), target, target.resolve(*te))
+        }
+        // A forward declaration stands in for any struct or union
+        (Some(BtfType::Fwd(_)), Some(BtfType::Struct { .. } | BtfType::Union { .. }))
+        | (Some(BtfType::Struct { .. } | BtfType::Union { .. }), Some(BtfType::Fwd(_))) => true,
+        (Some(a), Some(b)) => discriminant(a) == discriminant(b),
+        _ => false,
+    }
+}
+
+/// libbpf's `bpf_core_types_are_compat`: named types only need the same kind, pointers and
+/// arrays are compared by what they point to
+fn types_compat(local: &Btf, l: TypeId, target: &Btf, t: TypeId, depth: u32) -> bool {
+    if depth > MAX_DEPTH {
+        return false;
+    }
+    match (local.get(local.resolve(l)), target.get(target.resolve(t))) {
+        (Some(BtfType::Ptr(lp)), Some(BtfType::Ptr(tp))) => types_compat(local, *lp, target, *tp, depth + 1),
+        (Some(BtfType::Array { elem: le, .. }), Some(BtfType::Array { elem: te, .. })) => {
+            types_compat(local, *le, target, *te, depth + 1)
+        }
+        (Some(a), Some(b)) => discriminant(a) == discriminant(b),
+        _ => false,
+    }
+}
+
+/// libbpf's `bpf_core_types_match`: the kernel type has every member the program declares,
+/// with matching names, sizes and signedness all the way down
+fn types_match(local: &Btf, l: TypeId, target: &Btf, t: TypeId, depth: u32) -> bool {
+    if depth > MAX_DEPTH {
+        return false;
+    }
+    let (l, t) = (local.resolve(l), target.resolve(t));
+    let (Some(lt), Some(tt)) = (local.get(l), target.get(t)) else { return false };
+    if let (Some(a), Some(b)) = (plain_name(lt), plain_name(tt)) {
+        if essential_name(a) != essential_name(b) {
+            return false;
+        }
+    }
+    match (lt, tt) {
+        (BtfType::Void, BtfType::Void) => true,
+        (BtfType::Fwd(_), BtfType::Fwd(_) | BtfType::Struct { .. } | BtfType::Union { .. })
+        | (BtfType::Struct { .. } | BtfType::Union { .. }, BtfType::Fwd(_)) => true,
+        (BtfType::Ptr(lp), BtfType::Ptr(tp)) => types_match(local, *lp, target, *tp, depth + 1),
+        (BtfType::Int { size: ls, signed: lsg, .. }, BtfType::Int { size: ts, signed: tsg, .. }) => ls == ts && lsg == tsg,
+        (BtfType::Float { size: ls, .. }, BtfType::Float { size: ts, .. }) => ls == ts,
+        (BtfType::Enum { size: ls, values: lv, .. }, BtfType::Enum { size: ts, values: tv, .. }) => {
+            ls == ts && lv.iter().all(|(n, _)| tv.iter().any(|(m, _)| essential_name(n) == essential_name(m)))
+        }
+        (BtfType::Array { elem: le, len: ll }, BtfType::Array { elem: te, len: tl }) => {
+            ll == tl && types_match(local, *le, target, *te, depth + 1)
+        }
+        (BtfType::Struct { members: lm, .. }, BtfType::Struct { members: tm, .. })
+        | (BtfType::Union { members: lm, .. }, BtfType::Union { members: tm, .. }) => {
+            lm.len() <= tm.len()
+                && lm.iter().all(|m| {
+                    tm.iter().any(|n| {
+                        n.name == m.name && n.bit_size == m.bit_size && types_match(local, m.ty, target, n.ty, depth + 1)
+                    })
+                })
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::btf::testing::BtfBuilder;
+
+    /// `.BTF.ext` with one section of (insn_off, type_id, access string offset, kind) records
+    fn btf_ext(section: u32, records: &[(u32, u32, u32, u32)]) -> Vec<u8> {
+        let mut block = 16u32.to_le_bytes().to_vec();
+        block.extend(section.to_le_bytes());
+        block.extend((records.len() as u32).to_le_bytes());
+        for (insn, ty, access, kind) in records {
+            for word in [insn, ty, access, kind] {
+                block.extend(word.to_le_bytes());
+            }
+        }
+        let mut out = BTF_EXT_MAGIC.to_le_bytes().to_vec();
+        out.extend([1, 0]);
+        for word in [CORE_RELO_HDR_LEN as u32, 0, 0, 0, 0, 0, block.len() as u32] {
+            out.extend(word.to_le_bytes());
+        }
+        out.extend(block);
+        out
+    }
+
+    fn relo(type_id: TypeId, access: &str, kind: ReloKind) -> CoreRelo {
+        CoreRelo { section: "kprobe/x".to_string(), insn_off: 0, type_id, access: access.to_string(), kind }
+    }
+
+    /// `struct task___v2 { int pid; union { long state; }; }` as the program sees it
+    fn object() -> (CoreObject, TypeId) {
+        let mut b = BtfBuilder::new();
+        let int = b.int("int", 4, true);
+        let long = b.int("long", 8, true);
+        let anon = b.union("", 8, &[("state", long, 0, 0)]);
+        let task = b.structure("task___v2", 16, &[("pid", int, 0, 0), ("", anon, 64, 0)]);
+        (CoreObject { btf: b.parse(), relos: Vec::new() }, task)
+    }
+
+    #[test]
+    fn btf_ext_records_are_read_per_section() {
+        let mut b = BtfBuilder::new();
+        let section = b.string("kprobe/x");
+        let access = b.string("0:1:0");
+        let btf = b.parse();
+        let relos = parse_relos(&btf_ext(section, &[(8, 3, access, 0), (16, 3, access, 99), (24, 3, access, 12)]), &btf).unwrap();
+
+        // Kind 99 is unknown and skipped
+        assert_eq!(relos.len(), 2);
+        assert_eq!(relos[0].section, "kprobe/x");
+        assert_eq!((relos[0].insn_off, relos[0].type_id, relos[0].access.as_str()), (8, 3, "0:1:0"));
+        assert_eq!(relos[0].kind, ReloKind::FieldByteOffset);
+        assert_eq!(relos[1].kind, ReloKind::TypeMatches);
+
+        let mut truncated = btf_ext(section, &[(8, 3, access, 0)]);
+        truncated.truncate(truncated.len() - 4);
+        let last = truncated.len() - CORE_RELO_HDR_LEN;
+        truncated[28..32].copy_from_slice(&(last as u32).to_le_bytes());
+        assert!(parse_relos(&truncated, &btf).is_err());
+        assert!(parse_relos(&[0; 8], &btf).is_err());
+    }
+
+    #[test]
+    fn fields_are_matched_by_name_through_anonymous_members() {
+        let (obj, task) = object();
+        assert_eq!(obj.describe(&relo(task, "0:1:0", ReloKind::FieldByteOffset)), "struct task___v2.state");
+
+        // The kernel moved `state` out of the union and added a field in front
+        let mut k = BtfBuilder::new();
+        let int = k.int("int", 4, true);
+        let long = k.int("long", 8, true);
+        k.structure("task", 24, &[("flags", int, 0, 0), ("pid", int, 32, 0), ("state", long, 64, 0)]);
+        let target = TargetBtf::new(k.parse());
+        assert!(obj.resolve(&relo(task, "0:0", ReloKind::FieldByteOffset), &target).is_ok());
+        assert!(obj.resolve(&relo(task, "0:1:0", ReloKind::FieldByteOffset), &target).is_ok());
+        assert!(obj.resolve(&relo(task, "0:5", ReloKind::FieldByteOffset), &target).is_err());
+
+        let mut k = BtfBuilder::new();
+        let int = k.int("int", 4, true);
+        k.structure("task", 4, &[("pid", int, 0, 0)]);
+        let target = TargetBtf::new(k.parse());
+        let err = obj.resolve(&relo(task, "0:1:0", ReloKind::FieldByteOffset), &target).unwrap_err();
+        assert!(err.contains("no field 'state'"), "{}", err);
+        // A missing field only fails the existence check
+        assert!(relo(task, "0:1:0", ReloKind::FieldExists).kind.is_guard());
+
+        let target = TargetBtf::new(BtfBuilder::new().parse());
+        assert!(obj.resolve(&relo(task, "0:0", ReloKind::FieldByteOffset), &target).unwrap_err().contains("does not exist"));
+    }
+
+    #[test]
+    fn enum_values_are_looked_up_by_name() {
+        let mut b = BtfBuilder::new();
+        let e = b.enumeration("bpf_func_id", 4, &[("BPF_FUNC_unspec", 0), ("BPF_FUNC_loop", 181)]);
+        let obj = CoreObject { btf: b.parse(), relos: Vec::new() };
+        assert_eq!(obj.describe(&relo(e, "1", ReloKind::EnumvalValue)), "enum bpf_func_id::BPF_FUNC_loop");
+
+        let mut k = BtfBuilder::new();
+        k.enumeration("bpf_func_id", 4, &[("BPF_FUNC_unspec", 0)]);
+        let old = TargetBtf::new(k.parse());
+        assert!(obj.resolve(&relo(e, "0", ReloKind::EnumvalValue), &old).is_ok());
+        assert!(obj.resolve(&relo(e, "1", ReloKind::EnumvalExists), &old).is_err());
+    }
+
+    #[test]
+    fn type_size_needs_compatible_candidates_that_agree() {
+        let mut b = BtfBuilder::new();
+        let int = b.int("int", 4, true);
+        let p = b.ptr(int);
+        let t = b.typedef("handle_t", p);
+        let obj = CoreObject { btf: b.parse(), relos: Vec::new() };
+
+        let mut k = BtfBuilder::new();
+        let int = k.int("int", 4, true);
+        let p = k.ptr(int);
+        k.typedef("handle_t", p);
+        assert!(obj.resolve(&relo(t, "0", ReloKind::TypeSize), &TargetBtf::new(k.parse())).is_ok());
+
+        // The kernel's handle_t is a struct, not a pointer
+        let mut k = BtfBuilder::new();
+        let int = k.int("int", 4, true);
+        let s = k.structure("handle", 4, &[("fd", int, 0, 0)]);
+        k.typedef("handle_t", s);
+        let err = obj.resolve(&relo(t, "0", ReloKind::TypeSize), &TargetBtf::new(k.parse())).unwrap_err();
+        assert!(err.contains("incompatible"), "{}", err);
+
+        // Two flavors of different sizes leave the loader no single answer
+        let (obj, task) = object();
+        let mut k = BtfBuilder::new();
+        let int = k.int("int", 4, true);
+        k.structure("task", 4, &[("pid", int, 0, 0)]);
+        k.structure("task___old", 8, &[("pid", int, 0, 0)]);
+        let err = obj.resolve(&relo(task, "0", ReloKind::TypeSize), &TargetBtf::new(k.parse())).unwrap_err();
+        assert!(err.contains("ambiguous"), "{}", err);
+    }
+
+    #[test]
+    fn type_matches_compares_layouts() {
+        let (obj, task) = object();
+        let build = |state_size: u32, state_name: &str| {
+            let mut k = BtfBuilder::new();
+            let int = k.int("int", 4, true);
+            let long = k.int("long", state_size, true);
+            let anon = k.union("", state_size, &[(state_name, long, 0, 0)]);
+            k.structure("task", 16, &[("pid", int, 0, 0), ("", anon, 64, 0), ("extra", int, 96, 0)]);
+            TargetBtf::new(k.parse())
+        };
+        assert!(obj.resolve(&relo(task, "0", ReloKind::TypeMatches), &build(8, "state")).is_ok());
+        assert!(obj.resolve(&relo(task, "0", ReloKind::TypeMatches), &build(4, "state")).is_err());
+        assert!(obj.resolve(&relo(task, "0", ReloKind::TypeMatches), &build(8, "__state")).is_err());
+        // Existence only needs the name and kind
+        assert!(obj.resolve(&relo(task, "0", ReloKind::TypeExists), &build(4, "__state")).is_ok());
+    }
+}
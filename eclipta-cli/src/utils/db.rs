@@ -2,7 +2,7 @@ use sqlx::{PgPool, Pool, Postgres};
 use dotenvy::dotenv;
 use std::env;
 use crate::utils::logger::error;
-use crate::db::migrations::{run_migrations, check_migration_status};
+use crate::db::migrations::{run_migrations, check_migration_status, apply_pending_migrations};
 
 pub type DbPool = Pool<Postgres>;
 
@@ -18,6 +18,9 @@ pub async fn ensure_db_ready() -> Result<DbPool, Box<dyn std::error::Error>> {
         error("Database is not ready. Please run 'cargo run migrate' first to initialize the database.");
         return Err("Database not initialized".into());
     }
+
+    // Bring older databases up to the current schema
+    apply_pending_migrations(&pool).await?;
     
     Ok(pool)
 }
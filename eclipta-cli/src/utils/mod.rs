@@ -2,3 +2,11 @@ pub mod logger;
 pub mod paths;
 pub mod state;
 pub mod db;
+pub mod bpftool;
+pub mod btf;
+pub mod bpf_stats;
+pub mod otlp;
+pub mod schedule;
+pub mod systemd;
+pub mod core_relo;
+pub mod caps;
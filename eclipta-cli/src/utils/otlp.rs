@@ -0,0 +1,512 @@
+use crate::utils::logger::warn;
+use anyhow::{anyhow, Result};
+use clap::Args;
+use serde_json::{json, Map, Value};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// Records per request; the collector's default limit is far higher
+const MAX_BATCH: usize = 512;
+const FLUSH_EVERY: Duration = Duration::from_secs(2);
+const MAX_ATTEMPTS: u32 = 5;
+const QUEUE_SIZE: usize = 8192;
+/// How long `shutdown` waits for queued data before giving up
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where and how to ship telemetry over OTLP/HTTP
+#[derive(Args, Debug, Clone, Default)]
+pub struct OtlpOptions {
+    /// OTLP/HTTP collector base URL, e.g. http://localhost:4318 (JSON encoding)
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Extra request header, e.g. "authorization=Bearer abc" (repeatable)
+    #[arg(long, value_name = "KEY=VALUE", requires = "otlp_endpoint")]
+    pub otlp_header: Vec<String>,
+
+    /// service.name resource attribute
+    #[arg(long, default_value = "eclipta", requires = "otlp_endpoint")]
+    pub otlp_service_name: String,
+
+    /// Append requests that could not be delivered to this file, one OTLP JSON request per line
+    #[arg(long, value_name = "FILE", requires = "otlp_endpoint")]
+    pub otlp_fallback: Option<PathBuf>,
+}
+
+/// One OTLP log record
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub time_ns: u64,
+    pub body: String,
+    pub attributes: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    Gauge,
+    /// Monotonic cumulative sum, i.e. a counter
+    Sum,
+}
+
+/// One data point of a gauge or sum
+#[derive(Debug, Clone)]
+pub struct MetricPoint {
+    pub name: String,
+    pub description: String,
+    pub unit: String,
+    pub kind: MetricKind,
+    pub attributes: Map<String, Value>,
+    pub value: f64,
+}
+
+enum Message {
+    Log(LogRecord),
+    Metric(MetricPoint),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Cheap handle for queueing telemetry from any task
+#[derive(Debug, Clone)]
+pub struct OtlpSender {
+    tx: mpsc::Sender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl OtlpSender {
+    /// Queue a record without waiting; records are dropped (and counted) when the queue is full
+    pub fn log(&self, record: LogRecord) {
+        if self.tx.try_send(Message::Log(record)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn metric(&self, point: MetricPoint) {
+        if self.tx.try_send(Message::Metric(point)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Send everything queued so far and wait until it is delivered or given up on
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.tx.send(Message::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+}
+
+/// Batches logs and metrics in a background task and posts them to the collector
+#[derive(Debug)]
+pub struct OtlpExporter {
+    sender: OtlpSender,
+    task: JoinHandle<()>,
+    closing: Arc<AtomicBool>,
+}
+
+impl OtlpExporter {
+    /// `None` when no endpoint is configured
+    pub fn start(opts: &OtlpOptions) -> Result<Option<OtlpExporter>> {
+        let Some(endpoint) = opts.otlp_endpoint.as_deref() else { return Ok(None) };
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(anyhow!("--otlp-endpoint must be an http:// or https:// URL (OTLP/HTTP), got '{}'", endpoint));
+        }
+        let mut headers = reqwest::header::HeaderMap::new();
+        for h in &opts.otlp_header {
+            let (k, v) = h
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid --otlp-header '{}', expected KEY=VALUE", h))?;
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(k.trim().as_bytes())?,
+                reqwest::header::HeaderValue::from_str(v.trim())?,
+            );
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        let closing = Arc::new(AtomicBool::new(false));
+        let shipper = Shipper {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            resource: resource(&opts.otlp_service_name),
+            fallback: opts.otlp_fallback.clone(),
+            start_ns: now_ns(),
+            closing: closing.clone(),
+        };
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let task = tokio::spawn(shipper.run(rx));
+        Ok(Some(OtlpExporter {
+            sender: OtlpSender { tx, dropped: Arc::new(AtomicU64::new(0)) },
+            task,
+            closing,
+        }))
+    }
+
+    pub fn sender(&self) -> OtlpSender {
+        self.sender.clone()
+    }
+
+    pub fn log(&self, record: LogRecord) {
+        self.sender.log(record)
+    }
+
+    pub fn metric(&self, point: MetricPoint) {
+        self.sender.metric(point)
+    }
+
+    pub async fn flush(&self) {
+        self.sender.flush().await
+    }
+
+    /// Deliver what is queued and stop the background task, even if cloned senders are still around.
+    /// Failed requests are not retried any more, and the whole flush is bounded by `SHUTDOWN_TIMEOUT`.
+    pub async fn shutdown(self) {
+        self.closing.store(true, Ordering::Relaxed);
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.sender.flush()).await.is_err() {
+            warn(&format!(
+                "OTLP collector did not accept queued data within {}s; dropping it",
+                SHUTDOWN_TIMEOUT.as_secs()
+            ));
+        }
+        self.task.abort();
+        let dropped = self.sender.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            warn(&format!("OTLP queue was full; dropped {} records", dropped));
+        }
+    }
+}
+
+struct Shipper {
+    client: reqwest::Client,
+    endpoint: String,
+    resource: Value,
+    fallback: Option<PathBuf>,
+    /// Start of the cumulative sums reported by this exporter
+    start_ns: u64,
+    /// Set on shutdown so failed requests go straight to the fallback
+    closing: Arc<AtomicBool>,
+}
+
+impl Shipper {
+    async fn run(self, mut rx: mpsc::Receiver<Message>) {
+        let mut logs = Vec::new();
+        let mut metrics = Vec::new();
+        let mut ticker = tokio::time::interval(FLUSH_EVERY);
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(Message::Log(r)) => {
+                        logs.push(r);
+                        if logs.len() >= MAX_BATCH {
+                            self.ship_logs(&mut logs).await;
+                        }
+                    }
+                    Some(Message::Metric(p)) => metrics.push(p),
+                    Some(Message::Flush(done)) => {
+                        self.ship_logs(&mut logs).await;
+                        self.ship_metrics(&mut metrics).await;
+                        let _ = done.send(());
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    self.ship_logs(&mut logs).await;
+                    self.ship_metrics(&mut metrics).await;
+                }
+            }
+        }
+        self.ship_logs(&mut logs).await;
+        self.ship_metrics(&mut metrics).await;
+    }
+
+    async fn ship_logs(&self, logs: &mut Vec<LogRecord>) {
+        if logs.is_empty() {
+            return;
+        }
+        let records: Vec<Value> = logs.drain(..).map(|r| log_json(&r)).collect();
+        let body = json!({
+            "resourceLogs": [{
+                "resource": self.resource,
+                "scopeLogs": [{ "scope": scope(), "logRecords": records }]
+            }]
+        });
+        self.ship("/v1/logs", body).await;
+    }
+
+    async fn ship_metrics(&self, metrics: &mut Vec<MetricPoint>) {
+        if metrics.is_empty() {
+            return;
+        }
+        let now = now_ns();
+        // Points of the same metric go into one metric entry
+        let mut grouped: Vec<(MetricPoint, Vec<Value>)> = Vec::new();
+        for p in metrics.drain(..) {
+            let point = point_json(&p, self.start_ns, now);
+            match grouped.iter_mut().find(|(m, _)| m.name == p.name) {
+                Some((_, points)) => points.push(point),
+                None => grouped.push((p, vec![point])),
+            }
+        }
+        let metrics: Vec<Value> = grouped
+            .into_iter()
+            .map(|(m, points)| {
+                let mut metric = json!({ "name": m.name, "description": m.description, "unit": m.unit });
+                match m.kind {
+                    MetricKind::Gauge => metric["gauge"] = json!({ "dataPoints": points }),
+                    MetricKind::Sum => {
+                        // 2 = AGGREGATION_TEMPORALITY_CUMULATIVE
+                        metric["sum"] = json!({ "dataPoints": points, "aggregationTemporality": 2, "isMonotonic": true })
+                    }
+                }
+                metric
+            })
+            .collect();
+        let body = json!({
+            "resourceMetrics": [{
+                "resource": self.resource,
+                "scopeMetrics": [{ "scope": scope(), "metrics": metrics }]
+            }]
+        });
+        self.ship("/v1/metrics", body).await;
+    }
+
+    /// POST with exponential backoff on network errors, 429 and 5xx; undeliverable
+    /// requests go to the fallback file
+    async fn ship(&self, path: &str, body: Value) {
+        let url = format!("{}{}", self.endpoint, path);
+        let mut delay = Duration::from_millis(500);
+        let mut last_error = String::new();
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.client.post(&url).json(&body).send().await {
+                Ok(resp) if resp.status().is_success() => return,
+                Ok(resp) => {
+                    let status = resp.status();
+                    last_error = format!("{} returned {}", url, status);
+                    if status.as_u16() != 429 && !status.is_server_error() {
+                        break;
+                    }
+                    if let Some(wait) = retry_after(&resp) {
+                        delay = wait;
+                    }
+                }
+                Err(e) => last_error = format!("{}: {}", url, e),
+            }
+            if attempt < MAX_ATTEMPTS && !self.closing.load(Ordering::Relaxed) {
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(30));
+            } else {
+                break;
+            }
+        }
+        self.fall_back(&body, &last_error);
+    }
+
+    fn fall_back(&self, body: &Value, error: &str) {
+        let Some(path) = &self.fallback else {
+            warn(&format!("OTLP export failed, data dropped: {}", error));
+            return;
+        };
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| writeln!(f, "{}", body));
+        match written {
+            Ok(()) => warn(&format!("OTLP export failed ({}); saved to {}", error, path.display())),
+            Err(e) => warn(&format!("OTLP export failed ({}) and {} is not writable: {}", error, path.display(), e)),
+        }
+    }
+}
+
+fn retry_after(resp: &reqwest::Response) -> Option<Duration> {
+    let secs: u64 = resp.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.parse().ok()?;
+    Some(Duration::from_secs(secs.min(30)))
+}
+
+fn resource(service_name: &str) -> Value {
+    let mut attrs = Map::new();
+    attrs.insert("service.name".to_string(), Value::from(service_name));
+    if let Ok(host) = hostname::get() {
+        attrs.insert("host.name".to_string(), Value::from(host.to_string_lossy().to_string()));
+    }
+    json!({ "attributes": attributes(&attrs) })
+}
+
+fn scope() -> Value {
+    json!({ "name": "eclipta", "version": env!("CARGO_PKG_VERSION") })
+}
+
+fn log_json(r: &LogRecord) -> Value {
+    json!({
+        "timeUnixNano": r.time_ns.to_string(),
+        "observedTimeUnixNano": now_ns().to_string(),
+        // 9 = SEVERITY_NUMBER_INFO
+        "severityNumber": 9,
+        "severityText": "INFO",
+        "body": { "stringValue": r.body },
+        "attributes": attributes(&r.attributes),
+    })
+}
+
+fn point_json(p: &MetricPoint, start: u64, now: u64) -> Value {
+    let mut point = json!({ "timeUnixNano": now.to_string(), "attributes": attributes(&p.attributes) });
+    if p.kind == MetricKind::Sum {
+        point["startTimeUnixNano"] = Value::from(start.to_string());
+    }
+    if p.kind == MetricKind::Sum && p.value.fract() == 0.0 && p.value >= 0.0 {
+        point["asInt"] = Value::from((p.value as u64).to_string());
+    } else {
+        point["asDouble"] = Value::from(p.value);
+    }
+    point
+}
+
+/// OTLP `KeyValue` list from a JSON object
+pub fn attributes(fields: &Map<String, Value>) -> Vec<Value> {
+    fields.iter().map(|(k, v)| json!({ "key": k, "value": any_value(v) })).collect()
+}
+
+/// OTLP/JSON `AnyValue`; 64-bit integers are strings, as the protobuf JSON mapping requires
+fn any_value(v: &Value) -> Value {
+    match v {
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n.as_f64().unwrap_or(0.0) }),
+        Value::Array(items) => json!({ "arrayValue": { "values": items.iter().map(any_value).collect::<Vec<_>>() } }),
+        Value::Object(fields) => json!({ "kvlistValue": { "values": attributes(fields) } }),
+        Value::Null => json!({}),
+    }
+}
+
+pub fn now_ns() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    type Requests = Arc<Mutex<Vec<(String, Value)>>>;
+
+    /// Minimal HTTP/1.1 collector that records every request and answers with `status`
+    async fn collector(status: u16) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests: Requests = Arc::default();
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { return };
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    loop {
+                        let mut request_line = String::new();
+                        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+                        let mut len = 0;
+                        loop {
+                            let mut line = String::new();
+                            stream.read_line(&mut line).await.unwrap();
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                            if let Some((k, v)) = line.split_once(':') {
+                                if k.eq_ignore_ascii_case("content-length") {
+                                    len = v.trim().parse().unwrap();
+                                }
+                            }
+                        }
+                        let mut body = vec![0u8; len];
+                        stream.read_exact(&mut body).await.unwrap();
+                        seen.lock().unwrap().push((path, serde_json::from_slice(&body).unwrap()));
+                        let resp = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\n\r\n", status);
+                        stream.get_mut().write_all(resp.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    fn options(endpoint: &str) -> OtlpOptions {
+        OtlpOptions {
+            otlp_endpoint: Some(endpoint.to_string()),
+            otlp_header: vec!["x-test=1".to_string()],
+            otlp_service_name: "eclipta-test".to_string(),
+            otlp_fallback: None,
+        }
+    }
+
+    fn counter(value: f64) -> MetricPoint {
+        MetricPoint {
+            name: "eclipta.program.runs".to_string(),
+            description: "Runs".to_string(),
+            unit: "1".to_string(),
+            kind: MetricKind::Sum,
+            attributes: Map::new(),
+            value,
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_logs_and_sums() {
+        let (url, requests) = collector(200).await;
+        let exporter = OtlpExporter::start(&options(&url)).unwrap().unwrap();
+        let mut attrs = Map::new();
+        attrs.insert("pid".to_string(), Value::from(42));
+        exporter.log(LogRecord { time_ns: 1_000, body: "exec pid=42".to_string(), attributes: attrs });
+        exporter.metric(counter(7.0));
+        exporter.shutdown().await;
+
+        let requests = requests.lock().unwrap();
+        let (_, logs) = requests.iter().find(|(p, _)| p == "/v1/logs").expect("logs were posted");
+        let record = &logs["resourceLogs"][0]["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(record["timeUnixNano"], "1000");
+        assert_eq!(record["body"]["stringValue"], "exec pid=42");
+        assert_eq!(record["attributes"][0], json!({ "key": "pid", "value": { "intValue": "42" } }));
+
+        let (_, metrics) = requests.iter().find(|(p, _)| p == "/v1/metrics").expect("metrics were posted");
+        let metric = &metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+        let point = &metric["sum"]["dataPoints"][0];
+        assert_eq!(metric["sum"]["isMonotonic"], true);
+        assert_eq!(point["asInt"], "7");
+        let start: u64 = point["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        let time: u64 = point["timeUnixNano"].as_str().unwrap().parse().unwrap();
+        assert!(start > 0 && start <= time);
+    }
+
+    #[tokio::test]
+    async fn shutdown_does_not_retry_a_failing_collector() {
+        let (url, requests) = collector(503).await;
+        let fallback = std::env::temp_dir().join(format!("eclipta-otlp-{}.jsonl", std::process::id()));
+        let mut opts = options(&url);
+        opts.otlp_fallback = Some(fallback.clone());
+        let exporter = OtlpExporter::start(&opts).unwrap().unwrap();
+        exporter.metric(counter(1.0));
+
+        let started = std::time::Instant::now();
+        exporter.shutdown().await;
+        assert!(started.elapsed() < SHUTDOWN_TIMEOUT);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let saved = std::fs::read_to_string(&fallback).unwrap();
+        assert!(saved.contains("eclipta.program.runs"));
+        std::fs::remove_file(&fallback).unwrap();
+    }
+}
@@ -6,32 +6,45 @@ pub fn default_bin_object() -> PathBuf {
         let p = PathBuf::from(custom);
         if p.exists() { return p; }
     }
+    find_bin_file("ebpf.so")
+}
+
+/// Built-in sampling program used by `eclipta profile` (bin/profile.o)
+pub fn default_profile_object() -> PathBuf {
+    if let Ok(custom) = env::var("ECLIPTA_PROFILE_BIN") {
+        let p = PathBuf::from(custom);
+        if p.exists() { return p; }
+    }
+    find_bin_file("profile.o")
+}
+
+fn find_bin_file(name: &str) -> PathBuf {
     if let Ok(home) = env::var("ECLIPTA_HOME") {
-        let p = PathBuf::from(home).join("bin").join("ebpf.so");
+        let p = PathBuf::from(home).join("bin").join(name);
         if p.exists() { return p; }
     }
     let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let p = cwd.join("bin").join("ebpf.so");
+    let p = cwd.join("bin").join(name);
     if p.exists() { return p; }
 
-    // Fallback to executable dir/../bin/ebpf.so
+    // Fallback to executable dir/../bin/<name>
     if let Ok(exe) = env::current_exe() {
         if let Some(parent) = exe.parent() {
-            let p = parent.join("..").join("bin").join("ebpf.so");
+            let p = parent.join("..").join("bin").join(name);
             if p.exists() { return p; }
         }
     }
 
-    // Last resort: return cwd/bin/ebpf.so even if it doesn't exist
-    cwd.join("bin").join("ebpf.so")
+    // Last resort: return cwd/bin/<name> even if it doesn't exist
+    cwd.join("bin").join(name)
 }
 
-// pub fn default_pin_prefix() -> PathBuf {
-//     if let Ok(p) = env::var("ECLIPTA_PIN_PATH") {
-//         return PathBuf::from(p);
-//     }
-//     PathBuf::from("/sys/fs/bpf/eclipta")
-// }
+pub fn default_pin_prefix() -> PathBuf {
+    if let Ok(p) = env::var("ECLIPTA_PIN_PATH") {
+        return PathBuf::from(p);
+    }
+    PathBuf::from("/sys/fs/bpf/eclipta")
+}
 
 pub fn default_state_path() -> PathBuf {
     if let Ok(p) = env::var("ECLIPTA_STATE") { return PathBuf::from(p); }
@@ -39,4 +52,19 @@ pub fn default_state_path() -> PathBuf {
         return dir.join("eclipta").join("state.json");
     }
     PathBuf::from(".eclipta_state.json")
-} 
\ No newline at end of file
+} 
+
+/// Cached `eclipta probe` results, next to the state file
+pub fn default_probe_path() -> PathBuf {
+    default_state_path().with_file_name("kernel-features.json")
+}
+
+/// Candidate tracefs locations, newest mount point first
+pub const TRACEFS_DIRS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+pub fn trace_pipe_path() -> Option<PathBuf> {
+    TRACEFS_DIRS
+        .iter()
+        .map(|d| PathBuf::from(d).join("trace_pipe"))
+        .find(|p| p.exists())
+}
@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Timelike};
+use std::fmt;
+
+const DAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+/// Weekly schedule like "Mon-Fri 09:00-18:00" in local time. A window whose end is before
+/// its start runs overnight into the next day; the days name the day it opens
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveWindow {
+    spec: String,
+    days: [bool; 7],
+    start: u32,
+    end: u32,
+}
+
+impl ActiveWindow {
+    /// Accepts "HH:MM-HH:MM" (every day) or days first: "Mon-Fri", "Sat,Sun", "Mon,Wed-Fri"
+    pub fn parse(spec: &str) -> Result<ActiveWindow> {
+        let invalid = || anyhow!("Invalid active window '{}', expected e.g. \"Mon-Fri 09:00-18:00\"", spec);
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        let (days, times) = match parts.as_slice() {
+            [times] => ([true; 7], *times),
+            [days, times] => (parse_days(days).ok_or_else(invalid)?, *times),
+            _ => return Err(invalid()),
+        };
+        let (start, end) = times.split_once('-').ok_or_else(invalid)?;
+        let start = parse_minute(start).ok_or_else(invalid)?;
+        let end = parse_minute(end).ok_or_else(invalid)?;
+        if start == end {
+            return Err(anyhow!("Active window '{}' is empty", spec));
+        }
+        Ok(ActiveWindow { spec: spec.to_string(), days, start, end })
+    }
+
+    pub fn contains(&self, at: DateTime<Local>) -> bool {
+        let minute = at.hour() * 60 + at.minute();
+        let today = at.weekday().num_days_from_monday() as usize;
+        if self.start < self.end {
+            return self.days[today] && minute >= self.start && minute < self.end;
+        }
+        let yesterday = (today + 6) % 7;
+        (self.days[today] && minute >= self.start) || (self.days[yesterday] && minute < self.end)
+    }
+
+    /// The next time `contains` changes, searched minute by minute over a week
+    pub fn next_change(&self, from: DateTime<Local>) -> Option<DateTime<Local>> {
+        let open = self.contains(from);
+        let mut t = from.with_second(0)?.with_nanosecond(0)?;
+        for _ in 0..7 * 24 * 60 {
+            t += Duration::minutes(1);
+            if self.contains(t) != open {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for ActiveWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+fn parse_days(s: &str) -> Option<[bool; 7]> {
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        let (from, to) = part.split_once('-').unwrap_or((part, part));
+        let (from, to) = (day_index(from)?, day_index(to)?);
+        let mut d = from;
+        loop {
+            days[d] = true;
+            if d == to {
+                break;
+            }
+            d = (d + 1) % 7;
+        }
+    }
+    Some(days)
+}
+
+/// A full day name or its three-letter abbreviation, in any case
+fn day_index(s: &str) -> Option<usize> {
+    let s = s.trim().to_ascii_lowercase();
+    DAYS.iter().position(|d| *d == s || d[..3] == s)
+}
+
+/// Minutes since midnight; "24:00" ends a window at midnight
+fn parse_minute(s: &str) -> Option<u32> {
+    if s == "24:00" {
+        return Some(24 * 60);
+    }
+    let t = NaiveTime::parse_from_str(s, "%H:%M").ok()?;
+    Some(t.hour() * 60 + t.minute())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// 2024-01-01 was a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn days_are_full_names_or_abbreviations() {
+        let w = ActiveWindow::parse("Mon-Fri 09:00-18:00").unwrap();
+        assert_eq!(w.days, [true, true, true, true, true, false, false]);
+        assert_eq!(ActiveWindow::parse("saturday,SUN 00:00-24:00").unwrap().days, [false, false, false, false, false, true, true]);
+        // Ranges wrap around the end of the week
+        assert_eq!(ActiveWindow::parse("Fri-Mon 10:00-11:00").unwrap().days, [true, false, false, false, true, true, true]);
+        assert_eq!(ActiveWindow::parse("Mon,Wed-Thu 10:00-11:00").unwrap().days, [true, false, true, true, false, false, false]);
+        assert_eq!(ActiveWindow::parse("10:00-11:00").unwrap().days, [true; 7]);
+
+        for bad in ["Monkey 09:00-18:00", "Mo 09:00-18:00", "Mon-Fri", "Mon 9-18", "Mon 09:00-25:00", "Mon 09:00-09:00", "a b c"] {
+            assert!(ActiveWindow::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn overnight_windows_belong_to_the_day_they_open() {
+        let w = ActiveWindow::parse("Fri 22:00-06:00").unwrap();
+        assert!(w.contains(at(5, 22, 0)));
+        assert!(w.contains(at(6, 5, 59)));
+        assert!(!w.contains(at(6, 6, 0)));
+        assert!(!w.contains(at(5, 21, 59)));
+        // Thursday night is not part of it, nor is early Friday
+        assert!(!w.contains(at(4, 23, 0)));
+        assert!(!w.contains(at(5, 3, 0)));
+
+        let day = ActiveWindow::parse("Mon-Fri 09:00-24:00").unwrap();
+        assert!(day.contains(at(1, 23, 59)));
+        assert!(!day.contains(at(6, 9, 0)));
+    }
+
+    #[test]
+    fn next_change_finds_the_next_edge() {
+        let w = ActiveWindow::parse("Mon-Fri 09:00-18:00").unwrap();
+        assert_eq!(w.next_change(at(1, 8, 30)), Some(at(1, 9, 0)));
+        assert_eq!(w.next_change(at(1, 12, 0)), Some(at(1, 18, 0)));
+        // Friday evening waits for Monday morning
+        assert_eq!(w.next_change(at(5, 18, 0)), Some(at(8, 9, 0)));
+        assert_eq!(ActiveWindow::parse("00:00-24:00").unwrap().next_change(at(1, 0, 0)), None);
+    }
+}
@@ -11,11 +11,102 @@ pub struct AttachmentRecord {
     pub pinned_maps: Vec<PathBuf>,
     pub pid: u32,
     pub created_at: i64,
+    /// Object the program was loaded from
+    #[serde(default)]
+    pub object: Option<PathBuf>,
+    /// Where `load` pinned the programs and links that keep it running
+    #[serde(default)]
+    pub pin_dir: Option<PathBuf>,
+}
+
+/// A program converged by `eclipta apply`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppliedRecord {
+    pub title: String,
+    pub version: String,
+    pub hook: String,
+    /// Object the hook was detected from, so pruning can find the hook again
+    pub path: String,
+    pub iface: Option<String>,
+    pub cgroup: Option<PathBuf>,
+    /// Hash of everything in the manifest entry that needs a reload when it changes
+    pub spec_hash: String,
+    pub applied_at: i64,
+}
+
+/// A program loaded with `load --ttl` or `--active-window`, enforced by `eclipta sweep`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeaseRecord {
+    /// Registry title, or the object's file stem
+    pub name: String,
+    pub registry_id: Option<i32>,
+    pub program: PathBuf,
+    pub iface: Option<String>,
+    pub map_init: Vec<String>,
+    #[serde(default)]
+    pub tail_calls: Vec<String>,
+    #[serde(default)]
+    pub share_maps: Vec<String>,
+    pub loaded_at: i64,
+    pub expires_at: Option<i64>,
+    /// Weekly schedule, see `ActiveWindow::parse`
+    pub window: Option<String>,
+    /// Unloaded because the window is closed; loaded again when it opens
+    pub suspended: bool,
+}
+
+impl LeaseRecord {
+    /// Seconds until the TTL runs out, zero once it has
+    pub fn remaining(&self, now: i64) -> Option<i64> {
+        self.expires_at.map(|e| (e - now).max(0))
+    }
+
+    /// Short text for status displays, e.g. "14m 32s left" or "window closed"
+    pub fn describe(&self, now: i64) -> String {
+        let mut parts = Vec::new();
+        if let Some(left) = self.remaining(now) {
+            parts.push(match left {
+                0 => "expired".to_string(),
+                s => format!("{} left", humantime::format_duration(std::time::Duration::from_secs(s as u64))),
+            });
+        }
+        if self.window.is_some() {
+            parts.push(if self.suspended { "window closed" } else { "window open" }.to_string());
+        }
+        parts.join(", ")
+    }
+}
+
+/// A map one object opened from another program or pin with `load --share-map`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SharedMapRecord {
+    pub map: String,
+    pub map_id: u32,
+    /// As given: a pinned path or a program title
+    pub source: String,
+    /// Object of the program that created the map, or the pin it was named by
+    pub owner: PathBuf,
+    /// Object that opened the map
+    pub user: PathBuf,
+    /// Pin the user's loader reopened the map through
+    pub pin: PathBuf,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct State {
     pub attachments: Vec<AttachmentRecord>,
+    #[serde(default)]
+    pub applied: Vec<AppliedRecord>,
+    #[serde(default)]
+    pub leases: Vec<LeaseRecord>,
+    #[serde(default)]
+    pub shared_maps: Vec<SharedMapRecord>,
+}
+
+/// The lease of a registry program, matched by ID or title
+pub fn lease_for<'a>(st: &'a State, registry_id: i32, title: &str) -> Option<&'a LeaseRecord> {
+    st.leases.iter().find(|l| l.registry_id == Some(registry_id))
+        .or_else(|| st.leases.iter().find(|l| l.name == title))
 }
 
 pub fn load_state(path: &PathBuf) -> State {
@@ -0,0 +1,114 @@
+use crate::utils::logger::{info, success, warn};
+use anyhow::{anyhow, Context, Result};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use tokio::process::Command;
+
+/// Read by eclipta's units, so the database URL stays out of the world-readable unit files
+pub const ENV_FILE: &str = "/etc/eclipta/eclipta.env";
+
+/// Variables copied into the environment file when they are set
+const UNIT_ENV: &[&str] = &["DATABASE_URL", "ECLIPTA_PIN_PATH", "ECLIPTA_STATE", "ECLIPTA_HOME"];
+
+/// A oneshot service running `eclipta <command>` with the shared environment file
+pub fn oneshot_service(description: &str, command: &str, remain_after_exit: bool, wanted_by: Option<&str>) -> Result<String> {
+    let exe = std::env::current_exe().context("Failed to locate the eclipta binary")?;
+    let mut unit = format!(
+        "[Unit]\n\
+         Description={}\n\
+         After=network-online.target postgresql.service\n\
+         Wants=network-online.target\n\
+         RequiresMountsFor=/sys/fs/bpf\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         RemainAfterExit={}\n\
+         EnvironmentFile=-{}\n\
+         ExecStart={} {}\n",
+        description,
+        if remain_after_exit { "yes" } else { "no" },
+        ENV_FILE,
+        exe.display(),
+        command
+    );
+    if let Some(target) = wanted_by {
+        unit.push_str(&format!("\n[Install]\nWantedBy={}\n", target));
+    }
+    Ok(unit)
+}
+
+/// Write the units (or print them) and the environment file, then enable `enable` if given
+pub async fn install_units(dir: &Path, units: &[(&str, String)], print: bool, enable: Option<&str>) -> Result<()> {
+    if print {
+        for (name, contents) in units {
+            println!("# {}\n{}", name, contents);
+        }
+        return Ok(());
+    }
+    for (name, contents) in units {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+        success(&format!("Wrote {}", path.display()));
+    }
+    write_env_file()?;
+
+    match enable {
+        Some(unit) => {
+            systemctl(&["daemon-reload"]).await?;
+            // Timers do nothing until started; services wait for the next boot
+            if unit.ends_with(".timer") {
+                systemctl(&["enable", "--now", unit]).await?;
+            } else {
+                systemctl(&["enable", unit]).await?;
+            }
+            success(&format!("{} is enabled", unit));
+        }
+        None => {
+            // The last unit is the one to enable, e.g. the timer after its service
+            let unit = units.last().map(|(n, _)| *n).unwrap_or_default();
+            let now = if unit.ends_with(".timer") { "--now " } else { "" };
+            info(&format!("Enable it with: systemctl daemon-reload && systemctl enable {}{}", now, unit));
+        }
+    }
+    Ok(())
+}
+
+async fn systemctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("systemctl")
+        .args(args)
+        .output()
+        .await
+        .context("Failed to execute systemctl")?;
+    if !output.status.success() {
+        return Err(anyhow!("systemctl {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Copy the settings this shell uses (including .env) for the units, readable by root only
+fn write_env_file() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let lines: Vec<String> = UNIT_ENV.iter()
+        .filter_map(|k| std::env::var(k).ok().map(|v| format!("{}={}", k, v)))
+        .collect();
+    if lines.is_empty() {
+        warn(&format!("DATABASE_URL is not set; put it in {} before the units run", ENV_FILE));
+        return Ok(());
+    }
+    let path = Path::new(ENV_FILE);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to write {}", ENV_FILE))?;
+    file.write_all((lines.join("\n") + "\n").as_bytes())?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    success(&format!("Wrote {} ({} settings)", ENV_FILE, lines.len()));
+    Ok(())
+}
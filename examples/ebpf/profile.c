@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-2.0
+// CPU sampling profiler used by `eclipta profile`
+
+#include <linux/bpf.h>
+#include <linux/bpf_perf_event.h>
+#include <bpf/bpf_helpers.h>
+
+#define MAX_STACKS 16384
+#define MAX_DEPTH 127
+
+// Set by the loader; 0 samples every process
+volatile const __u32 target_pid = 0;
+
+struct sample_key {
+    __u32 pid;
+    __s32 kstack_id;
+    __s32 ustack_id;
+    char comm[16];
+};
+
+struct {
+    __uint(type, BPF_MAP_TYPE_STACK_TRACE);
+    __uint(max_entries, MAX_STACKS);
+    __uint(key_size, sizeof(__u32));
+    __uint(value_size, MAX_DEPTH * sizeof(__u64));
+} stacks SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, MAX_STACKS);
+    __type(key, struct sample_key);
+    __type(value, __u64);
+} counts SEC(".maps");
+
+SEC("perf_event")
+int profile_cpu(struct bpf_perf_event_data *ctx)
+{
+    __u32 pid = bpf_get_current_pid_tgid() >> 32;
+    struct sample_key key = {};
+    __u64 one = 1, *count;
+
+    if (target_pid && pid != target_pid)
+        return 0;
+
+    key.pid = pid;
+    key.kstack_id = bpf_get_stackid(ctx, &stacks, 0);
+    key.ustack_id = bpf_get_stackid(ctx, &stacks, BPF_F_USER_STACK);
+    bpf_get_current_comm(&key.comm, sizeof(key.comm));
+
+    count = bpf_map_lookup_elem(&counts, &key);
+    if (count)
+        __sync_fetch_and_add(count, 1);
+    else
+        bpf_map_update_elem(&counts, &key, &one, BPF_NOEXIST);
+
+    return 0;
+}
+
+char _license[] SEC("license") = "GPL";